@@ -26,7 +26,7 @@ fn main() {
         Num::new(3),
         Num::new(4),
     ];
-    let sorted = vec![
+    let sorted = [
         Num::new(1),
         Num::new(2),
         Num::new(3),
@@ -0,0 +1,43 @@
+/* C API of the external_sort crate, built with the `ffi` feature */
+
+#ifndef EXTERNAL_SORT_H
+#define EXTERNAL_SORT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Opaque handle to a sort of byte records */
+typedef struct ExtSort ExtSort;
+
+/* Create a sort holding about `buffer_bytes` bytes of records in memory, and
+ * spilling the rest to `tmp_dir` (or the system temporary directory if NULL).
+ * Returns NULL if `tmp_dir` is not valid UTF-8. */
+ExtSort *extsort_new(uint64_t buffer_bytes, const char *tmp_dir);
+
+/* Push a copy of the `len` bytes at `bytes`. Returns 0, or -1 on error. */
+int extsort_push(ExtSort *sort, const uint8_t *bytes, size_t len);
+
+/* Finish pushing, and wait for the records to be sorted. Returns 0, or -1 on
+ * error. */
+int extsort_finish(ExtSort *sort);
+
+/* Read the next record in byte order, valid until the next call with `sort`.
+ * Returns 1 if a record was read, 0 at the end, or -1 on error. */
+int extsort_next(ExtSort *sort, const uint8_t **bytes, size_t *len);
+
+/* Describe the last error, or return NULL if there was none, valid until the
+ * next error or until `sort` is freed. */
+const char *extsort_last_error(const ExtSort *sort);
+
+/* Release the sort and its intermediate sorted chunks. */
+void extsort_free(ExtSort *sort);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,119 @@
+use std::cmp::Ordering::{self, Equal, Greater, Less};
+use std::error::Error;
+use std::iter::Peekable;
+
+use crate::{ExtSortedIterator, ExternallySortable};
+
+type AlignFn<T, U> = dyn Fn(&T, &U) -> Ordering + Send + Sync;
+
+/// A record of either or both of two aligned iterators, yielded by an
+/// [AlignedIterator](struct.AlignedIterator.html)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EitherOrBoth<A, B> {
+    /// A record of the first iterator without a counterpart in the second
+    Left(A),
+    /// A record of the second iterator without a counterpart in the first
+    Right(B),
+    /// Records of both iterators with equal keys
+    Both(A, B),
+}
+
+/// Iterator that provides the records of two sorted iterators aligned by key,
+/// created by
+/// [ExtSortedIterator::align_by_key](struct.ExtSortedIterator.html#method.align_by_key)
+pub struct AlignedIterator<T, U>
+where
+    T: ExternallySortable,
+    U: ExternallySortable,
+{
+    left: Peekable<ExtSortedIterator<T>>,
+    right: Peekable<ExtSortedIterator<U>>,
+    compare: Box<AlignFn<T, U>>,
+    failed: bool,
+}
+
+impl<T> ExtSortedIterator<T>
+where
+    T: ExternallySortable,
+{
+    /// Walk this iterator and `other` together, yielding the records of
+    /// either or both of them for every key, in key order
+    ///
+    /// Both iterators must be sorted in the order of their keys. Records
+    /// sharing a key are paired one to one in their sorted order, so a key
+    /// with two records in this iterator and three in `other` yields two
+    /// [Both](enum.EitherOrBoth.html#variant.Both) pairs followed by one
+    /// [Right](enum.EitherOrBoth.html#variant.Right) record. Both iterators
+    /// are read in a single streaming pass.
+    pub fn align_by_key<U, K, KF, UF>(self, other: ExtSortedIterator<U>, key: KF, other_key: UF)
+                                      -> AlignedIterator<T, U>
+    where
+        U: ExternallySortable,
+        K: Ord,
+        KF: 'static + Fn(&T) -> K + Send + Sync,
+        UF: 'static + Fn(&U) -> K + Send + Sync,
+    {
+        AlignedIterator {
+            left: self.peekable(),
+            right: other.peekable(),
+            compare: Box::new(move |a: &T, b: &U| key(a).cmp(&other_key(b))),
+            failed: false,
+        }
+    }
+}
+
+impl<T, U> AlignedIterator<T, U>
+where
+    T: ExternallySortable,
+    U: ExternallySortable,
+{
+    /// Yield the next aligned record, with the error type of the iterator
+    fn next_aligned(&mut self) -> Result<Option<EitherOrBoth<T, U>>, Box<dyn Error>> {
+        if let Some(Err(_)) = self.left.peek() {
+            return Err(self.left.next().unwrap().err().unwrap());
+        }
+        if let Some(Err(_)) = self.right.peek() {
+            return Err(self.right.next().unwrap().err().unwrap());
+        }
+        let order = match (self.left.peek(), self.right.peek()) {
+            (Some(Ok(left)), Some(Ok(right))) => (self.compare)(left, right),
+            (Some(_), None) => Less,
+            (None, Some(_)) => Greater,
+            _ => return Ok(None),
+        };
+        // unwraps due to the peek() above
+        Ok(Some(match order {
+                    Less => EitherOrBoth::Left(self.left.next().unwrap()?),
+                    Greater => EitherOrBoth::Right(self.right.next().unwrap()?),
+                    Equal => {
+                        EitherOrBoth::Both(self.left.next().unwrap()?, self.right.next().unwrap()?)
+                    },
+                }))
+    }
+}
+
+impl<T, U> Iterator for AlignedIterator<T, U>
+where
+    T: ExternallySortable,
+    U: ExternallySortable,
+{
+    type Item = Result<EitherOrBoth<T, U>, Box<dyn Error>>;
+
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// of either iterator from disk, or due to serde deserialization issues
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.next_aligned() {
+            Ok(aligned) => aligned.map(Ok),
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, Range};
+use std::sync::{Arc, OnceLock};
+
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use crate::ExternallySortable;
+
+/// Text of the strings of a batch of records, appended to while the batch is
+/// read and frozen once it is
+pub(crate) struct Arena {
+    text: OnceLock<String>,
+}
+
+thread_local! {
+    /// Arena of the batch being read on this thread, if any, along with the
+    /// text appended to it so far
+    static BATCH: RefCell<Option<(Arc<Arena>, String)>> = const { RefCell::new(None) };
+}
+
+/// Batch of records being read into an arena on the current thread, whose
+/// arena is frozen when the batch is dropped
+pub(crate) struct Batch {
+    arena: Arc<Arena>,
+}
+
+impl Batch {
+    /// Start reading a batch on the current thread, into the text of
+    /// `previous` (the arena of the last batch of the same run) if none of
+    /// its records are left
+    pub(crate) fn start(previous: Option<Arc<Arena>>) -> Batch {
+        let mut text = previous.and_then(|arena| Arc::try_unwrap(arena).ok())
+                               .and_then(|arena| arena.text.into_inner())
+                               .unwrap_or_default();
+        text.clear();
+        let arena = Arc::new(Arena { text: OnceLock::new() });
+        BATCH.with(|batch| *batch.borrow_mut() = Some((arena.clone(), text)));
+        Batch { arena }
+    }
+
+    /// Arena of the batch, for the next batch of its run
+    pub(crate) fn arena(&self) -> Arc<Arena> {
+        self.arena.clone()
+    }
+}
+
+impl Drop for Batch {
+    fn drop(&mut self) {
+        if let Some((arena, text)) = BATCH.with(|batch| batch.borrow_mut().take()) {
+            let _ = arena.text.set(text);
+        }
+    }
+}
+
+/// String field of a record that, when the record is read into a merge
+/// buffer by a sorter set with
+/// [ExternalSorter::arena_batches](struct.ExternalSorter.html#method.arena_batches),
+/// is stored in the arena of its batch rather than in an allocation of its
+/// own
+///
+/// Every string of a batch is appended to the same arena, which is reused for
+/// the next batch of the run once every record of the batch has been
+/// dropped. A record kept after its batch is merged keeps the whole arena
+/// alive, and the next batch gets a new one. Strings read or created
+/// elsewhere get an arena of their own. The string dereferences to `str`, and
+/// compares, hashes and serializes like one.
+#[derive(Clone)]
+pub struct ArenaStr {
+    arena: Arc<Arena>,
+    range: Range<usize>,
+}
+
+impl ArenaStr {
+    /// Create a string in an arena of its own
+    pub fn new(s: &str) -> ArenaStr {
+        let arena = Arena { text: OnceLock::from(s.to_string()) };
+        ArenaStr { arena: Arc::new(arena), range: 0..s.len() }
+    }
+
+    /// Create a string in the arena of the batch being read on this thread,
+    /// if any
+    fn read(s: &str) -> ArenaStr {
+        BATCH.with(|batch| match *batch.borrow_mut() {
+                 Some((ref arena, ref mut text)) => {
+                     let start = text.len();
+                     text.push_str(s);
+                     ArenaStr { arena: arena.clone(), range: start..text.len() }
+                 },
+                 None => ArenaStr::new(s),
+             })
+    }
+
+    /// Length of the string in bytes, which is known even while the batch it
+    /// is read with is still being read, unlike the text itself (so that
+    /// `get_size()` can be based on it)
+    pub fn len(&self) -> usize {
+        self.range.len()
+    }
+
+    /// Whether the string is empty
+    pub fn is_empty(&self) -> bool {
+        self.range.is_empty()
+    }
+
+    /// The string, which is empty while the batch it is read with is still
+    /// being read
+    pub fn as_str(&self) -> &str {
+        self.arena.text.get().map_or("", |text| &text[self.range.clone()])
+    }
+}
+
+impl Deref for ArenaStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for ArenaStr {
+    fn from(s: &str) -> ArenaStr {
+        ArenaStr::new(s)
+    }
+}
+
+impl From<String> for ArenaStr {
+    fn from(s: String) -> ArenaStr {
+        let range = 0..s.len();
+        ArenaStr { arena: Arc::new(Arena { text: OnceLock::from(s) }), range }
+    }
+}
+
+impl fmt::Debug for ArenaStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for ArenaStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl PartialEq for ArenaStr {
+    fn eq(&self, other: &ArenaStr) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for ArenaStr {}
+
+impl PartialOrd for ArenaStr {
+    fn partial_cmp(&self, other: &ArenaStr) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ArenaStr {
+    fn cmp(&self, other: &ArenaStr) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Hash for ArenaStr {
+    fn hash<H>(&self, state: &mut H)
+    where
+        H: Hasher,
+    {
+        self.as_str().hash(state)
+    }
+}
+
+impl Serialize for ArenaStr {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ArenaStr {
+    fn deserialize<D>(deserializer: D) -> Result<ArenaStr, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ArenaStrVisitor;
+
+        impl Visitor<'_> for ArenaStrVisitor {
+            type Value = ArenaStr;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string")
+            }
+
+            fn visit_str<E>(self, s: &str) -> Result<ArenaStr, E>
+            where
+                E: de::Error,
+            {
+                Ok(ArenaStr::read(s))
+            }
+        }
+
+        deserializer.deserialize_str(ArenaStrVisitor)
+    }
+}
+
+impl ExternallySortable for ArenaStr {
+    fn get_size(&self) -> u64 {
+        self.len() as u64
+    }
+}
@@ -0,0 +1,116 @@
+use std::cmp::Ordering;
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ExtSortedIterator, ExternalSorter, ExternallySortable};
+
+/// A record along with its position in the input
+#[derive(Serialize, Deserialize, Clone)]
+struct Indexed<T> {
+    record: T,
+    index: u64,
+}
+
+impl<T: Ord> PartialEq for Indexed<T> {
+    fn eq(&self, other: &Indexed<T>) -> bool {
+        self.record == other.record
+    }
+}
+
+impl<T: Ord> Eq for Indexed<T> {}
+
+impl<T: Ord> PartialOrd for Indexed<T> {
+    fn partial_cmp(&self, other: &Indexed<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Ord> Ord for Indexed<T> {
+    fn cmp(&self, other: &Indexed<T>) -> Ordering {
+        self.record.cmp(&other.record)
+    }
+}
+
+impl<T> ExternallySortable for Indexed<T>
+where
+    T: ExternallySortable,
+{
+    fn get_size(&self) -> u64 {
+        self.record.get_size() + 8
+    }
+}
+
+/// Iterator that provides the (0-based) input positions of sorted `T`s,
+/// created by [ExternalSorter::argsort](struct.ExternalSorter.html#method.argsort)
+pub struct ArgsortIterator<T> {
+    iter: ExtSortedIterator<Indexed<T>>,
+}
+
+impl<T> Iterator for ArgsortIterator<T>
+where
+    T: ExternallySortable,
+{
+    type Item = Result<u64, Box<dyn Error>>;
+
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// from disk, or due to serde deserialization issues
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|r| r.map(|indexed| indexed.index))
+    }
+
+    fn count(self) -> usize {
+        self.iter.count()
+    }
+}
+
+impl<T> ExternalSorter<T>
+where
+    T: ExternallySortable,
+{
+    /// Sort the `T`s provided by `unsorted`, and return an iterator over
+    /// their (0-based) positions in the input in sorted (ascending) order
+    ///
+    /// See [argsort_by](#method.argsort_by).
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    pub fn argsort<I>(&self, unsorted: I) -> Result<ArgsortIterator<T>, Box<dyn Error>>
+    where
+        I: Iterator<Item = T>,
+        T: 'static + Send,
+    {
+        self.argsort_by(unsorted, |a: &T, b: &T| a.cmp(b))
+    }
+
+    /// Sort (based on `compare`) the `T`s provided by `unsorted`, and return
+    /// an iterator over their (0-based) positions in the input in sorted
+    /// order, e.g. to reorder a dataset that is already on disk or to build
+    /// an index of it, without reading the records back from the sorter
+    ///
+    /// Every record is spilled along with its position, and records that
+    /// compare as equal keep their input order, as with
+    /// [sort_by](#method.sort_by).
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    pub fn argsort_by<I, F>(&self, unsorted: I, compare: F)
+                            -> Result<ArgsortIterator<T>, Box<dyn Error>>
+    where
+        T: Send,
+        I: Iterator<Item = T>,
+        F: 'static + Fn(&T, &T) -> Ordering + Send + Sync,
+    {
+        let indexed = unsorted.zip(0..).map(|(record, index)| Indexed { record, index });
+        let iter = self.retype::<Indexed<T>>()
+                       .sort_by_sync(indexed, move |a, b| compare(&a.record, &b.record))?;
+        Ok(ArgsortIterator { iter })
+    }
+}
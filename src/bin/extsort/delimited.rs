@@ -0,0 +1,84 @@
+use std::error::Error;
+use std::io::BufRead;
+
+use csv::{ReaderBuilder, StringRecord, Terminator, WriterBuilder};
+use serde_json::{Number, Value};
+
+use external_sort::JsonKey;
+
+use crate::{Record, Records};
+
+/// Options of the `csv` subcommand
+pub struct CsvOptions {
+    /// Header name or 1-based index of the column to sort by
+    pub column: String,
+    /// Whether the column is compared as numbers
+    pub numeric: bool,
+    pub delimiter: u8,
+    /// Whether the first row of every input is a header
+    pub header: bool,
+}
+
+/// Read the CSV records of `inputs`, keyed by the sort column, along with the
+/// header row of the first input (the header rows of the others are skipped)
+pub fn records<'a>(options: &'a CsvOptions, inputs: Vec<Box<dyn BufRead>>)
+                   -> Result<(Option<String>, Records<'a>), Box<dyn Error>> {
+    let mut readers: Vec<_> = inputs.into_iter()
+                                    .map(|input| {
+                                             ReaderBuilder::new().delimiter(options.delimiter)
+                                                                 .has_headers(options.header)
+                                                                 .flexible(true)
+                                                                 .from_reader(input)
+                                         })
+                                    .collect();
+    let mut header = None;
+    if options.header {
+        // there is always at least one input
+        header = Some(readers[0].headers()?.clone());
+    }
+    let column = match options.column.parse::<usize>() {
+        Ok(0) => return Err("column indexes start at 1".into()),
+        Ok(i) => i - 1,
+        Err(_) => {
+            header.as_ref()
+                  .and_then(|h| h.iter().position(|name| name == options.column))
+                  .ok_or_else(|| format!("no column named {}", options.column))?
+        },
+    };
+    let header = match header {
+        Some(ref header) => Some(to_line(options, header)?),
+        None => None,
+    };
+
+    let records = readers.into_iter()
+                         .flat_map(|reader| reader.into_records())
+                         .map(move |record| {
+                                  let record = record?;
+                                  let key = match record.get(column) {
+                                      Some(field) if options.numeric => {
+                                          field.trim()
+                                               .parse::<f64>()
+                                               .ok()
+                                               .and_then(Number::from_f64)
+                                               .map_or(Value::Null, Value::Number)
+                                      },
+                                      Some(field) => Value::String(field.to_string()),
+                                      None => Value::Null,
+                                  };
+                                  Ok(Some(Record { key: JsonKey(key), line: to_line(options, &record)? }))
+                              });
+
+    Ok((header, Box::new(records)))
+}
+
+/// Write a record back to CSV, quoting its fields as needed
+fn to_line(options: &CsvOptions, record: &StringRecord) -> Result<String, Box<dyn Error>> {
+    let mut writer = WriterBuilder::new().delimiter(options.delimiter)
+                                         .terminator(Terminator::Any(b'\n'))
+                                         .from_writer(Vec::new());
+    writer.write_record(record)?;
+    let mut line = writer.into_inner().map_err(|e| e.to_string())?;
+    line.pop();
+
+    Ok(String::from_utf8(line)?)
+}
@@ -0,0 +1,154 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::process;
+
+use serde_json::Value;
+
+use external_sort::{inspect_run, RunInfo};
+
+const USAGE: &str = "\
+usage: extsort inspect [--block-records N] [--records] [--json] RUN...
+
+Describe intermediate sorted runs, e.g. those left in the temporary directory
+of a failed sort: their size, checksum and number of records, their first and
+last records, the first record of every block, and whether they match the
+manifest of their sort.
+
+options:
+    -b, --block-records N   records per block (default 1000)
+    -r, --records           also print every record of the runs
+        --json              print each run (and record) as a line of JSON
+    -h, --help              print this message";
+
+/// Command line options of the subcommand
+struct InspectOptions {
+    block_records: u64,
+    records: bool,
+    json: bool,
+    runs: Vec<PathBuf>,
+}
+
+/// Run the subcommand with the arguments following `inspect`, and exit
+pub fn main<I>(args: I)
+where
+    I: Iterator<Item = String>,
+{
+    let options = match parse_args(args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        },
+        Err(e) => {
+            eprintln!("extsort: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        },
+    };
+    if let Err(e) = run(&options) {
+        eprintln!("extsort: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Parse the command line, returning `None` if help was requested
+fn parse_args<I>(mut args: I) -> Result<Option<InspectOptions>, Box<dyn Error>>
+where
+    I: Iterator<Item = String>,
+{
+    let mut options =
+        InspectOptions { block_records: 1000, records: false, json: false, runs: Vec::new() };
+    while let Some(arg) = args.next() {
+        // split `--flag=value` arguments
+        let (flag, mut value) = match arg.find('=') {
+            Some(i) if arg.starts_with("--") => {
+                (arg[..i].to_string(), Some(arg[i + 1..].to_string()))
+            },
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| {
+            value.take()
+                 .or_else(|| args.next())
+                 .ok_or_else(|| format!("missing value for {}", name))
+        };
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-b" | "--block-records" => {
+                let block_records = value(&flag)?;
+                options.block_records =
+                    block_records.parse()
+                                 .map_err(|_| format!("invalid block size {}", block_records))?;
+            },
+            "-r" | "--records" => options.records = true,
+            "--json" => options.json = true,
+            _ if flag.starts_with('-') => return Err(format!("unknown option {}", flag).into()),
+            _ => options.runs.push(PathBuf::from(arg)),
+        }
+    }
+    if options.runs.is_empty() {
+        return Err("missing run to inspect".into());
+    }
+
+    Ok(Some(options))
+}
+
+fn run(options: &InspectOptions) -> Result<(), Box<dyn Error>> {
+    let stdout = io::stdout();
+    let mut output = BufWriter::new(stdout.lock());
+    for path in &options.runs {
+        let info = inspect_run(path, options.block_records)?;
+        if options.json {
+            serde_json::to_writer(&mut output, &info)?;
+            writeln!(output)?;
+        } else {
+            describe(&mut output, &info)?;
+        }
+        if options.records {
+            if !options.json {
+                writeln!(output, "  records:")?;
+            }
+            for (position, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+                let record: Value = serde_json::from_str(&line?)?;
+                if options.json {
+                    writeln!(output, "{}", record)?;
+                } else {
+                    writeln!(output, "    {}: {}", position, record)?;
+                }
+            }
+        }
+    }
+    output.flush()?;
+
+    Ok(())
+}
+
+/// Write a description of a run for people to read
+fn describe<W>(output: &mut W, info: &RunInfo) -> io::Result<()>
+where
+    W: Write,
+{
+    let or_none = |record: &Option<Value>| record.as_ref().map_or("none".into(), Value::to_string);
+    writeln!(output, "run {}", info.path.display())?;
+    writeln!(output, "  file bytes: {}", info.file_bytes)?;
+    writeln!(output, "  checksum: {:#018x}", info.checksum)?;
+    writeln!(output, "  records: {}", info.records)?;
+    writeln!(output, "  min: {}", or_none(&info.min))?;
+    writeln!(output, "  max: {}", or_none(&info.max))?;
+    match (&info.manifest, info.intact()) {
+        (Some(listed), Some(intact)) => {
+            writeln!(output,
+                     "  manifest: {} ({} records, {} bytes, checksum {:#018x})",
+                     if intact { "matches" } else { "DIFFERS" },
+                     listed.records,
+                     listed.file_bytes,
+                     listed.checksum)?
+        },
+        _ => writeln!(output, "  manifest: none")?,
+    }
+    writeln!(output, "  blocks:")?;
+    for block in &info.blocks {
+        writeln!(output, "    {} at byte {}: {}", block.position, block.offset, block.first)?;
+    }
+    Ok(())
+}
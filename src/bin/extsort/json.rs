@@ -0,0 +1,40 @@
+use std::error::Error;
+use std::io::BufRead;
+
+use serde_json::Value;
+
+use external_sort::{JsonKey, TextRecords};
+
+use crate::{Record, Records};
+
+/// Read the JSON records of `inputs`, each ended by `terminator`, keyed by
+/// the value at the `key` path
+pub fn records<'a>(key: &'a [String], terminator: u8, inputs: Vec<Box<dyn BufRead>>)
+                   -> Records<'a> {
+    Box::new(inputs.into_iter()
+                   .flat_map(move |input| TextRecords::new(input, terminator))
+                   .enumerate()
+                   .map(move |(i, line)| {
+                            line.map_err(|e| e.into())
+                                .and_then(|line| read_record(key, line))
+                                .map_err(|e| format!("line {}: {}", i + 1, e).into())
+                        }))
+}
+
+/// Parse a line of input, skipping blank lines
+fn read_record(key: &[String], line: String) -> Result<Option<Record>, Box<dyn Error>> {
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+    let value: Value = serde_json::from_str(&line)?;
+    let mut field = &value;
+    for name in key {
+        field = match *field {
+            Value::Object(ref object) => object.get(name),
+            Value::Array(ref array) => name.parse::<usize>().ok().and_then(|i| array.get(i)),
+            _ => None,
+        }.unwrap_or(&Value::Null);
+    }
+
+    Ok(Some(Record { key: JsonKey(field.clone()), line }))
+}
@@ -0,0 +1,73 @@
+use std::cmp::Ordering::{self, Equal};
+use std::error::Error;
+use std::io::{BufRead, Write};
+
+use external_sort::compare_versions;
+
+use crate::Options;
+
+/// Options of the `lines` subcommand
+pub struct LinesOptions {
+    /// Whether lines are compared by their leading number
+    pub numeric: bool,
+    /// Whether lines are compared as version strings
+    pub version: bool,
+    /// Whether lines comparing as equal keep their input order, rather than
+    /// being compared byte by byte as a last resort
+    pub stable: bool,
+}
+
+/// Sort the lines of `inputs` into `output`, like `sort(1)`
+pub fn sort(options: &Options, lines: &LinesOptions, inputs: Vec<Box<dyn BufRead>>,
+            output: &mut dyn Write)
+            -> Result<(), Box<dyn Error>> {
+    let (numeric, version, reverse) = (lines.numeric, lines.version, options.reverse);
+    // as with sort(1), unique lines are only compared by their key
+    let last_resort = !lines.stable && !options.unique;
+    let compare = move |a: &String, b: &String| {
+        let mut order = if version {
+            compare_versions(a, b)
+        } else if numeric {
+            compare_numbers(a, b)
+        } else {
+            a.cmp(b)
+        };
+        if order == Equal && last_resort {
+            order = a.cmp(b);
+        }
+        if reverse { order.reverse() } else { order }
+    };
+
+    let mut sorted = crate::sorter::<String>(options).sort_lines_by(inputs, compare)?;
+    if options.unique {
+        sorted = sorted.dedup();
+    }
+    sorted.write_lines(output)?;
+
+    Ok(())
+}
+
+/// Compare the numbers leading two lines, after any blanks (lines without
+/// one compare as zero)
+fn compare_numbers(a: &str, b: &str) -> Ordering {
+    let (a, b) = (leading_number(a), leading_number(b));
+    a.partial_cmp(&b).unwrap_or(Equal)
+}
+
+/// Parse the optionally negative decimal number leading `line`
+fn leading_number(line: &str) -> f64 {
+    let line = line.trim_start();
+    let mut end = 0;
+    let mut point = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '-' if i == 0 => {},
+            '.' if !point => point = true,
+            '0'..='9' => {},
+            _ => break,
+        }
+        end = i + 1;
+    }
+
+    line[..end].parse().unwrap_or(0.0)
+}
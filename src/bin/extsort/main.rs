@@ -0,0 +1,301 @@
+//! Sort newline-delimited JSON, CSV or text files by a key, without reading
+//! them into memory
+//!
+//! ```text
+//! extsort [json] --key user.id [OPTIONS] [FILE...]
+//! extsort csv --column NAME [--numeric] [OPTIONS] [FILE...]
+//! extsort lines [--numeric | --version-sort] [--stable] [OPTIONS] [FILE...]
+//! extsort inspect [--block-records N] [--records] [--json] RUN...
+//! ```
+//!
+//! Records are read from the given files (or standard input) and written to
+//! the output file (or standard output) in the order of their key. For JSON,
+//! the key is found by following a dot-separated path through objects and
+//! arrays (missing keys sort first, as `null`); for CSV, it is a column; and
+//! text lines are sorted whole, as a memory-bounded `sort(1)`. `inspect`
+//! describes the intermediate sorted runs of a sort, for debugging.
+
+mod delimited;
+mod inspect;
+mod json;
+mod lines;
+
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::process;
+
+use external_sort::{ExternalSorter, ExternallySortable, JsonKey, KeyedLine};
+
+use crate::delimited::CsvOptions;
+use crate::lines::LinesOptions;
+
+const USAGE: &str = "\
+usage: extsort [json] --key PATH [OPTIONS] [FILE...]
+       extsort csv --column COLUMN [CSV OPTIONS] [OPTIONS] [FILE...]
+       extsort lines [LINES OPTIONS] [OPTIONS] [FILE...]
+       extsort inspect [INSPECT OPTIONS] RUN...
+
+Sort newline-delimited JSON records by the value at a dot-separated key path,
+CSV records by a column, or lines of text, or describe intermediate sorted runs
+(see `extsort inspect --help`).
+
+options:
+    -S, --buffer-size SIZE  memory buffer, in bytes or with a K, M or G suffix
+                            (default 100M)
+    -T, --temp-dir DIR      directory for the intermediate sorted chunks
+    -r, --reverse           sort in descending order
+    -u, --unique            only output the first record of every key
+        --parallel N        sort with N threads
+    -o, --output FILE       write to FILE instead of standard output
+    -h, --help              print this message
+
+json options:
+    -k, --key PATH          key path to sort by, e.g. `user.id` or `tags.0`
+    -z, --zero-terminated   records end with a NUL byte rather than a newline
+
+csv options:
+    -c, --column COLUMN     column to sort by, as a header name or a 1-based
+                            index
+    -n, --numeric           compare the column as numbers (other values sort
+                            first)
+    -d, --delimiter CHAR    field delimiter (default `,`)
+    -t, --tab               use tabs as the field delimiter, for TSV
+        --no-header         the input has no header row
+
+lines options:
+    -n, --numeric           compare the numbers leading the lines (lines
+                            without one compare as zero)
+    -V, --version-sort      compare the lines as version strings, e.g. 1.2.9
+                            before 1.2.10
+    -s, --stable            keep equal lines in input order, rather than
+                            comparing them byte by byte as a last resort
+    -z, --zero-terminated   lines end with a NUL byte rather than a newline";
+
+/// Input format, along with its options
+enum Format {
+    Json(Vec<String>),
+    Csv(CsvOptions),
+    Lines(LinesOptions),
+}
+
+/// Command line options
+struct Options {
+    format: Format,
+    buffer_bytes: u64,
+    tmp_dir: Option<PathBuf>,
+    reverse: bool,
+    unique: bool,
+    threads: usize,
+    /// Byte ending the records of the input and output
+    terminator: u8,
+    output: Option<PathBuf>,
+    inputs: Vec<PathBuf>,
+}
+
+/// A record of input along with its sort key
+type Record = KeyedLine<JsonKey>;
+
+/// The records of a format, or `None` for input to skip
+type Records<'a> = Box<dyn Iterator<Item = Result<Option<Record>, Box<dyn Error>>> + 'a>;
+
+fn main() {
+    let mut args = env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("inspect") {
+        args.next();
+        return inspect::main(args);
+    }
+    let options = match parse_args(args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        },
+        Err(e) => {
+            eprintln!("extsort: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        },
+    };
+    if let Err(e) = run(&options) {
+        eprintln!("extsort: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Parse the command line, returning `None` if help was requested
+fn parse_args<I>(args: I) -> Result<Option<Options>, Box<dyn Error>>
+where
+    I: Iterator<Item = String>,
+{
+    let mut args = args.peekable();
+    let command = match args.peek().map(String::as_str) {
+        Some("json") => Command::Json,
+        Some("csv") => Command::Csv,
+        Some("lines") => Command::Lines,
+        _ => return parse_options(args, Command::Json),
+    };
+    args.next();
+    parse_options(args, command)
+}
+
+/// Subcommands, which take the options of their format
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Json,
+    Csv,
+    Lines,
+}
+
+/// Parse the options of a subcommand
+fn parse_options<I>(mut args: I, command: Command) -> Result<Option<Options>, Box<dyn Error>>
+where
+    I: Iterator<Item = String>,
+{
+    use crate::Command::{Csv, Json, Lines};
+
+    let mut options = Options {
+        format: Format::Json(Vec::new()),
+        buffer_bytes: 100 << 20,
+        tmp_dir: None,
+        reverse: false,
+        unique: false,
+        threads: 1,
+        terminator: b'\n',
+        output: None,
+        inputs: Vec::new(),
+    };
+    let mut csv_options = CsvOptions {
+        column: String::new(),
+        numeric: false,
+        delimiter: b',',
+        header: true,
+    };
+    let mut lines_options = LinesOptions { numeric: false, version: false, stable: false };
+    let mut key = None;
+    while let Some(arg) = args.next() {
+        // split `--flag=value` arguments
+        let (flag, mut value) = match arg.find('=') {
+            Some(i) if arg.starts_with("--") => {
+                (arg[..i].to_string(), Some(arg[i + 1..].to_string()))
+            },
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| {
+            value.take()
+                 .or_else(|| args.next())
+                 .ok_or_else(|| format!("missing value for {}", name))
+        };
+        match (flag.as_str(), command) {
+            ("-h", _) | ("--help", _) => return Ok(None),
+            ("-S", _) | ("--buffer-size", _) => options.buffer_bytes = parse_size(&value(&flag)?)?,
+            ("-T", _) | ("--temp-dir", _) => options.tmp_dir = Some(PathBuf::from(value(&flag)?)),
+            ("-r", _) | ("--reverse", _) => options.reverse = true,
+            ("-u", _) | ("--unique", _) => options.unique = true,
+            ("-o", _) | ("--output", _) => options.output = Some(PathBuf::from(value(&flag)?)),
+            ("--parallel", _) => {
+                let threads = value(&flag)?;
+                options.threads = threads.parse()
+                                         .map_err(|_| format!("invalid thread count {}", threads))?;
+            },
+            ("-k", Json) | ("--key", Json) | ("-c", Csv) | ("--column", Csv) => {
+                key = Some(value(&flag)?)
+            },
+            ("-n", Csv) | ("--numeric", Csv) => csv_options.numeric = true,
+            ("-n", Lines) | ("--numeric", Lines) => lines_options.numeric = true,
+            ("-V", Lines) | ("--version-sort", Lines) => lines_options.version = true,
+            ("-s", Lines) | ("--stable", Lines) => lines_options.stable = true,
+            ("-z", Json) | ("--zero-terminated", Json) | ("-z", Lines)
+            | ("--zero-terminated", Lines) => options.terminator = b'\0',
+            ("-d", Csv) | ("--delimiter", Csv) => {
+                let delimiter = value(&flag)?;
+                if delimiter.len() != 1 {
+                    return Err(format!("invalid delimiter {}", delimiter).into());
+                }
+                csv_options.delimiter = delimiter.as_bytes()[0];
+            },
+            ("-t", Csv) | ("--tab", Csv) => csv_options.delimiter = b'\t',
+            ("--no-header", Csv) => csv_options.header = false,
+            ("-", _) => options.inputs.push(PathBuf::from(arg)),
+            _ if flag.starts_with('-') => return Err(format!("unknown option {}", flag).into()),
+            _ => options.inputs.push(PathBuf::from(arg)),
+        }
+    }
+    options.format = match (key, command) {
+        (Some(key), Json) => Format::Json(key.split('.').map(String::from).collect()),
+        (Some(column), Csv) => {
+            csv_options.column = column;
+            Format::Csv(csv_options)
+        },
+        (_, Lines) => Format::Lines(lines_options),
+        (None, Json) => return Err("missing --key".into()),
+        (None, Csv) => return Err("missing --column".into()),
+    };
+
+    Ok(Some(options))
+}
+
+/// Parse a size in bytes, with an optional K, M or G suffix
+fn parse_size(size: &str) -> Result<u64, Box<dyn Error>> {
+    let (digits, shift) = match size.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&size[..size.len() - 1], 10),
+        Some('M') => (&size[..size.len() - 1], 20),
+        Some('G') => (&size[..size.len() - 1], 30),
+        _ => (size, 0),
+    };
+    let bytes: u64 = digits.parse().map_err(|_| format!("invalid size {}", size))?;
+    Ok(bytes << shift)
+}
+
+/// Create a sorter with the memory, disk and thread options
+fn sorter<T>(options: &Options) -> ExternalSorter<T>
+where
+    T: ExternallySortable + Send,
+{
+    let sorter = ExternalSorter::new(options.buffer_bytes, options.tmp_dir.clone());
+    sorter.threads(options.threads).terminator(options.terminator)
+}
+
+fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    let mut inputs: Vec<Box<dyn BufRead>> = Vec::new();
+    for input in &options.inputs {
+        if input.to_str() == Some("-") {
+            inputs.push(Box::new(BufReader::new(io::stdin())));
+        } else {
+            let file = File::open(input).map_err(|e| format!("{}: {}", input.display(), e))?;
+            inputs.push(Box::new(BufReader::new(file)));
+        }
+    }
+    if inputs.is_empty() {
+        inputs.push(Box::new(BufReader::new(io::stdin())));
+    }
+    let output: Box<dyn Write> = match options.output {
+        Some(ref path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+    let mut output = BufWriter::new(output);
+
+    let (header, records) = match options.format {
+        Format::Json(ref key) => (None, json::records(key, options.terminator, inputs)),
+        Format::Csv(ref csv) => delimited::records(csv, inputs)?,
+        Format::Lines(ref lines) => return lines::sort(options, lines, inputs, &mut output),
+    };
+
+    let records = records.filter_map(|record| record.transpose());
+    let reverse = options.reverse;
+    let mut sorted = sorter(options).try_sort_by(records, move |a: &Record, b: &Record| {
+                                            if reverse { b.cmp(a) } else { a.cmp(b) }
+                                        })?;
+    if options.unique {
+        sorted = sorted.dedup();
+    }
+
+    for line in header.into_iter().map(Ok).chain(sorted.map(|r| r.map(|r| r.line))) {
+        output.write_all(line?.as_bytes())?;
+        output.write_all(&[options.terminator])?;
+    }
+    output.flush()?;
+
+    Ok(())
+}
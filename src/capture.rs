@@ -0,0 +1,91 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::external_sort::SendError;
+
+/// Last records read by a sort of a sorter set with
+/// [ExternalSorter::capture_on_error](struct.ExternalSorter.html#method.capture_on_error),
+/// written to the capture file if the sort fails or panics
+pub(crate) struct Capture<T>
+where
+    T: Clone + Serialize,
+{
+    path: PathBuf,
+    window: usize,
+    records: RefCell<VecDeque<T>>,
+    /// Whether the records are still to be written if the capture is dropped,
+    /// as when the sort panics
+    armed: Cell<bool>,
+}
+
+impl<T> Capture<T>
+where
+    T: Clone + Serialize,
+{
+    pub(crate) fn new(path: PathBuf, window: usize) -> Capture<T> {
+        Capture { path, window, records: RefCell::new(VecDeque::new()), armed: Cell::new(true) }
+    }
+
+    /// Keep a copy of `record`, dropping the oldest one kept if the window is
+    /// full
+    pub(crate) fn record(&self, record: &T) {
+        if self.window == 0 {
+            return;
+        }
+        let mut records = self.records.borrow_mut();
+        if records.len() == self.window {
+            records.pop_front();
+        }
+        records.push_back(record.clone());
+    }
+
+    /// Write the records kept if `result` is an error, naming the capture
+    /// file in it
+    pub(crate) fn finish<S>(self, result: Result<S, SendError>) -> Result<S, SendError> {
+        self.armed.set(false);
+        result.map_err(|e| match self.save() {
+                  Ok(0) => format!("{} (input captured to {})", e, self.path.display()).into(),
+                  Ok(skipped) => format!("{} (input captured to {}, without {} records that \
+                                          failed to serialize)",
+                                         e,
+                                         self.path.display(),
+                                         skipped).into(),
+                  Err(save) => format!("{} (capturing the input failed: {})", e, save).into(),
+              })
+    }
+
+    /// Write the records kept to the capture file, as newline-delimited JSON,
+    /// returning the number of them that could not be serialized (and were
+    /// left out)
+    fn save(&self) -> io::Result<usize> {
+        let mut file = BufWriter::new(File::create(&self.path)?);
+        let mut skipped = 0;
+        for record in self.records.borrow().iter() {
+            match serde_json::to_vec(record) {
+                Ok(json) => {
+                    file.write_all(&json)?;
+                    file.write_all(b"\n")?;
+                },
+                Err(_) => skipped += 1,
+            }
+        }
+        file.flush()?;
+        Ok(skipped)
+    }
+}
+
+impl<T> Drop for Capture<T>
+where
+    T: Clone + Serialize,
+{
+    fn drop(&mut self) {
+        if self.armed.get() {
+            let _ = self.save();
+        }
+    }
+}
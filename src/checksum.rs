@@ -0,0 +1,94 @@
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::external_sort::SendError;
+
+/// FNV-1a offset basis and prime, for 64-bit hashes
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Running checksum of the bytes of a run file
+#[derive(Clone, Copy)]
+pub(crate) struct Checksum(u64);
+
+impl Checksum {
+    pub(crate) fn new() -> Checksum {
+        Checksum(FNV_OFFSET)
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    pub(crate) fn value(self) -> u64 {
+        self.0
+    }
+}
+
+/// FNV-1a as a `Hasher`, which unlike `DefaultHasher` is the same in every
+/// process and every release of the standard library
+impl Hasher for Checksum {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.update(bytes);
+    }
+}
+
+/// Size and checksum of a run file, as it was written
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub(crate) struct RunDigest {
+    pub(crate) file_bytes: u64,
+    pub(crate) checksum: u64,
+}
+
+/// Check that the run at `path` is there and has the size it was written
+/// with, without reading it
+fn verify_size(path: &Path, written: u64) -> Result<(), SendError> {
+    let file_bytes = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) => return Err(format!("run {} is missing: {}", path.display(), e).into()),
+    };
+    if file_bytes != written {
+        return Err(format!("run {} has {} bytes, but {} were written",
+                           path.display(),
+                           file_bytes,
+                           written).into());
+    }
+    Ok(())
+}
+
+/// Checksum the run at `path`, reading it in full, once checked to have the
+/// `file_bytes` bytes it was written with
+pub(crate) fn read(path: &Path, file_bytes: u64) -> Result<u64, SendError> {
+    verify_size(path, file_bytes)?;
+    let mut file = BufReader::new(File::open(path)?);
+    let mut checksum = Checksum::new();
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        checksum.update(&buf[..read]);
+    }
+    Ok(checksum.value())
+}
+
+/// Check that the run at `path` has the size and checksum it was written
+/// with, reading it in full
+pub(crate) fn verify(path: &Path, digest: &RunDigest) -> Result<(), SendError> {
+    if read(path, digest.file_bytes)? != digest.checksum {
+        return Err(format!("run {} does not match the checksum it was written with",
+                           path.display()).into());
+    }
+    Ok(())
+}
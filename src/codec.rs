@@ -0,0 +1,193 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::iter;
+use std::path::Path;
+use std::process;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{ExtSortedIterator, ExternalSorter, ExternallySortable};
+
+/// Format of the records of a byte stream, read by
+/// [ExternalSorter::sort_reader](struct.ExternalSorter.html#method.sort_reader)
+///
+/// Closures taking the input and returning the next record (or `None` at the
+/// end of the input) are decoders as well.
+pub trait Decoder<T> {
+    /// Read the next record from `input`, or return `None` at its end
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading `input`, or if it does not
+    /// hold a valid record
+    fn decode(&mut self, input: &mut dyn BufRead) -> Result<Option<T>, Box<dyn Error>>;
+}
+
+impl<T, F> Decoder<T> for F
+where
+    F: FnMut(&mut dyn BufRead) -> Result<Option<T>, Box<dyn Error>>,
+{
+    fn decode(&mut self, input: &mut dyn BufRead) -> Result<Option<T>, Box<dyn Error>> {
+        self(input)
+    }
+}
+
+/// Format of the records written to a byte stream, the counterpart of a
+/// [Decoder](trait.Decoder.html)
+pub trait Encoder<T> {
+    /// Write `record` to `output`
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing `output`, or if `record`
+    /// cannot be encoded
+    fn encode(&mut self, record: &T, output: &mut dyn Write) -> Result<(), Box<dyn Error>>;
+}
+
+/// Newline-delimited JSON records, one per line, both decoded and encoded
+///
+/// Blank lines are skipped when decoding.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonLines;
+
+impl<T> Decoder<T> for JsonLines
+where
+    T: DeserializeOwned,
+{
+    fn decode(&mut self, input: &mut dyn BufRead) -> Result<Option<T>, Box<dyn Error>> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if !line.trim().is_empty() {
+                return Ok(Some(serde_json::from_str(&line)?));
+            }
+        }
+    }
+}
+
+impl<T> Encoder<T> for JsonLines
+where
+    T: Serialize,
+{
+    fn encode(&mut self, record: &T, output: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer(&mut *output, record)?;
+        output.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+impl<T> ExternalSorter<T>
+where
+    T: ExternallySortable,
+{
+    /// Sort the records that `decoder` reads from `reader` (such as a file,
+    /// standard input or a socket) and return a sorted (ascending) iterator
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading or decoding the input,
+    /// writing intermediate sorted chunks to disk, or due to serde
+    /// serialization issues
+    pub fn sort_reader<R, D>(&self, reader: R, decoder: D)
+                             -> Result<ExtSortedIterator<T>, Box<dyn Error>>
+    where
+        R: Read,
+        D: Decoder<T>,
+    {
+        self.sort_reader_by(reader, decoder, |a, b| a.cmp(b))
+    }
+
+    /// Sort (based on `compare`) the records that `decoder` reads from
+    /// `reader` and return an iterator
+    ///
+    /// The input is decoded as the sorter consumes it, so it is never held
+    /// in memory beyond the memory buffer.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading or decoding the input
+    /// (reported with the number of the record that failed), writing
+    /// intermediate sorted chunks to disk, or due to serde serialization
+    /// issues
+    pub fn sort_reader_by<R, D, F>(&self, reader: R, mut decoder: D, compare: F)
+                                   -> Result<ExtSortedIterator<T>, Box<dyn Error>>
+    where
+        R: Read,
+        D: Decoder<T>,
+        F: 'static + Fn(&T, &T) -> Ordering + Send + Sync,
+    {
+        let mut input = BufReader::new(reader);
+        let mut records = 0;
+        let decoded = iter::from_fn(|| {
+                          records += 1;
+                          decoder.decode(&mut input)
+                                 .map_err(|e| format!("record {}: {}", records, e))
+                                 .transpose()
+                      });
+        self.try_sort_by(decoded, compare)
+    }
+
+    /// Sort (based on `compare`) the records of the file at `input`, read and
+    /// written with `codec`, into the file at `output`, returning the number
+    /// of records written
+    ///
+    /// The sorted records are written to a temporary file next to `output`,
+    /// which replaces it once they all are, so a failed sort leaves any
+    /// previous `output` in place. The input is read in full before then, so
+    /// `output` may be `input` to sort a file in place. An empty input makes
+    /// an empty output.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading or decoding the input,
+    /// writing intermediate sorted chunks to disk, encoding or writing the
+    /// output, or due to serde serialization issues
+    pub fn sort_file<P, Q, C, F>(&self, input: P, output: Q, mut codec: C, compare: F)
+                                 -> Result<u64, Box<dyn Error>>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+        C: Decoder<T> + Encoder<T>,
+        F: 'static + Fn(&T, &T) -> Ordering + Send + Sync,
+    {
+        let output = output.as_ref();
+        let decoder = |input: &mut dyn BufRead| codec.decode(input);
+        let sorted = self.sort_reader_by(File::open(input)?, decoder, compare)?;
+        let name = output.file_name().ok_or("output path has no file name")?;
+        let tmp = output.with_file_name(format!(".{}.{}.tmp",
+                                                name.to_string_lossy(),
+                                                process::id()));
+        let written = write_file(&tmp, sorted, &mut codec).and_then(|records| {
+                                                                  fs::rename(&tmp, output)?;
+                                                                  Ok(records)
+                                                              });
+        if written.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        written
+    }
+}
+
+/// Encode the `sorted` records to a new file at `path`, synced to disk
+fn write_file<T, E>(path: &Path, sorted: ExtSortedIterator<T>, codec: &mut E)
+                    -> Result<u64, Box<dyn Error>>
+where
+    T: ExternallySortable,
+    E: Encoder<T>,
+{
+    let mut file = BufWriter::new(File::create(path)?);
+    let mut records = 0;
+    for record in sorted {
+        codec.encode(&record?, &mut file)?;
+        records += 1;
+    }
+    file.flush()?;
+    file.get_ref().sync_all()?;
+    Ok(records)
+}
@@ -0,0 +1,232 @@
+use std::cmp::Ordering::{self, Equal};
+use std::error::Error;
+use std::str;
+
+use csv::{ByteRecord, StringRecord};
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+use crate::ExternallySortable;
+
+/// Sortable wrapper for a `csv::StringRecord`, built with the `csv` feature
+///
+/// Records are ordered field by field, and can be sorted by columns with a
+/// [CsvOrder](struct.CsvOrder.html).
+///
+/// # Examples
+///
+/// ```
+/// extern crate csv;
+/// extern crate external_sort;
+///
+/// use external_sort::{CsvOrder, CsvRecord, ExternalSorter};
+///
+/// fn main() {
+///     let data = "name,age\nbob,30\nal,9\n";
+///     let mut reader = csv::Reader::from_reader(data.as_bytes());
+///     let order = CsvOrder::new().header(reader.headers().unwrap(), "age").unwrap().numeric();
+///     let records = reader.into_records().map(|r| CsvRecord(r.unwrap()));
+///
+///     let sorted = ExternalSorter::new(1_000, None)
+///         .sort_by(records, move |a, b| order.compare(a, b))
+///         .unwrap();
+///     let names: Vec<String> = sorted.map(|r| r.unwrap().0[0].to_string()).collect();
+///     assert_eq!(names, vec!["al", "bob"]);
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CsvRecord(pub StringRecord);
+
+/// Sortable wrapper for a `csv::ByteRecord`, built with the `csv` feature
+///
+/// Records are ordered field by field, and can be sorted by columns with a
+/// [CsvOrder](struct.CsvOrder.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CsvByteRecord(pub ByteRecord);
+
+impl PartialOrd for CsvRecord {
+    fn partial_cmp(&self, other: &CsvRecord) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CsvRecord {
+    fn cmp(&self, other: &CsvRecord) -> Ordering {
+        self.0.iter().cmp(other.0.iter())
+    }
+}
+
+impl PartialOrd for CsvByteRecord {
+    fn partial_cmp(&self, other: &CsvByteRecord) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CsvByteRecord {
+    fn cmp(&self, other: &CsvByteRecord) -> Ordering {
+        self.0.iter().cmp(other.0.iter())
+    }
+}
+
+impl Serialize for CsvRecord {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.0.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for CsvRecord {
+    fn deserialize<D>(deserializer: D) -> Result<CsvRecord, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let fields: Vec<String> = Vec::deserialize(deserializer)?;
+        Ok(CsvRecord(StringRecord::from(fields)))
+    }
+}
+
+impl Serialize for CsvByteRecord {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.0.iter().map(Field))
+    }
+}
+
+impl<'de> Deserialize<'de> for CsvByteRecord {
+    fn deserialize<D>(deserializer: D) -> Result<CsvByteRecord, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let fields: Vec<Vec<u8>> = Vec::deserialize(deserializer)?;
+        Ok(CsvByteRecord(ByteRecord::from(fields)))
+    }
+}
+
+/// A field of a byte record, serialized as a sequence of bytes
+struct Field<'a>(&'a [u8]);
+
+impl<'a> Serialize for Field<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.0)
+    }
+}
+
+impl ExternallySortable for CsvRecord {
+    fn get_size(&self) -> u64 {
+        self.0.as_slice().len() as u64
+    }
+}
+
+impl ExternallySortable for CsvByteRecord {
+    fn get_size(&self) -> u64 {
+        self.0.as_slice().len() as u64
+    }
+}
+
+/// How a [CsvOrder](struct.CsvOrder.html) compares a column
+#[derive(Clone, Copy, Debug)]
+struct SortColumn {
+    index: usize,
+    numeric: bool,
+    descending: bool,
+}
+
+/// Comparator builder ordering CSV records by one or more columns, each
+/// compared as text or as numbers, ascending or descending
+///
+/// Missing fields compare as empty, and numeric fields that are not numbers
+/// sort before all numbers.
+#[derive(Clone, Debug, Default)]
+pub struct CsvOrder {
+    columns: Vec<SortColumn>,
+}
+
+impl CsvOrder {
+    /// Create an order that considers every record equal, to add columns to
+    pub fn new() -> CsvOrder {
+        CsvOrder::default()
+    }
+
+    /// Compare the (0-based) column `index`, as text and ascending unless
+    /// changed with [numeric](#method.numeric) or
+    /// [descending](#method.descending), if the previous columns are equal
+    pub fn column(mut self, index: usize) -> CsvOrder {
+        self.columns.push(SortColumn { index, numeric: false, descending: false });
+        self
+    }
+
+    /// Compare the column named `name` in `headers`, as with
+    /// [column](#method.column)
+    ///
+    /// # Errors
+    ///
+    /// This method fails if no column is named `name`
+    pub fn header(self, headers: &StringRecord, name: &str) -> Result<CsvOrder, Box<dyn Error>> {
+        match headers.iter().position(|h| h == name) {
+            Some(index) => Ok(self.column(index)),
+            None => Err(format!("no column named {}", name).into()),
+        }
+    }
+
+    /// Compare the last added column as numbers
+    pub fn numeric(mut self) -> CsvOrder {
+        if let Some(column) = self.columns.last_mut() {
+            column.numeric = true;
+        }
+        self
+    }
+
+    /// Compare the last added column in descending order
+    pub fn descending(mut self) -> CsvOrder {
+        if let Some(column) = self.columns.last_mut() {
+            column.descending = true;
+        }
+        self
+    }
+
+    /// Compare two string records by the columns of this order
+    pub fn compare(&self, a: &CsvRecord, b: &CsvRecord) -> Ordering {
+        self.compare_fields(|i| a.0.get(i).map(str::as_bytes), |i| b.0.get(i).map(str::as_bytes))
+    }
+
+    /// Compare two byte records by the columns of this order
+    pub fn compare_bytes(&self, a: &CsvByteRecord, b: &CsvByteRecord) -> Ordering {
+        self.compare_fields(|i| a.0.get(i), |i| b.0.get(i))
+    }
+
+    fn compare_fields<'a, 'b, A, B>(&self, a: A, b: B) -> Ordering
+    where
+        A: Fn(usize) -> Option<&'a [u8]>,
+        B: Fn(usize) -> Option<&'b [u8]>,
+    {
+        for column in &self.columns {
+            let (a, b) = (a(column.index).unwrap_or(b""), b(column.index).unwrap_or(b""));
+            let order = if column.numeric {
+                let (a, b) = (parse_number(a), parse_number(b));
+                a.partial_cmp(&b).unwrap_or(Equal)
+            } else {
+                a.cmp(b)
+            };
+            let order = if column.descending { order.reverse() } else { order };
+            if order != Equal {
+                return order;
+            }
+        }
+
+        Equal
+    }
+}
+
+/// Parse a numeric field, which is `None` (sorting first) if it is not a
+/// number
+fn parse_number(field: &[u8]) -> Option<f64> {
+    str::from_utf8(field).ok().and_then(|f| f.trim().parse().ok())
+}
+
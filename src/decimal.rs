@@ -0,0 +1,250 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::mem;
+
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use crate::keyenc::EncodeKey;
+use crate::ExternallySortable;
+
+/// Most zeros that a decimal displays between its digits and its decimal
+/// point, beyond which it displays in scientific notation
+const MAX_ZEROS: i64 = 20;
+
+/// Decimal number of arbitrary precision, such as an amount of money, that
+/// is ordered by value
+///
+/// A decimal is parsed from a string like `-1234.50` or `1.2e-30`, and keeps
+/// every digit of it, where a float would round them. Decimals that are equal
+/// in value, like `1.50` and `1.5`, are equal, and display (and serialize, as
+/// strings) as the shortest of them. Decimals deserialize from strings and
+/// JSON numbers alike. Their [key encoding](keyenc/index.html) compares like
+/// their values.
+///
+/// # Examples
+///
+/// ```
+/// use external_sort::Decimal;
+///
+/// let a = Decimal::parse("-0.10").unwrap();
+/// let b = Decimal::parse("-1e-1").unwrap();
+/// let c = Decimal::parse("12345678901234567890.000000000000000001").unwrap();
+/// assert_eq!(a, b);
+/// assert!(b < c);
+/// assert_eq!(a.to_string(), "-0.1");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Decimal {
+    negative: bool,
+    /// Power of ten of the position after the first digit, so that the value
+    /// is `0.digits` times ten to this power
+    exponent: i64,
+    /// Digits from 0 to 9, without leading or trailing zeros, so that zero
+    /// has none
+    digits: Vec<u8>,
+}
+
+impl Decimal {
+    /// Parse a decimal made of an optional sign, digits with an optional
+    /// decimal point, and an optional exponent (`e` or `E` followed by an
+    /// integer)
+    ///
+    /// # Errors
+    ///
+    /// This method fails if `s` is not such a decimal, or if its exponent
+    /// does not fit in an `i64`
+    pub fn parse(s: &str) -> Result<Decimal, Box<dyn Error>> {
+        let invalid = || format!("invalid decimal: {:?}", s);
+        let (negative, unsigned) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+            Some(e) => (&unsigned[..e], unsigned[e + 1..].parse::<i64>().map_err(|_| invalid())?),
+            None => (unsigned, 0),
+        };
+        let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let all_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if integer.len() + fraction.len() == 0 || !all_digits(integer) || !all_digits(fraction) {
+            return Err(invalid().into());
+        }
+
+        let mut digits: Vec<u8> =
+            integer.bytes().chain(fraction.bytes()).map(|b| b - b'0').collect();
+        let leading = digits.iter().take_while(|&&d| d == 0).count();
+        digits.drain(..leading);
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        if digits.is_empty() {
+            return Ok(Decimal { negative: false, exponent: 0, digits });
+        }
+        let exponent = exponent.checked_add(integer.len() as i64 - leading as i64)
+                               .ok_or_else(|| format!("decimal out of range: {:?}", s))?;
+        Ok(Decimal { negative, exponent, digits })
+    }
+
+    /// Whether the decimal is below zero
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Compare the magnitudes of two decimals
+    fn cmp_magnitude(&self, other: &Decimal) -> Ordering {
+        match (self.digits.is_empty(), other.digits.is_empty()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            // without trailing zeros, a decimal whose digits start with those
+            // of another is larger
+            (false, false) => {
+                self.exponent.cmp(&other.exponent).then_with(|| self.digits.cmp(&other.digits))
+            },
+        }
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Decimal) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Decimal) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, false) => self.cmp_magnitude(other),
+            (true, true) => other.cmp_magnitude(self),
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+        }
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.digits.is_empty() {
+            return f.write_str("0");
+        }
+        if self.negative {
+            f.write_str("-")?;
+        }
+        let digits: String = self.digits.iter().map(|&d| (b'0' + d) as char).collect();
+        let len = digits.len() as i64;
+        if self.exponent > len + MAX_ZEROS || self.exponent < -MAX_ZEROS {
+            let (first, rest) = digits.split_at(1);
+            let point = if rest.is_empty() { "" } else { "." };
+            write!(f, "{}{}{}e{}", first, point, rest, self.exponent as i128 - 1)
+        } else if self.exponent <= 0 {
+            write!(f, "0.{}{}", "0".repeat(-self.exponent as usize), digits)
+        } else if self.exponent >= len {
+            write!(f, "{}{}", digits, "0".repeat((self.exponent - len) as usize))
+        } else {
+            let (integer, fraction) = digits.split_at(self.exponent as usize);
+            write!(f, "{}.{}", integer, fraction)
+        }
+    }
+}
+
+impl EncodeKey for Decimal {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        // negative numbers, zero, then positive numbers, with the encoding
+        // of the magnitude complemented for negative numbers
+        if self.digits.is_empty() {
+            out.push(1);
+            return;
+        }
+        out.push(if self.negative { 0 } else { 2 });
+        let start = out.len();
+        self.exponent.encode_key(out);
+        // digits are shifted from 0, the end of the digits, so that a
+        // decimal sorts before those whose digits start with its own
+        out.extend(self.digits.iter().map(|d| d + 1));
+        out.push(0);
+        if self.negative {
+            for byte in &mut out[start..] {
+                *byte = !*byte;
+            }
+        }
+    }
+}
+
+impl Serialize for Decimal {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D>(deserializer: D) -> Result<Decimal, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct DecimalVisitor;
+
+        impl Visitor<'_> for DecimalVisitor {
+            type Value = Decimal;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a decimal string or number")
+            }
+
+            fn visit_str<E>(self, s: &str) -> Result<Decimal, E>
+            where
+                E: de::Error,
+            {
+                Decimal::parse(s).map_err(E::custom)
+            }
+
+            fn visit_i64<E>(self, n: i64) -> Result<Decimal, E>
+            where
+                E: de::Error,
+            {
+                self.visit_str(&n.to_string())
+            }
+
+            fn visit_u64<E>(self, n: u64) -> Result<Decimal, E>
+            where
+                E: de::Error,
+            {
+                self.visit_str(&n.to_string())
+            }
+
+            fn visit_i128<E>(self, n: i128) -> Result<Decimal, E>
+            where
+                E: de::Error,
+            {
+                self.visit_str(&n.to_string())
+            }
+
+            fn visit_u128<E>(self, n: u128) -> Result<Decimal, E>
+            where
+                E: de::Error,
+            {
+                self.visit_str(&n.to_string())
+            }
+
+            fn visit_f64<E>(self, n: f64) -> Result<Decimal, E>
+            where
+                E: de::Error,
+            {
+                // the shortest digits that read back as the same float
+                self.visit_str(&format!("{:e}", n))
+            }
+        }
+
+        deserializer.deserialize_any(DecimalVisitor)
+    }
+}
+
+impl ExternallySortable for Decimal {
+    fn get_size(&self) -> u64 {
+        (mem::size_of::<Decimal>() + self.digits.len()) as u64
+    }
+}
@@ -0,0 +1,134 @@
+use std::cmp::Ordering::{Equal, Greater, Less};
+use std::error::Error;
+use std::iter::Peekable;
+use std::sync::Arc;
+
+use crate::external_sort::CompareFn;
+use crate::{ExtSortedIterator, ExternalSorter, ExternallySortable};
+
+/// A change between two datasets, yielded by a [DiffIterator](struct.DiffIterator.html)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change<T> {
+    /// A record of the new dataset without an equal record in the old one
+    Added(T),
+    /// A record of the old dataset without an equal record in the new one
+    Removed(T),
+    /// A record of the new dataset with an equal record in the old one, only
+    /// yielded after calling
+    /// [with_unchanged](struct.DiffIterator.html#method.with_unchanged)
+    Unchanged(T),
+}
+
+/// Iterator that provides the changes between two sorted datasets in sorted
+/// order, created by [ExternalSorter::diff](struct.ExternalSorter.html#method.diff)
+pub struct DiffIterator<T>
+where
+    T: ExternallySortable,
+{
+    old: Peekable<ExtSortedIterator<T>>,
+    new: Peekable<ExtSortedIterator<T>>,
+    compare: Arc<CompareFn<T>>,
+    unchanged: bool,
+    failed: bool,
+}
+
+impl<T> ExternalSorter<T>
+where
+    T: ExternallySortable + Ord,
+{
+    /// Sort the `old` and `new` datasets, and yield the records added to or
+    /// removed from `old` to get `new`, by walking both sorted outputs
+    /// together
+    ///
+    /// Both inputs are sorted with this sorter's configuration. Duplicates
+    /// are paired one to one, so a record occurring twice in `old` and once
+    /// in `new` is yielded as removed once.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    pub fn diff<O, N>(&self, old: O, new: N) -> Result<DiffIterator<T>, Box<dyn Error>>
+    where
+        O: Iterator<Item = T>,
+        N: Iterator<Item = T>,
+    {
+        let old = self.sort(old)?;
+        let new = self.sort(new)?;
+
+        Ok(DiffIterator {
+            compare: old.sort_by_fn(),
+            old: old.peekable(),
+            new: new.peekable(),
+            unchanged: false,
+            failed: false,
+        })
+    }
+}
+
+impl<T> DiffIterator<T>
+where
+    T: ExternallySortable,
+{
+    /// Also yield the records found in both datasets, as
+    /// [Change::Unchanged](enum.Change.html#variant.Unchanged)
+    pub fn with_unchanged(mut self) -> DiffIterator<T> {
+        self.unchanged = true;
+        self
+    }
+
+    /// Yield the next change, with the error type of the iterator
+    fn next_change(&mut self) -> Result<Option<Change<T>>, Box<dyn Error>> {
+        loop {
+            if let Some(Err(_)) = self.old.peek() {
+                return Err(self.old.next().unwrap().err().unwrap());
+            }
+            if let Some(Err(_)) = self.new.peek() {
+                return Err(self.new.next().unwrap().err().unwrap());
+            }
+            let order = match (self.old.peek(), self.new.peek()) {
+                (Some(Ok(old)), Some(Ok(new))) => (self.compare)(old, new),
+                (Some(_), None) => Less,
+                (None, Some(_)) => Greater,
+                _ => return Ok(None),
+            };
+            // unwraps due to the peek() above
+            match order {
+                Less => return Ok(Some(Change::Removed(self.old.next().unwrap()?))),
+                Greater => return Ok(Some(Change::Added(self.new.next().unwrap()?))),
+                Equal => {
+                    self.old.next().unwrap()?;
+                    let new = self.new.next().unwrap()?;
+                    if self.unchanged {
+                        return Ok(Some(Change::Unchanged(new)));
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl<T> Iterator for DiffIterator<T>
+where
+    T: ExternallySortable,
+{
+    type Item = Result<Change<T>, Box<dyn Error>>;
+
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// of either dataset from disk, or due to serde deserialization issues
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.next_change() {
+            Ok(change) => change.map(Ok),
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
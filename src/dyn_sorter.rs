@@ -0,0 +1,39 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::sync::Arc;
+
+use crate::ExternalSorter;
+
+/// Function comparing two byte records, as used by a
+/// [DynSorter](trait.DynSorter.html)
+pub type DynCompareFn = dyn Fn(&[u8], &[u8]) -> Ordering + Send + Sync;
+
+/// Sorted byte records returned by a [DynSorter](trait.DynSorter.html)
+pub type DynSorted = Box<dyn Iterator<Item = Result<Vec<u8>, Box<dyn Error>>>>;
+
+/// Object-safe interface to a sorter, erasing the type of the records by
+/// sorting them as bytes with a comparator
+///
+/// Sorters of different strategies (e.g. differently configured
+/// [ExternalSorter](struct.ExternalSorter.html)s) can be selected at runtime
+/// and held as a `Box<dyn DynSorter>`, with each caller encoding its records
+/// as bytes and decoding the sorted bytes itself.
+pub trait DynSorter: Send + Sync {
+    /// Sort (based on `compare`) the byte records provided by `records` and
+    /// return an iterator
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    fn sort_dyn(&self, records: &mut dyn Iterator<Item = Vec<u8>>, compare: Arc<DynCompareFn>)
+                -> Result<DynSorted, Box<dyn Error>>;
+}
+
+impl DynSorter for ExternalSorter<Vec<u8>> {
+    fn sort_dyn(&self, records: &mut dyn Iterator<Item = Vec<u8>>, compare: Arc<DynCompareFn>)
+                -> Result<DynSorted, Box<dyn Error>> {
+        let sorted = self.sort_by_sync(records, move |a: &Vec<u8>, b: &Vec<u8>| compare(a, b))?;
+        Ok(Box::new(sorted))
+    }
+}
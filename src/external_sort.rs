@@ -1,298 +1,4491 @@
+use std::cell::RefCell;
 use std::clone::Clone;
-use std::cmp::Ordering::{self, Less};
-use std::collections::VecDeque;
+use std::cmp::Ordering::{self, Equal, Greater, Less};
+use std::collections::{BTreeMap, VecDeque};
 use std::error::Error;
-use std::fs::{File, OpenOptions};
+use std::fs::{self, File, OpenOptions};
 use std::io::SeekFrom::Start;
-use std::io::{BufRead, BufReader, Seek, Write};
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, Write};
+use std::iter;
 use std::marker::PhantomData;
-use std::path::PathBuf;
+use std::mem::{self, ManuallyDrop};
+use std::panic;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "rayon")]
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "indicatif")]
+use indicatif::ProgressBar;
+#[cfg(feature = "rayon")]
+use rayon::iter::{IndexedParallelIterator, ParallelIterator};
+#[cfg(feature = "rayon")]
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use serde::de::DeserializeOwned;
-use serde::Serialize;
-use serde_json;
+use serde::{Deserialize, Serialize};
 use tempdir::TempDir;
 
+use crate::arena::{Arena, Batch};
+use crate::capture::Capture;
+use crate::checksum::{self, RunDigest};
+#[cfg(feature = "testing")]
+use crate::fault::{Fault, Faults};
+use crate::ioprio::{IoPriority, IoPriorityGuard};
+use crate::memory::{self, FALLBACK_MEMORY};
+use crate::merge_plan::{DryRun, MergePlan, RunSummary, SortPlan};
+#[cfg(feature = "metrics")]
+use crate::metrics::{self, MergeMetrics};
+use crate::pool::{BufferAllocator, BufferPool};
+#[cfg(feature = "pressure")]
+use crate::pressure::MemoryPressure;
+use crate::progress::{InputTracker, Progress, ProgressFn, ProgressPhase, ProgressTracker};
+#[cfg(feature = "indicatif")]
+use crate::progress_bar::{self, InputProgress};
+use crate::tag::{self, TagUsage, Tags};
+use crate::timing::{PhaseTimings, Timer};
+
 /// Trait for types that can be used by
 /// [ExternalSorter](struct.ExternalSorter.html). Must be sortable, cloneable,
 /// serializeable, and able to report on it's size
 pub trait ExternallySortable: Ord + Clone + Serialize + DeserializeOwned {
+    /// Approximate size, in bytes, of every object of this type, returned by
+    /// the default [get_size](#method.get_size)
+    const APPROX_SIZE: Option<u64> = None;
+
+    /// Whether sorters keep objects that compare as equal in input order by
+    /// default, rather than in an unspecified order that sorts faster, unless
+    /// they are given a [TieBreak](enum.TieBreak.html)
+    const STABLE_TIES: bool = true;
+
     /// Get the size, in bytes, of this object (used to constrain the buffer
     /// used in the external sort).
     ///
     /// If you are unable to calculate a size, simply return `1` from this
     /// function, and then set the `buffer_bytes` to the number of objects
     /// to hold in memory when creating an
-    /// [ExternalSorter](struct.ExternalSorter.html)
-    fn get_size(&self) -> u64;
+    /// [ExternalSorter](struct.ExternalSorter.html), allowing for the
+    /// `size_of::<Self>()` bytes that the sorter adds for every buffered
+    /// object. By default, this is [APPROX_SIZE](#associatedconstant.APPROX_SIZE),
+    /// or `size_of::<Self>()` without one.
+    fn get_size(&self) -> u64 {
+        Self::APPROX_SIZE.unwrap_or(mem::size_of::<Self>() as u64)
+    }
+}
+
+pub(crate) type CompareFn<T> = dyn Fn(&T, &T) -> Ordering + Send + Sync;
+
+/// Comparator of [sort_by](struct.ExternalSorter.html#method.sort_by), which
+/// need not be `Send` or `Sync`, so that it is only ever called, and dropped,
+/// on the thread that created it
+struct LocalCompare<F> {
+    thread: ThreadId,
+    compare: ManuallyDrop<RefCell<F>>,
+}
+
+// the comparator is never touched on any other thread than its own
+unsafe impl<F> Send for LocalCompare<F> {}
+unsafe impl<F> Sync for LocalCompare<F> {}
+
+impl<F> LocalCompare<F> {
+    fn new(compare: F) -> LocalCompare<F> {
+        LocalCompare { thread: thread::current().id(),
+                       compare: ManuallyDrop::new(RefCell::new(compare)) }
+    }
+
+    fn call<T>(&self, a: &T, b: &T) -> Ordering
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        assert!(thread::current().id() == self.thread,
+                "the comparator of sort_by can only be called on the thread that sorted, \
+                 use sort_by_sync to merge on other threads");
+        (self.compare.borrow_mut())(a, b)
+    }
+}
+
+impl<F> Drop for LocalCompare<F> {
+    fn drop(&mut self) {
+        // a comparator dropped on another thread is leaked instead
+        if thread::current().id() == self.thread {
+            unsafe { ManuallyDrop::drop(&mut self.compare) }
+        }
+    }
+}
+
+/// Error type for the parts of the sort that may run on worker threads
+pub(crate) type SendError = Box<dyn Error + Send + Sync>;
+
+/// Number of records sampled from each chunk as it is written, used to find
+/// key boundaries within the sorted chunks
+const CHUNK_SAMPLES: usize = 32;
+
+/// First byte of the line that precedes a record repeated in a run-length
+/// encoded run, followed by the number of repeats, which JSON never starts
+/// with
+pub(crate) const REPEAT_PREFIX: u8 = b'*';
+
+/// Most repeats of a record written as one, which bounds how far a merge
+/// buffer may grow past its share when it reads them back
+const MAX_REPEATS: u64 = 1024;
+
+/// Default number of records between progress reports
+const PROGRESS_INTERVAL: u64 = 10_000;
+
+/// Largest size of the records whose heads are compared within a single
+/// array during the merge, if they hold no data elsewhere
+const COMPACT_RECORD_BYTES: usize = 16;
+
+/// Information gathered about a sorted chunk while writing it to disk
+#[derive(Clone)]
+pub(crate) struct ChunkMeta<T> {
+    pub(crate) path: PathBuf,
+    pub(crate) records: u64,
+    /// Total size of the records, as reported by `get_size()`
+    bytes: u64,
+    first: Option<T>,
+    last: Option<T>,
+    /// Sparse `(byte offset, record)` samples in sorted order
+    samples: Vec<(u64, T)>,
+    /// Number of records between consecutive samples
+    sample_step: u64,
+    /// Size of the file as written, or `None` for records held in memory
+    file_bytes: Option<u64>,
+    /// Checksum of the file, computed by [digest](#method.digest) the first
+    /// time it is needed
+    checksum: OnceLock<u64>,
+    /// Handle removing the file once it and every other handle to it are
+    /// closed, if it is temporary
+    #[cfg(windows)]
+    anchor: Option<Arc<File>>,
+    /// Time spent sorting the records of the chunk in memory
+    sort_time: Duration,
+    /// Time spent writing the chunk to disk
+    write_time: Duration,
+}
+
+/// Merge progress of a sorted run, saved by
+/// [ExtSortedIterator::checkpoint](struct.ExtSortedIterator.html#method.checkpoint)
+/// or [SortSession::save](struct.SortSession.html#method.save)
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RunCheckpoint<T> {
+    /// Name of the run within the checkpoint directory
+    file: String,
+    records: u64,
+    bytes: u64,
+    first: Option<T>,
+    last: Option<T>,
+    samples: Vec<(u64, T)>,
+    sample_step: u64,
+    /// Size and checksum of the run file, checked when it is loaded back
+    /// (missing from checkpoints saved by earlier versions)
+    #[serde(default)]
+    digest: Option<RunDigest>,
+    /// Number of records of the run already merged
+    position: u64,
+}
+
+/// Merge progress of a sort, saved by
+/// [ExtSortedIterator::checkpoint](struct.ExtSortedIterator.html#method.checkpoint)
+#[derive(Serialize, Deserialize)]
+struct Checkpoint<T> {
+    runs: Vec<RunCheckpoint<T>>,
+    lower: Option<T>,
+    upper: Option<T>,
+    buffer_bytes: u64,
+}
+
+/// Flag of `CreateFileW` removing a file once every handle to it is closed
+#[cfg(windows)]
+const FILE_FLAG_DELETE_ON_CLOSE: u32 = 0x0400_0000;
+
+/// Name of the file describing a checkpoint within its directory
+const CHECKPOINT_FILE: &str = "checkpoint.json";
+
+impl<T> ChunkMeta<T> {
+    /// Have the run removed as soon as it is no longer open, on Windows,
+    /// even if the process is killed before its temporary directory can be
+    /// removed (it is kept open until the metadata is dropped)
+    #[cfg(windows)]
+    pub(crate) fn delete_on_close(&mut self) -> io::Result<()> {
+        use std::os::windows::fs::OpenOptionsExt;
+
+        let anchor = OpenOptions::new().read(true)
+                                       .custom_flags(FILE_FLAG_DELETE_ON_CLOSE)
+                                       .open(&self.path)?;
+        self.anchor = Some(Arc::new(anchor));
+        Ok(())
+    }
+
+    /// Temporary runs are only removed along with their directory elsewhere
+    #[cfg(not(windows))]
+    pub(crate) fn delete_on_close(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<T> ChunkMeta<T>
+where
+    T: Clone,
+{
+    /// Size and checksum of the file, or `None` for records held in memory
+    ///
+    /// Runs are not checksummed as they are written, since only checkpoints,
+    /// sessions and [verify_runs](struct.ExternalSorter.html#method.verify_runs)
+    /// need it: the file is read for it the first time, once checked to still
+    /// have the size it was written with.
+    pub(crate) fn digest(&self) -> Result<Option<RunDigest>, SendError> {
+        let file_bytes = match self.file_bytes {
+            Some(file_bytes) => file_bytes,
+            None => return Ok(None),
+        };
+        let checksum = match self.checksum.get() {
+            Some(&checksum) => checksum,
+            None => {
+                let checksum = checksum::read(&self.path, file_bytes)?;
+                *self.checksum.get_or_init(|| checksum)
+            },
+        };
+        Ok(Some(RunDigest { file_bytes, checksum }))
+    }
+
+    /// Hard-link (or copy, across file systems) the run into `dir` as `file`,
+    /// unless it is there already, and describe it as merged up to `position`
+    pub(crate) fn save(&self, dir: &Path, file: String, position: u64)
+                       -> Result<RunCheckpoint<T>, SendError> {
+        let digest = self.digest()?;
+        let saved = dir.join(&file);
+        if !saved.exists() && fs::hard_link(&self.path, &saved).is_err() {
+            fs::copy(&self.path, &saved)?;
+        }
+        Ok(RunCheckpoint { file,
+                           records: self.records,
+                           bytes: self.bytes,
+                           first: self.first.clone(),
+                           last: self.last.clone(),
+                           samples: self.samples.clone(),
+                           sample_step: self.sample_step,
+                           digest,
+                           position })
+    }
+
+    /// Describe a run saved into `dir` by [save](#method.save), after
+    /// checking that its file still has the size and checksum it was saved
+    /// with
+    pub(crate) fn saved(dir: &Path, run: RunCheckpoint<T>) -> Result<ChunkMeta<T>, SendError> {
+        let path = dir.join(run.file);
+        if let Some(ref digest) = run.digest {
+            checksum::verify(&path, digest)?;
+        }
+        Ok(ChunkMeta { path,
+                       records: run.records,
+                       bytes: run.bytes,
+                       first: run.first,
+                       last: run.last,
+                       samples: run.samples,
+                       sample_step: run.sample_step,
+                       file_bytes: run.digest.map(|digest| digest.file_bytes),
+                       checksum: run.digest.map_or_else(OnceLock::new,
+                                                        |digest| OnceLock::from(digest.checksum)),
+                       #[cfg(windows)]
+                       anchor: None,
+                       sort_time: Duration::ZERO,
+                       write_time: Duration::ZERO })
+    }
+}
+
+/// Run of a sort, as listed in its manifest
+#[derive(Serialize)]
+struct ManifestRun<'a> {
+    path: &'a Path,
+    records: u64,
+    digest: Option<RunDigest>,
+}
+
+/// Name of the file listing the runs of a sort within its temporary directory
+pub(crate) const MANIFEST_FILE: &str = "runs.json";
+
+/// Check that each run of a sort is there with the size it was written with,
+/// once they are all written, so that a missing or truncated run fails the
+/// sort before its merge starts, and list them with their sizes and
+/// checksums
+fn write_manifest<T>(dir: &Path, chunk_meta: &[ChunkMeta<T>]) -> Result<(), SendError>
+where
+    T: Clone,
+{
+    let mut runs = Vec::with_capacity(chunk_meta.len());
+    for meta in chunk_meta {
+        runs.push(ManifestRun { path: &meta.path, records: meta.records, digest: meta.digest()? });
+    }
+    let mut file = BufWriter::new(File::create(dir.join(MANIFEST_FILE))?);
+    serde_json::to_writer(&mut file, &runs)?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Replace the file `name` in `dir` with `value` serialized to JSON at once,
+/// so that a failure leaves either the previous or the new file in place
+pub(crate) fn save_json<V>(dir: &Path, name: &str, value: &V) -> Result<(), Box<dyn Error>>
+where
+    V: Serialize,
+{
+    let tmp = dir.join(format!("{}.tmp", name));
+    let mut file = BufWriter::new(File::create(&tmp)?);
+    serde_json::to_writer(&mut file, value)?;
+    file.flush()?;
+    file.get_ref().sync_all()?;
+    fs::rename(tmp, dir.join(name))?;
+    Ok(())
+}
+
+/// Configuration of the sorter that made a sort, reported in its
+/// [SortStats](struct.SortStats.html) (and saved along with a
+/// [SortSession](struct.SortSession.html))
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SortConfig {
+    /// Memory buffer of the sort, in bytes
+    pub buffer_bytes: u64,
+    /// Number of threads sorting and writing chunks, if set
+    pub threads: Option<usize>,
+    /// Fan-in of the background merge, if enabled
+    pub premerge: Option<usize>,
+    /// Whether sorted prefixes of the input were detected
+    pub check_sorted: bool,
+    /// [Tag](struct.ExternalSorter.html#method.tag) of the sort, if any
+    #[serde(default)]
+    pub tag: Option<String>,
+}
+
+/// Statistics about the records of a sort, gathered while writing its chunks
+/// to disk, returned by
+/// [ExtSortedIterator::stats](struct.ExtSortedIterator.html#method.stats)
+///
+/// The statistics serialize to a machine-readable summary of the sort, e.g.
+/// with [to_json](#method.to_json).
+#[derive(Serialize, Clone, Debug)]
+pub struct SortStats<T> {
+    /// Configuration of the sorter, or `None` for iterators that were not
+    /// made by a sorter, such as a [union](struct.ExtSortedIterator.html#method.union)
+    pub config: Option<SortConfig>,
+    /// Number of sorted runs merged into the output
+    pub runs: u64,
+    /// Number of records
+    pub records: u64,
+    /// Total size of the records, as reported by `get_size()`
+    pub bytes: u64,
+    /// Time spent in each phase of the sort so far
+    pub timings: PhaseTimings,
+    /// Smallest record
+    pub min: Option<T>,
+    /// Largest record
+    pub max: Option<T>,
+    /// Approximate equi-depth histogram of the records, as buckets of a lower
+    /// bound and about how many records are at least that bound but below
+    /// the bound of the next bucket, in sorted order
+    pub histogram: Vec<(T, u64)>,
+}
+
+impl<T> SortStats<T>
+where
+    T: Serialize,
+{
+    /// Serialize the statistics to a JSON object
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to serde serialization issues
+    pub fn to_json(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+/// Which record to keep among consecutive duplicates, for
+/// [ExtSortedIterator::dedup_by_key](struct.ExtSortedIterator.html#method.dedup_by_key)
+pub enum DedupPolicy<T> {
+    /// Keep the first of the duplicates in the sorted output
+    KeepFirst,
+    /// Keep the last of the duplicates in the sorted output
+    KeepLast,
+    /// Combine the duplicates in sorted order with the given closure, which
+    /// is called with the record combined so far and the next duplicate, and
+    /// must not change the key of the record
+    Combine(Arc<dyn Fn(T, T) -> T + Send + Sync>),
+}
+
+impl<T> Clone for DedupPolicy<T> {
+    fn clone(&self) -> DedupPolicy<T> {
+        match *self {
+            DedupPolicy::KeepFirst => DedupPolicy::KeepFirst,
+            DedupPolicy::KeepLast => DedupPolicy::KeepLast,
+            DedupPolicy::Combine(ref combine) => DedupPolicy::Combine(combine.clone()),
+        }
+    }
+}
+
+/// How records that compare as equal are ordered, both when sorting the
+/// chunks and when merging them, set with
+/// [ExternalSorter::tie_break](struct.ExternalSorter.html#method.tie_break)
+pub enum TieBreak<T> {
+    /// Keep equal records in the order of the input, the default. Besides the
+    /// sequential sorts, only
+    /// [par_sort_stable](struct.ExternalSorter.html#method.par_sort_stable)
+    /// is stable among the parallel ones, since the workers of
+    /// [par_sort](struct.ExternalSorter.html#method.par_sort) each take
+    /// records from anywhere in the input.
+    InputOrder,
+    /// Order equal records with a secondary comparator, and then in the order
+    /// of the input. The secondary comparator becomes part of the order of
+    /// the sorted iterator, so methods like
+    /// [dedup](struct.ExtSortedIterator.html#method.dedup) only consider
+    /// records equal by both comparators duplicates.
+    Then(Arc<CompareFn<T>>),
+    /// Leave the order of equal records unspecified, so that the chunks can be
+    /// sorted with a faster unstable sort. The order is still deterministic
+    /// for a given input and configuration.
+    Unspecified,
+}
+
+impl<T> Clone for TieBreak<T> {
+    fn clone(&self) -> TieBreak<T> {
+        match *self {
+            TieBreak::InputOrder => TieBreak::InputOrder,
+            TieBreak::Then(ref then) => TieBreak::Then(then.clone()),
+            TieBreak::Unspecified => TieBreak::Unspecified,
+        }
+    }
+}
+
+impl<T> TieBreak<T> {
+    /// The policy for another type, where a secondary comparator (comparing
+    /// `T`s) leaves ties in input order
+    fn retype<U>(&self) -> TieBreak<U> {
+        match *self {
+            TieBreak::Unspecified => TieBreak::Unspecified,
+            _ => TieBreak::InputOrder,
+        }
+    }
+}
+
+/// Add the secondary comparator of a tie-breaking policy to a comparator
+type BreakTiesFn<T> = dyn Fn(Arc<CompareFn<T>>) -> Arc<CompareFn<T>> + Send + Sync;
+
+/// How the initial chunks are made: how big they get before they are
+/// spilled, and how their records are sorted in memory
+struct ChunkPolicy<T> {
+    compare: Arc<CompareFn<T>>,
+    /// Whether equal records keep their input order
+    stable: bool,
+    /// Factor of the average record size of a chunk beyond which its records
+    /// are split off to a run of their own
+    skew: Option<u64>,
+    #[cfg(feature = "pressure")]
+    pressure: Option<MemoryPressure>,
+    /// I/O priority of the threads writing the chunks
+    io_priority: Option<IoPriority>,
+    /// Spills of the tag of the sort, checked against its disk quota as the
+    /// chunks are written
+    quota: Option<Arc<TagUsage>>,
+    /// Allocator of the chunks, if any
+    allocator: Option<Arc<dyn BufferAllocator<T>>>,
+    /// Whether repeated records are written once, with their number of
+    /// repeats
+    run_length: bool,
+}
+
+impl<T> ChunkPolicy<T> {
+    /// Empty chunk to fill with up to `chunk_bytes` of records
+    fn new_chunk(&self, chunk_bytes: u64) -> Vec<T> {
+        match self.allocator {
+            Some(ref allocator) => allocator.chunk(chunk_bytes),
+            None => Vec::new(),
+        }
+    }
+
+    /// Give a chunk whose records were written back to the allocator, if any
+    fn release_chunk(&self, mut chunk: Vec<T>) {
+        if let Some(ref allocator) = self.allocator {
+            chunk.clear();
+            allocator.release_chunk(chunk);
+        }
+    }
+
+    /// Size at which a chunk that could grow to `chunk_bytes` is spilled, as
+    /// a record is added to it
+    fn limit(&self, chunk_bytes: u64) -> u64 {
+        #[cfg(feature = "pressure")]
+        if let Some(ref pressure) = self.pressure {
+            return pressure.limit(chunk_bytes);
+        }
+        chunk_bytes
+    }
+
+    /// Take the records of `chunk` larger than the skew factor times the
+    /// average record size out of it, if some but not all of them are
+    fn split(&self, chunk: &mut Vec<T>) -> Option<Vec<T>>
+    where
+        T: ExternallySortable,
+    {
+        let factor = self.skew?;
+        let bytes: u64 = chunk.iter().map(|t| t.get_size()).sum();
+        let threshold = (bytes / chunk.len().max(1) as u64).saturating_mul(factor);
+        let large = chunk.iter().filter(|t| t.get_size() > threshold).count();
+        if large == 0 || large == chunk.len() {
+            return None;
+        }
+        Some(chunk.extract_if(.., |t| t.get_size() > threshold).collect())
+    }
+}
+
+type SameFn<T> = dyn Fn(&T, &T) -> bool + Send + Sync;
+
+type MergePlanFn = dyn Fn(&mut MergePlan) + Send + Sync;
+
+/// A record merged out of the chunks
+struct Merged<T> {
+    record: T,
+    /// Number of duplicates resolved into the record
+    count: u64,
+    /// Rank of the (first) record in the sorted output
+    rank: u64,
+}
+
+/// How duplicates are detected and resolved during the merge
+struct Dedup<T> {
+    /// Whether two records are duplicates, or `None` if they are duplicates
+    /// when they compare as equal
+    same: Option<Arc<SameFn<T>>>,
+    policy: DedupPolicy<T>,
+}
+
+impl<T> Clone for Dedup<T> {
+    fn clone(&self) -> Dedup<T> {
+        Dedup {
+            same: self.same.clone(),
+            policy: self.policy.clone(),
+        }
+    }
 }
 
 /// Iterator that provides sorted `T`s
 pub struct ExtSortedIterator<T> {
     buffers: Vec<VecDeque<T>>,
+    /// Copy of the record at the front of every buffer, for compact records,
+    /// so that the merge compares them within a single array (empty until
+    /// the merge starts)
+    heads: Vec<Option<T>>,
     chunk_offsets: Vec<u64>,
+    /// Number of records of each chunk that precede its buffer
+    chunk_positions: Vec<u64>,
+    /// Number of records of all chunks that precede the buffers, i.e. the
+    /// rank of the next record in the sorted output
+    rank: u64,
+    chunk_done: Vec<bool>,
+    chunk_meta: Vec<ChunkMeta<T>>,
     max_per_chunk: u64,
     chunks: u64,
-    tmp_dir: TempDir,
-    sort_by_fn: Box<dyn FnMut(&T, &T) -> Ordering>,
+    lower: Option<T>,
+    upper: Option<T>,
+    /// Directories holding the chunks, removed once the last iterator
+    /// merging them is dropped (after `chunk_meta`, which keeps the chunks
+    /// open on Windows)
+    tmp_dirs: Vec<Arc<TempDir>>,
+    sort_by_fn: Arc<CompareFn<T>>,
+    dedup: Option<Dedup<T>>,
+    /// Configuration of the sorter that made the chunks
+    config: Option<SortConfig>,
+    /// Timings of the sort, other than those of the chunks
+    timings: PhaseTimings,
+    /// Whether the merge is timed
+    time_merge: bool,
+    /// Started at the first record returned by the iterator
+    merge_timer: Option<Timer>,
+    /// When the iterator last returned a record
+    returned: Option<Instant>,
+    /// Progress reports of the merge
+    on_progress: Option<ProgressTracker>,
+    /// Terminator of the records written by `write_lines()`
+    pub(crate) terminator: u8,
+    /// I/O priority of the thread while it reads the chunks
+    pub(crate) io_priority: Option<IoPriority>,
+    /// Scratch line that the records of every refill are read into
+    line: Vec<u8>,
+    /// Allocator that the buffers are taken from and returned to
+    allocator: Option<Arc<dyn BufferAllocator<T>>>,
+    /// Arena of the last batch read from each chunk, if batches are read
+    /// into arenas
+    arenas: Option<Vec<Option<Arc<Arena>>>>,
+    /// Progress bar advanced to the rank of each merged record
+    #[cfg(feature = "indicatif")]
+    progress: Option<ProgressBar>,
+    #[cfg(feature = "metrics")]
+    metrics: MergeMetrics,
+    /// Faults injected into the reads of the chunks
+    #[cfg(feature = "testing")]
+    faults: Faults,
     failed: bool,
 }
 
-impl<T> Iterator for ExtSortedIterator<T>
+impl<T> ExtSortedIterator<T>
+where
+    T: ExternallySortable,
+{
+    /// Whether the records are small and hold no data elsewhere (like `Copy`
+    /// integers or tuples of them), so that the record at the front of every
+    /// buffer is copied into `heads` and compared there, rather than looked
+    /// up in every buffer (the buffers themselves are left as they are)
+    const COMPACT: bool =
+        !mem::needs_drop::<T>() && mem::size_of::<T>() <= COMPACT_RECORD_BYTES;
+
+    fn new(tmp_dirs: Vec<Arc<TempDir>>, sort_by_fn: Arc<CompareFn<T>>) -> ExtSortedIterator<T> {
+        ExtSortedIterator {
+            buffers: Vec::new(),
+            heads: Vec::new(),
+            chunk_offsets: Vec::new(),
+            chunk_positions: Vec::new(),
+            rank: 0,
+            chunk_done: Vec::new(),
+            chunk_meta: Vec::new(),
+            max_per_chunk: 0,
+            chunks: 0,
+            lower: None,
+            upper: None,
+            tmp_dirs,
+            sort_by_fn,
+            dedup: None,
+            config: None,
+            timings: PhaseTimings::default(),
+            time_merge: false,
+            merge_timer: None,
+            returned: None,
+            on_progress: None,
+            terminator: b'\n',
+            io_priority: None,
+            line: Vec::new(),
+            allocator: None,
+            arenas: None,
+            #[cfg(feature = "indicatif")]
+            progress: None,
+            #[cfg(feature = "metrics")]
+            metrics: MergeMetrics::new(),
+            #[cfg(feature = "testing")]
+            faults: Faults::default(),
+            failed: false,
+        }
+    }
+
+    /// Create an iterator merging the given chunks, with buffers taken from
+    /// `allocator` if any
+    pub(crate) fn from_chunks(tmp_dir: Arc<TempDir>, sort_by_fn: Arc<CompareFn<T>>,
+                   chunk_meta: Vec<ChunkMeta<T>>, buffer_bytes: u64,
+                   allocator: Option<Arc<dyn BufferAllocator<T>>>)
+                   -> Result<ExtSortedIterator<T>, SendError> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(runs = chunk_meta.len(),
+                        records = chunk_meta.iter().map(|m| m.records).sum::<u64>(),
+                        bytes = chunk_meta.iter().map(|m| m.bytes).sum::<u64>(),
+                        buffer_bytes,
+                        "starting merge");
+        #[cfg(feature = "log")]
+        log::info!("merging {} runs of {} records ({} bytes)",
+                   chunk_meta.len(),
+                   chunk_meta.iter().map(|m| m.records).sum::<u64>(),
+                   chunk_meta.iter().map(|m| m.bytes).sum::<u64>());
+        let mut iter = ExtSortedIterator::new(vec![tmp_dir], sort_by_fn);
+        iter.chunks = chunk_meta.len() as u64;
+        iter.chunk_meta = chunk_meta;
+        iter.allocator = allocator;
+        iter.init_buffers(buffer_bytes)?;
+        Ok(iter)
+    }
+
+    /// Create an iterator merging sorted runs kept outside of a sort, whose
+    /// files are in `tmp_dirs` (if removed along with the iterator)
+    pub(crate) fn from_runs(tmp_dirs: Vec<Arc<TempDir>>, sort_by_fn: Arc<CompareFn<T>>,
+                            runs: Vec<ChunkMeta<T>>, buffer_bytes: u64)
+                            -> Result<ExtSortedIterator<T>, SendError> {
+        let mut iter = ExtSortedIterator::new(tmp_dirs, sort_by_fn);
+        iter.chunks = runs.len() as u64;
+        iter.chunk_meta = runs;
+        iter.init_buffers(buffer_bytes)?;
+        Ok(iter)
+    }
+
+    /// Create an iterator over records that were already sorted in memory,
+    /// without writing them to disk
+    pub(crate) fn from_memory(tmp_dir: Arc<TempDir>, sort_by_fn: Arc<CompareFn<T>>,
+                              sorted: Vec<T>)
+                              -> ExtSortedIterator<T> {
+        let mut iter = ExtSortedIterator::new(vec![tmp_dir], sort_by_fn);
+        iter.chunks = 1;
+        iter.chunk_meta = vec![ChunkMeta {
+                                   path: PathBuf::new(),
+                                   records: sorted.len() as u64,
+                                   bytes: sorted.iter().map(|t| t.get_size()).sum(),
+                                   first: sorted.first().cloned(),
+                                   last: sorted.last().cloned(),
+                                   samples: Vec::new(),
+                                   sample_step: 1,
+                                   file_bytes: None,
+                                   checksum: OnceLock::new(),
+                                   #[cfg(windows)]
+                                   anchor: None,
+                                   sort_time: Duration::ZERO,
+                                   write_time: Duration::ZERO,
+                               }];
+        iter.chunk_offsets = vec![0];
+        iter.chunk_positions = vec![0];
+        iter.chunk_done = vec![true];
+        iter.buffers = vec![sorted.into()];
+        iter
+    }
+
+    /// Merge several sorted iterators into a single sorted iterator
+    ///
+    /// The chunks of all of the `inputs` are combined into one k-way merge,
+    /// which yields every record that the inputs have left to yield, in the
+    /// order of the comparator of the first input. All of the inputs must be
+    /// sorted by that same order. Records that compare as equal are yielded
+    /// in the order of the inputs, and can be de-duplicated by calling
+    /// [dedup](#method.dedup) on the result.
+    ///
+    /// # Errors
+    ///
+    /// This method fails if there are no `inputs`, if any of them already
+    /// failed, or if any of them is bounded by [split](#method.split) or
+    /// [split_at](#method.split_at)
+    pub fn union(inputs: Vec<ExtSortedIterator<T>>)
+                 -> Result<ExtSortedIterator<T>, Box<dyn Error>> {
+        let mut union = match inputs.first() {
+            Some(first) => first.empty_part(),
+            None => return Err("no iterators to merge".into()),
+        };
+        union.tmp_dirs.clear();
+        union.dedup = None;
+        union.config = None;
+        let mut buffer_bytes = 0;
+        for mut input in inputs {
+            if input.failed {
+                return Err("cannot merge an iterator that failed".into());
+            }
+            if input.lower.is_some() || input.upper.is_some() {
+                return Err("cannot merge a split iterator".into());
+            }
+            buffer_bytes = buffer_bytes.max(input.max_per_chunk * input.chunks);
+            union.chunks += input.chunks;
+            union.buffers.append(&mut input.buffers);
+            union.chunk_offsets.append(&mut input.chunk_offsets);
+            union.chunk_positions.append(&mut input.chunk_positions);
+            union.rank += input.rank;
+            union.chunk_done.append(&mut input.chunk_done);
+            union.chunk_meta.append(&mut input.chunk_meta);
+            union.tmp_dirs.append(&mut input.tmp_dirs);
+        }
+        // the merge buffers use as much memory as those of the largest input
+        union.max_per_chunk = buffer_bytes / union.chunks.max(1);
+
+        Ok(union)
+    }
+
+    /// Split the merge into one iterator per sorted run, to merge the runs
+    /// with a custom policy
+    ///
+    /// Every run yields the records that it has left to yield within this
+    /// iterator's bounds, in sorted order, and describes the whole run with
+    /// its [summary](struct.SortedRun.html#method.summary). The runs are in
+    /// the order of the input, so that equal records of earlier runs came
+    /// first in the input. Each run keeps the merge buffer of its chunk, and
+    /// the sorted chunks are removed once every run is dropped.
+    pub fn into_runs(mut self) -> Vec<SortedRun<T>> {
+        let chunk_meta = mem::take(&mut self.chunk_meta);
+        let buffers = mem::take(&mut self.buffers);
+        let mut runs = Vec::with_capacity(chunk_meta.len());
+        for ((chunk_num, meta), buffer) in chunk_meta.into_iter().enumerate().zip(buffers) {
+            let mut iter = self.empty_part();
+            iter.chunks = 1;
+            iter.max_per_chunk = self.max_per_chunk;
+            iter.chunk_offsets = vec![self.chunk_offsets[chunk_num]];
+            iter.chunk_positions = vec![self.chunk_positions[chunk_num]];
+            iter.rank = self.chunk_positions[chunk_num];
+            iter.chunk_done = vec![self.chunk_done[chunk_num]];
+            iter.lower = self.lower.clone();
+            iter.upper = self.upper.clone();
+            iter.failed = self.failed;
+            let summary = RunSummary { records: meta.records, bytes: meta.bytes };
+            let (first, last) = (meta.first.clone(), meta.last.clone());
+            iter.chunk_meta = vec![meta];
+            iter.buffers = vec![buffer];
+            runs.push(SortedRun { summary, first, last, iter });
+        }
+
+        runs
+    }
+
+    /// Save the progress of the merge into the directory `dir` (which is
+    /// created if needed), to resume it later with [restore](#method.restore)
+    /// or [restore_by](#method.restore_by), e.g. after a restart of the
+    /// process
+    ///
+    /// The sorted chunks are hard-linked (or copied, across file systems)
+    /// into `dir` the first time, so they outlive this iterator, and later
+    /// checkpoints into the same directory only update the merge progress.
+    /// Each chunk is read once to checksum it, so that
+    /// [restore](#method.restore) can tell if it changed.
+    /// The directory is left for the caller to remove once the merge is done.
+    /// De-duplication is not saved, and has to be enabled again on the
+    /// restored iterator.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing the checkpoint, or due to
+    /// serde serialization issues
+    pub fn checkpoint<P>(&self, dir: P) -> Result<(), Box<dyn Error>>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let mut runs = Vec::with_capacity(self.chunk_meta.len());
+        for (chunk_num, meta) in self.chunk_meta.iter().enumerate() {
+            // the buffered records are not merged yet
+            let position = self.chunk_positions[chunk_num];
+            let run = meta.save(dir, format!("run_{}", chunk_num), position);
+            runs.push(run.map_err(|e| e as Box<dyn Error>)?);
+        }
+        let checkpoint = Checkpoint { runs,
+                                      lower: self.lower.clone(),
+                                      upper: self.upper.clone(),
+                                      buffer_bytes: self.max_per_chunk * self.chunks };
+        save_json(dir, CHECKPOINT_FILE, &checkpoint)
+    }
+
+    /// Resume a merge saved by [checkpoint](#method.checkpoint) into `dir`,
+    /// with records that sort by their natural order
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading the checkpoint or its
+    /// chunks, if a chunk no longer has the size or checksum it was saved
+    /// with, or due to serde deserialization issues
+    pub fn restore<P>(dir: P) -> Result<ExtSortedIterator<T>, Box<dyn Error>>
+    where
+        P: AsRef<Path>,
+    {
+        ExtSortedIterator::restore_by(dir, |a: &T, b: &T| a.cmp(b))
+    }
+
+    /// Resume a merge saved by [checkpoint](#method.checkpoint) into `dir`,
+    /// with records that sort by `compare`, which must be the order they were
+    /// sorted by
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading the checkpoint or its
+    /// chunks, if a chunk no longer has the size or checksum it was saved
+    /// with, or due to serde deserialization issues
+    pub fn restore_by<P, F>(dir: P, compare: F) -> Result<ExtSortedIterator<T>, Box<dyn Error>>
+    where
+        P: AsRef<Path>,
+        F: 'static + Fn(&T, &T) -> Ordering + Send + Sync,
+    {
+        let dir = dir.as_ref();
+        let file = BufReader::new(File::open(dir.join(CHECKPOINT_FILE))?);
+        let checkpoint: Checkpoint<T> = serde_json::from_reader(file)?;
+        let mut iter = ExtSortedIterator::new(Vec::new(), Arc::new(compare));
+        iter.lower = checkpoint.lower;
+        iter.upper = checkpoint.upper;
+        let mut positions = Vec::with_capacity(checkpoint.runs.len());
+        for run in checkpoint.runs {
+            positions.push(run.position);
+            iter.chunk_meta.push(ChunkMeta::saved(dir, run).map_err(|e| e as Box<dyn Error>)?);
+        }
+        iter.chunks = iter.chunk_meta.len() as u64;
+        iter.max_per_chunk = checkpoint.buffer_bytes / iter.chunks.max(1);
+        iter.buffers = vec![VecDeque::new(); iter.chunk_meta.len()];
+        iter.chunk_offsets = vec![0; iter.chunk_meta.len()];
+        iter.chunk_positions = vec![0; iter.chunk_meta.len()];
+        iter.chunk_done = vec![false; iter.chunk_meta.len()];
+        for (chunk_num, position) in positions.into_iter().enumerate() {
+            iter.seek_run(chunk_num, position).map_err(|e| e as Box<dyn Error>)?;
+        }
+        iter.rank = iter.chunk_positions.iter().sum();
+
+        Ok(iter)
+    }
+
+    /// Move the buffer of a chunk to its record at `position`, starting from
+    /// the last sample at or before it
+    fn seek_run(&mut self, chunk_num: usize, position: u64) -> Result<(), SendError> {
+        self.heads.clear();
+        let meta = &self.chunk_meta[chunk_num];
+        let sample = (position / meta.sample_step).min(meta.samples.len().saturating_sub(1) as u64);
+        if let Some(&(offset, _)) = meta.samples.get(sample as usize) {
+            self.chunk_offsets[chunk_num] = offset;
+            self.chunk_positions[chunk_num] = sample * meta.sample_step;
+        }
+        self.refill(chunk_num)?;
+        while self.chunk_positions[chunk_num] < position {
+            if self.buffers[chunk_num].pop_front().is_none() {
+                break;
+            }
+            self.chunk_positions[chunk_num] += 1;
+            self.refill(chunk_num)?;
+        }
+
+        Ok(())
+    }
+
+    /// Smallest of the records left to merge, without merging it, found among
+    /// the buffered records of every run
+    ///
+    /// This is the next record merged, or (when duplicates are resolved by
+    /// [dedup_with](#method.dedup_with) or [dedup_by_key](#method.dedup_by_key))
+    /// the first of the records resolved into it.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// from disk, or due to serde deserialization issues
+    pub fn smallest(&mut self) -> Result<Option<T>, Box<dyn Error>> {
+        if self.failed {
+            return Ok(None);
+        }
+        match self.next_chunk() {
+            Ok(chunk_num) => Ok(chunk_num.and_then(|c| self.buffers[c].front().cloned())),
+            Err(e) => {
+                self.failed = true;
+                Err(e)
+            },
+        }
+    }
+
+    /// Largest of the records left to merge, without merging them, found
+    /// among the last records of every run
+    ///
+    /// Runs ending past the upper bound of an iterator created by
+    /// [split](#method.split) or [split_at](#method.split_at) are read from
+    /// their last sample before the bound.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// from disk, or due to serde deserialization issues
+    pub fn largest(&mut self) -> Result<Option<T>, Box<dyn Error>> {
+        if self.failed {
+            return Ok(None);
+        }
+        let mut largest: Option<T> = None;
+        for chunk_num in 0..self.chunks as usize {
+            let last = match self.last_remaining(chunk_num) {
+                Ok(last) => last,
+                Err(e) => {
+                    self.failed = true;
+                    return Err(e);
+                },
+            };
+            if let Some(last) = last {
+                if largest.as_ref().is_none_or(|l| (self.sort_by_fn)(l, &last) == Less) {
+                    largest = Some(last);
+                }
+            }
+        }
+
+        Ok(largest)
+    }
+
+    /// Last record of a chunk left to merge, within the bounds of the iterator
+    fn last_remaining(&mut self, chunk_num: usize) -> Result<Option<T>, SendError> {
+        self.refill(chunk_num)?;
+        // the buffer holds every record left of a chunk that is done
+        if self.chunk_done[chunk_num] {
+            return Ok(self.buffers[chunk_num].back().cloned());
+        }
+        let meta = &self.chunk_meta[chunk_num];
+        let compare = &self.sort_by_fn;
+        let upper = match self.upper {
+            Some(ref upper) if meta.last.as_ref().is_some_and(|l| compare(l, upper) != Less) => {
+                upper
+            },
+            _ => return Ok(meta.last.clone()),
+        };
+        let sample = meta.samples.partition_point(|(_, r)| compare(r, upper) == Less);
+        let offset = match sample {
+            0 => 0,
+            sample => meta.samples[sample - 1].0,
+        };
+        let mut file = File::open(&meta.path)?;
+        file.seek(Start(offset.max(self.chunk_offsets[chunk_num])))?;
+        let mut last = self.buffers[chunk_num].back().cloned();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            // repeats of a record are all the same
+            if line.as_bytes().first() == Some(&REPEAT_PREFIX) {
+                continue;
+            }
+            let t: T = serde_json::from_str(&line)?;
+            if compare(&t, upper) != Less {
+                break;
+            }
+            last = Some(t);
+        }
+
+        Ok(last)
+    }
+
+    /// Rank in the whole sorted output of the next record to merge, which is
+    /// the number of records merged so far unless the iterator is a part of
+    /// a [split](#method.split)
+    pub fn position(&self) -> u64 {
+        self.rank
+    }
+
+    /// Statistics about all of the records of the sort, regardless of bounds
+    /// or of how many were already merged
+    ///
+    /// The statistics are gathered from the metadata of the chunks, so they
+    /// come at no extra pass over the data. The histogram has up to
+    /// 32 buckets, and is built from the records sampled in each chunk.
+    pub fn stats(&self) -> SortStats<T> {
+        let compare = &self.sort_by_fn;
+        let mut min: Option<&T> = None;
+        let mut max: Option<&T> = None;
+        for meta in self.chunk_meta.iter() {
+            if let Some(ref first) = meta.first {
+                if min.is_none_or(|min| compare(first, min) == Less) {
+                    min = Some(first);
+                }
+            }
+            if let Some(ref last) = meta.last {
+                if max.is_none_or(|max| compare(last, max) == Greater) {
+                    max = Some(last);
+                }
+            }
+        }
+
+        let records = self.chunk_records();
+        let mut samples: Vec<(&T, u64)> =
+            self.chunk_meta
+                .iter()
+                .flat_map(|m| {
+                              m.samples.iter().enumerate().map(move |(i, (_, r))| {
+                                  (r, m.sample_step.min(m.records - i as u64 * m.sample_step))
+                              })
+                          })
+                .collect();
+        samples.sort_by(|a, b| compare(a.0, b.0));
+        let bucket = (records / CHUNK_SAMPLES as u64).max(1);
+        let mut histogram: Vec<(T, u64)> = Vec::new();
+        for (r, weight) in samples {
+            match histogram.last_mut() {
+                Some((_, n)) if *n < bucket => *n += weight,
+                _ => histogram.push((r.clone(), weight)),
+            }
+        }
+
+        let mut timings = self.timings;
+        timings.sort = self.chunk_meta.iter().map(|meta| meta.sort_time).sum();
+        timings.spill_write = self.chunk_meta.iter().map(|meta| meta.write_time).sum();
+
+        SortStats {
+            config: self.config.clone(),
+            runs: self.chunks,
+            records,
+            bytes: self.chunk_meta.iter().map(|meta| meta.bytes).sum(),
+            timings,
+            min: min.cloned(),
+            max: max.cloned(),
+            histogram,
+        }
+    }
+
+    /// Comparator that the chunks are sorted by
+    pub(crate) fn sort_by_fn(&self) -> Arc<CompareFn<T>> {
+        self.sort_by_fn.clone()
+    }
+
+    /// Total number of records written to the chunks, regardless of bounds or
+    /// of how many were already merged
+    pub(crate) fn chunk_records(&self) -> u64 {
+        self.chunk_meta.iter().map(|meta| meta.records).sum()
+    }
+
+    /// Skip as many records as the chunk samples allow, while keeping the
+    /// `n`th record of the sorted output in the rest, returning the number of
+    /// records skipped
+    ///
+    /// For a pivot sample that at most `n` records can precede, every chunk
+    /// skips to its last sample before the pivot. The skipped records precede
+    /// the pivot, and so every record that is still to be merged at or after
+    /// the pivot, which includes the `n`th one: the `n`th record of the sorted
+    /// output is the `(n - skipped)`th of the rest. Must be called before any
+    /// record is merged.
+    pub(crate) fn skip_to_rank(&mut self, n: u64) -> Result<u64, SendError> {
+        let compare = &self.sort_by_fn;
+        let mut best: Option<(u64, Vec<usize>)> = None;
+        for pivot in self.chunk_meta.iter().flat_map(|meta| meta.samples.iter().map(|(_, r)| r)) {
+            // number of samples of each chunk that precede the pivot
+            let preceding: Vec<usize> =
+                self.chunk_meta
+                    .iter()
+                    .map(|meta| meta.samples.partition_point(|(_, r)| compare(r, pivot) == Less))
+                    .collect();
+            let mut most_preceding = 0;
+            let mut skipped = 0;
+            for (&m, meta) in preceding.iter().zip(self.chunk_meta.iter()) {
+                // sample m, if any, is the first one not preceding the pivot
+                most_preceding += if m < meta.samples.len() {
+                    m as u64 * meta.sample_step
+                } else {
+                    meta.records
+                };
+                skipped += m.saturating_sub(1) as u64 * meta.sample_step;
+            }
+            if most_preceding <= n && best.as_ref().is_none_or(|(s, _)| *s < skipped) {
+                best = Some((skipped, preceding));
+            }
+        }
+        let (skipped, preceding) = match best {
+            Some(best) => best,
+            None => return Ok(0),
+        };
+        self.heads.clear();
+
+        for (chunk_num, &m) in preceding.iter().enumerate() {
+            if m > 1 {
+                let meta = &self.chunk_meta[chunk_num];
+                self.chunk_offsets[chunk_num] = meta.samples[m - 1].0;
+                self.chunk_positions[chunk_num] = (m as u64 - 1) * meta.sample_step;
+                self.buffers[chunk_num].clear();
+                self.refill(chunk_num)?;
+            }
+        }
+        self.rank = self.chunk_positions.iter().sum();
+
+        Ok(skipped)
+    }
+
+    /// Initialize the merge buffers for each chunk written to `tmp_dir`
+    fn init_buffers(&mut self, buffer_bytes: u64) -> Result<(), SendError> {
+        if self.chunks == 0 {
+            return Ok(());
+        }
+        self.max_per_chunk = buffer_bytes / self.chunks;
+        self.buffers = match self.allocator {
+            Some(ref allocator) => {
+                self.line = allocator.line();
+                (0..self.chunks).map(|_| allocator.merge_buffer(self.max_per_chunk)).collect()
+            },
+            None => vec![VecDeque::new(); self.chunks as usize],
+        };
+        self.chunk_offsets = vec![0; self.chunks as usize];
+        self.chunk_positions = vec![0; self.chunks as usize];
+        self.chunk_done = vec![false; self.chunks as usize];
+        for chunk_num in 0..self.chunks as usize {
+            self.refill(chunk_num)?;
+        }
+
+        Ok(())
+    }
+
+    /// Read the next records of a chunk into its (empty) buffer, dropping any
+    /// records outside of this iterator's bounds
+    fn refill(&mut self, chunk_num: usize) -> Result<(), SendError> {
+        while self.buffers[chunk_num].is_empty() && !self.chunk_done[chunk_num] {
+            #[cfg(feature = "testing")]
+            self.faults.fire(chunk_num, self.chunk_offsets[chunk_num])?;
+            let _priority = IoPriorityGuard::set(self.io_priority);
+            let mut f = File::open(&self.chunk_meta[chunk_num].path)?;
+            f.seek(Start(self.chunk_offsets[chunk_num]))?;
+            let started = self.time_merge.then(Instant::now);
+            let batch = self.arenas.as_mut().map(|arenas| {
+                                                 if arenas.len() <= chunk_num {
+                                                     arenas.resize(chunk_num + 1, None);
+                                                 }
+                                                 Batch::start(arenas[chunk_num].take())
+                                             });
+            let bytes_read =
+                fill_buff(&mut self.buffers[chunk_num], &f, self.max_per_chunk, &mut self.line)?;
+            // the arena is frozen as the batch is dropped, before the records
+            // are compared
+            if let (Some(batch), Some(arenas)) = (batch, self.arenas.as_mut()) {
+                arenas[chunk_num] = Some(batch.arena());
+            }
+            // the records read are buffered, so the chunk is never read
+            // there again
+            drop_behind(&f, self.chunk_offsets[chunk_num], bytes_read);
+            if let Some(started) = started {
+                self.timings.merge_read += started.elapsed();
+            }
+            #[cfg(feature = "tracing")]
+            tracing::trace!(run = %self.chunk_meta[chunk_num].path.display(),
+                            offset = self.chunk_offsets[chunk_num],
+                            bytes = bytes_read,
+                            records = self.buffers[chunk_num].len(),
+                            "refilled run buffer");
+            self.chunk_offsets[chunk_num] += bytes_read;
+            if bytes_read == 0 {
+                self.chunk_done[chunk_num] = true;
+            }
+            self.apply_bounds(chunk_num);
+            if Self::COMPACT && self.heads.len() == self.chunks as usize {
+                self.heads[chunk_num] = self.buffers[chunk_num].front().cloned();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove the buffered records of a chunk that fall outside of
+    /// `lower..upper`, marking the chunk as done once `upper` is reached
+    fn apply_bounds(&mut self, chunk_num: usize) {
+        let buffer = &mut self.buffers[chunk_num];
+        let compare = &self.sort_by_fn;
+        if let Some(ref lower) = self.lower {
+            while buffer.front().is_some_and(|r| compare(r, lower) == Less) {
+                buffer.pop_front();
+                self.chunk_positions[chunk_num] += 1;
+                self.rank += 1;
+            }
+        }
+        if let Some(ref upper) = self.upper {
+            if let Some(pos) = buffer.iter().position(|r| compare(r, upper) != Less) {
+                buffer.truncate(pos);
+                self.chunk_done[chunk_num] = true;
+            }
+        }
+    }
+
+    /// Inject `fault` into the merge, the next time it reads the run `run`
+    /// (below the number of [runs](struct.SortStats.html#structfield.runs)
+    /// of its [stats](#method.stats)) from byte `offset` or past it, with the
+    /// `testing` feature
+    ///
+    /// This lets applications test how they handle `Err` items without
+    /// tampering with the temporary directory. Every run is read a batch of
+    /// records at a time, starting with the batch read when the iterator is
+    /// created, so a fault fires at the first refill of its run that starts
+    /// at or after `offset`. A [corrupted](enum.Fault.html#variant.Corrupt)
+    /// record is instead written to the run at once, and fails whenever it
+    /// is read, unless it was already read.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail if there is no run `run` on disk, or due to
+    /// issues writing a corrupted record to it
+    #[cfg(feature = "testing")]
+    pub fn inject_fault(&mut self, run: usize, offset: u64, fault: Fault)
+                        -> Result<(), Box<dyn Error>> {
+        let path = match self.chunk_meta.get(run) {
+            Some(meta) if !meta.path.as_os_str().is_empty() => &meta.path,
+            _ => return Err(format!("no run {} on disk", run).into()),
+        };
+        if let Fault::Corrupt = fault {
+            // a NUL byte is never valid JSON
+            let mut file = OpenOptions::new().write(true).open(path)?;
+            file.seek(Start(offset))?;
+            file.write_all(b"\0")?;
+        }
+        self.faults.push(run, offset, fault);
+        Ok(())
+    }
+
+    /// Drop consecutive equal records (according to the comparator used for
+    /// the sort) from the sorted output, only yielding the first of them.
+    ///
+    /// Duplicates are removed from the chunk buffers during the merge, so
+    /// they are never yielded, cloned, or compared again by the consumer.
+    pub fn dedup(self) -> ExtSortedIterator<T> {
+        self.dedup_policy(DedupPolicy::KeepFirst)
+    }
+
+    /// Resolve consecutive equal records (according to the comparator used for
+    /// the sort) with `merge`, which is called with the record merged so far
+    /// and the next duplicate in sorted order (e.g. to keep the latest
+    /// timestamp, or sum counters).
+    ///
+    /// As with [dedup](#method.dedup), duplicates are resolved during the
+    /// merge, before they are yielded. `merge` must return a record equal to
+    /// its arguments.
+    pub fn dedup_with<F>(self, merge: F) -> ExtSortedIterator<T>
+    where
+        F: 'static + Fn(T, T) -> T + Send + Sync,
+    {
+        self.dedup_policy(DedupPolicy::Combine(Arc::new(merge)))
+    }
+
+    /// Resolve consecutive equal records (according to the comparator used for
+    /// the sort) with `policy`
+    pub(crate) fn dedup_policy(mut self, policy: DedupPolicy<T>) -> ExtSortedIterator<T> {
+        self.dedup = Some(Dedup { same: None, policy });
+        self
+    }
+
+    /// Yield each distinct record of the sorted output once, along with the
+    /// number of times it occurs, like `sort | uniq -c`
+    ///
+    /// Records are distinct according to the comparator used for the sort,
+    /// or according to the key and policy given to
+    /// [dedup_by_key](#method.dedup_by_key) if it was called first. Duplicates
+    /// are counted during the merge and never yielded.
+    pub fn counts(self) -> CountedIterator<T> {
+        CountedIterator { iter: self }
+    }
+
+    /// Yield each record of the sorted output along with its (0-based) rank
+    /// in the global sorted order
+    ///
+    /// Ranks are tracked during the merge, so they stay global for the
+    /// iterators created by [split](#method.split), and count every record
+    /// of the sorted output, including duplicates dropped by
+    /// [dedup](#method.dedup) (a de-duplicated record has the rank of the
+    /// first of its duplicates).
+    pub fn ranked(self) -> RankedIterator<T> {
+        RankedIterator { iter: self }
+    }
+
+    /// Reduce consecutive records with equal keys in the sorted output to a
+    /// single record, chosen by `policy`.
+    ///
+    /// The sort order must keep records with equal keys together, e.g. by
+    /// sorting on the key first. As with [dedup](#method.dedup), duplicates
+    /// are resolved during the merge, before they are yielded.
+    pub fn dedup_by_key<K, KF>(mut self, key: KF, policy: DedupPolicy<T>) -> ExtSortedIterator<T>
+    where
+        K: PartialEq,
+        KF: 'static + Fn(&T) -> K + Send + Sync,
+    {
+        self.dedup = Some(Dedup {
+                              same: Some(Arc::new(move |a, b| key(a) == key(b))),
+                              policy,
+                          });
+        self
+    }
+
+    /// Split the remaining sorted output into `n` disjoint iterators, each
+    /// providing a contiguous range of the sorted `T`s, so that they can be
+    /// consumed on separate threads.
+    ///
+    /// Chaining the returned iterators in order yields the same records as
+    /// this iterator would have. The key boundaries between the ranges are
+    /// derived from records sampled while writing the sorted chunks, so the
+    /// ranges are of similar (but not necessarily equal) size, and some may be
+    /// empty for small or duplicate-heavy inputs.
+    ///
+    /// The memory buffer is divided evenly between the returned iterators.
+    /// If this iterator has already failed, every returned iterator is empty.
+    ///
+    /// With the `rayon` feature, the returned `Vec` can be consumed with
+    /// `into_par_iter()`.
+    pub fn split(self, n: usize) -> Vec<ExtSortedIterator<T>> {
+        let n = n.max(1);
+        let boundaries = if self.failed { Vec::new() } else { self.boundaries(n) };
+        let mut parts = self.split_at(&boundaries);
+        // fewer boundaries are found than requested for small inputs, in which
+        // case the remaining parts are empty
+        while parts.len() < n {
+            parts.push(parts[0].empty_part());
+        }
+
+        parts
+    }
+
+    /// Split the remaining sorted output at the given (increasing) key
+    /// `boundaries` into `boundaries.len() + 1` disjoint iterators, where the
+    /// iterator `i` provides the `T`s from `boundaries[i - 1]` (inclusive) up
+    /// to `boundaries[i]` (exclusive).
+    ///
+    /// Chaining the returned iterators in order yields the same records as
+    /// this iterator would have. The memory buffer is divided evenly between
+    /// the returned iterators. If this iterator has already failed, every
+    /// returned iterator is empty.
+    pub fn split_at(self, boundaries: &[T]) -> Vec<ExtSortedIterator<T>> {
+        let n = boundaries.len() + 1;
+        (0..n).map(|part| {
+                  if self.failed {
+                      return self.empty_part();
+                  }
+                  let lower = if part == 0 { None } else { Some(boundaries[part - 1].clone()) };
+                  let upper = boundaries.get(part).cloned();
+                  self.part(lower, upper, n as u64)
+              })
+              .collect()
+    }
+
+    /// Choose up to `n - 1` increasing key boundaries from the records sampled
+    /// while writing the sorted chunks, splitting the remaining output into
+    /// `n` ranges of similar size (e.g. to pass to
+    /// [split_at](#method.split_at) or
+    /// [write_partitions](#method.write_partitions)).
+    ///
+    /// Fewer boundaries are returned for small or duplicate-heavy inputs.
+    pub fn boundaries(&self, n: usize) -> Vec<T> {
+        self.splitters(n.max(1))
+    }
+
+    /// Write the remaining sorted output into one newline-delimited JSON file
+    /// per range of keys, split at the given (increasing) key `boundaries` as
+    /// in [split_at](#method.split_at), returning the number of records
+    /// written to each file.
+    ///
+    /// `paths` must hold exactly `boundaries.len() + 1` paths, which are
+    /// written concurrently, each from its own thread. Every file is created,
+    /// even if its range is empty.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// from disk or writing the output files, or due to serde issues
+    pub fn write_partitions<P>(self, boundaries: &[T], paths: &[P])
+                               -> Result<Vec<u64>, Box<dyn Error>>
+    where
+        T: Send,
+        P: AsRef<Path> + Sync,
+    {
+        if paths.len() != boundaries.len() + 1 {
+            return Err(format!("expected {} paths for {} boundaries, got {}",
+                               boundaries.len() + 1,
+                               boundaries.len(),
+                               paths.len()).into());
+        }
+        let parts = self.split_at(boundaries);
+
+        thread::scope(|scope| {
+            let writers: Vec<_> = parts.into_iter()
+                                       .zip(paths)
+                                       .map(|(mut part, path)| {
+                                           scope.spawn(move || -> Result<u64, SendError> {
+                                               let file = File::create(path)?;
+                                               let mut file = BufWriter::new(file);
+                                               let mut records = 0;
+                                               while let Some(t) = part.next_record() {
+                                                   file.write_all(to_line(&t?)?.as_bytes())?;
+                                                   records += 1;
+                                               }
+                                               file.flush()?;
+                                               Ok(records)
+                                           })
+                                       })
+                                       .collect();
+            writers.into_iter()
+                   .map(|w| w.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+                   .collect::<Result<Vec<_>, _>>()
+        }).map_err(|e| e as Box<dyn Error>)
+    }
+
+    /// Write the remaining sorted output into one newline-delimited JSON file
+    /// per path, with similar numbers of records in each, returning the
+    /// (increasing) boundary keys between the files
+    ///
+    /// Unlike [write_partitions](#method.write_partitions), the boundaries
+    /// are chosen while merging, by counting the records written, so the
+    /// files are balanced even when the samples are not representative.
+    /// Records with equal keys are never split across files, so files may
+    /// be somewhat unbalanced for duplicate-heavy inputs, and fewer than
+    /// `paths.len() - 1` boundaries are returned (leaving the last files
+    /// empty) for small ones. The number of records is estimated from the
+    /// chunk samples for iterators returned by [split](#method.split) or
+    /// [split_at](#method.split_at). Every file is created, even if empty.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// from disk or writing the output files, or due to serde issues
+    pub fn write_shards<P>(mut self, paths: &[P]) -> Result<Vec<T>, Box<dyn Error>>
+    where
+        P: AsRef<Path>,
+    {
+        if paths.is_empty() {
+            return Err("expected at least one path".into());
+        }
+        let mut files = Vec::with_capacity(paths.len());
+        for path in paths {
+            files.push(BufWriter::new(File::create(path)?));
+        }
+        let n = paths.len() as u64;
+        let total = self.remaining_records();
+
+        let mut boundaries: Vec<T> = Vec::with_capacity(paths.len() - 1);
+        let mut previous: Option<T> = None;
+        let mut shard = 0;
+        let mut written = 0;
+        while let Some(t) = self.next_record() {
+            let t = t.map_err(|e| e as Box<dyn Error>)?;
+            if shard + 1 < n && written >= total * (shard + 1) / n
+               && previous.as_ref().is_some_and(|p| (self.sort_by_fn)(p, &t) == Less)
+            {
+                boundaries.push(t.clone());
+                shard += 1;
+            }
+            files[shard as usize].write_all(to_line(&t).map_err(|e| e as Box<dyn Error>)?
+                                                       .as_bytes())?;
+            written += 1;
+            previous = Some(t);
+        }
+        for mut file in files {
+            file.flush()?;
+        }
+
+        Ok(boundaries)
+    }
+
+    /// Number of records left to merge, exact for unbounded iterators and
+    /// estimated from the chunk samples otherwise
+    fn remaining_records(&self) -> u64 {
+        if self.lower.is_none() && self.upper.is_none() {
+            return self.chunk_records() - self.rank;
+        }
+        self.chunk_meta
+            .iter()
+            .zip(&self.chunk_positions)
+            .map(|(m, &position)| {
+                     m.samples
+                      .iter()
+                      .enumerate()
+                      .filter(|&(i, (_, r))| {
+                                  (i as u64 + 1) * m.sample_step > position && self.in_bounds(r)
+                              })
+                      .map(|(i, _)| m.sample_step.min(m.records - i as u64 * m.sample_step))
+                      .sum::<u64>()
+                 })
+            .sum()
+    }
+
+    /// Choose up to `n - 1` increasing boundaries splitting the remaining
+    /// records into ranges of similar size
+    fn splitters(&self, n: usize) -> Vec<T> {
+        let mut samples: Vec<(&T, u64)> = self.chunk_meta
+                                              .iter()
+                                              .flat_map(|m| {
+                                                  m.samples.iter().map(move |(_, r)| (r, m.sample_step))
+                                              })
+                                              .filter(|(r, _)| self.in_bounds(r))
+                                              .collect();
+        samples.sort_by(|a, b| (self.sort_by_fn)(a.0, b.0));
+        let total: u64 = samples.iter().map(|(_, w)| w).sum();
+
+        let mut splitters: Vec<T> = Vec::with_capacity(n - 1);
+        let mut seen = 0;
+        let mut part = 1;
+        for (r, w) in samples {
+            // a boundary at record `r` places `r` at the start of a new range
+            while part < n as u64 && seen >= total * part / n as u64 {
+                if seen > 0
+                   && splitters.last().is_none_or(|l| (self.sort_by_fn)(l, r) == Less)
+                {
+                    splitters.push(r.clone());
+                }
+                part += 1;
+            }
+            seen += w;
+        }
+
+        splitters
+    }
+
+    fn in_bounds(&self, r: &T) -> bool {
+        self.lower.as_ref().is_none_or(|l| (self.sort_by_fn)(r, l) != Less)
+        && self.upper.as_ref().is_none_or(|u| (self.sort_by_fn)(r, u) == Less)
+    }
+
+    /// Create an iterator over the `lower..upper` range of the remaining
+    /// records (within this iterator's own bounds), sharing this iterator's
+    /// chunks
+    fn part(&self, lower: Option<T>, upper: Option<T>, n: u64) -> ExtSortedIterator<T> {
+        let compare = &self.sort_by_fn;
+        let lower = match (lower, &self.lower) {
+            (Some(l), Some(own)) if compare(&l, own) == Less => Some(own.clone()),
+            (None, own) => own.clone(),
+            (l, _) => l,
+        };
+        let upper = match (upper, &self.upper) {
+            (Some(u), Some(own)) if compare(own, &u) == Less => Some(own.clone()),
+            (None, own) => own.clone(),
+            (u, _) => u,
+        };
+        let mut part = self.empty_part();
+        part.chunks = self.chunks;
+        part.max_per_chunk = self.max_per_chunk / n;
+        part.chunk_meta = self.chunk_meta.clone();
+        part.chunk_done = self.chunk_done.clone();
+        part.chunk_offsets = self.chunk_offsets.clone();
+        part.chunk_positions = self.chunk_positions.clone();
+        part.buffers = self.buffers.clone();
+        if let Some(ref lower) = lower {
+            // skip straight to the last sample preceding the lower bound
+            for (chunk_num, meta) in self.chunk_meta.iter().enumerate() {
+                let (sample, start) = meta.samples
+                                          .iter()
+                                          .enumerate()
+                                          .take_while(|(_, (_, r))| {
+                                                          (self.sort_by_fn)(r, lower) == Less
+                                                      })
+                                          .last()
+                                          .map_or((0, 0), |(i, (offset, _))| (i as u64, *offset));
+                if start > part.chunk_offsets[chunk_num] {
+                    part.chunk_offsets[chunk_num] = start;
+                    part.chunk_positions[chunk_num] = sample * meta.sample_step;
+                    part.buffers[chunk_num].clear();
+                }
+            }
+        }
+        part.lower = lower;
+        part.upper = upper;
+        for chunk_num in 0..self.chunks as usize {
+            part.apply_bounds(chunk_num);
+        }
+        part.rank = part.chunk_positions.iter().sum();
+
+        part
+    }
+
+    fn empty_part(&self) -> ExtSortedIterator<T> {
+        ExtSortedIterator {
+            buffers: Vec::new(),
+            heads: Vec::new(),
+            chunk_offsets: Vec::new(),
+            chunk_positions: Vec::new(),
+            rank: 0,
+            chunk_done: Vec::new(),
+            chunk_meta: Vec::new(),
+            max_per_chunk: 0,
+            chunks: 0,
+            lower: None,
+            upper: None,
+            tmp_dirs: self.tmp_dirs.clone(),
+            sort_by_fn: self.sort_by_fn.clone(),
+            dedup: self.dedup.clone(),
+            config: self.config.clone(),
+            timings: PhaseTimings::default(),
+            time_merge: self.time_merge,
+            merge_timer: None,
+            returned: None,
+            on_progress: None,
+            terminator: self.terminator,
+            io_priority: self.io_priority,
+            line: Vec::new(),
+            allocator: self.allocator.clone(),
+            arenas: self.arenas.as_ref().map(|_| Vec::new()),
+            #[cfg(feature = "indicatif")]
+            progress: None,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics.clone(),
+            #[cfg(feature = "testing")]
+            faults: Faults::default(),
+            failed: false,
+        }
+    }
+
+    /// Number of records left to merge, if known without merging them
+    fn remaining(&self) -> Option<u64> {
+        if self.failed {
+            return Some(0);
+        }
+        if self.dedup.is_some() || self.lower.is_some() || self.upper.is_some() {
+            return None;
+        }
+        // the merge is reported as it goes
+        #[cfg(feature = "indicatif")]
+        if self.progress.is_some() {
+            return None;
+        }
+        if self.on_progress.is_some() {
+            return None;
+        }
+        Some(self.chunk_meta.iter().map(|m| m.records).sum::<u64>() - self.rank)
+    }
+
+    /// Merge the next record out of the chunks, with an error type that can be
+    /// sent between threads
+    pub(crate) fn next_record(&mut self) -> Option<Result<T, SendError>> {
+        let dedup = self.dedup.clone();
+        self.next_group(dedup.as_ref()).map(|r| r.map(|merged| merged.record))
+    }
+
+    /// Merge the next record out of the chunks along with the number of
+    /// duplicates of it found by `dedup`
+    fn next_group(&mut self, dedup: Option<&Dedup<T>>) -> Option<Result<Merged<T>, SendError>> {
+        if self.failed {
+            return None;
+        }
+        let (record, rank) = match self.next_chunk() {
+            // the rank is read after any records before the lower bound were
+            // dropped by next_chunk()
+            Ok(Some(idx)) => (self.pop_record(idx), self.rank - 1),
+            Ok(None) => return None,
+            Err(e) => {
+                self.failed = true;
+                return Some(Err(e));
+            },
+        };
+        let dedup = match dedup {
+            Some(dedup) => dedup,
+            None => return Some(Ok(Merged { record, count: 1, rank })),
+        };
+        match self.resolve_duplicates(record, dedup) {
+            Ok((record, count)) => Some(Ok(Merged { record, count, rank })),
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            },
+        }
+    }
+
+    /// Take the record at the front of a (non-empty) chunk buffer
+    fn pop_record(&mut self, chunk_num: usize) -> T {
+        self.chunk_positions[chunk_num] += 1;
+        self.rank += 1;
+        // unwrap due to the checks of the callers
+        let record = self.buffers[chunk_num].pop_front().unwrap();
+        if Self::COMPACT && self.heads.len() == self.chunks as usize {
+            self.heads[chunk_num] = self.buffers[chunk_num].front().cloned();
+        }
+        record
+    }
+
+    /// Find the chunk holding the next record to write, after filling up any
+    /// empty buffers
+    fn next_chunk(&mut self) -> Result<Option<usize>, SendError> {
+        if Self::COMPACT {
+            return self.next_compact_chunk();
+        }
+        let mut empty = true;
+        for chunk_num in 0..self.chunks as usize {
+            self.refill(chunk_num)?;
+            if !self.buffers[chunk_num].is_empty() {
+                empty = false;
+            }
+        }
+        if empty {
+            return Ok(None);
+        }
+
+        // check is_empty() before unwrap()ing
+        let started = self.time_merge.then(Instant::now);
+        let mut idx = 0;
+        for chunk_num in 0..self.chunks as usize {
+            if self.buffers[chunk_num].is_empty() {
+                continue;
+            }
+            if self.buffers[idx].is_empty() {
+                idx = chunk_num;
+                continue;
+            }
+            #[cfg(feature = "metrics")]
+            self.metrics.comparisons.increment(1);
+            if (self.sort_by_fn)(self.buffers[chunk_num].front().unwrap(),
+                                 self.buffers[idx].front().unwrap())
+               == Less
+            {
+                idx = chunk_num;
+            }
+        }
+        if let Some(started) = started {
+            self.timings.compare += started.elapsed();
+        }
+
+        Ok(Some(idx))
+    }
+
+    /// Find the chunk holding the next record to write among the heads of
+    /// the chunks, refilling the buffers of those without one
+    fn next_compact_chunk(&mut self) -> Result<Option<usize>, SendError> {
+        if self.heads.len() != self.chunks as usize {
+            for chunk_num in 0..self.chunks as usize {
+                self.refill(chunk_num)?;
+            }
+            self.heads = self.buffers.iter().map(|buffer| buffer.front().cloned()).collect();
+        }
+
+        let mut idx: Option<usize> = None;
+        for chunk_num in 0..self.heads.len() {
+            if self.heads[chunk_num].is_none() && !self.chunk_done[chunk_num] {
+                self.refill(chunk_num)?;
+            }
+            let head = match self.heads[chunk_num] {
+                Some(ref head) => head,
+                None => continue,
+            };
+            match idx {
+                // unwrap since only chunks with a head are chosen
+                Some(i) => {
+                    #[cfg(feature = "metrics")]
+                    self.metrics.comparisons.increment(1);
+                    let started = self.time_merge.then(Instant::now);
+                    let less = (self.sort_by_fn)(head, self.heads[i].as_ref().unwrap()) == Less;
+                    if let Some(started) = started {
+                        self.timings.compare += started.elapsed();
+                    }
+                    if less {
+                        idx = Some(chunk_num);
+                    }
+                },
+                None => idx = Some(chunk_num),
+            }
+        }
+
+        Ok(idx)
+    }
+
+    /// Merge any duplicates of `r` that follow it in the sorted output into a
+    /// single record, according to the dedup policy, and count them
+    fn resolve_duplicates(&mut self, mut r: T, dedup: &Dedup<T>) -> Result<(T, u64), SendError> {
+        let mut count = 1;
+        while let Some(idx) = self.next_chunk()? {
+            // unwrap due to the check in next_chunk()
+            let next = self.buffers[idx].front().unwrap();
+            let started = self.time_merge.then(Instant::now);
+            let same = match dedup.same {
+                Some(ref same) => same(next, &r),
+                None => (self.sort_by_fn)(next, &r) == Equal,
+            };
+            if let Some(started) = started {
+                self.timings.compare += started.elapsed();
+            }
+            if !same {
+                break;
+            }
+            let next = self.pop_record(idx);
+            count += 1;
+            r = match dedup.policy {
+                DedupPolicy::KeepFirst => r,
+                DedupPolicy::KeepLast => next,
+                DedupPolicy::Combine(ref combine) => combine(r, next),
+            };
+        }
+
+        Ok((r, count))
+    }
+}
+
+impl<T> Iterator for ExtSortedIterator<T>
+where
+    T: ExternallySortable,
+{
+    type Item = Result<T, Box<dyn Error>>;
+
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// from disk, or due to serde deserialization issues
+    fn next(&mut self) -> Option<Self::Item> {
+        let rank = self.rank;
+        if self.time_merge {
+            if let Some(returned) = self.returned {
+                self.timings.output += returned.elapsed();
+            }
+            if self.merge_timer.is_none() {
+                self.merge_timer = Some(Timer::start());
+            }
+        }
+        let next = self.next_record();
+        if self.time_merge {
+            self.returned = Some(Instant::now());
+        }
+        if next.is_none() {
+            self.returned = None;
+            if let Some(timer) = self.merge_timer.take() {
+                self.timings.merge_cpu = timer.elapsed().1;
+            }
+        }
+        if let Some(ref tracker) = self.on_progress {
+            match next {
+                Some(Ok(ref t)) => tracker.advance(self.rank - rank, t.get_size()),
+                Some(Err(_)) => {},
+                None => {
+                    tracker.finish();
+                    self.on_progress = None;
+                },
+            }
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(Ok(_)) = next {
+            self.metrics.records_out.increment(1);
+        }
+        #[cfg(feature = "indicatif")]
+        if let Some(ref bar) = self.progress {
+            match next {
+                Some(_) => bar.set_position(self.rank),
+                None => bar.finish(),
+            }
+        }
+        next.map(|r| r.map_err(|e| e as Box<dyn Error>))
+    }
+
+    /// Count the remaining records from the number of records of every run,
+    /// without merging them, unless the iterator de-duplicates records, is
+    /// bounded by [split](struct.ExtSortedIterator.html#method.split) or
+    /// reports the progress of the merge, in which case they are merged to be
+    /// counted
+    fn count(self) -> usize {
+        match self.remaining() {
+            Some(remaining) => remaining as usize,
+            None => self.fold(0, |count, _| count + 1),
+        }
+    }
+}
+
+impl<T> Drop for ExtSortedIterator<T> {
+    fn drop(&mut self) {
+        if let Some(ref allocator) = self.allocator {
+            allocator.release_merge(mem::take(&mut self.buffers), mem::take(&mut self.line));
+        }
+        #[cfg(any(feature = "log", feature = "metrics"))]
+        for tmp_dir in &self.tmp_dirs {
+            // the directory is removed along with its last reference
+            if Arc::strong_count(tmp_dir) == 1 {
+                #[cfg(feature = "log")]
+                log::debug!("removing temporary directory {}", tmp_dir.path().display());
+                #[cfg(feature = "metrics")]
+                metrics::dir_removed(tmp_dir.path());
+            }
+        }
+    }
+}
+
+/// Iterator that provides distinct sorted `T`s and their number of
+/// occurrences, created by
+/// [ExtSortedIterator::counts](struct.ExtSortedIterator.html#method.counts)
+pub struct CountedIterator<T> {
+    iter: ExtSortedIterator<T>,
+}
+
+impl<T> Iterator for CountedIterator<T>
+where
+    T: ExternallySortable,
+{
+    type Item = Result<(T, u64), Box<dyn Error>>;
+
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// from disk, or due to serde deserialization issues
+    fn next(&mut self) -> Option<Self::Item> {
+        let dedup = self.iter.dedup.clone().unwrap_or(Dedup {
+                                                          same: None,
+                                                          policy: DedupPolicy::KeepFirst,
+                                                      });
+        self.iter
+            .next_group(Some(&dedup))
+            .map(|r| r.map(|merged| (merged.record, merged.count)).map_err(|e| e as Box<dyn Error>))
+    }
+}
+
+/// Iterator that provides sorted `T`s and their rank in the sorted output,
+/// created by
+/// [ExtSortedIterator::ranked](struct.ExtSortedIterator.html#method.ranked)
+pub struct RankedIterator<T> {
+    iter: ExtSortedIterator<T>,
+}
+
+impl<T> Iterator for RankedIterator<T>
+where
+    T: ExternallySortable,
+{
+    type Item = Result<(u64, T), Box<dyn Error>>;
+
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// from disk, or due to serde deserialization issues
+    fn next(&mut self) -> Option<Self::Item> {
+        let dedup = self.iter.dedup.clone();
+        self.iter
+            .next_group(dedup.as_ref())
+            .map(|r| r.map(|merged| (merged.rank, merged.record)).map_err(|e| e as Box<dyn Error>))
+    }
+}
+
+/// Iterator that provides the sorted `T`s of a single run of a sort, created
+/// by [ExtSortedIterator::into_runs](struct.ExtSortedIterator.html#method.into_runs)
+pub struct SortedRun<T> {
+    summary: RunSummary,
+    first: Option<T>,
+    last: Option<T>,
+    iter: ExtSortedIterator<T>,
+}
+
+impl<T> SortedRun<T>
+where
+    T: ExternallySortable,
+{
+    /// Number and total size of all of the records of the run, regardless of
+    /// bounds or of how many were already read
+    pub fn summary(&self) -> RunSummary {
+        self.summary
+    }
+
+    /// Smallest record of the run, regardless of bounds
+    pub fn smallest(&self) -> Option<&T> {
+        self.first.as_ref()
+    }
+
+    /// Largest record of the run, regardless of bounds
+    pub fn largest(&self) -> Option<&T> {
+        self.last.as_ref()
+    }
+
+    /// Turn the run back into a sorted iterator, e.g. to merge several runs
+    /// with [ExtSortedIterator::union](struct.ExtSortedIterator.html#method.union)
+    pub fn into_sorted(self) -> ExtSortedIterator<T> {
+        self.iter
+    }
+}
+
+impl<T> Iterator for SortedRun<T>
+where
+    T: ExternallySortable,
+{
+    type Item = Result<T, Box<dyn Error>>;
+
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading the intermediate sorted
+    /// chunk from disk, or due to serde deserialization issues
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
+
+/// Perform an external sort on an unsorted stream of incoming data
+///
+/// An `ExternalSorter` only holds configuration, so it is `Send + Sync` and a
+/// single sorter (e.g. behind an `Arc`) can run concurrent sorts from several
+/// threads. Every sort writes its chunks to its own temporary directory
+/// within the configured `tmp_dir`, which is removed when the returned
+/// iterator is dropped.
+///
+/// # Examples
+///
+/// ```
+/// extern crate external_sort;
+/// #[macro_use]
+/// extern crate serde_derive;
+///
+/// use external_sort::{ExternallySortable, ExternalSorter};
+///
+/// #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// struct Num {
+///     the_num: u32
+/// }
+///
+/// impl Num {
+///     fn new(num:u32) -> Num {
+///         Num { the_num: num }
+///     }
+/// }
+///
+/// impl ExternallySortable for Num {
+///     fn get_size(&self) -> u64 {
+///         4
+///     }
+/// }
+///
+/// fn main() {
+///     let unsorted = vec![Num::new(5), Num::new(2), Num::new(1), Num::new(3),
+///         Num::new(4)]; 
+///     let sorted = vec![Num::new(1), Num::new(2), Num::new(3), Num::new(4), 
+///         Num::new(5)];
+///
+///     let external_sorter = ExternalSorter::new(16, None);
+///     let iter = external_sorter.sort(unsorted.into_iter()).unwrap();
+///     for (idx, i) in iter.enumerate() {
+///         assert_eq!(i.unwrap().the_num, sorted[idx].the_num);
+///     }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct ExternalSorter<T>
+where
+    T: ExternallySortable,
+{
+    pub(crate) tmp_dir: Option<PathBuf>,
+    pub(crate) buffer_bytes: u64,
+    threads: Option<usize>,
+    pub(crate) premerge: Option<usize>,
+    check_sorted: bool,
+    #[cfg(feature = "rayon")]
+    pool: Option<Arc<ThreadPool>>,
+    on_progress: Option<Arc<ProgressFn>>,
+    progress_interval: u64,
+    on_merge_plan: Option<Arc<MergePlanFn>>,
+    pub(crate) terminator: u8,
+    io_priority: Option<IoPriority>,
+    ties: TieBreak<T>,
+    break_ties: Option<Arc<BreakTiesFn<T>>>,
+    skew: Option<u64>,
+    watermarks: Option<(f64, f64)>,
+    /// Allocator of the chunk and merge buffers, shared with clones
+    pub(crate) allocator: Option<Arc<dyn BufferAllocator<T>>>,
+    arena_batches: bool,
+    run_length: bool,
+    time_merge: bool,
+    verify_runs: bool,
+    /// File the last records read are written to if a sort fails, with the
+    /// number of them
+    capture: Option<(PathBuf, usize)>,
+    tag: Option<String>,
+    /// Spills of every tag, shared with clones
+    tags: Arc<Tags>,
+    #[cfg(feature = "pressure")]
+    pressure: Option<u64>,
+    /// Spilling and merging on other threads, set along with the settings
+    /// that use them, which require records that can be sent between threads
+    threaded: Option<Threaded<T>>,
+    #[cfg(feature = "indicatif")]
+    progress: Option<ProgressBar>,
+    // the sorter never holds any `T`s itself, so it is `Send + Sync` whatever
+    // `T` is
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<T> ExternalSorter<T>
+where
+    T: ExternallySortable,
+{
+    /// Create a new `ExternalSorter` with a specified memory buffer and
+    /// temporary directory
+    ///
+    /// Besides the sizes reported by `get_size()`, the buffer accounts for
+    /// `size_of::<T>()` bytes for every record held in the chunk and merge
+    /// buffers. A record that fills the buffer by itself is spilled
+    /// to a sorted run of its own, after the records buffered before it, so
+    /// that it is never held in memory along with other records of its run.
+    pub fn new(buffer_bytes: u64, tmp_dir: Option<PathBuf>) -> ExternalSorter<T> {
+        ExternalSorter {
+            buffer_bytes,
+            tmp_dir,
+            threads: None,
+            premerge: None,
+            check_sorted: false,
+            #[cfg(feature = "rayon")]
+            pool: None,
+            on_progress: None,
+            progress_interval: PROGRESS_INTERVAL,
+            on_merge_plan: None,
+            terminator: b'\n',
+            io_priority: None,
+            ties: if T::STABLE_TIES { TieBreak::InputOrder } else { TieBreak::Unspecified },
+            break_ties: None,
+            skew: None,
+            watermarks: None,
+            allocator: None,
+            arena_batches: false,
+            run_length: false,
+            time_merge: false,
+            verify_runs: false,
+            capture: None,
+            tag: None,
+            tags: Arc::new(Tags::default()),
+            #[cfg(feature = "pressure")]
+            pressure: None,
+            threaded: None,
+            #[cfg(feature = "indicatif")]
+            progress: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Create a new `ExternalSorter` whose memory buffer is `fraction` of the
+    /// memory available to the process, and a specified temporary directory
+    ///
+    /// The available memory is the least of what is left under the memory
+    /// limit of the cgroup of the process (as in a container) and of the
+    /// memory available system-wide, read once, when the sorter is created.
+    /// Where neither can be read, as outside Linux, 1 GiB is assumed to be
+    /// available. `fraction` is clamped between 0 and 1.
+    pub fn with_auto_buffer(fraction: f64, tmp_dir: Option<PathBuf>) -> ExternalSorter<T> {
+        let available = memory::available_memory().unwrap_or(FALLBACK_MEMORY);
+        let fraction = if fraction.is_nan() { 0.0 } else { fraction.clamp(0.0, 1.0) };
+        ExternalSorter::new((available as f64 * fraction) as u64, tmp_dir)
+    }
+
+    /// Copy the configuration of this sorter to a sorter of another type
+    pub(crate) fn retype<U>(&self) -> ExternalSorter<U>
+    where
+        U: ExternallySortable + Send,
+    {
+        ExternalSorter { threaded: self.threaded.map(|_| Threaded::new()), ..self.retype_local() }
+    }
+
+    /// Copy the configuration of this sorter to a sorter of another type,
+    /// whose records may not be sent between threads, and are therefore
+    /// spilled and merged on the calling thread
+    pub(crate) fn retype_local<U>(&self) -> ExternalSorter<U>
+    where
+        U: ExternallySortable,
+    {
+        ExternalSorter {
+            buffer_bytes: self.buffer_bytes,
+            tmp_dir: self.tmp_dir.clone(),
+            threads: self.threads,
+            premerge: self.premerge,
+            check_sorted: self.check_sorted,
+            #[cfg(feature = "rayon")]
+            pool: self.pool.clone(),
+            on_progress: self.on_progress.clone(),
+            progress_interval: self.progress_interval,
+            on_merge_plan: self.on_merge_plan.clone(),
+            terminator: self.terminator,
+            io_priority: self.io_priority,
+            ties: self.ties.retype(),
+            break_ties: None,
+            skew: self.skew,
+            watermarks: self.watermarks,
+            // the buffers hold records of this sorter's type
+            allocator: None,
+            arena_batches: self.arena_batches,
+            run_length: self.run_length,
+            time_merge: self.time_merge,
+            verify_runs: self.verify_runs,
+            capture: self.capture.clone(),
+            tag: self.tag.clone(),
+            tags: self.tags.clone(),
+            #[cfg(feature = "pressure")]
+            pressure: self.pressure,
+            threaded: None,
+            #[cfg(feature = "indicatif")]
+            progress: self.progress.clone(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Set the number of threads used to sort and write chunks to disk.
+    ///
+    /// By default, [sort](#method.sort) and [sort_by](#method.sort_by) sort
+    /// and write every chunk on the calling thread. With `threads > 1`, full
+    /// chunks are handed off to `threads - 1` worker threads while the calling
+    /// thread keeps consuming the input, and the memory buffer is divided
+    /// evenly between the chunks held by all threads.
+    ///
+    /// With the `rayon` feature, this also sets the size of the thread pool
+    /// used by [par_sort](#method.par_sort) and
+    /// [par_sort_by](#method.par_sort_by), unless a pool is provided with
+    /// [thread_pool](#method.thread_pool).
+    pub fn threads(mut self, threads: usize) -> ExternalSorter<T>
+    where
+        T: Send,
+    {
+        self.threads = Some(threads.max(1));
+        self.threaded = Some(Threaded::new());
+        self
+    }
+
+    /// Merge completed chunks in the background while the input is still
+    /// being consumed.
+    ///
+    /// Whenever `fan_in` adjacent chunks of the same size class have been
+    /// written, a background thread merges them into a single larger chunk,
+    /// so that by the time the input ends the final merge has few chunks to
+    /// combine and the first sorted records are available sooner. Half of the
+    /// memory buffer is reserved for the background merge.
+    pub fn premerge(mut self, fan_in: usize) -> ExternalSorter<T>
+    where
+        T: Send,
+    {
+        self.premerge = Some(fan_in.max(2));
+        self.threaded = Some(Threaded::new());
+        self
+    }
+
+    /// Check whether the input is already sorted while consuming it.
+    ///
+    /// With this option, [sort](#method.sort) and [sort_by](#method.sort_by)
+    /// write records straight to a single chunk on disk for as long as they
+    /// arrive in order, without buffering or sorting them. An input that is
+    /// already sorted is therefore written once, sequentially, and read back
+    /// as a single chunk. If a record arrives out of order, the rest of the
+    /// input is sorted as usual, and merged with the sorted prefix. Only one
+    /// record is held in memory to check the order.
+    pub fn check_sorted(mut self) -> ExternalSorter<T> {
+        self.check_sorted = true;
+        self
+    }
+
+    /// Use an existing rayon thread pool for [par_sort](#method.par_sort) and
+    /// [par_sort_by](#method.par_sort_by), rather than the current (usually
+    /// global) pool.
+    #[cfg(feature = "rayon")]
+    pub fn thread_pool(mut self, pool: Arc<ThreadPool>) -> ExternalSorter<T> {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Report the progress of sorts to `callback`.
+    ///
+    /// While [sort](#method.sort), [sort_by](#method.sort_by) and their
+    /// parallel variants consume the input, `callback` is called every
+    /// [progress_interval](#method.progress_interval) records read, in the
+    /// [Spilling](enum.ProgressPhase.html#variant.Spilling) phase. Once the
+    /// input ends, it is called as the returned iterator starts merging, and
+    /// then every interval of records merged, in the
+    /// [Merging](enum.ProgressPhase.html#variant.Merging) phase, with an
+    /// estimate of the fraction of the records merged. It is called a last
+    /// time in the [Done](enum.ProgressPhase.html#variant.Done) phase at the
+    /// end of the sorted output.
+    ///
+    /// With a parallel sort, `callback` may be called from several threads.
+    pub fn on_progress<F>(mut self, callback: F) -> ExternalSorter<T>
+    where
+        F: 'static + Fn(Progress) + Send + Sync,
+    {
+        self.on_progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Set the number of records between the calls of the callback set with
+    /// [on_progress](#method.on_progress), 10,000 by default
+    pub fn progress_interval(mut self, records: u64) -> ExternalSorter<T> {
+        self.progress_interval = records.max(1);
+        self
+    }
+
+    /// Set the byte that ends the records read by the text methods, such as
+    /// [sort_lines](#method.sort_lines), and written by the `write_lines`
+    /// methods of their iterators, `b'\n'` by default
+    ///
+    /// With a newline, a carriage return before it is dropped along with it,
+    /// so that CRLF input sorts the same as LF input (and is written with
+    /// bare newlines). The last record of an input may end without a
+    /// terminator.
+    pub fn terminator(mut self, terminator: u8) -> ExternalSorter<T> {
+        self.terminator = terminator;
+        self
+    }
+
+    /// Give the disk activity of sorts the I/O scheduling `priority`, e.g.
+    /// [Idle](enum.IoPriority.html#variant.Idle) so that a background sort
+    /// does not starve latency-sensitive services sharing its disks
+    ///
+    /// The priority applies to every thread writing runs while the input is
+    /// spilled (including those of [threads](#method.threads),
+    /// [premerge](#method.premerge) and the rayon pool of
+    /// [par_sort](#method.par_sort)), to intermediate merges, and to the reads
+    /// of the returned iterator, and each thread gets its own priority back
+    /// once it is done. It is only applied on Linux.
+    pub fn io_priority(mut self, priority: IoPriority) -> ExternalSorter<T> {
+        self.io_priority = Some(priority);
+        self
+    }
+
+    /// Set how records that compare as equal are ordered, in input order
+    /// ([TieBreak::InputOrder](enum.TieBreak.html#variant.InputOrder)) by
+    /// default, unless the
+    /// [STABLE_TIES](trait.ExternallySortable.html#associatedconstant.STABLE_TIES)
+    /// of `T` is `false`
+    ///
+    /// The policy applies to both the sorting of the chunks and their merge,
+    /// so that with [InputOrder](enum.TieBreak.html#variant.InputOrder) or
+    /// [Then](enum.TieBreak.html#variant.Then) the output does not depend on
+    /// the buffer size.
+    pub fn tie_break(mut self, ties: TieBreak<T>) -> ExternalSorter<T>
+    where
+        T: 'static,
+    {
+        self.break_ties = match ties {
+            TieBreak::Then(ref then) => {
+                let then = then.clone();
+                Some(Arc::new(move |compare: Arc<CompareFn<T>>| -> Arc<CompareFn<T>> {
+                    let then = then.clone();
+                    Arc::new(move |a, b| compare(a, b).then_with(|| then(a, b)))
+                }))
+            },
+            _ => None,
+        };
+        self.ties = ties;
+        self
+    }
+
+    /// Add the secondary comparator of the tie-breaking policy, if any, to
+    /// `compare`
+    pub(crate) fn break_ties(&self, compare: Arc<CompareFn<T>>) -> Arc<CompareFn<T>> {
+        match self.break_ties {
+            Some(ref break_ties) => break_ties(compare),
+            None => compare,
+        }
+    }
+
+    /// Pass the plan of the merge of each sort to `hook` before merging.
+    ///
+    /// Once the first pass of [sort](#method.sort), [sort_by](#method.sort_by)
+    /// or their parallel variants has written every sorted run (after any
+    /// background merges done by [premerge](#method.premerge)), `hook` is
+    /// called with a [MergePlan](struct.MergePlan.html) describing the runs and
+    /// how they will be merged together, e.g. to estimate the I/O left to do.
+    /// By default, the runs are merged in a single pass. `hook` may change the
+    /// passes of the plan, e.g. with [cascade](struct.MergePlan.html#method.cascade),
+    /// in which case the intermediate passes are merged to disk before the
+    /// sorted iterator is returned.
+    ///
+    /// The sort fails if `hook` leaves a plan whose passes do not cover every
+    /// run in order, or that does not end with a single final merge.
+    pub fn on_merge_plan<F>(mut self, hook: F) -> ExternalSorter<T>
+    where
+        F: 'static + Fn(&mut MergePlan) + Send + Sync,
+    {
+        self.on_merge_plan = Some(Arc::new(hook));
+        self
+    }
+
+    /// Split the records of every chunk that are more than `factor` times as
+    /// large as the average record of the chunk off to a sorted run of their
+    /// own, when a chunk is spilled.
+    ///
+    /// When record sizes are skewed, a few large records can take up most of
+    /// a run, and every refill of its merge buffer, which is a share of the
+    /// memory buffer sized for the average run, then overshoots its share by
+    /// one of them. With their own run, a few large records only take one
+    /// buffer at a time, and the buffers of the other runs refill in even
+    /// steps. Since equal records of different runs are merged in the order
+    /// of their runs, equal records split apart no longer keep their input
+    /// order.
+    pub fn split_skewed(mut self, factor: u64) -> ExternalSorter<T> {
+        self.skew = Some(factor.max(1));
+        self
+    }
+
+    /// Spill in the background between a `high` and a `low` watermark, as
+    /// fractions of the memory buffer, rather than all at once when the
+    /// buffer is full
+    ///
+    /// Once the buffered records take up more than `high` of the buffer, they
+    /// are sorted and the smallest of them are handed off to a background
+    /// thread to be written as a run, down to `low` of the buffer, while the
+    /// others stay buffered and more input is accepted. Producers feeding the
+    /// sort (e.g. through a [SortSink](struct.SortSink.html)) only wait for a
+    /// run to be written if the previous one is still being written when the
+    /// high watermark is passed again, rather than for every run. Equal
+    /// records keep their input order.
+    ///
+    /// The buffered records and the run being written take up to
+    /// `2 * high - low` of the buffer together, so e.g. watermarks of 0.75
+    /// and 0.5 stay within it, with runs of a quarter of the buffer (and a
+    /// final one of up to three quarters). `high` is clamped to at most 1 and
+    /// `low` to at most `high`. Watermarks
+    /// do not apply with [threads](#method.threads), whose workers already
+    /// write runs in the background, nor to
+    /// [par_sort](#method.par_sort).
+    pub fn watermarks(mut self, high: f64, low: f64) -> ExternalSorter<T>
+    where
+        T: Send,
+    {
+        let high = high.clamp(f64::MIN_POSITIVE, 1.0);
+        self.watermarks = Some((high, low.clamp(0.0, high)));
+        self.threaded = Some(Threaded::new());
+        self
+    }
+
+    /// Keep the merge buffers of finished merges, and the scratch lines their
+    /// records are read into, for the next merges of this sorter, rather than
+    /// allocating them again for every merge
+    ///
+    /// The pool is shared with the clones of the sorter and the
+    /// [sessions](#method.session) it starts, so that a service running many
+    /// sorts, or a sort merging runs in the background with
+    /// [premerge](#method.premerge), reaches a steady state without
+    /// allocating buffers. Buffers are returned to the pool when the iterator
+    /// merging them is dropped, and the pool keeps those with the largest
+    /// capacity that add up to at most the memory buffer. A buffer taken
+    /// from the pool is shrunk to its share of the memory buffer.
+    pub fn reuse_buffers(self) -> ExternalSorter<T>
+    where
+        T: 'static + Send,
+    {
+        let pool = BufferPool::new(self.buffer_bytes);
+        self.buffer_allocator(pool)
+    }
+
+    /// Take the chunks of records read from the input, the merge buffers of
+    /// the sorted runs and the scratch lines records are read into from
+    /// `allocator`, and give them back to it once done with them
+    ///
+    /// This lets an embedder with its own allocation policy, such as a bound
+    /// on the memory of the process, direct the largest transient
+    /// allocations of the sorts. The allocator is shared with the clones of
+    /// the sorter. Chunks are taken from it by every sort but the parallel
+    /// sorts of the `rayon` feature. This replaces the pool of
+    /// [reuse_buffers](#method.reuse_buffers).
+    pub fn buffer_allocator<A>(mut self, allocator: A) -> ExternalSorter<T>
+    where
+        A: 'static + BufferAllocator<T>,
+    {
+        self.allocator = Some(Arc::new(allocator));
+        self
+    }
+
+    /// Read every batch of records that refills a merge buffer of the sorted
+    /// iterators into an arena, for records whose strings are
+    /// [ArenaStr](struct.ArenaStr.html)s
+    ///
+    /// The strings of a batch are appended to a single arena rather than
+    /// allocated one by one, and the arena is reused for the next batch of
+    /// the same run once every record of the batch has been merged and
+    /// dropped, so that the merge allocates per batch rather than per string.
+    /// Records of other types are read as usual.
+    pub fn arena_batches(mut self) -> ExternalSorter<T> {
+        self.arena_batches = true;
+        self
+    }
+
+    /// Time the final merge of the sorted iterators, for the `merge_read`,
+    /// `compare`, `output` and `merge_cpu`
+    /// [timings](struct.PhaseTimings.html) of their
+    /// [stats](struct.ExtSortedIterator.html#method.stats)
+    ///
+    /// Timing the merge reads the clock around every record returned and
+    /// every comparison, so these timings are left at zero unless it is set.
+    /// The phases of the first pass are timed per chunk, and always reported.
+    pub fn time_merge(mut self) -> ExternalSorter<T> {
+        self.time_merge = true;
+        self
+    }
+
+    /// Check that every sorted chunk is still there with the size it was
+    /// written with before the merge starts, and list the chunks with their
+    /// sizes and checksums in a `runs.json` manifest in the temporary
+    /// directory, for [inspect_run](fn.inspect_run.html)
+    ///
+    /// Checksumming reads every chunk once more before the merge, so chunks
+    /// are neither checked nor listed unless it is set.
+    pub fn verify_runs(mut self) -> ExternalSorter<T> {
+        self.verify_runs = true;
+        self
+    }
+
+    /// Write consecutive records that serialize the same way to the sorted
+    /// chunks once, along with their number of repeats, for inputs with many
+    /// duplicates
+    ///
+    /// The chunks shrink with the number of duplicates, as does the time
+    /// spent writing and reading them back. The merge still yields every
+    /// repeat, so that [dedup](struct.ExtSortedIterator.html#method.dedup)
+    /// and [counts](struct.ExtSortedIterator.html#method.counts) see them as
+    /// usual. Up to 1024 repeats are written as one, and a merge buffer reads
+    /// them back together, so it may exceed its share of the memory buffer
+    /// by that many records. Chunks merged into larger ones by intermediate
+    /// merge passes are written in full.
+    ///
+    /// The merge seeks within a chunk to the records sampled from it, one
+    /// every 1/32 of the chunk, so repeats are not written as one across
+    /// them: a chunk whose records all repeat still takes 32 lines or more,
+    /// and chunks of fewer than 64 records (as with a small memory buffer)
+    /// are written in full.
+    pub fn run_length_encoding(mut self) -> ExternalSorter<T> {
+        self.run_length = true;
+        self
+    }
+
+    /// Write the last `window` records read by a sort to the file at `path`
+    /// if the sort fails (or panics, as a comparator may), so that the
+    /// failure can be replayed from them in a test
+    ///
+    /// The records are kept as they are read, in input order, and are
+    /// written as newline-delimited JSON, which
+    /// [sort_reader](#method.sort_reader) reads back with
+    /// [JsonLines](struct.JsonLines.html). The error of the sort names the
+    /// file. Keeping the records costs a clone of each of them, so a
+    /// `window` of `usize::MAX` keeps the whole input in memory. This
+    /// applies to [sort](#method.sort), [sort_by](#method.sort_by) and the
+    /// sorts built on them, but not to the iteration of their output.
+    pub fn capture_on_error<P>(mut self, path: P, window: usize) -> ExternalSorter<T>
+    where
+        P: Into<PathBuf>,
+    {
+        self.capture = Some((path.into(), window));
+        self
+    }
+
+    /// Tag the sorts of this sorter with `tag`, such as the tenant of a
+    /// shared sorting service they run for
+    ///
+    /// The temporary directory of every sort is named after the tag (with
+    /// characters other than ASCII letters, digits, `-` and `_` replaced by
+    /// `_`), and the tag is reported in the [configuration](struct.SortConfig.html)
+    /// of the [stats](struct.ExtSortedIterator.html#method.stats) of the
+    /// sort, in the [merge plan](struct.MergePlan.html) passed to its
+    /// [hook](#method.on_merge_plan), and in its `tracing` spans. Sorts of a
+    /// tag share its [disk quota](#method.tag_quota) and [disk
+    /// usage](#method.tag_disk_usage) with the other sorts of the tag, made
+    /// by this sorter or by its clones.
+    pub fn tag<S>(mut self, tag: S) -> ExternalSorter<T>
+    where
+        S: Into<String>,
+    {
+        self.tag = Some(tag.into());
+        self
+    }
+
+    /// Limit the bytes that the sorts [tagged](#method.tag) `tag` by this
+    /// sorter and its clones keep on disk at once to `max_bytes`
+    ///
+    /// The usage of the tag is checked every time a sort writes a sorted
+    /// run, and a sort that takes it over `max_bytes` fails, which removes
+    /// its runs. The runs of intermediate merges are not checked, so they can
+    /// take the usage over the quota until the next run is written.
+    pub fn tag_quota<S>(self, tag: S, max_bytes: u64) -> ExternalSorter<T>
+    where
+        S: Into<String>,
+    {
+        self.tags.usage(&tag.into()).set_quota(max_bytes);
+        self
+    }
+
+    /// Bytes that the sorts [tagged](#method.tag) `tag` by this sorter and
+    /// its clones have on disk, in the temporary directories of the sorts
+    /// and of the iterators they returned that were not dropped yet
+    pub fn tag_disk_usage(&self, tag: &str) -> u64 {
+        self.tags.disk_usage(tag)
+    }
+
+    /// Spill chunks early while the memory available to the process is below
+    /// `threshold_bytes`, with the `pressure` feature.
+    ///
+    /// The available memory is the least of what is left under the memory
+    /// limit of the cgroup of the process (as in a container) and of the
+    /// memory available system-wide, checked every 1024 records read. At every
+    /// check under the threshold, the share of the buffer that chunks may grow
+    /// to is halved (down to 1/64 of it), so that the chunk being filled is
+    /// spilled sooner, and at every check above it the share is doubled back.
+    /// The buffers of the merge are not affected.
+    #[cfg(feature = "pressure")]
+    pub fn memory_pressure(mut self, threshold_bytes: u64) -> ExternalSorter<T> {
+        self.pressure = Some(threshold_bytes);
+        self
+    }
+
+    /// Drive an `indicatif` progress bar while sorting, with the `indicatif`
+    /// feature.
+    ///
+    /// While [sort](#method.sort), [sort_by](#method.sort_by) and their
+    /// parallel variants consume the input, `bar` spins and counts the records
+    /// and bytes written to sorted chunks. Once the input ends and the totals
+    /// are known, it becomes a bar of the records merged out of the chunks by
+    /// the returned iterator, which finishes it at the end of the output. The
+    /// style of `bar` is replaced at the start of each phase.
+    #[cfg(feature = "indicatif")]
+    pub fn progress_bar(mut self, bar: ProgressBar) -> ExternalSorter<T> {
+        self.progress = Some(bar);
+        self
+    }
+
+    /// Predict the runs, merge passes, and peak disk and memory usage of a
+    /// sort of `estimated_items` records of `estimated_item_bytes` each with
+    /// the current configuration, without sorting anything
+    ///
+    /// The prediction follows [sort](#method.sort) and
+    /// [sort_by](#method.sort_by) (sorting unsorted input, when checking for
+    /// a sorted prefix), including the chunks held by
+    /// [threads](#method.threads) and the background merges of
+    /// [premerge](#method.premerge). The predicted merge plan is passed to
+    /// the [on_merge_plan](#method.on_merge_plan) hook, if any, to predict the
+    /// passes it plans; a plan the hook leaves invalid (failing the sort) is
+    /// predicted as a single pass.
+    pub fn plan(&self, estimated_items: u64, estimated_item_bytes: u64) -> SortPlan {
+        let size = mem::size_of::<T>() as u64;
+        let record_bytes = estimated_item_bytes + size;
+        let threads = self.spill_threads();
+        let (records_per_run, chunk_memory) =
+            chunk_records(self.spill_bytes() / threads, estimated_item_bytes, size);
+        let records_per_run = records_per_run.min(estimated_items).max(1);
+        let chunk_memory = chunk_memory.min(estimated_items.saturating_mul(record_bytes));
+        let runs: Vec<_> = (0..estimated_items.div_ceil(records_per_run))
+            .map(|run| {
+                let records = records_per_run.min(estimated_items - run * records_per_run);
+                let bytes = records.saturating_mul(estimated_item_bytes);
+                (RunSummary { records, bytes }, bytes)
+            })
+            .collect();
+        let spill_memory = chunk_memory.saturating_mul(threads.min(runs.len().max(1) as u64));
+        self.project(runs, spill_memory, record_bytes)
+    }
+
+    /// Consume `unsorted` as a sort would, measuring the records and their
+    /// serialized sizes and the runs they would be spilled to, without
+    /// sorting or writing anything
+    ///
+    /// The returned [DryRun](struct.DryRun.html) projects the rest of the
+    /// sort (the merge passes and the peak disk and memory usage) from the
+    /// measured runs, as [plan](#method.plan) does. To project a sort of a
+    /// larger input from a sample of it, pass [plan](#method.plan) the
+    /// average serialized size of the sampled records.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to serde serialization issues
+    pub fn dry_run<I>(&self, unsorted: I) -> Result<DryRun, Box<dyn Error>>
+    where
+        I: Iterator<Item = T>,
+    {
+        let size = mem::size_of::<T>() as u64;
+        let threads = self.spill_threads();
+        let chunk_bytes = self.spill_bytes() / threads;
+        let (mut records, mut bytes, mut serialized_bytes) = (0, 0, 0);
+        let mut runs = Vec::new();
+        let mut run = (RunSummary { records: 0, bytes: 0 }, 0);
+        let mut chunk_memory = 0;
+        for t in unsorted {
+            let serialized = to_line(&t).map_err(|e| e as Box<dyn Error>)?.len() as u64;
+            if fills_chunk::<T>(t.get_size(), chunk_bytes) && run.0.records > 0 {
+                runs.push(mem::replace(&mut run, (RunSummary { records: 0, bytes: 0 }, 0)));
+            }
+            records += 1;
+            bytes += t.get_size();
+            serialized_bytes += serialized;
+            run.0.records += 1;
+            run.0.bytes += t.get_size();
+            run.1 += serialized;
+            let footprint = run.0.bytes + run.0.records.saturating_mul(size);
+            chunk_memory = chunk_memory.max(footprint);
+            if footprint >= chunk_bytes {
+                runs.push(mem::replace(&mut run, (RunSummary { records: 0, bytes: 0 }, 0)));
+            }
+        }
+        if run.0.records > 0 {
+            runs.push(run);
+        }
+
+        let record_bytes = bytes.checked_div(records).unwrap_or(0) + size;
+        let spill_memory = chunk_memory.saturating_mul(threads.min(runs.len().max(1) as u64));
+        let plan = self.project(runs, spill_memory, record_bytes);
+        Ok(DryRun { records, bytes, serialized_bytes, plan })
+    }
+
+    /// Threads holding chunks while spilling
+    fn spill_threads(&self) -> u64 {
+        match self.threads {
+            Some(threads) if threads > 1 => threads as u64,
+            _ => 1,
+        }
+    }
+
+    /// Share of the memory buffer for the chunks being spilled
+    fn spill_bytes(&self) -> u64 {
+        match self.premerge {
+            Some(_) => self.buffer_bytes / 2,
+            None => self.buffer_bytes,
+        }
+    }
+
+    /// Project the merge of `runs`, given with their sizes on disk, where
+    /// `spill_memory` is held by the chunks filled while spilling
+    /// and every record takes `record_bytes` of memory
+    fn project(&self, runs: Vec<(RunSummary, u64)>, spill_memory: u64, record_bytes: u64)
+               -> SortPlan {
+        let records_per_run = runs.first().map_or(0, |(run, _)| run.records);
+        let run_count = runs.len() as u64;
+        let total_memory =
+            runs.iter().map(|(run, _)| run.records).sum::<u64>().saturating_mul(record_bytes);
+        // the merge buffers hold at least one record of every run merged
+        let merge_memory = |runs: usize, budget: u64| {
+            budget.min(total_memory).max((runs as u64).saturating_mul(record_bytes))
+        };
+        let merged = |group: &[(RunSummary, u64)]| {
+            let run = RunSummary {
+                records: group.iter().map(|(r, _)| r.records).sum(),
+                bytes: group.iter().map(|(r, _)| r.bytes).sum(),
+            };
+            (run, group.iter().map(|(_, disk)| disk).sum())
+        };
+
+        let mut disk = 0;
+        let mut peak_disk = 0;
+        let mut levels = 0;
+        let mut peak_memory = spill_memory;
+        // runs left for the final merge, with their levels of background merges
+        let mut left: Vec<((RunSummary, u64), u32)> = Vec::new();
+        for run in runs {
+            disk += run.1;
+            peak_disk = peak_disk.max(disk);
+            left.push((run, 0));
+            let fan_in = match self.premerge {
+                Some(fan_in) => fan_in,
+                None => continue,
+            };
+            while left.len() >= fan_in {
+                let level = left[left.len() - 1].1;
+                if left[left.len() - fan_in..].iter().any(|(_, l)| *l != level) {
+                    break;
+                }
+                let group: Vec<_> = left.split_off(left.len() - fan_in)
+                                        .into_iter()
+                                        .map(|(run, _)| run)
+                                        .collect();
+                let run = merged(&group);
+                peak_disk = peak_disk.max(disk + run.1);
+                let background = merge_memory(fan_in, self.buffer_bytes / 2);
+                peak_memory = peak_memory.max(spill_memory + background);
+                levels = levels.max(level as usize + 1);
+                left.push((run, level + 1));
+            }
+        }
+
+        let mut sizes: Vec<_> = left.into_iter().map(|(run, _)| run).collect();
+        let mut merge = MergePlan::new(sizes.iter().map(|(run, _)| *run).collect(),
+                                       self.tag.clone());
+        if let Some(ref hook) = self.on_merge_plan {
+            hook(&mut merge);
+            if merge.check().is_err() {
+                merge = MergePlan::new(merge.runs, self.tag.clone());
+            }
+        }
+        for (i, pass) in merge.passes.iter().enumerate() {
+            let mut next = Vec::new();
+            for group in pass {
+                let run = merged(&sizes[group.clone()]);
+                if i < merge.passes.len() - 1 && group.len() > 1 {
+                    peak_disk = peak_disk.max(disk + run.1);
+                }
+                peak_memory = peak_memory.max(merge_memory(group.len(), self.buffer_bytes));
+                next.push(run);
+            }
+            sizes = next;
+        }
+
+        SortPlan {
+            records_per_run,
+            runs: run_count,
+            merge_passes: levels + merge.passes.len(),
+            merge,
+            peak_disk_bytes: peak_disk,
+            peak_memory_bytes: peak_memory,
+        }
+    }
+
+    /// Sort the `T`s provided by `unsorted` and return a sorted (ascending)
+    /// iterator
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    pub fn sort<I>(&self, unsorted: I) -> Result<ExtSortedIterator<T>, Box<dyn Error>>
+    where
+        I: Iterator<Item = T>,
+    {
+        self.sort_by_sync(unsorted, |a, b| a.cmp(b))
+    }
+
+    /// Sort (based on `compare`) the `T`s provided by `unsorted` and return an
+    /// iterator
+    ///
+    /// `compare` need not be `Send` or `Sync`, so it is only ever called on
+    /// the calling thread: chunks are sorted and written there whatever the
+    /// [threads](#method.threads), [premerge](#method.premerge) and
+    /// [watermarks](#method.watermarks) settings, and the returned iterator
+    /// panics if it is merged on another thread, as by the parts of
+    /// [split](struct.ExtSortedIterator.html#method.split) or by
+    /// [prefetch](struct.ExtSortedIterator.html#method.prefetch). Use
+    /// [sort_by_sync](#method.sort_by_sync) to sort and merge on other
+    /// threads.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    pub fn sort_by<I, F>(&self, unsorted: I, compare: F) -> Result<ExtSortedIterator<T>, Box<dyn Error>>
+    where
+        I: Iterator<Item = T>,
+        F: 'static + FnMut(&T, &T) -> Ordering,
+    {
+        let compare = LocalCompare::new(compare);
+        let sorter = ExternalSorter { threaded: None, ..self.clone() };
+        sorter.sort_by_sync(unsorted, move |a, b| compare.call(a, b))
+    }
+
+    /// Sort (based on `compare`) the `T`s provided by `unsorted` and return an
+    /// iterator, sharing `compare` between threads
+    ///
+    /// Unlike with [sort_by](#method.sort_by), chunks are sorted and merged
+    /// on the threads of [threads](#method.threads),
+    /// [premerge](#method.premerge) and [watermarks](#method.watermarks), and
+    /// the returned iterator, as well as the parts of
+    /// [split](struct.ExtSortedIterator.html#method.split), can be merged on
+    /// any thread, all calling `compare` at the same time.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    pub fn sort_by_sync<I, F>(&self, unsorted: I, compare: F)
+                              -> Result<ExtSortedIterator<T>, Box<dyn Error>>
+    where
+        I: Iterator<Item = T>,
+        F: 'static + Fn(&T, &T) -> Ordering + Send + Sync,
+    {
+        self.sort_shared(unsorted, Arc::new(compare)).map_err(|e| e as Box<dyn Error>)
+    }
+
+    /// Sort the `T`s provided by `unsorted` by the key that `key` extracts from
+    /// each of them, and return an iterator
+    ///
+    /// Keys are extracted again for every comparison, both when sorting the
+    /// chunks and when merging them. Records with equal keys are merged in
+    /// the order of the input.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    pub fn sort_by_key<I, K, KF>(&self, unsorted: I, key: KF)
+                                 -> Result<ExtSortedIterator<T>, Box<dyn Error>>
+    where
+        I: Iterator<Item = T>,
+        K: Ord,
+        KF: 'static + Fn(&T) -> K + Send + Sync,
+    {
+        self.sort_by_sync(unsorted, move |a, b| key(a).cmp(&key(b)))
+    }
+
+    /// Sort the `T`s provided by `unsorted` and merge them into the sorted
+    /// (ascending) output previously written to `sorted`, returning an
+    /// iterator over both
+    ///
+    /// See [sort_onto_by](#method.sort_onto_by).
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading `sorted` (including if it is
+    /// not sorted), writing intermediate sorted chunks to disk, or due to serde
+    /// issues
+    pub fn sort_onto<P, I>(&self, sorted: P, unsorted: I)
+                           -> Result<ExtSortedIterator<T>, Box<dyn Error>>
+    where
+        P: AsRef<Path>,
+        I: Iterator<Item = T>,
+    {
+        self.sort_onto_by(sorted, unsorted, |a, b| a.cmp(b))
+    }
+
+    /// Sort (based on `compare`) the `T`s provided by `unsorted` and merge
+    /// them into the output previously sorted by `compare` and written to
+    /// `sorted`, returning an iterator over both
+    ///
+    /// `sorted` holds newline-delimited JSON records, as written by
+    /// [write_indexed](struct.ExtSortedIterator.html#method.write_indexed) or
+    /// [write_partitions](struct.ExtSortedIterator.html#method.write_partitions),
+    /// and is merged as one more sorted run, without sorting or copying it
+    /// again: it is only read once to check its order (and sample it) before
+    /// the merge reads it. It is left in place, so the merged output must be
+    /// written elsewhere, e.g. to replace `sorted` once complete. Records of
+    /// `sorted` come before the new records they compare as equal to, as if
+    /// the new records were appended to it.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading `sorted` (including if it is
+    /// not sorted), writing intermediate sorted chunks to disk, or due to serde
+    /// issues
+    pub fn sort_onto_by<P, I, F>(&self, sorted: P, unsorted: I, compare: F)
+                                 -> Result<ExtSortedIterator<T>, Box<dyn Error>>
+    where
+        P: AsRef<Path>,
+        I: Iterator<Item = T>,
+        F: 'static + Fn(&T, &T) -> Ordering + Send + Sync,
+    {
+        let compare: Arc<CompareFn<T>> = Arc::new(compare);
+        let run = scan_run(sorted.as_ref(), &*compare).map_err(|e| e as Box<dyn Error>)?;
+        let batch = self.sort_shared(unsorted, compare).map_err(|e| e as Box<dyn Error>)?;
+        // the run is merged with the comparator of the batch, tie-breaking
+        // policy included
+        let mut existing = ExtSortedIterator::from_runs(Vec::new(),
+                                                        batch.sort_by_fn.clone(),
+                                                        vec![run],
+                                                        self.buffer_bytes)
+            .map_err(|e| e as Box<dyn Error>)?;
+        existing.terminator = self.terminator;
+        existing.io_priority = self.io_priority;
+        existing.time_merge = self.time_merge;
+        ExtSortedIterator::union(vec![existing, batch])
+    }
+
+    /// Compact a previous snapshot with a delta of changes: merge the `T`s
+    /// provided by `delta` into the output previously sorted by `key` and
+    /// written to `snapshot`, keeping only the last record of every key
+    ///
+    /// Records of the delta win over those of the snapshot, and later records
+    /// of the delta over earlier ones, so the returned iterator yields the
+    /// latest version of every key, in the order of the keys. Only the delta
+    /// is sorted, as in [sort_onto_by](#method.sort_onto_by), so the output
+    /// can be written (e.g. with
+    /// [write_indexed](struct.ExtSortedIterator.html#method.write_indexed))
+    /// to become the next snapshot. Deletions can be carried as tombstone
+    /// records, which win like any other change and are then filtered out of
+    /// the output.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading `snapshot` (including if it
+    /// is not sorted by `key`), writing intermediate sorted chunks to disk, or
+    /// due to serde issues. It also fails if the [tie-breaking
+    /// policy](#method.tie_break) does not keep equal records in input order.
+    pub fn compact_onto<P, I, K, KF>(&self, snapshot: P, delta: I, key: KF)
+                                     -> Result<ExtSortedIterator<T>, Box<dyn Error>>
+    where
+        P: AsRef<Path>,
+        I: Iterator<Item = T>,
+        K: Ord,
+        KF: 'static + Fn(&T) -> K + Send + Sync,
+    {
+        if !matches!(self.ties, TieBreak::InputOrder) {
+            return Err("compaction needs records with equal keys in input order".into());
+        }
+        let key = Arc::new(key);
+        let compare = {
+            let key = key.clone();
+            move |a: &T, b: &T| key(a).cmp(&key(b))
+        };
+        let merged = self.sort_onto_by(snapshot, delta, compare)?;
+        Ok(merged.dedup_by_key(move |t| key(t), DedupPolicy::KeepLast))
+    }
+
+    /// Sort the `T`s provided by `unsorted` with a shared comparator, with an
+    /// error type that can be sent between threads
+    pub(crate) fn sort_shared<I>(&self, unsorted: I, compare: Arc<CompareFn<T>>)
+                                 -> Result<ExtSortedIterator<T>, SendError>
+    where
+        I: Iterator<Item = T>,
+    {
+        let timer = Timer::start();
+        let compare = self.break_ties(compare);
+        let input = self.track_input();
+        let capture = self.capture
+                          .as_ref()
+                          .map(|(path, window)| Capture::new(path.clone(), *window));
+        let unsorted = unsorted.inspect(|t| {
+                                   if let Some(ref capture) = capture {
+                                       capture.record(t);
+                                   }
+                               });
+        let sorted = self.spill_runs(unsorted, &compare, &input)
+                         .and_then(|(tmp_dir, chunk_meta)| {
+                             self.merge_runs(vec![tmp_dir], compare, chunk_meta, timer)
+                         });
+        match capture {
+            Some(capture) => capture.finish(sorted),
+            None => sorted,
+        }
+    }
+
+    /// Make the sorted runs of `unsorted` in a temporary directory of their
+    /// own, counting its records with `input`
+    pub(crate) fn spill_runs<I>(&self, unsorted: I, compare: &Arc<CompareFn<T>>,
+                                input: &InputTracker)
+                                -> Result<(Arc<TempDir>, Vec<ChunkMeta<T>>), SendError>
+    where
+        I: Iterator<Item = T>,
+    {
+        let tmp_dir = Arc::new(self.make_tmp_dir()?);
+        let unsorted = unsorted.inspect(|t| input.record(t));
+        #[cfg(feature = "tracing")]
+        let span = self.run_generation_span(&tmp_dir);
+        let chunk_meta = self.spill_with(&tmp_dir, compare, |chunk_bytes, chunks| {
+                                 self.spill_iter(unsorted, compare, &tmp_dir, chunk_bytes, chunks)
+                             })?;
+        #[cfg(feature = "tracing")]
+        trace_runs(span, &chunk_meta);
+        Ok((tmp_dir, chunk_meta))
+    }
+
+    /// Configuration of the sorter, as reported by the sorts it makes
+    pub(crate) fn config(&self) -> SortConfig {
+        SortConfig { buffer_bytes: self.buffer_bytes,
+                     threads: self.threads,
+                     premerge: self.premerge,
+                     check_sorted: self.check_sorted,
+                     tag: self.tag.clone() }
+    }
+
+    /// Merge the runs of a sort started when `timer` was, whose files are in
+    /// `tmp_dirs` (any intermediate passes are written to the last of them)
+    pub(crate) fn merge_runs(&self, mut tmp_dirs: Vec<Arc<TempDir>>, compare: Arc<CompareFn<T>>,
+                             chunk_meta: Vec<ChunkMeta<T>>, timer: Timer)
+                             -> Result<ExtSortedIterator<T>, SendError> {
+        let tmp_dir = match tmp_dirs.last() {
+            Some(tmp_dir) => tmp_dir.clone(),
+            None => {
+                let tmp_dir = Arc::new(self.make_tmp_dir()?);
+                tmp_dirs.push(tmp_dir.clone());
+                tmp_dir
+            },
+        };
+        let chunk_meta = self.plan_merge(&tmp_dir, &compare, chunk_meta)?;
+
+        let mut iter = ExtSortedIterator::from_chunks(tmp_dir, compare, chunk_meta,
+                                                      self.buffer_bytes, self.allocator.clone())?;
+        iter.tmp_dirs = tmp_dirs;
+        Ok(self.track_merge(iter, timer))
+    }
+
+    /// Make the initial chunks of `unsorted` on disk, on the calling thread or
+    /// on worker threads, after writing its sorted prefix if checking for one
+    fn spill_iter<I>(&self, mut unsorted: I, compare: &Arc<CompareFn<T>>, tmp_dir: &TempDir,
+                     chunk_bytes: u64, chunks: &Sender<(u64, ChunkMeta<T>)>)
+                     -> Result<(), SendError>
+    where
+        I: Iterator<Item = T>,
+    {
+        let mut seq = 0;
+        let mut first = None;
+        if self.check_sorted {
+            let path = tmp_dir.path().join("sorted_run");
+            let (run, next) = spill_sorted_run(&mut unsorted, compare, &path)?;
+            if let Some(meta) = run {
+                seq = meta.records;
+                send_chunk(chunks, 0, meta, self.tag_usage().as_deref())?;
+            }
+            first = next;
+        }
+        let mut unsorted = first.into_iter().chain(unsorted);
+        let policy = self.chunk_policy(compare);
+        match (self.threaded, self.threads, self.watermarks) {
+            (Some(threaded), Some(threads), _) if threads > 1 => {
+                (threaded.spill)(&mut unsorted, &policy, tmp_dir, chunk_bytes, threads, seq, chunks)
+            },
+            (Some(threaded), _, Some(watermarks)) => {
+                (threaded.spill_watermarked)(&mut unsorted, &policy, tmp_dir, chunk_bytes,
+                                             watermarks, seq, chunks)
+            },
+            _ => spill(unsorted, &policy, tmp_dir, chunk_bytes, seq, chunks),
+        }
+    }
+
+    /// How the initial chunks are made and sorted with `compare`, under the
+    /// tie-breaking policy
+    fn chunk_policy(&self, compare: &Arc<CompareFn<T>>) -> ChunkPolicy<T> {
+        ChunkPolicy {
+            compare: compare.clone(),
+            stable: !matches!(self.ties, TieBreak::Unspecified),
+            skew: self.skew,
+            #[cfg(feature = "pressure")]
+            pressure: self.pressure.map(MemoryPressure::new),
+            io_priority: self.io_priority,
+            quota: self.tag_usage(),
+            allocator: self.allocator.clone(),
+            run_length: self.run_length,
+        }
+    }
+
+    /// Spills of the tag of the sorts of this sorter, if any
+    fn tag_usage(&self) -> Option<Arc<TagUsage>> {
+        self.tag.as_ref().map(|tag| self.tags.usage(tag))
+    }
+
+    /// Sort the `T`s provided by the parallel iterator `unsorted` and return a
+    /// sorted (ascending) iterator
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    #[cfg(feature = "rayon")]
+    pub fn par_sort<I>(&self, unsorted: I) -> Result<ExtSortedIterator<T>, Box<dyn Error>>
+    where
+        T: Send,
+        I: ParallelIterator<Item = T>,
+    {
+        self.par_sort_by(unsorted, |a, b| a.cmp(b))
+    }
+
+    /// Sort (based on `compare`) the `T`s provided by the parallel iterator
+    /// `unsorted` and return an iterator
+    ///
+    /// Each rayon worker accumulates and spills its own chunks, so the memory
+    /// buffer is divided evenly between the threads of the pool. The chunks of
+    /// all workers are combined in the final merge.
+    ///
+    /// The work is done on the pool set with
+    /// [thread_pool](#method.thread_pool), on a new pool sized by
+    /// [threads](#method.threads), or on the current pool, in that order of
+    /// preference.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    #[cfg(feature = "rayon")]
+    pub fn par_sort_by<I, F>(&self, unsorted: I, compare: F)
+                             -> Result<ExtSortedIterator<T>, Box<dyn Error>>
+    where
+        T: Send,
+        I: ParallelIterator<Item = T>,
+        F: 'static + Fn(&T, &T) -> Ordering + Send + Sync,
+    {
+        let compare = self.break_ties(Arc::new(compare));
+        self.par_sort_with(compare, |input, policy, tmp_dir, chunk_bytes, chunks| {
+                par_spill(unsorted.inspect(|t| input.record(t)), policy, tmp_dir, chunk_bytes,
+                          chunks)
+            })
+    }
+
+    /// Sort the `T`s provided by the indexed parallel iterator `unsorted`
+    /// (such as that of a `Vec` or a range) and return a sorted (ascending)
+    /// iterator, keeping equal records in input order
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    #[cfg(feature = "rayon")]
+    pub fn par_sort_stable<I>(&self, unsorted: I) -> Result<ExtSortedIterator<T>, Box<dyn Error>>
+    where
+        T: Send,
+        I: IndexedParallelIterator<Item = T>,
+    {
+        self.par_sort_stable_by(unsorted, |a, b| a.cmp(b))
+    }
+
+    /// Sort (based on `compare`) the `T`s provided by the indexed parallel
+    /// iterator `unsorted` and return an iterator, keeping equal records in
+    /// input order under the [tie-breaking policy](#method.tie_break)
+    ///
+    /// Like [par_sort_by](#method.par_sort_by), every rayon worker spills its
+    /// own chunks, but each chunk only holds consecutive records of the input
+    /// and is numbered by the position of its first record, so the merge can
+    /// order equal records of different chunks as they were in the input.
+    /// Workers spill the records they have left over once they are done with
+    /// their part of the input, rather than combining them with those of
+    /// other workers, unless they are consecutive, so there may be a few more
+    /// (smaller) runs than with [par_sort_by](#method.par_sort_by).
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    #[cfg(feature = "rayon")]
+    pub fn par_sort_stable_by<I, F>(&self, unsorted: I, compare: F)
+                                    -> Result<ExtSortedIterator<T>, Box<dyn Error>>
+    where
+        T: Send,
+        I: IndexedParallelIterator<Item = T>,
+        F: 'static + Fn(&T, &T) -> Ordering + Send + Sync,
+    {
+        let compare = self.break_ties(Arc::new(compare));
+        self.par_sort_with(compare, |input, policy, tmp_dir, chunk_bytes, chunks| {
+                par_spill_stable(unsorted.inspect(|t| input.record(t)), policy, tmp_dir,
+                                 chunk_bytes, chunks)
+            })
+    }
+
+    /// Sort with `spill`, which makes the initial chunks on disk from the
+    /// workers of the current rayon pool, on the pool of the sorter
+    #[cfg(feature = "rayon")]
+    fn par_sort_with<S>(&self, compare: Arc<CompareFn<T>>, spill: S)
+                        -> Result<ExtSortedIterator<T>, Box<dyn Error>>
+    where
+        T: Send,
+        S: FnOnce(&InputTracker, &ChunkPolicy<T>, &TempDir, u64, &Sender<(u64, ChunkMeta<T>)>)
+                  -> Result<(), SendError>
+            + Send,
+    {
+        let timer = Timer::start();
+        let tmp_dir = Arc::new(self.make_tmp_dir().map_err(|e| e as Box<dyn Error>)?);
+        let input = self.track_input();
+        #[cfg(feature = "tracing")]
+        let span = self.run_generation_span(&tmp_dir);
+        let pool = match (&self.pool, self.threads) {
+            (Some(pool), _) => Some(pool.clone()),
+            (None, Some(threads)) => {
+                Some(Arc::new(ThreadPoolBuilder::new().num_threads(threads).build()?))
+            },
+            (None, None) => None,
+        };
+        let policy = self.chunk_policy(&compare);
+        let chunk_meta = self.spill_with(&tmp_dir, &compare, |chunk_bytes, chunks| {
+                                 let spill =
+                                     || spill(&input, &policy, &tmp_dir, chunk_bytes, chunks);
+                                 match pool {
+                                     Some(pool) => pool.install(spill),
+                                     None => spill(),
+                                 }
+                             })
+                             .map_err(|e| e as Box<dyn Error>)?;
+        #[cfg(feature = "tracing")]
+        trace_runs(span, &chunk_meta);
+        let chunk_meta =
+            self.plan_merge(&tmp_dir, &compare, chunk_meta).map_err(|e| e as Box<dyn Error>)?;
+
+        let iter = ExtSortedIterator::from_chunks(tmp_dir, compare, chunk_meta,
+                                                  self.buffer_bytes, self.allocator.clone())
+            .map_err(|e| e as Box<dyn Error>)?;
+        Ok(self.track_merge(iter, timer))
+    }
+
+    /// Enter a span covering the generation of the initial sorted runs
+    #[cfg(feature = "tracing")]
+    fn run_generation_span(&self, tmp_dir: &TempDir) -> tracing::span::EnteredSpan {
+        tracing::info_span!("run_generation",
+                            tmp_dir = %tmp_dir.path().display(),
+                            buffer_bytes = self.buffer_bytes,
+                            threads = self.threads.unwrap_or(1),
+                            premerge = self.premerge.unwrap_or(0),
+                            tag = self.tag.as_deref().unwrap_or(""))
+            .entered()
+    }
+
+    /// Pass the plan of the merge of `chunk_meta` to the hook, if any, and
+    /// merge its intermediate passes, returning the chunks left for the final
+    /// merge
+    fn plan_merge(&self, tmp_dir: &Arc<TempDir>, compare: &Arc<CompareFn<T>>,
+                  mut chunk_meta: Vec<ChunkMeta<T>>)
+                  -> Result<Vec<ChunkMeta<T>>, SendError> {
+        if self.verify_runs {
+            write_manifest(tmp_dir.path(), &chunk_meta)?;
+        }
+        let hook = match self.on_merge_plan {
+            Some(ref hook) => hook,
+            None => return Ok(chunk_meta),
+        };
+        let _priority = IoPriorityGuard::set(self.io_priority);
+        let runs = chunk_meta.iter()
+                             .map(|m| RunSummary { records: m.records, bytes: m.bytes })
+                             .collect();
+        let mut plan = MergePlan::new(runs, self.tag.clone());
+        hook(&mut plan);
+        plan.check()?;
+
+        let mut merged = 0;
+        // the last pass is the final merge
+        for pass in &plan.passes[..plan.passes.len() - 1] {
+            let mut runs = chunk_meta.into_iter();
+            chunk_meta = Vec::new();
+            for group in pass {
+                let group: Vec<_> = runs.by_ref().take(group.len()).collect();
+                if group.len() == 1 {
+                    chunk_meta.extend(group);
+                    continue;
+                }
+                let path = tmp_dir.path().join(format!("planned_{}", merged));
+                merged += 1;
+                chunk_meta.push(merge_chunks(group, &path, tmp_dir, compare, self.buffer_bytes,
+                                             &self.allocator)?);
+            }
+        }
+
+        Ok(chunk_meta)
+    }
+
+    /// Start following the records read from the input of a sort
+    pub(crate) fn track_input(&self) -> InputTracker {
+        InputTracker {
+            callback: self.on_progress.clone().map(|callback| {
+                          ProgressTracker::new(callback, self.progress_interval,
+                                               ProgressPhase::Spilling, None)
+                      }),
+            #[cfg(feature = "indicatif")]
+            bar: self.progress.clone().map(InputProgress::new),
+            #[cfg(feature = "metrics")]
+            records_in: metrics::records_in(),
+        }
+    }
+
+    /// Record the configuration of the sort in `iter`, and switch the
+    /// progress reports and bar, if any, to the merge it does
+    fn track_merge(&self, mut iter: ExtSortedIterator<T>, timer: Timer) -> ExtSortedIterator<T> {
+        let (ingest, ingest_cpu) = timer.elapsed();
+        iter.timings.ingest = ingest;
+        iter.timings.ingest_cpu = ingest_cpu;
+        iter.config = Some(self.config());
+        iter.terminator = self.terminator;
+        iter.io_priority = self.io_priority;
+        iter.time_merge = self.time_merge;
+        if self.arena_batches {
+            iter.arenas = Some(Vec::new());
+        }
+        let records = iter.chunk_records();
+        if let Some(ref callback) = self.on_progress {
+            let tracker = ProgressTracker::new(callback.clone(), self.progress_interval,
+                                               ProgressPhase::Merging, Some(records));
+            tracker.report_now();
+            iter.on_progress = Some(tracker);
+        }
+        #[cfg(feature = "indicatif")]
+        if let Some(ref bar) = self.progress {
+            let bytes = iter.chunk_meta.iter().map(|m| m.bytes).sum();
+            progress_bar::start_merge(bar, records, bytes);
+            iter.progress = Some(bar.clone());
+        }
+        iter
+    }
+
+    /// Run `spill`, which writes sorted chunks of up to `chunk_bytes` and
+    /// sends their sequence numbers and metadata to `chunks`, while merging
+    /// them in the background if enabled. Returns the metadata of the chunks
+    /// left for the final merge, in input order.
+    ///
+    /// The sequence number of a chunk is the position in the input of its
+    /// first record, so that the chunk following it in the input starts at
+    /// its sequence number plus its number of records.
+    fn spill_with<S>(&self, tmp_dir: &Arc<TempDir>, compare: &Arc<CompareFn<T>>, spill: S)
+                     -> Result<Vec<ChunkMeta<T>>, SendError>
+    where
+        S: FnOnce(u64, &Sender<(u64, ChunkMeta<T>)>) -> Result<(), SendError>,
+    {
+        let _priority = IoPriorityGuard::set(self.io_priority);
+        if let (Some(threaded), Some(fan_in)) = (self.threaded, self.premerge) {
+            return (threaded.spill_premerged)(self, tmp_dir, compare, fan_in, Box::new(spill));
+        }
+        let (tx, rx) = mpsc::channel();
+        spill(self.buffer_bytes, &tx)?;
+        drop(tx);
+        let mut chunk_meta: Vec<_> = rx.into_iter().collect();
+        chunk_meta.sort_by_key(|(seq, _)| *seq);
+        Ok(chunk_meta.into_iter().map(|(_, meta)| meta).collect())
+    }
+
+    pub(crate) fn make_tmp_dir(&self) -> Result<TempDir, SendError> {
+        let prefix = match self.tag {
+            Some(ref tag) => format!("sort_fasta_{}", tag::dir_name(tag)),
+            None => "sort_fasta".to_string(),
+        };
+        let tmp_dir = match self.tmp_dir {
+            Some(ref p) => TempDir::new_in(p, &prefix)?,
+            None => TempDir::new(&prefix)?,
+        };
+        if let Some(usage) = self.tag_usage() {
+            usage.register(tmp_dir.path());
+        }
+        #[cfg(feature = "log")]
+        log::debug!("created temporary directory {}", tmp_dir.path().display());
+        Ok(tmp_dir)
+    }
+}
+
+/// Spill of a sort, given the size of its chunks and where to send them
+type SpillChunks<'a, T> =
+    Box<dyn FnOnce(u64, &Sender<(u64, ChunkMeta<T>)>) -> Result<(), SendError> + 'a>;
+
+/// Spill of a sort on other threads, given its records, the chunk policy, the
+/// temporary directory, the size of the chunks, the `settings` that spread
+/// the work, the first sequence number and where to send the chunks
+type ThreadedSpillFn<T, S> = fn(&mut dyn Iterator<Item = T>, &ChunkPolicy<T>, &TempDir, u64, S,
+                                u64, &Sender<(u64, ChunkMeta<T>)>)
+                                -> Result<(), SendError>;
+
+/// Spill of a sort merging its chunks in the background
+type PremergedSpillFn<T> = fn(&ExternalSorter<T>, &Arc<TempDir>, &Arc<CompareFn<T>>, usize,
+                              SpillChunks<'_, T>)
+                              -> Result<Vec<ChunkMeta<T>>, SendError>;
+
+/// The parts of a sort that hand records over to other threads, set by the
+/// settings that use them, where records are known to be `Send`, so that
+/// sorts of other records never need them to be
+struct Threaded<T>
+where
+    T: ExternallySortable,
+{
+    spill: ThreadedSpillFn<T, usize>,
+    spill_watermarked: ThreadedSpillFn<T, (f64, f64)>,
+    spill_premerged: PremergedSpillFn<T>,
+}
+
+impl<T> Threaded<T>
+where
+    T: ExternallySortable + Send,
+{
+    fn new() -> Threaded<T> {
+        Threaded { spill: |unsorted, policy, tmp_dir, chunk_bytes, threads, seq, chunks| {
+                       spill_threaded(unsorted, policy, tmp_dir, chunk_bytes, threads, seq, chunks)
+                   },
+                   spill_watermarked:
+                       |unsorted, policy, tmp_dir, chunk_bytes, watermarks, seq, chunks| {
+                           spill_watermarked(unsorted, policy, tmp_dir, chunk_bytes, watermarks,
+                                             seq, chunks)
+                       },
+                   spill_premerged }
+    }
+}
+
+impl<T> Clone for Threaded<T>
+where
+    T: ExternallySortable,
+{
+    fn clone(&self) -> Threaded<T> {
+        *self
+    }
+}
+
+impl<T> Copy for Threaded<T> where T: ExternallySortable {}
+
+/// Spill with `spill` to half of the buffer of `sorter`, while merging the
+/// chunks `fan_in` at a time in the background with the other half
+fn spill_premerged<T>(sorter: &ExternalSorter<T>, tmp_dir: &Arc<TempDir>,
+                      compare: &Arc<CompareFn<T>>, fan_in: usize, spill: SpillChunks<'_, T>)
+                      -> Result<Vec<ChunkMeta<T>>, SendError>
+where
+    T: ExternallySortable + Send,
+{
+    let (tx, rx) = mpsc::channel();
+    let half = sorter.buffer_bytes / 2;
+    thread::scope(|scope| {
+        let merger = scope.spawn(in_current_span(|| {
+                                                     let _priority =
+                                                         IoPriorityGuard::set(sorter.io_priority);
+                                                     premerge(rx, fan_in, tmp_dir, compare, half,
+                                                              &sorter.allocator)
+                                                 }));
+        let spilled = spill(half, &tx);
+        drop(tx);
+        let merged = merger.join().unwrap_or_else(|e| panic::resume_unwind(e));
+        spilled?;
+        merged
+    })
+}
+
+/// Write the records of `unsorted` straight to a chunk at `path` for as long
+/// as they are in order, without buffering or sorting them, returning the
+/// chunk (if any records were in order) and the first record out of order
+fn spill_sorted_run<T, I>(unsorted: &mut I, compare: &Arc<CompareFn<T>>, path: &Path)
+                          -> Result<(Option<ChunkMeta<T>>, Option<T>), SendError>
+where
+    T: ExternallySortable,
+    I: Iterator<Item = T>,
+{
+    let mut writer: Option<ChunkWriter<T>> = None;
+    let mut last: Option<T> = None;
+    let mut next = None;
+    for t in unsorted {
+        if last.as_ref().is_some_and(|l| compare(l, &t) == Greater) {
+            next = Some(t);
+            break;
+        }
+        if writer.is_none() {
+            writer = Some(ChunkWriter::streaming(path)?);
+        }
+        // unwrap due to the check above
+        writer.as_mut().unwrap().push(&t)?;
+        last = Some(t);
+    }
+    let run = match writer {
+        Some(writer) => Some(writer.finish(last)?),
+        None => None,
+    };
+
+    Ok((run, next))
+}
+
+/// Gather the metadata of a sorted run written to `path` outside of a sort,
+/// reading it once to check that it is sorted by `compare`
+pub(crate) fn scan_run<T>(path: &Path, compare: &CompareFn<T>) -> Result<ChunkMeta<T>, SendError>
+where
+    T: ExternallySortable,
+{
+    let mut input = BufReader::new(File::open(path)?);
+    let mut scanner = ChunkWriter::scanning(path);
+    let mut line = String::new();
+    let mut last: Option<T> = None;
+    while input.read_line(&mut line)? > 0 {
+        let t: T = serde_json::from_str(&line)?;
+        if last.as_ref().is_some_and(|l| compare(l, &t) == Greater) {
+            return Err(format!("{} is not sorted at line {}",
+                               path.display(),
+                               scanner.records + 1).into());
+        }
+        scanner.record(&t, line.len() as u64);
+        last = Some(t);
+        line.clear();
+    }
+
+    Ok(scanner.into_meta(last))
+}
+
+/// Make the initial chunks on disk, sorting and writing them on the calling
+/// thread
+fn spill<T, I>(unsorted: I, policy: &ChunkPolicy<T>, tmp_dir: &TempDir, chunk_bytes: u64,
+               mut seq: u64, chunks: &Sender<(u64, ChunkMeta<T>)>)
+               -> Result<(), SendError>
+where
+    T: ExternallySortable,
+    I: Iterator<Item = T>,
+{
+    let mut total_read = 0;
+    let mut chunk = policy.new_chunk(chunk_bytes);
+    let write = |chunk: &mut Vec<T>, seq: &mut u64| -> Result<(), SendError> {
+        let mut large = policy.split(chunk);
+        for part in iter::once(&mut *chunk).chain(&mut large) {
+            let meta = sort_and_write(part, policy, &tmp_dir.path().join(seq.to_string()))?;
+            let records = meta.records;
+            send_chunk(chunks, *seq, meta, policy.quota.as_deref())?;
+            *seq += records;
+        }
+        chunk.clear();
+        Ok(())
+    };
+
+    for t in unsorted {
+        let size = t.get_size();
+        let limit = policy.limit(chunk_bytes);
+        if fills_chunk::<T>(size, limit) && !chunk.is_empty() {
+            write(&mut chunk, &mut seq)?;
+            total_read = 0;
+        }
+        total_read += size;
+        chunk.push(t);
+        if footprint(&chunk, total_read) >= limit {
+            write(&mut chunk, &mut seq)?;
+            total_read = 0;
+        }
+    }
+    // write the last chunk
+    if !chunk.is_empty() {
+        write(&mut chunk, &mut seq)?;
+    }
+    policy.release_chunk(chunk);
+
+    Ok(())
+}
+
+/// Make the initial chunks on disk, handing the smallest records of the
+/// buffered chunk off to a background thread to be written once the chunk
+/// passes the `high` watermark, down to the `low` one, and buffering the rest
+/// along with further input
+fn spill_watermarked<T, I>(unsorted: I, policy: &ChunkPolicy<T>, tmp_dir: &TempDir,
+                           chunk_bytes: u64, (high, low): (f64, f64), mut seq: u64,
+                           chunks: &Sender<(u64, ChunkMeta<T>)>)
+                           -> Result<(), SendError>
+where
+    T: ExternallySortable + Send,
+    I: Iterator<Item = T>,
+{
+    // a rendezvous channel, so that no more than one run is written at a time
+    let (tx, rx) = mpsc::sync_channel::<(u64, Vec<T>, Duration)>(0);
+
+    thread::scope(|scope| {
+        let writer = scope.spawn(in_current_span(|| -> Result<(), SendError> {
+            let _priority = IoPriorityGuard::set(policy.io_priority);
+            for (seq, run, sort_time) in rx {
+                let path = tmp_dir.path().join(seq.to_string());
+                let mut meta = write_chunk(&path, &run, policy.run_length)?;
+                policy.release_chunk(run);
+                meta.sort_time = sort_time;
+                send_chunk(chunks, seq, meta, policy.quota.as_deref())?;
+            }
+            Ok(())
+        }));
+
+        // sort the chunk and hand off its smallest records, or its parts if
+        // they are split, until what is left takes up no more than `keep`,
+        // returning whether they were all sent, which only fails if the
+        // writer has failed
+        let hand_off = |chunk: &mut Vec<T>, total_read: &mut u64, keep: u64, seq: &mut u64| {
+            let started = Instant::now();
+            let compare = &policy.compare;
+            if policy.stable {
+                chunk.sort_by(|a, b| compare(a, b));
+            } else {
+                chunk.sort_unstable_by(|a, b| compare(a, b));
+            }
+            let sort_time = started.elapsed();
+            // keep the largest records that fit in `keep` along with a buffer
+            // just large enough for them, handing off at least the smallest
+            let mut kept = chunk.len();
+            let mut kept_bytes = 0;
+            while keep > 0 && kept > 1 {
+                let size = chunk[kept - 1].get_size();
+                let buffer = ((chunk.len() - kept + 1) * mem::size_of::<T>()) as u64;
+                if kept_bytes + size + buffer > keep {
+                    break;
+                }
+                kept -= 1;
+                kept_bytes += size;
+            }
+            let kept = chunk.split_off(kept);
+            let mut run = mem::replace(chunk, kept);
+            *total_read = kept_bytes;
+            // the split keeps both parts sorted
+            let large = policy.split(&mut run);
+            for part in iter::once(run).chain(large) {
+                let records = part.len() as u64;
+                if tx.send((*seq, part, sort_time)).is_err() {
+                    return false;
+                }
+                *seq += records;
+            }
+            true
+        };
+        let mut total_read = 0;
+        let mut chunk = policy.new_chunk(chunk_bytes);
+        for t in unsorted {
+            let size = t.get_size();
+            let limit = policy.limit(chunk_bytes);
+            if fills_chunk::<T>(size, limit)
+               && !chunk.is_empty()
+               && !hand_off(&mut chunk, &mut total_read, 0, &mut seq)
+            {
+                break;
+            }
+            total_read += size;
+            chunk.push(t);
+            if footprint(&chunk, total_read) as f64 >= high * limit as f64 {
+                let keep = (low * limit as f64) as u64;
+                if !hand_off(&mut chunk, &mut total_read, keep, &mut seq) {
+                    break;
+                }
+            }
+        }
+        if !chunk.is_empty() {
+            // a failed send is reported by the writer
+            hand_off(&mut chunk, &mut total_read, 0, &mut seq);
+        }
+        policy.release_chunk(chunk);
+        drop(tx);
+
+        writer.join().unwrap_or_else(|e| panic::resume_unwind(e))
+    })
+}
+
+/// Make the initial chunks on disk, handing full chunks off to `threads - 1`
+/// worker threads to be sorted and written
+fn spill_threaded<T, I>(unsorted: I, policy: &ChunkPolicy<T>, tmp_dir: &TempDir,
+                        chunk_bytes: u64, threads: usize, mut seq: u64,
+                        chunks: &Sender<(u64, ChunkMeta<T>)>)
+                        -> Result<(), SendError>
 where
-    T: ExternallySortable,
+    T: ExternallySortable + Send,
+    I: Iterator<Item = T>,
 {
-    type Item = Result<T, Box<dyn Error>>;
+    let chunk_bytes = chunk_bytes / threads as u64;
+    // a rendezvous channel, so no more than one chunk per thread is ever held
+    // in memory
+    let (tx, rx) = mpsc::sync_channel::<(u64, Vec<T>)>(0);
+    let rx = Mutex::new(rx);
 
-    ///
-    /// # Errors
-    ///
-    /// This method can fail due to issues reading intermediate sorted chunks
-    /// from disk, or due to serde deserialization issues
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.failed {
-            return None;
-        }
-        // fill up any empty buffers
-        let mut empty = true;
-        for chunk_num in 0..self.chunks {
-            if self.buffers[chunk_num as usize].is_empty() {
-                let mut f = match File::open(self.tmp_dir.path().join(chunk_num.to_string())) {
-                    Ok(f) => f,
-                    Err(e) => {
-                        self.failed = true;
-                        return Some(Err(Box::new(e)));
-                    }
-                };
-                match f.seek(Start(self.chunk_offsets[chunk_num as usize])) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        self.failed = true;
-                        return Some(Err(Box::new(e)));
+    thread::scope(|scope| {
+        let workers: Vec<_> = (1..threads)
+            .map(|_| {
+                scope.spawn(in_current_span(|| -> Result<(), SendError> {
+                    loop {
+                        let received = rx.lock().unwrap().recv();
+                        let (seq, mut chunk) = match received {
+                            Ok(c) => c,
+                            Err(_) => return Ok(()),
+                        };
+                        let path = tmp_dir.path().join(seq.to_string());
+                        let meta = sort_and_write(&mut chunk, policy, &path)?;
+                        policy.release_chunk(chunk);
+                        send_chunk(chunks, seq, meta, policy.quota.as_deref())?;
                     }
+                }))
+            })
+            .collect();
+
+        // hand off a chunk, or its parts if it is split, returning whether
+        // they were all sent, which only fails if every worker has failed
+        let hand_off = |mut chunk: Vec<T>, seq: &mut u64| -> bool {
+            let large = policy.split(&mut chunk);
+            for part in iter::once(chunk).chain(large) {
+                let records = part.len() as u64;
+                if tx.send((*seq, part)).is_err() {
+                    return false;
                 }
-                let bytes_read =
-                    match fill_buff(&mut self.buffers[chunk_num as usize], f, self.max_per_chunk) {
-                        Ok(bytes_read) => bytes_read,
-                        Err(e) => {
-                            self.failed = true;
-                            return Some(Err(e));
-                        }
-                    };
-                self.chunk_offsets[chunk_num as usize] += bytes_read;
-                if !self.buffers[chunk_num as usize].is_empty() {
-                    empty = false;
+                *seq += records;
+            }
+            true
+        };
+        let mut total_read = 0;
+        let mut chunk = policy.new_chunk(chunk_bytes);
+        for t in unsorted {
+            let size = t.get_size();
+            let limit = policy.limit(chunk_bytes);
+            if fills_chunk::<T>(size, limit) && !chunk.is_empty() {
+                if !hand_off(mem::replace(&mut chunk, policy.new_chunk(chunk_bytes)), &mut seq) {
+                    break;
                 }
-            } else {
-                empty = false;
+                total_read = 0;
+            }
+            total_read += size;
+            chunk.push(t);
+            if footprint(&chunk, total_read) >= limit {
+                if !hand_off(mem::replace(&mut chunk, policy.new_chunk(chunk_bytes)), &mut seq) {
+                    break;
+                }
+                total_read = 0;
             }
         }
-        if empty {
-            return None;
+        if chunk.is_empty() {
+            policy.release_chunk(chunk);
+        } else {
+            // a failed send is reported by the workers
+            hand_off(chunk, &mut seq);
         }
+        drop(tx);
 
-        // find the next record to write
-        // check is_empty() before unwrap()ing
-        let mut idx = 0;
-        for chunk_num in 0..self.chunks as usize {
-            if !self.buffers[chunk_num].is_empty() {
-                if self.buffers[idx].is_empty()
-                    || (self.sort_by_fn)(
-                        self.buffers[chunk_num].front().unwrap(),
-                        self.buffers[idx].front().unwrap(),
-                    ) == Less
-                {
-                    idx = chunk_num;
-                }
-            }
+        for worker in workers {
+            worker.join().unwrap_or_else(|e| panic::resume_unwind(e))?;
+        }
+        Ok(())
+    })
+}
+
+/// Make the initial chunks on disk from the workers of the current rayon pool
+#[cfg(feature = "rayon")]
+fn par_spill<T, I>(unsorted: I, policy: &ChunkPolicy<T>, tmp_dir: &TempDir, chunk_bytes: u64,
+                   chunks: &Sender<(u64, ChunkMeta<T>)>)
+                   -> Result<(), SendError>
+where
+    T: ExternallySortable + Send,
+    I: ParallelIterator<Item = T>,
+{
+    let worker_bytes = chunk_bytes / rayon::current_num_threads() as u64;
+    let next_seq = AtomicU64::new(0);
+    let spill = |chunk: &mut Vec<T>| -> Result<(), SendError> {
+        let mut large = policy.split(chunk);
+        for part in iter::once(&mut *chunk).chain(&mut large) {
+            // the chunks of different workers come in no particular order
+            let seq = next_seq.fetch_add(part.len() as u64, AtomicOrdering::SeqCst);
+            let meta = sort_and_write(part, policy, &tmp_dir.path().join(seq.to_string()))?;
+            send_chunk(chunks, seq, meta, policy.quota.as_deref())?;
         }
+        chunk.clear();
+        Ok(())
+    };
 
-        // unwrap due to checks above
-        let r = self.buffers[idx].pop_front().unwrap();
-        Some(Ok(r))
+    // every worker spills once its share of the buffer is full, and the
+    // partially filled chunks left over are combined before the last spill
+    let (mut chunk, _) = unsorted.try_fold(|| (Vec::new(), 0),
+                                           |(mut chunk, mut total_read), t| {
+                                               let size = t.get_size();
+                                               let limit = policy.limit(worker_bytes);
+                                               if fills_chunk::<T>(size, limit)
+                                                  && !chunk.is_empty()
+                                               {
+                                                   spill(&mut chunk)?;
+                                                   total_read = 0;
+                                               }
+                                               total_read += size;
+                                               chunk.push(t);
+                                               if footprint(&chunk, total_read) >= limit {
+                                                   spill(&mut chunk)?;
+                                                   total_read = 0;
+                                               }
+                                               Ok::<_, SendError>((chunk, total_read))
+                                           })
+                                 .try_reduce(|| (Vec::new(), 0),
+                                             |(mut chunk, total_read), (mut other, other_read)| {
+                                                 chunk.append(&mut other);
+                                                 let total_read = total_read + other_read;
+                                                 if footprint(&chunk, total_read) >= chunk_bytes {
+                                                     spill(&mut chunk)?;
+                                                     return Ok((chunk, 0));
+                                                 }
+                                                 Ok((chunk, total_read))
+                                             })?;
+    if !chunk.is_empty() {
+        spill(&mut chunk)?;
     }
+
+    Ok(())
 }
 
-/// Perform an external sort on an unsorted stream of incoming data
-///
-/// # Examples
-///
-/// ```
-/// extern crate external_sort;
-/// #[macro_use]
-/// extern crate serde_derive;
-///
-/// use external_sort::{ExternallySortable, ExternalSorter};
-///
-/// #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
-/// struct Num {
-///     the_num: u32
-/// }
-///
-/// impl Num {
-///     fn new(num:u32) -> Num {
-///         Num { the_num: num }
-///     }
-/// }
-///
-/// impl ExternallySortable for Num {
-///     fn get_size(&self) -> u64 {
-///         4
-///     }
-/// }
-///
-/// fn main() {
-///     let unsorted = vec![Num::new(5), Num::new(2), Num::new(1), Num::new(3),
-///         Num::new(4)]; 
-///     let sorted = vec![Num::new(1), Num::new(2), Num::new(3), Num::new(4), 
-///         Num::new(5)];
+/// Make the initial chunks on disk from the workers of the current rayon pool,
+/// each from consecutive records of the input, numbered by the position of
+/// their first record
+#[cfg(feature = "rayon")]
+fn par_spill_stable<T, I>(unsorted: I, policy: &ChunkPolicy<T>, tmp_dir: &TempDir,
+                          chunk_bytes: u64, chunks: &Sender<(u64, ChunkMeta<T>)>)
+                          -> Result<(), SendError>
+where
+    T: ExternallySortable + Send,
+    I: IndexedParallelIterator<Item = T>,
+{
+    let worker_bytes = chunk_bytes / rayon::current_num_threads() as u64;
+    let spill = |chunk: &mut Vec<T>, first: u64| -> Result<(), SendError> {
+        let mut seq = first;
+        let mut large = policy.split(chunk);
+        for part in iter::once(&mut *chunk).chain(&mut large) {
+            let meta = sort_and_write(part, policy, &tmp_dir.path().join(seq.to_string()))?;
+            seq += meta.records;
+            send_chunk(chunks, seq - meta.records, meta, policy.quota.as_deref())?;
+        }
+        chunk.clear();
+        Ok(())
+    };
+
+    // a chunk, the size of its records and the position of its first one
+    type Part<T> = (Vec<T>, u64, u64);
+    let fold = |(mut chunk, mut total_read, mut first): Part<T>, (position, t): (usize, T)| {
+        let size = t.get_size();
+        let limit = policy.limit(worker_bytes);
+        // a worker may be handed records that do not follow its chunk
+        if !chunk.is_empty()
+           && (fills_chunk::<T>(size, limit) || first + chunk.len() as u64 != position as u64)
+        {
+            spill(&mut chunk, first)?;
+            total_read = 0;
+        }
+        if chunk.is_empty() {
+            first = position as u64;
+        }
+        total_read += size;
+        chunk.push(t);
+        if footprint(&chunk, total_read) >= limit {
+            spill(&mut chunk, first)?;
+            total_read = 0;
+        }
+        Ok::<_, SendError>((chunk, total_read, first))
+    };
+    // the records left over by adjacent workers are combined if they are
+    // consecutive, and spilled on their own otherwise
+    let reduce = |left: Part<T>, right: Part<T>| {
+        let ((mut chunk, total_read, first), (mut other, other_read, other_first)) = (left, right);
+        if other.is_empty() {
+            return Ok((chunk, total_read, first));
+        }
+        if chunk.is_empty() {
+            return Ok((other, other_read, other_first));
+        }
+        if first + chunk.len() as u64 != other_first {
+            spill(&mut chunk, first)?;
+            return Ok((other, other_read, other_first));
+        }
+        chunk.append(&mut other);
+        let total_read = total_read + other_read;
+        if footprint(&chunk, total_read) >= chunk_bytes {
+            spill(&mut chunk, first)?;
+            return Ok((chunk, 0, first));
+        }
+        Ok((chunk, total_read, first))
+    };
+    let (mut chunk, _, first) = unsorted.enumerate()
+                                        .try_fold(|| (Vec::new(), 0, 0), fold)
+                                        .try_reduce(|| (Vec::new(), 0, 0), reduce)?;
+    if !chunk.is_empty() {
+        spill(&mut chunk, first)?;
+    }
+
+    Ok(())
+}
+
+/// Receive written chunks and merge every `fan_in` adjacent chunks of the same
+/// level into a single chunk of the next level, returning the metadata of the
+/// remaining chunks in input order.
 ///
-///     let external_sorter = ExternalSorter::new(16, None);
-///     let iter = external_sorter.sort(unsorted.into_iter()).unwrap();
-///     for (idx, i) in iter.enumerate() {
-///         assert_eq!(i.unwrap().the_num, sorted[idx].the_num);
-///     }
-/// }
-/// ```
-pub struct ExternalSorter<T>
+/// Only merging chunks that are adjacent in the input keeps the final merge
+/// stable, even when the chunks arrive out of order from worker threads.
+fn premerge<T>(chunks: Receiver<(u64, ChunkMeta<T>)>, fan_in: usize, tmp_dir: &Arc<TempDir>,
+               compare: &Arc<CompareFn<T>>, buffer_bytes: u64,
+               allocator: &Option<Arc<dyn BufferAllocator<T>>>)
+               -> Result<Vec<ChunkMeta<T>>, SendError>
 where
     T: ExternallySortable,
 {
-    tmp_dir: Option<PathBuf>,
-    buffer_bytes: u64,
-    phantom: PhantomData<T>,
+    // keyed by the sequence number of the first input chunk merged into each
+    // run, with the sequence number following its last one and its level
+    let mut runs: BTreeMap<u64, (u64, u32, ChunkMeta<T>)> = BTreeMap::new();
+    let mut merged = 0;
+    for (seq, meta) in chunks {
+        runs.insert(seq, (seq + meta.records, 0, meta));
+        while let Some(start) = find_mergeable(&runs, fan_in) {
+            let group: Vec<_> = runs.range(start..).take(fan_in).map(|(s, _)| *s).collect();
+            let group: Vec<_> = group.into_iter().map(|s| runs.remove(&s).unwrap()).collect();
+            let (end, level) = (group[fan_in - 1].0, group[0].1);
+            let chunk_meta: Vec<_> = group.into_iter().map(|(_, _, meta)| meta).collect();
+            #[cfg(any(feature = "tracing", feature = "log"))]
+            let records: u64 = chunk_meta.iter().map(|m| m.records).sum();
+            #[cfg(feature = "tracing")]
+            let _span = tracing::debug_span!("merge_pass",
+                                             run = merged,
+                                             level,
+                                             runs = fan_in,
+                                             records)
+                .entered();
+            #[cfg(feature = "log")]
+            log::debug!("merging {} runs of level {} ({} records) in the background",
+                        fan_in, level, records);
+
+            let path = tmp_dir.path().join(format!("merged_{}", merged));
+            merged += 1;
+            let meta = merge_chunks(chunk_meta, &path, tmp_dir, compare, buffer_bytes, allocator)?;
+            runs.insert(start, (end, level + 1, meta));
+        }
+    }
+
+    Ok(runs.into_iter().map(|(_, (_, _, meta))| meta).collect())
 }
 
-impl<T> ExternalSorter<T>
+/// Merge adjacent chunks into a single chunk at `path`, removing them, with
+/// buffers taken from and returned to `allocator` if any
+fn merge_chunks<T>(chunk_meta: Vec<ChunkMeta<T>>, path: &Path, tmp_dir: &Arc<TempDir>,
+                   compare: &Arc<CompareFn<T>>, buffer_bytes: u64,
+                   allocator: &Option<Arc<dyn BufferAllocator<T>>>)
+                   -> Result<ChunkMeta<T>, SendError>
 where
     T: ExternallySortable,
 {
-    /// Create a new `ExternalSorter` with a specified memory buffer and
-    /// temporary directory
-    pub fn new(buffer_bytes: u64, tmp_dir: Option<PathBuf>) -> ExternalSorter<T> {
-        ExternalSorter {
-            buffer_bytes,
-            tmp_dir,
-            phantom: PhantomData,
+    let sources: Vec<_> = chunk_meta.iter().map(|m| m.path.clone()).collect();
+    let records = chunk_meta.iter().map(|m| m.records).sum();
+    // the merged chunk carries the times spent making its sources
+    let sort_time = chunk_meta.iter().map(|m| m.sort_time).sum();
+    let write_time: Duration = chunk_meta.iter().map(|m| m.write_time).sum();
+    let started = Instant::now();
+
+    let iter = ExtSortedIterator::from_chunks(tmp_dir.clone(), compare.clone(), chunk_meta,
+                                              buffer_bytes, allocator.clone())?;
+    let mut meta = write_merged(iter, path, records)?;
+    meta.delete_on_close()?;
+    for source in sources {
+        #[cfg(feature = "metrics")]
+        metrics::run_removed(&source);
+        fs::remove_file(source)?;
+    }
+    meta.sort_time = sort_time;
+    meta.write_time = write_time + started.elapsed();
+
+    Ok(meta)
+}
+
+/// Write the records merged by `iter` (about `records` of them) to a single
+/// chunk at `path`
+pub(crate) fn write_merged<T>(mut iter: ExtSortedIterator<T>, path: &Path, records: u64)
+                              -> Result<ChunkMeta<T>, SendError>
+where
+    T: ExternallySortable,
+{
+    let mut writer = ChunkWriter::new(path, records)?;
+    let mut last = None;
+    while let Some(t) = iter.next_record() {
+        let t = t?;
+        writer.push(&t)?;
+        last = Some(t);
+    }
+    writer.finish(last)
+}
+
+/// Find the first `fan_in` adjacent runs of the same level
+fn find_mergeable<T>(runs: &BTreeMap<u64, (u64, u32, ChunkMeta<T>)>, fan_in: usize)
+                     -> Option<u64> {
+    let mut start = None;
+    let mut len = 0;
+    let mut prev: Option<(u64, u32)> = None;
+    for (seq, (end, level, _)) in runs {
+        match prev {
+            Some((prev_end, prev_level)) if prev_end == *seq && prev_level == *level => len += 1,
+            _ => {
+                start = Some(*seq);
+                len = 1;
+            },
+        }
+        if len == fan_in {
+            return start;
         }
+        prev = Some((*end, *level));
     }
 
-    /// Sort the `T`s provided by `unsorted` and return a sorted (ascending)
-    /// iterator
-    ///
-    /// # Errors
-    ///
-    /// This method can fail due to issues writing intermediate sorted chunks
-    /// to disk, or due to serde serialization issues
-    pub fn sort<I>(&self, unsorted: I) -> Result<ExtSortedIterator<T>, Box<dyn Error>>
-    where
-        I: Iterator<Item = T>,
-    {
-        self.sort_by(unsorted, |a, b| a.cmp(b))
+    None
+}
+
+/// Wrap `f` to run in the current span, and with the current subscriber, of
+/// the calling thread, for work handed off to another thread
+#[cfg(feature = "tracing")]
+fn in_current_span<F, R>(f: F) -> impl FnOnce() -> R
+where
+    F: FnOnce() -> R,
+{
+    let span = tracing::Span::current();
+    let dispatch = tracing::dispatcher::get_default(|dispatch| dispatch.clone());
+    move || tracing::dispatcher::with_default(&dispatch, || span.in_scope(f))
+}
+
+#[cfg(not(feature = "tracing"))]
+fn in_current_span<F, R>(f: F) -> F
+where
+    F: FnOnce() -> R,
+{
+    f
+}
+
+/// Record the runs left for the final merge, and leave the run generation span
+#[cfg(feature = "tracing")]
+fn trace_runs<T>(span: tracing::span::EnteredSpan, chunk_meta: &[ChunkMeta<T>]) {
+    tracing::info!(runs = chunk_meta.len(),
+                   records = chunk_meta.iter().map(|m| m.records).sum::<u64>(),
+                   bytes = chunk_meta.iter().map(|m| m.bytes).sum::<u64>(),
+                   "generated runs");
+    drop(span);
+}
+
+/// Hand a written chunk over to the merge, once it is within the disk quota
+/// of the tag of the sort, if any
+fn send_chunk<T>(chunks: &Sender<(u64, ChunkMeta<T>)>, seq: u64, mut meta: ChunkMeta<T>,
+                 quota: Option<&TagUsage>)
+                 -> Result<(), SendError> {
+    if let Some(quota) = quota {
+        quota.check()?;
     }
+    meta.delete_on_close()?;
+    // the receiver is only dropped early if merging chunks in the background
+    // failed, in which case that error is reported instead
+    chunks.send((seq, meta)).map_err(|_| "background merge stopped".into())
+}
 
-    /// Sort (based on `compare`) the `T`s provided by `unsorted` and return an
-    /// iterator
-    ///
-    /// # Errors
-    ///
-    /// This method can fail due to issues writing intermediate sorted chunks
-    /// to disk, or due to serde serialization issues
-    pub fn sort_by<I, F>(&self, unsorted: I, compare: F) -> Result<ExtSortedIterator<T>, Box<dyn Error>>
-    where
-        I: Iterator<Item = T>,
-        F: 'static + FnMut(&T, &T) -> Ordering,
-    {
-        let tmp_dir = match self.tmp_dir {
-            Some(ref p) => TempDir::new_in(p, "sort_fasta")?,
-            None => TempDir::new("sort_fasta")?,
-        };
-        // creating the thing we need to return first due to the face that we need to
-        // borrow tmp_dir and move it out
-        let mut iter = ExtSortedIterator {
-            buffers: Vec::new(),
-            chunk_offsets: Vec::new(),
-            max_per_chunk: 0,
-            chunks: 0,
-            tmp_dir,
-            sort_by_fn: Box::new(compare),
-            failed: false,
-        };
+/// Writes sorted records to a chunk file, gathering its metadata
+struct ChunkWriter<T, W = BufWriter<File>> {
+    file: W,
+    path: PathBuf,
+    offset: u64,
+    records: u64,
+    bytes: u64,
+    first: Option<T>,
+    sample_step: u64,
+    samples: Vec<(u64, T)>,
+    /// Whether the number of records is unknown, so the sample step grows
+    /// with the chunk
+    streaming: bool,
+    /// Whether repeated records are written once, with their number of
+    /// repeats
+    run_length: bool,
+    /// Serialized record last pushed, with the record and its number of
+    /// repeats, not written yet
+    repeated: Option<(Vec<u8>, T, u64)>,
+}
 
-        {
-            let mut total_read = 0;
-            let mut chunk = Vec::new();
-
-            // make the initial chunks on disk
-            for seq in unsorted {
-                total_read += seq.get_size();
-                chunk.push(seq);
-                if total_read >= self.buffer_bytes {
-                    chunk.sort_by(|a, b| (iter.sort_by_fn)(a, b));
-                    self.write_chunk(
-                        &iter.tmp_dir.path().join(iter.chunks.to_string()),
-                        &mut chunk,
-                    )?;
-                    chunk.clear();
-                    total_read = 0;
-                    iter.chunks += 1;
-                }
-            }
-            // write the last chunk
-            if chunk.len() > 0 {
-                chunk.sort_by(|a, b| (iter.sort_by_fn)(a, b));
-                self.write_chunk(
-                    &iter.tmp_dir.path().join(iter.chunks.to_string()),
-                    &mut chunk,
-                )?;
-                iter.chunks += 1;
+impl<T> ChunkWriter<T>
+where
+    T: ExternallySortable,
+{
+    /// Create a writer for a chunk that will hold `records` records
+    fn new(path: &Path, records: u64) -> Result<ChunkWriter<T>, SendError> {
+        Ok(ChunkWriter {
+            file: BufWriter::new(OpenOptions::new().create(true).append(true).open(path)?),
+            path: path.to_path_buf(),
+            offset: 0,
+            records: 0,
+            bytes: 0,
+            first: None,
+            sample_step: (records / CHUNK_SAMPLES as u64).max(1),
+            samples: Vec::new(),
+            streaming: false,
+            run_length: false,
+            repeated: None,
+        })
+    }
+
+    /// Create a writer for a chunk of unknown size
+    fn streaming(path: &Path) -> Result<ChunkWriter<T>, SendError> {
+        let mut writer = ChunkWriter::new(path, 0)?;
+        writer.streaming = true;
+        Ok(writer)
+    }
+}
+
+impl<T> ChunkWriter<T, io::Sink>
+where
+    T: ExternallySortable,
+{
+    /// Create a writer gathering the metadata of the run already written to
+    /// `path`, whose records are passed to [record](#method.record)
+    fn scanning(path: &Path) -> ChunkWriter<T, io::Sink> {
+        ChunkWriter {
+            file: io::sink(),
+            path: path.to_path_buf(),
+            offset: 0,
+            records: 0,
+            bytes: 0,
+            first: None,
+            sample_step: 1,
+            samples: Vec::new(),
+            streaming: true,
+            run_length: false,
+            repeated: None,
+        }
+    }
+}
+
+impl<T, W> ChunkWriter<T, W>
+where
+    T: ExternallySortable,
+    W: Write,
+{
+    fn push(&mut self, t: &T) -> Result<(), SendError> {
+        if self.run_length {
+            return self.push_repeated(t);
+        }
+        // serialized straight to the file, so that a large record is not
+        // held in memory a second time
+        let mut file = CountingWriter { inner: &mut self.file, written: 0 };
+        serde_json::to_writer(&mut file, t)?;
+        file.write_all(b"\n")?;
+        let serialized = file.written;
+        self.record(t, serialized);
+        Ok(())
+    }
+
+    /// Push a record of a run-length encoded chunk, which is written once
+    /// the next record differs from it
+    fn push_repeated(&mut self, t: &T) -> Result<(), SendError> {
+        let line = to_line(t)?.into_bytes();
+        if let Some((ref last, _, ref mut repeats)) = self.repeated {
+            // repeats are not written across a sample, so that the merge can
+            // start reading the chunk at any sample
+            let position = self.records + *repeats;
+            if *last == line
+               && *repeats < MAX_REPEATS
+               && !position.is_multiple_of(self.sample_step)
+            {
+                *repeats += 1;
+                return Ok(());
             }
+        }
+        self.write_repeated()?;
+        self.repeated = Some((line, t.clone(), 1));
+        Ok(())
+    }
 
-            // initialize buffers for each chunk
-            iter.max_per_chunk = self.buffer_bytes / iter.chunks;
-            iter.buffers = vec![VecDeque::new(); iter.chunks as usize];
-            iter.chunk_offsets = vec![0 as u64; iter.chunks as usize];
-            for chunk_num in 0..iter.chunks {
-                let offset = fill_buff(
-                    &mut iter.buffers[chunk_num as usize],
-                    File::open(iter.tmp_dir.path().join(chunk_num.to_string()))?,
-                    iter.max_per_chunk,
-                )?;
-                iter.chunk_offsets[chunk_num as usize] = offset;
+    /// Write the record last pushed to a run-length encoded chunk, preceded
+    /// by its number of repeats if it has several
+    fn write_repeated(&mut self) -> Result<(), SendError> {
+        let (line, t, repeats) = match self.repeated.take() {
+            Some(repeated) => repeated,
+            None => return Ok(()),
+        };
+        let mut file = CountingWriter { inner: &mut self.file, written: 0 };
+        if repeats > 1 {
+            writeln!(file, "{}{}", REPEAT_PREFIX as char, repeats)?;
+        }
+        file.write_all(&line)?;
+        let serialized = file.written;
+        // every repeat is at the offset of the first one
+        self.record(&t, serialized);
+        for _ in 1..repeats {
+            self.record(&t, 0);
+        }
+        Ok(())
+    }
+
+    /// Account for a record taking `serialized` bytes of the chunk
+    fn record(&mut self, t: &T, serialized: u64) {
+        if self.records.is_multiple_of(self.sample_step) {
+            if self.streaming && self.samples.len() == 2 * CHUNK_SAMPLES {
+                // keep every other sample, which are those at multiples of
+                // the doubled step
+                let mut i = 0;
+                self.samples.retain(|_| {
+                                        i += 1;
+                                        i % 2 == 1
+                                    });
+                self.sample_step *= 2;
             }
+            if self.records.is_multiple_of(self.sample_step) {
+                self.samples.push((self.offset, t.clone()));
+            }
+        }
+        if self.records == 0 {
+            self.first = Some(t.clone());
         }
+        self.offset += serialized;
+        self.records += 1;
+        self.bytes += t.get_size();
+    }
 
-        Ok(iter)
+    /// Finish writing the chunk, whose last record was `last`
+    fn finish(mut self, last: Option<T>) -> Result<ChunkMeta<T>, SendError> {
+        self.write_repeated()?;
+        self.file.flush()?;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(run = %self.path.display(),
+                        records = self.records,
+                        bytes = self.bytes,
+                        file_bytes = self.offset,
+                        "wrote run");
+        #[cfg(feature = "log")]
+        log::debug!("wrote run {} ({} records, {} bytes)",
+                    self.path.display(), self.records, self.bytes);
+        #[cfg(feature = "metrics")]
+        metrics::run_written(self.offset);
+        Ok(self.into_meta(last))
     }
 
-    fn write_chunk(&self, file: &PathBuf, chunk: &mut Vec<T>) -> Result<(), Box<dyn Error>> {
-        let mut new_file = OpenOptions::new().create(true).append(true).open(file)?;
-        for s in chunk {
-            let mut serialized = serde_json::to_string(&s)?;
-            serialized.push_str("\n");
-            new_file.write_all(serialized.as_bytes())?;
+    /// Metadata of the chunk, whose last record was `last`
+    fn into_meta(self, last: Option<T>) -> ChunkMeta<T> {
+        ChunkMeta {
+            path: self.path,
+            records: self.records,
+            bytes: self.bytes,
+            first: self.first,
+            last,
+            samples: self.samples,
+            sample_step: self.sample_step,
+            file_bytes: Some(self.offset),
+            checksum: OnceLock::new(),
+            #[cfg(windows)]
+            anchor: None,
+            sort_time: Duration::ZERO,
+            write_time: Duration::ZERO,
         }
+    }
+}
 
-        Ok(())
+/// Writer counting the bytes written through it
+struct CountingWriter<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W> Write for CountingWriter<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Serialize a record as a line of JSON
+pub(crate) fn to_line<T>(t: &T) -> Result<String, SendError>
+where
+    T: ExternallySortable,
+{
+    let mut serialized = serde_json::to_string(t)?;
+    serialized.push('\n');
+    Ok(serialized)
+}
+
+/// Sort a chunk in memory and write it to `file`, timing both
+fn sort_and_write<T>(chunk: &mut [T], policy: &ChunkPolicy<T>, file: &Path)
+                     -> Result<ChunkMeta<T>, SendError>
+where
+    T: ExternallySortable,
+{
+    let started = Instant::now();
+    let compare = &policy.compare;
+    if policy.stable {
+        chunk.sort_by(|a, b| compare(a, b));
+    } else {
+        chunk.sort_unstable_by(|a, b| compare(a, b));
+    }
+    let sort_time = started.elapsed();
+    // set for every chunk, as chunks may be written by the threads of a pool
+    // that are not the sorter's own
+    let _priority = IoPriorityGuard::set(policy.io_priority);
+    let mut meta = write_chunk(file, chunk, policy.run_length)?;
+    meta.sort_time = sort_time;
+    Ok(meta)
+}
+
+/// Write the sorted records of `chunk` to `file`, repeated records once with
+/// their number of repeats if `run_length` is set
+pub(crate) fn write_chunk<T>(file: &Path, chunk: &[T], run_length: bool)
+                             -> Result<ChunkMeta<T>, SendError>
+where
+    T: ExternallySortable,
+{
+    let started = Instant::now();
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("write_run", run = %file.display(), records = chunk.len())
+        .entered();
+    let mut writer = ChunkWriter::new(file, chunk.len() as u64)?;
+    writer.run_length = run_length;
+    for t in chunk {
+        writer.push(t)?;
+    }
+    let mut meta = writer.finish(chunk.last().cloned())?;
+    meta.write_time = started.elapsed();
+    Ok(meta)
+}
+
+/// Number of records of `item_bytes` each (and `size` bytes in memory) that
+/// fill a chunk of `chunk_bytes`, as measured by `footprint()`, along with
+/// the footprint of the full chunk
+fn chunk_records(chunk_bytes: u64, item_bytes: u64, size: u64) -> (u64, u64) {
+    let record_bytes = item_bytes + size;
+    if record_bytes == 0 {
+        return (u64::MAX, 0);
+    }
+    let records = chunk_bytes.div_ceil(record_bytes).max(1);
+    (records, records.saturating_mul(record_bytes))
+}
+
+/// Whether a record of `size` bytes fills a chunk of `chunk_bytes` by itself,
+/// so that it is spilled to a run of its own rather than along with the
+/// records buffered before it
+fn fills_chunk<T>(size: u64, chunk_bytes: u64) -> bool {
+    size.saturating_add(mem::size_of::<T>() as u64) >= chunk_bytes
+}
+
+/// Memory held by the records of `chunk`, whose sizes add up to `total_read`,
+/// and by their slots in `chunk` itself
+///
+/// Chunks are cleared once they are written, keeping their capacity, which
+/// is not counted so that later chunks hold as many records as the first.
+pub(crate) fn footprint<T>(chunk: &[T], total_read: u64) -> u64 {
+    total_read + mem::size_of_val(chunk) as u64
+}
+
+/// Advise the OS to drop the `len` bytes of `file` at `offset` from its page
+/// cache, so that merging large runs does not evict other cached data
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn drop_behind(file: &File, offset: u64, len: u64) {
+    use std::os::unix::io::AsRawFd;
+
+    // the advice is only a hint, so a failure leaves the pages cached
+    unsafe {
+        libc::posix_fadvise(file.as_raw_fd(),
+                            offset as libc::off_t,
+                            len as libc::off_t,
+                            libc::POSIX_FADV_DONTNEED);
     }
 }
 
-fn fill_buff<T>(vec: &mut VecDeque<T>, file: File, max_bytes: u64) -> Result<u64, Box<dyn Error>>
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn drop_behind(_file: &File, _offset: u64, _len: u64) {}
+
+/// Read records from `file` into `vec` until they take up `max_bytes`, every
+/// line being read into `line`, which is kept from one refill to the next
+fn fill_buff<T>(vec: &mut VecDeque<T>, file: &File, max_bytes: u64, line: &mut Vec<u8>)
+                -> Result<u64, SendError>
 where
     T: ExternallySortable,
 {
+    let mut reader = BufReader::new(file);
+    // the capacity of the line (along with the buffer's) counts against
+    // `max_bytes`
     let mut total_read = 0;
     let mut bytes_read = 0;
-    for line in BufReader::new(file).lines() {
-        let line_s = line?;
-        bytes_read += line_s.len() + 1;
-        let deserialized: T = serde_json::from_str(&line_s)?;
-        total_read += deserialized.get_size();
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', line)?;
+        if read == 0 {
+            break;
+        }
+        bytes_read += read;
+        let mut repeats = 1;
+        if line.first() == Some(&REPEAT_PREFIX) {
+            repeats = parse_repeats(line)?;
+            line.clear();
+            bytes_read += reader.read_until(b'\n', line)?;
+        }
+        let deserialized: T = serde_json::from_slice(line.strip_suffix(b"\n").unwrap_or(line))?;
+        total_read += deserialized.get_size() * repeats;
+        for _ in 1..repeats {
+            vec.push_back(deserialized.clone());
+        }
         vec.push_back(deserialized);
-        if total_read > max_bytes {
+        let overhead = vec.len() * mem::size_of::<T>() + line.capacity();
+        if total_read + overhead as u64 > max_bytes {
             break;
         }
     }
 
     Ok(bytes_read as u64)
 }
+
+/// Number of repeats of the record after `line`, which starts with
+/// `REPEAT_PREFIX`, no more than the `MAX_REPEATS` that are ever written
+pub(crate) fn parse_repeats(line: &[u8]) -> Result<u64, SendError> {
+    let repeats = std::str::from_utf8(&line[1..]).ok().and_then(|r| r.trim_end().parse().ok());
+    match repeats {
+        Some(repeats) if repeats > 0 && repeats <= MAX_REPEATS => Ok(repeats),
+        _ => {
+            Err(format!("invalid repeat count: {}", String::from_utf8_lossy(line).trim_end()).into())
+        },
+    }
+}
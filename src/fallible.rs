@@ -0,0 +1,84 @@
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::error::Error;
+use std::rc::Rc;
+
+use crate::{ExtSortedIterator, ExternalSorter, ExternallySortable};
+
+impl<T> ExternalSorter<T>
+where
+    T: ExternallySortable,
+{
+    /// Sort the `T`s provided by `unsorted`, up to its first error, and
+    /// return a sorted (ascending) iterator
+    ///
+    /// # Errors
+    ///
+    /// This method fails with the first error of `unsorted`, once the
+    /// records before it are sorted, or due to issues writing intermediate
+    /// sorted chunks to disk, or due to serde serialization issues
+    pub fn try_sort<I, E>(&self, unsorted: I) -> Result<ExtSortedIterator<T>, Box<dyn Error>>
+    where
+        I: Iterator<Item = Result<T, E>>,
+        E: Into<Box<dyn Error>>,
+    {
+        self.try_sort_by(unsorted, |a, b| a.cmp(b))
+    }
+
+    /// Sort (based on `compare`) the `T`s provided by `unsorted`, up to its
+    /// first error, and return an iterator
+    ///
+    /// As with [sort_by_sync](#method.sort_by_sync), `compare` is shared
+    /// between threads.
+    ///
+    /// # Errors
+    ///
+    /// This method fails with the first error of `unsorted`, once the
+    /// records before it are sorted, or due to issues writing intermediate
+    /// sorted chunks to disk, or due to serde serialization issues
+    pub fn try_sort_by<I, E, F>(&self, unsorted: I, compare: F)
+                                -> Result<ExtSortedIterator<T>, Box<dyn Error>>
+    where
+        I: Iterator<Item = Result<T, E>>,
+        E: Into<Box<dyn Error>>,
+        F: 'static + Fn(&T, &T) -> Ordering + Send + Sync,
+    {
+        let (records, error) = until_error(unsorted);
+        let sorted = self.sort_by_sync(records, compare)?;
+        match error.take() {
+            Some(e) => Err(e.into()),
+            None => Ok(sorted),
+        }
+    }
+}
+
+/// The first error of an input read through [until_error], once the input
+/// was read
+pub(crate) struct FirstError<E>(Rc<Cell<Option<E>>>);
+
+impl<E> FirstError<E> {
+    pub(crate) fn take(&self) -> Option<E> {
+        self.0.take()
+    }
+}
+
+/// Iterate over the values of `input` up to its first error, which is kept
+/// in the returned [FirstError] rather than yielded
+///
+/// The sorter reads records from an iterator, so the first error of the
+/// input stops it, and is reported once the records before it are sorted.
+pub(crate) fn until_error<I, T, E>(input: I) -> (impl Iterator<Item = T>, FirstError<E>)
+where
+    I: IntoIterator<Item = Result<T, E>>,
+{
+    let error = Rc::new(Cell::new(None));
+    let first = error.clone();
+    let values = input.into_iter().map_while(move |value| match value {
+                                      Ok(value) => Some(value),
+                                      Err(e) => {
+                                          first.set(Some(e));
+                                          None
+                                      },
+                                  });
+    (values, FirstError(error))
+}
@@ -0,0 +1,47 @@
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+/// Fault injected into the merge of a sorted iterator with
+/// [ExtSortedIterator::inject_fault](struct.ExtSortedIterator.html#method.inject_fault),
+/// built with the `testing` feature
+#[derive(Clone, Debug)]
+pub enum Fault {
+    /// Fail the read with an I/O error of this kind, which the iterator
+    /// yields as an `Err` item
+    Io(io::ErrorKind),
+    /// Corrupt the record of the run at the offset, so that it fails to
+    /// deserialize when it is read
+    Corrupt,
+    /// Stall the read for this long before it goes on
+    Delay(Duration),
+}
+
+/// Faults waiting for the merge to read their run from their offset on
+#[derive(Default)]
+pub(crate) struct Faults {
+    pending: Vec<(usize, u64, Fault)>,
+}
+
+impl Faults {
+    pub(crate) fn push(&mut self, run: usize, offset: u64, fault: Fault) {
+        self.pending.push((run, offset, fault));
+    }
+
+    /// Fire the faults of `run` at or before `offset`, which the merge reads
+    /// the run from next, each of them once
+    pub(crate) fn fire(&mut self, run: usize, offset: u64) -> io::Result<()> {
+        while let Some(i) = self.pending.iter().position(|f| f.0 == run && f.1 <= offset) {
+            match self.pending.remove(i).2 {
+                Fault::Io(kind) => {
+                    return Err(io::Error::new(kind, format!("fault injected at offset {} of run {}",
+                                                            offset, run)))
+                },
+                Fault::Delay(delay) => thread::sleep(delay),
+                // corrupted when injected
+                Fault::Corrupt => {},
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+//! C API for sorting opaque byte records, built with the `ffi` feature
+//!
+//! Records are pushed into a sort created by [extsort_new], which is then
+//! finished with [extsort_finish] and read back in byte order with
+//! [extsort_next]. Every sort must be released with [extsort_free]. The
+//! declarations are in `include/external_sort.h`, and a shared library is
+//! built with `cargo rustc --release --lib --features ffi --crate-type cdylib`.
+//!
+//! Functions returning `int` return `0` on success and `-1` on failure, in
+//! which case [extsort_last_error] describes the error.
+
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::os::raw::{c_char, c_int};
+use std::path::PathBuf;
+use std::ptr;
+use std::slice;
+
+use crate::{ExtSortedIterator, ExternalSorter, SortSink};
+
+/// Opaque handle to a sort of byte records
+pub struct ExtSort {
+    state: State,
+    /// Record returned by the last call to `extsort_next()`
+    record: Vec<u8>,
+    error: Option<CString>,
+}
+
+enum State {
+    Pushing(SortSink<Vec<u8>>),
+    Sorted(Box<ExtSortedIterator<Vec<u8>>>),
+    Done,
+}
+
+impl ExtSort {
+    fn fail(&mut self, error: &str) -> c_int {
+        self.state = State::Done;
+        self.error = CString::new(error.replace('\0', " ")).ok();
+        -1
+    }
+}
+
+/// Create a sort holding about `buffer_bytes` bytes of records in memory, and
+/// spilling the rest to sorted chunks in `tmp_dir` (or the system temporary
+/// directory if `NULL`)
+///
+/// Returns `NULL` if `tmp_dir` is not valid UTF-8.
+///
+/// # Safety
+///
+/// `tmp_dir` must be `NULL` or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn extsort_new(buffer_bytes: u64, tmp_dir: *const c_char) -> *mut ExtSort {
+    let tmp_dir = if tmp_dir.is_null() {
+        None
+    } else {
+        match CStr::from_ptr(tmp_dir).to_str() {
+            Ok(tmp_dir) => Some(PathBuf::from(tmp_dir)),
+            Err(_) => return ptr::null_mut(),
+        }
+    };
+    let sink = ExternalSorter::new(buffer_bytes, tmp_dir).sink();
+
+    Box::into_raw(Box::new(ExtSort {
+                               state: State::Pushing(sink),
+                               record: Vec::new(),
+                               error: None,
+                           }))
+}
+
+/// Push a copy of the `len` bytes at `bytes` into the sort
+///
+/// # Safety
+///
+/// `sort` must have been returned by `extsort_new()` and not yet freed, and
+/// `bytes` must point to `len` readable bytes (or be `NULL` if `len` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn extsort_push(sort: *mut ExtSort, bytes: *const u8, len: usize) -> c_int {
+    let sort = &mut *sort;
+    let record = if len == 0 { Vec::new() } else { slice::from_raw_parts(bytes, len).to_vec() };
+    let pushed = match sort.state {
+        State::Pushing(ref sink) => sink.push(record),
+        _ => return sort.fail("extsort_push() called after extsort_finish()"),
+    };
+    match pushed {
+        Ok(()) => 0,
+        Err(e) => sort.fail(&e.to_string()),
+    }
+}
+
+/// Finish pushing records, and wait for them to be sorted
+///
+/// # Safety
+///
+/// `sort` must have been returned by `extsort_new()` and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn extsort_finish(sort: *mut ExtSort) -> c_int {
+    let sort = &mut *sort;
+    let sink = match mem::replace(&mut sort.state, State::Done) {
+        State::Pushing(sink) => sink,
+        _ => return sort.fail("extsort_finish() called twice"),
+    };
+    match sink.finish() {
+        Ok(iter) => {
+            sort.state = State::Sorted(Box::new(iter));
+            0
+        },
+        Err(e) => sort.fail(&e.to_string()),
+    }
+}
+
+/// Read the next record in byte order into `*bytes` and `*len`
+///
+/// Returns `1` if a record was read, or `0` once all of them have been. The
+/// record stays valid until the next call with this sort.
+///
+/// # Safety
+///
+/// `sort` must have been returned by `extsort_new()` and not yet freed, and
+/// `bytes` and `len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn extsort_next(sort: *mut ExtSort, bytes: *mut *const u8, len: *mut usize)
+                                      -> c_int {
+    let sort = &mut *sort;
+    let next = match sort.state {
+        State::Sorted(ref mut iter) => iter.next(),
+        State::Pushing(_) => return sort.fail("extsort_next() called before extsort_finish()"),
+        State::Done => return sort.fail("extsort_next() called after an error"),
+    };
+    match next {
+        Some(Ok(record)) => {
+            sort.record = record;
+            *bytes = sort.record.as_ptr();
+            *len = sort.record.len();
+            1
+        },
+        Some(Err(e)) => sort.fail(&e.to_string()),
+        None => 0,
+    }
+}
+
+/// Describe the last error of the sort, or return `NULL` if there was none
+///
+/// The message stays valid until the next error of the sort, or until it is
+/// freed.
+///
+/// # Safety
+///
+/// `sort` must have been returned by `extsort_new()` and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn extsort_last_error(sort: *const ExtSort) -> *const c_char {
+    match (*sort).error {
+        Some(ref error) => error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Release the sort and its intermediate sorted chunks
+///
+/// # Safety
+///
+/// `sort` must be `NULL`, or have been returned by `extsort_new()` and not
+/// yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn extsort_free(sort: *mut ExtSort) {
+    if !sort.is_null() {
+        drop(Box::from_raw(sort));
+    }
+}
@@ -0,0 +1,103 @@
+use std::cmp::Ordering;
+use std::mem;
+
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+use crate::ExternallySortable;
+
+/// Where NaNs sort among the other floats compared by
+/// [cmp_f64](fn.cmp_f64.html), [cmp_f32](fn.cmp_f32.html) or
+/// [by_f64_key](fn.by_f64_key.html)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NanOrder {
+    /// NaNs sort before every other float, as equals
+    First,
+    /// NaNs sort after every other float, as equals
+    Last,
+    /// NaNs sort by `total_cmp()`: negative NaNs first and positive NaNs last
+    Total,
+}
+
+macro_rules! total_float {
+    ($name:ident, $t:ty, $bits:ty, $cmp:ident) => {
+        /// Float that is totally ordered by `total_cmp()`, so that it
+        /// implements `Ord` and can be sorted (or used in sort keys) like an
+        /// integer
+        ///
+        /// Negative NaNs sort first and positive NaNs last, and `-0.0` sorts
+        /// before `0.0`. The float serializes as its bits, which keeps NaNs
+        /// and infinities (that JSON has no numbers for) intact.
+        #[derive(Clone, Copy, Debug, Default)]
+        pub struct $name(pub $t);
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &$name) -> bool {
+                self.cmp(other) == Ordering::Equal
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &$name) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $name {
+            fn cmp(&self, other: &$name) -> Ordering {
+                self.0.total_cmp(&other.0)
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                self.0.to_bits().serialize(serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> Result<$name, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                <$bits>::deserialize(deserializer).map(|bits| $name(<$t>::from_bits(bits)))
+            }
+        }
+
+        impl ExternallySortable for $name {
+            fn get_size(&self) -> u64 {
+                mem::size_of::<$t>() as u64
+            }
+        }
+
+        /// Compare two floats by `total_cmp()`, except for NaNs, which sort
+        /// according to `nans`
+        pub fn $cmp(a: $t, b: $t, nans: NanOrder) -> Ordering {
+            match (nans, a.is_nan(), b.is_nan()) {
+                (NanOrder::Total, _, _) | (_, false, false) => a.total_cmp(&b),
+                (_, true, true) => Ordering::Equal,
+                (NanOrder::First, a_nan, _) | (NanOrder::Last, _, a_nan) => {
+                    if a_nan { Ordering::Less } else { Ordering::Greater }
+                },
+            }
+        }
+    };
+}
+
+total_float!(TotalF64, f64, u64, cmp_f64);
+total_float!(TotalF32, f32, u32, cmp_f32);
+
+/// Create a comparator ordering records by the `f64` key that `key` extracts
+/// from them, as with [cmp_f64](fn.cmp_f64.html), e.g. for
+/// [ExternalSorter::sort_by](struct.ExternalSorter.html#method.sort_by)
+pub fn by_f64_key<T, F>(key: F, nans: NanOrder) -> impl Fn(&T, &T) -> Ordering + Send + Sync
+where
+    F: Fn(&T) -> f64 + Send + Sync,
+{
+    move |a, b| cmp_f64(key(a), key(b), nans)
+}
@@ -0,0 +1,95 @@
+use std::error::Error;
+use std::iter::Peekable;
+use std::marker::PhantomData;
+
+use crate::{ExtSortedIterator, ExternallySortable};
+
+/// Iterator that provides groups of consecutive sorted `T`s with equal keys,
+/// created by
+/// [ExtSortedIterator::group_by_key](struct.ExtSortedIterator.html#method.group_by_key)
+pub struct GroupedIterator<T, K, F>
+where
+    T: ExternallySortable,
+{
+    iter: Peekable<ExtSortedIterator<T>>,
+    key: F,
+    max_group_bytes: u64,
+    failed: bool,
+    phantom: PhantomData<fn() -> K>,
+}
+
+impl<T> ExtSortedIterator<T>
+where
+    T: ExternallySortable,
+{
+    /// Group consecutive records with equal keys in the sorted output,
+    /// yielding each key along with its records in sorted order
+    ///
+    /// The sort order must keep records with equal keys together, e.g. by
+    /// sorting on the key first. A group is held in memory while it is built,
+    /// so a group whose records add up to more than `max_group_bytes` (as
+    /// reported by `get_size()`) yields an error instead, and ends the
+    /// iteration.
+    pub fn group_by_key<K, F>(self, key: F, max_group_bytes: u64) -> GroupedIterator<T, K, F>
+    where
+        K: PartialEq,
+        F: FnMut(&T) -> K,
+    {
+        GroupedIterator {
+            iter: self.peekable(),
+            key,
+            max_group_bytes,
+            failed: false,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T, K, F> Iterator for GroupedIterator<T, K, F>
+where
+    T: ExternallySortable,
+    K: PartialEq,
+    F: FnMut(&T) -> K,
+{
+    type Item = Result<(K, Vec<T>), Box<dyn Error>>;
+
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// from disk, due to serde deserialization issues, or if a group is larger
+    /// than `max_group_bytes`
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let first = match self.iter.next()? {
+            Ok(first) => first,
+            Err(e) => return Some(Err(e)),
+        };
+        let key = (self.key)(&first);
+        let mut bytes = first.get_size();
+        let mut group = vec![first];
+        loop {
+            match self.iter.peek() {
+                Some(Ok(next)) if (self.key)(next) == key => {},
+                Some(Ok(_)) | None => break,
+                Some(Err(_)) => {},
+            }
+            // unwrap due to the peek() above
+            let next = match self.iter.next().unwrap() {
+                Ok(next) => next,
+                Err(e) => return Some(Err(e)),
+            };
+            bytes += next.get_size();
+            if bytes > self.max_group_bytes {
+                self.failed = true;
+                return Some(Err(format!("group is larger than {} bytes",
+                                        self.max_group_bytes).into()));
+            }
+            group.push(next);
+        }
+
+        Some(Ok((key, group)))
+    }
+}
@@ -0,0 +1,176 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::checksum::{Checksum, RunDigest};
+use crate::external_sort::{parse_repeats, MANIFEST_FILE, REPEAT_PREFIX};
+
+/// First record of a block of a run, and where the block starts
+#[derive(Serialize, Clone, Debug)]
+pub struct RunBlock {
+    /// Byte offset of the block within the run file
+    pub offset: u64,
+    /// Number of records of the run before the block
+    pub position: u64,
+    /// First record of the block
+    pub first: Value,
+}
+
+/// What the manifest of a sort lists about one of its runs
+#[derive(Serialize, Clone, Debug)]
+pub struct ManifestEntry {
+    /// Number of records the run was written with
+    pub records: u64,
+    /// Size of the run file as it was written, in bytes
+    pub file_bytes: u64,
+    /// Checksum of the run file as it was written
+    pub checksum: u64,
+}
+
+/// Description of a run file, returned by [inspect_run](fn.inspect_run.html)
+#[derive(Serialize, Clone, Debug)]
+pub struct RunInfo {
+    /// Path of the run file
+    pub path: PathBuf,
+    /// Size of the run file, in bytes
+    pub file_bytes: u64,
+    /// Checksum of the run file, as listed in manifests
+    pub checksum: u64,
+    /// Number of records of the run
+    pub records: u64,
+    /// First (smallest) record of the run
+    pub min: Option<Value>,
+    /// Last (largest) record of the run
+    pub max: Option<Value>,
+    /// Boundaries of the blocks of `block_records` records each
+    pub blocks: Vec<RunBlock>,
+    /// What the manifest next to the run (if any) lists about it
+    pub manifest: Option<ManifestEntry>,
+}
+
+impl RunInfo {
+    /// Whether the run matches what its manifest lists about it, or `None`
+    /// if there is no manifest listing it
+    pub fn intact(&self) -> Option<bool> {
+        self.manifest.as_ref().map(|listed| {
+                                   listed.records == self.records
+                                   && listed.file_bytes == self.file_bytes
+                                   && listed.checksum == self.checksum
+                               })
+    }
+}
+
+/// Run of a sort, as listed in its manifest
+#[derive(Deserialize)]
+struct ListedRun {
+    path: PathBuf,
+    records: u64,
+    digest: Option<RunDigest>,
+}
+
+/// Describe the run file at `path`, e.g. one left in the temporary
+/// directory of a failed or suspicious sort: its size, checksum and number
+/// of records, its first and last records, the first record of every block
+/// of `block_records` records, and what the `runs.json` manifest next to it
+/// lists about it, for sorts with
+/// [verify_runs](struct.ExternalSorter.html#method.verify_runs)
+///
+/// Records are decoded as JSON values, so that any run can be inspected
+/// whatever type it was sorted as. The repeats of a record of a run written
+/// with [run_length_encoding](struct.ExternalSorter.html#method.run_length_encoding)
+/// each count as a record, and the blocks that start within them start at
+/// the offset of the record. The run is not checked to be in order.
+///
+/// # Errors
+///
+/// This method can fail due to issues reading the run or its manifest, or
+/// if a line of the run is neither valid JSON nor a valid number of repeats
+pub fn inspect_run<P>(path: P, block_records: u64) -> Result<RunInfo, Box<dyn Error>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let block_records = block_records.max(1);
+    let mut input = BufReader::new(File::open(path)?);
+    let mut info = RunInfo {
+        path: path.to_path_buf(),
+        file_bytes: 0,
+        checksum: 0,
+        records: 0,
+        min: None,
+        max: None,
+        blocks: Vec::new(),
+        manifest: listed_run(path)?,
+    };
+    let mut checksum = Checksum::new();
+    let mut line = String::new();
+    let mut last = None;
+    let mut lines = 0;
+    while input.read_line(&mut line)? > 0 {
+        lines += 1;
+        let offset = info.file_bytes;
+        checksum.update(line.as_bytes());
+        info.file_bytes += line.len() as u64;
+        // a record of a run-length encoded run follows its number of
+        // repeats, which all are at its offset
+        let mut repeats = 1;
+        if line.as_bytes().first() == Some(&REPEAT_PREFIX) {
+            repeats = parse_repeats(line.as_bytes()).map_err(|e| {
+                                                         format!("{} line {}: {}",
+                                                                 path.display(),
+                                                                 lines,
+                                                                 e)
+                                                     })?;
+            line.clear();
+            if input.read_line(&mut line)? == 0 {
+                return Err(format!("{} line {}: repeated record missing",
+                                   path.display(),
+                                   lines + 1).into());
+            }
+            lines += 1;
+            checksum.update(line.as_bytes());
+            info.file_bytes += line.len() as u64;
+        }
+        let record: Value = serde_json::from_str(&line).map_err(|e| {
+                                                           format!("{} line {}: {}",
+                                                                   path.display(),
+                                                                   lines,
+                                                                   e)
+                                                       })?;
+        for position in info.records..info.records + repeats {
+            if position.is_multiple_of(block_records) {
+                info.blocks.push(RunBlock { offset, position, first: record.clone() });
+            }
+        }
+        if info.records == 0 {
+            info.min = Some(record.clone());
+        }
+        info.records += repeats;
+        last = Some(record);
+        line.clear();
+    }
+    info.max = last;
+    info.checksum = checksum.value();
+
+    Ok(info)
+}
+
+/// Find what the manifest in the directory of the run at `path` lists about
+/// it
+fn listed_run(path: &Path) -> Result<Option<ManifestEntry>, Box<dyn Error>> {
+    let manifest = match path.parent().map(|dir| dir.join(MANIFEST_FILE)) {
+        Some(ref manifest) if manifest.exists() => manifest.clone(),
+        _ => return Ok(None),
+    };
+    let runs: Vec<ListedRun> = serde_json::from_reader(BufReader::new(File::open(manifest)?))?;
+    let listed = runs.into_iter().find(|run| run.path.file_name() == path.file_name());
+    Ok(listed.and_then(|run| {
+                           run.digest.map(|digest| ManifestEntry { records: run.records,
+                                                                   file_bytes: digest.file_bytes,
+                                                                   checksum: digest.checksum })
+                       }))
+}
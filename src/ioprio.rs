@@ -0,0 +1,88 @@
+//! I/O scheduling priority of the disk activity of sorts, set with
+//! [ExternalSorter::io_priority](struct.ExternalSorter.html#method.io_priority)
+
+/// I/O scheduling class and level of the threads of a sort while they read
+/// and write its runs, as with `ionice(1)`
+///
+/// Priorities are only applied on Linux, by I/O schedulers that support
+/// them (such as BFQ); elsewhere they are ignored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoPriority {
+    /// Best-effort scheduling at a level from 0 (highest) to 7 (lowest),
+    /// like other processes (which default to level 4)
+    BestEffort(u8),
+    /// Only use the disk when no other process has used it for a while
+    Idle,
+}
+
+/// Sets the I/O priority of the calling thread, restoring the previous one
+/// when dropped
+pub(crate) struct IoPriorityGuard {
+    #[cfg(target_os = "linux")]
+    previous: Option<libc::c_int>,
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use super::IoPriority;
+
+    /// `ioprio_set()` target for a single thread (0 being the calling one)
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    const IOPRIO_CLASS_BE: libc::c_int = 2;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+
+    pub(super) fn encode(priority: IoPriority) -> libc::c_int {
+        match priority {
+            IoPriority::BestEffort(level) => {
+                IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT | libc::c_int::from(level.min(7))
+            },
+            IoPriority::Idle => IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+        }
+    }
+
+    /// Priority of the calling thread, or `None` if it cannot be read
+    pub(super) fn get() -> Option<libc::c_int> {
+        let ioprio = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, 0) };
+        if ioprio < 0 { None } else { Some(ioprio as libc::c_int) }
+    }
+
+    /// Set the priority of the calling thread, returning whether it was set
+    pub(super) fn set(ioprio: libc::c_int) -> bool {
+        unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) == 0 }
+    }
+}
+
+impl IoPriorityGuard {
+    /// Give the calling thread `priority`, if any, until the guard is dropped
+    pub(crate) fn set(priority: Option<IoPriority>) -> IoPriorityGuard {
+        #[cfg(target_os = "linux")]
+        {
+            // the priority is only a hint, so a thread that cannot be given
+            // it keeps its own
+            let previous = priority.and_then(|priority| {
+                                             let previous = sys::get()?;
+                                             if sys::set(sys::encode(priority)) {
+                                                 Some(previous)
+                                             } else {
+                                                 None
+                                             }
+                                         });
+            IoPriorityGuard { previous }
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = priority;
+            IoPriorityGuard {}
+        }
+    }
+}
+
+impl Drop for IoPriorityGuard {
+    fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        if let Some(previous) = self.previous {
+            sys::set(previous);
+        }
+    }
+}
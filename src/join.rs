@@ -0,0 +1,299 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::iter::Peekable;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tempdir::TempDir;
+
+use crate::external_sort::to_line;
+use crate::{ExtSortedIterator, ExternalSorter, ExternallySortable};
+
+type KeyFn<T, K> = dyn Fn(&T) -> K + Send + Sync;
+type Pair<L, R> = (Option<L>, Option<R>);
+
+/// Which records a [join](struct.ExternalSorter.html#method.join) yields
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JoinKind {
+    /// Only pairs of left and right records with equal keys
+    Inner,
+    /// Pairs of records with equal keys, and left records without a match
+    Left,
+    /// Pairs of records with equal keys, and left or right records without a
+    /// match
+    Full,
+}
+
+/// The right records sharing a key
+enum Group<R> {
+    Memory(Vec<R>),
+    /// Groups larger than the memory buffer are written to a file, which is
+    /// read again for every left record of the key (the directory is only
+    /// held to be removed with the group)
+    Spilled(#[allow(dead_code)] TempDir, PathBuf),
+}
+
+/// Position in a group of right records that are paired with `left`
+struct Matches<L, R> {
+    left: Option<L>,
+    group: Arc<Group<R>>,
+    next: usize,
+    lines: Option<Lines<BufReader<File>>>,
+}
+
+impl<L, R> Matches<L, R>
+where
+    R: ExternallySortable,
+{
+    /// Read the next record of the group
+    fn next_right(&mut self) -> Result<Option<R>, Box<dyn Error>> {
+        match *self.group {
+            Group::Memory(ref group) => {
+                self.next += 1;
+                Ok(group.get(self.next - 1).cloned())
+            },
+            Group::Spilled(_, ref path) => {
+                if self.lines.is_none() {
+                    self.lines = Some(BufReader::new(File::open(path)?).lines());
+                }
+                // unwrap due to the check above
+                match self.lines.as_mut().unwrap().next() {
+                    Some(line) => Ok(Some(serde_json::from_str(&line?)?)),
+                    None => Ok(None),
+                }
+            },
+        }
+    }
+}
+
+/// Iterator that provides the joined records of two sorted inputs, created by
+/// [ExternalSorter::join](struct.ExternalSorter.html#method.join)
+///
+/// Pairs are yielded in key order, as `(Some(left), Some(right))` for
+/// records with equal keys, and as `(Some(left), None)` or
+/// `(None, Some(right))` for records without a match.
+pub struct JoinedIterator<L, R, K>
+where
+    L: ExternallySortable,
+    R: ExternallySortable,
+{
+    left: Peekable<ExtSortedIterator<L>>,
+    right: Peekable<ExtSortedIterator<R>>,
+    left_key: Arc<KeyFn<L, K>>,
+    right_key: Arc<KeyFn<R, K>>,
+    kind: JoinKind,
+    /// Right records of the last key read from `right`, and whether any left
+    /// record matched them
+    group: Option<(K, Arc<Group<R>>, bool)>,
+    matches: Option<Matches<L, R>>,
+    sorter: ExternalSorter<R>,
+    failed: bool,
+}
+
+impl<L> ExternalSorter<L>
+where
+    L: ExternallySortable,
+{
+    /// Sort `left` and `right` by key, and join their records with equal
+    /// keys
+    ///
+    /// Both inputs are sorted with this sorter's configuration. While
+    /// joining, the right records of the current key are held in memory up to
+    /// the memory buffer; larger groups are written to disk and read again
+    /// for every left record of the key.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    pub fn join<R, K, LI, RI, LK, RK>(&self, left: LI, right: RI, left_key: LK, right_key: RK,
+                                      kind: JoinKind)
+                                      -> Result<JoinedIterator<L, R, K>, Box<dyn Error>>
+    where
+        L: 'static,
+        R: 'static + ExternallySortable + Send,
+        K: 'static + Ord,
+        LI: Iterator<Item = L>,
+        RI: Iterator<Item = R>,
+        LK: 'static + Fn(&L) -> K + Send + Sync,
+        RK: 'static + Fn(&R) -> K + Send + Sync,
+    {
+        let left_key: Arc<KeyFn<L, K>> = Arc::new(left_key);
+        let right_key: Arc<KeyFn<R, K>> = Arc::new(right_key);
+        let sorter = self.retype::<R>();
+        let key = left_key.clone();
+        let left = self.sort_by_sync(left, move |a, b| key(a).cmp(&key(b)))?;
+        let key = right_key.clone();
+        let right = sorter.sort_by_sync(right, move |a, b| key(a).cmp(&key(b)))?;
+
+        Ok(JoinedIterator {
+            left: left.peekable(),
+            right: right.peekable(),
+            left_key,
+            right_key,
+            kind,
+            group: None,
+            matches: None,
+            sorter,
+            failed: false,
+        })
+    }
+}
+
+impl<L, R, K> JoinedIterator<L, R, K>
+where
+    L: ExternallySortable,
+    R: ExternallySortable,
+    K: Ord,
+{
+    /// Yield the next pair of the joined output, with the error type of the
+    /// iterator
+    fn next_pair(&mut self) -> Result<Option<Pair<L, R>>, Box<dyn Error>> {
+        loop {
+            if let Some(pair) = self.next_match()? {
+                return Ok(Some(pair));
+            }
+            let left_key = match self.left.peek() {
+                Some(Ok(l)) => Some((self.left_key)(l)),
+                Some(Err(_)) => return Err(self.left.next().unwrap().err().unwrap()),
+                None => None,
+            };
+
+            // done with the current group once the left records move past it
+            let group_done = match (&self.group, &left_key) {
+                (Some((key, _, _)), Some(left_key)) => key < left_key,
+                (Some(_), None) => true,
+                (None, _) => false,
+            };
+            if group_done {
+                // unwrap due to the check above
+                let (_, group, matched) = self.group.take().unwrap();
+                if !matched && self.kind == JoinKind::Full {
+                    self.matches = Some(Matches { left: None, group, next: 0, lines: None });
+                }
+                continue;
+            }
+            if let (Some((key, group, matched)), Some(left_key)) = (&mut self.group, &left_key) {
+                if key == left_key {
+                    *matched = true;
+                    let group = group.clone();
+                    // unwrap due to the peek() above
+                    let left = Some(self.left.next().unwrap()?);
+                    self.matches = Some(Matches { left, group, next: 0, lines: None });
+                    continue;
+                }
+            }
+
+            let right_key = match self.right.peek() {
+                Some(Ok(r)) => Some((self.right_key)(r)),
+                Some(Err(_)) => return Err(self.right.next().unwrap().err().unwrap()),
+                None => None,
+            };
+            match (left_key, right_key) {
+                (None, None) => return Ok(None),
+                // the remaining right records can't match anything
+                (None, Some(_)) if self.kind != JoinKind::Full => return Ok(None),
+                (Some(left_key), Some(right_key)) if left_key == right_key => {
+                    let group = self.read_group(&right_key)?;
+                    self.group = Some((right_key, Arc::new(group), false));
+                },
+                (Some(left_key), right_key) if right_key.as_ref().is_none_or(|r| left_key < *r) => {
+                    // unwrap due to the peek() above
+                    let l = self.left.next().unwrap()?;
+                    if self.kind != JoinKind::Inner {
+                        return Ok(Some((Some(l), None)));
+                    }
+                },
+                (_, _) => {
+                    // unwrap due to the peek() above
+                    let r = self.right.next().unwrap()?;
+                    if self.kind == JoinKind::Full {
+                        return Ok(Some((None, Some(r))));
+                    }
+                },
+            }
+        }
+    }
+
+    /// Read the right records with the key `key` into a group
+    fn read_group(&mut self, key: &K) -> Result<Group<R>, Box<dyn Error>> {
+        let mut group = Vec::new();
+        let mut total_read = 0;
+        let mut spilled: Option<(TempDir, PathBuf, BufWriter<File>)> = None;
+        let right_key = self.right_key.clone();
+        while self.right
+                  .peek()
+                  .is_some_and(|r| r.as_ref().map_or(true, |r| right_key(r) == *key))
+        {
+            // unwrap due to the peek() above
+            let r = self.right.next().unwrap()?;
+            if let Some((_, _, ref mut file)) = spilled {
+                file.write_all(to_line(&r).map_err(|e| e as Box<dyn Error>)?.as_bytes())?;
+                continue;
+            }
+            total_read += r.get_size();
+            group.push(r);
+            if total_read > self.sorter.buffer_bytes {
+                let tmp_dir = self.sorter.make_tmp_dir().map_err(|e| e as Box<dyn Error>)?;
+                let path = tmp_dir.path().join("group");
+                let mut file = BufWriter::new(File::create(&path)?);
+                for r in group.drain(..) {
+                    file.write_all(to_line(&r).map_err(|e| e as Box<dyn Error>)?.as_bytes())?;
+                }
+                spilled = Some((tmp_dir, path, file));
+            }
+        }
+
+        Ok(match spilled {
+            Some((tmp_dir, path, mut file)) => {
+                file.flush()?;
+                Group::Spilled(tmp_dir, path)
+            },
+            None => Group::Memory(group),
+        })
+    }
+
+    /// Yield the next pair of the current matches, if any
+    fn next_match(&mut self) -> Result<Option<Pair<L, R>>, Box<dyn Error>> {
+        let matches = match self.matches {
+            Some(ref mut matches) => matches,
+            None => return Ok(None),
+        };
+        match matches.next_right()? {
+            Some(r) => Ok(Some((matches.left.clone(), Some(r)))),
+            None => {
+                self.matches = None;
+                Ok(None)
+            },
+        }
+    }
+}
+
+impl<L, R, K> Iterator for JoinedIterator<L, R, K>
+where
+    L: ExternallySortable,
+    R: ExternallySortable,
+    K: Ord,
+{
+    type Item = Result<Pair<L, R>, Box<dyn Error>>;
+
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// from disk, due to issues writing or reading large groups of right
+    /// records, or due to serde (de)serialization issues
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.next_pair() {
+            Ok(pair) => pair.map(Ok),
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
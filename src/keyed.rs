@@ -0,0 +1,131 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::io::{self, Write};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::{ExtSortedIterator, ExternalSorter, ExternallySortable};
+
+/// A record made of a sort key and a payload, ordered (and equal) by its key
+/// only
+///
+/// Deriving `Ord` for a record compares all of its fields, so that records
+/// with equal keys are ordered by their payloads as well, and a manual `Ord`
+/// impl is easy to get wrong. The payload of a `SortKeyed` needs no ordering
+/// at all, and records with equal keys keep their input order when sorted,
+/// as [dedup](struct.ExtSortedIterator.html#method.dedup) does when it keeps
+/// the first record of each key. Records serialize as a `key` and a `value`
+/// field, and are sized by the length of their serialization, which counts
+/// the data the key and payload hold on the heap.
+///
+/// # Examples
+///
+/// ```
+/// use external_sort::{ExternalSorter, SortKeyed};
+///
+/// let pairs = vec![(3, "c"), (1, "b"), (1, "a"), (2, "d")];
+/// let pairs = pairs.into_iter().map(|(key, value)| (key, value.to_string()));
+/// let sorted = ExternalSorter::new(1024, None).sort_pairs(pairs).unwrap();
+/// let values: Vec<String> = sorted.map(|r| r.unwrap().value).collect();
+/// assert_eq!(values, ["b", "a", "d", "c"]);
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SortKeyed<K, V> {
+    /// Key the record is sorted by
+    pub key: K,
+    /// Payload of the record
+    pub value: V,
+}
+
+impl<K, V> SortKeyed<K, V> {
+    /// Create a record sorted by `key` and carrying `value`
+    pub fn new(key: K, value: V) -> SortKeyed<K, V> {
+        SortKeyed { key, value }
+    }
+
+    /// Split the record into its key and payload
+    pub fn into_pair(self) -> (K, V) {
+        (self.key, self.value)
+    }
+}
+
+impl<K, V> From<(K, V)> for SortKeyed<K, V> {
+    fn from((key, value): (K, V)) -> SortKeyed<K, V> {
+        SortKeyed { key, value }
+    }
+}
+
+impl<K: Ord, V> PartialEq for SortKeyed<K, V> {
+    fn eq(&self, other: &SortKeyed<K, V>) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K: Ord, V> Eq for SortKeyed<K, V> {}
+
+impl<K: Ord, V> PartialOrd for SortKeyed<K, V> {
+    fn partial_cmp(&self, other: &SortKeyed<K, V>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, V> Ord for SortKeyed<K, V> {
+    fn cmp(&self, other: &SortKeyed<K, V>) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+impl<K, V> ExternallySortable for SortKeyed<K, V>
+where
+    K: Ord + Clone + Serialize + DeserializeOwned,
+    V: Clone + Serialize + DeserializeOwned,
+{
+    fn get_size(&self) -> u64 {
+        let mut counter = ByteCounter(0);
+        match serde_json::to_writer(&mut counter, self) {
+            Ok(()) => counter.0,
+            // the record fails to serialize when it is spilled anyway
+            Err(_) => 1,
+        }
+    }
+}
+
+/// Writer counting the bytes written to it, and dropping them
+struct ByteCounter(u64);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<K, V> ExternalSorter<SortKeyed<K, V>>
+where
+    K: Ord + Clone + Serialize + DeserializeOwned,
+    V: Clone + Serialize + DeserializeOwned,
+{
+    /// Sort the `(key, payload)` pairs provided by `pairs` by key only, and
+    /// return a sorted (ascending) iterator of
+    /// [SortKeyed](struct.SortKeyed.html) records
+    ///
+    /// Pairs with equal keys are yielded in input order, unless the sorter
+    /// has another [tie_break](#method.tie_break) policy.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    pub fn sort_pairs<I>(&self, pairs: I)
+                         -> Result<ExtSortedIterator<SortKeyed<K, V>>, Box<dyn Error>>
+    where
+        I: Iterator<Item = (K, V)>,
+    {
+        self.sort(pairs.map(SortKeyed::from))
+    }
+}
@@ -0,0 +1,193 @@
+//! Order-preserving byte encodings of keys, for sorting records by composite
+//! keys in byte order (as with `Vec<u8>` records, or the C and Python APIs)
+//!
+//! The encoding of a key compares (byte by byte) the same way as the key
+//! itself: integers are written big-endian with their sign bit flipped,
+//! floats in their total order (as `total_cmp()`), and strings and bytes with
+//! their NUL bytes escaped and a terminator, so that no encoding is a prefix
+//! of another. Tuples concatenate the encodings of their fields, and so
+//! compare field by field.
+//!
+//! # Examples
+//!
+//! ```
+//! use external_sort::keyenc::{self, Descending};
+//!
+//! let a = keyenc::encode(&("bob", -3i32, Descending(2.5f64)));
+//! let b = keyenc::encode(&("bob", -3i32, Descending(1.0f64)));
+//! let c = keyenc::encode(&("bobby", -10i32, Descending(9.0f64)));
+//! assert!(a < b && b < c);
+//! ```
+
+/// Key with an order-preserving byte encoding
+pub trait EncodeKey {
+    /// Append the encoding of this key to `out`
+    fn encode_key(&self, out: &mut Vec<u8>);
+}
+
+/// Encode `key` into a new buffer
+pub fn encode<K>(key: &K) -> Vec<u8>
+where
+    K: EncodeKey + ?Sized,
+{
+    let mut out = Vec::new();
+    key.encode_key(&mut out);
+    out
+}
+
+/// Key encoded in descending order, by complementing the bytes of its
+/// encoding
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Descending<K>(pub K);
+
+impl<K> EncodeKey for Descending<K>
+where
+    K: EncodeKey,
+{
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        self.0.encode_key(out);
+        // as no encoding is a prefix of another, complementing every byte
+        // reverses the order
+        for byte in &mut out[start..] {
+            *byte = !*byte;
+        }
+    }
+}
+
+macro_rules! encode_unsigned {
+    ($($t:ty)*) => {$(
+        impl EncodeKey for $t {
+            fn encode_key(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_be_bytes());
+            }
+        }
+    )*};
+}
+
+encode_unsigned!(u8 u16 u32 u64 u128);
+
+macro_rules! encode_signed {
+    ($($t:ty: $u:ty)*) => {$(
+        impl EncodeKey for $t {
+            fn encode_key(&self, out: &mut Vec<u8>) {
+                // flipping the sign bit puts negative numbers first
+                (*self as $u ^ (1 << (<$u>::BITS - 1))).encode_key(out);
+            }
+        }
+    )*};
+}
+
+encode_signed!(i8: u8 i16: u16 i32: u32 i64: u64 i128: u128);
+
+impl EncodeKey for usize {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        // the same on every platform
+        (*self as u64).encode_key(out);
+    }
+}
+
+impl EncodeKey for isize {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        (*self as i64).encode_key(out);
+    }
+}
+
+impl EncodeKey for bool {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+}
+
+macro_rules! encode_float {
+    ($($t:ty: $u:ty)*) => {$(
+        impl EncodeKey for $t {
+            fn encode_key(&self, out: &mut Vec<u8>) {
+                let bits = self.to_bits();
+                let sign = 1 << (<$u>::BITS - 1);
+                // negative numbers (with the sign bit set) are ordered by
+                // decreasing magnitude
+                let ordered = if bits & sign != 0 { !bits } else { bits ^ sign };
+                ordered.encode_key(out);
+            }
+        }
+    )*};
+}
+
+encode_float!(f32: u32 f64: u64);
+
+impl EncodeKey for [u8] {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        // NUL bytes are escaped as 0x00 0xff, and the end is 0x00 0x01, so
+        // that shorter strings sort before those that they are a prefix of
+        for &byte in self {
+            out.push(byte);
+            if byte == 0 {
+                out.push(0xff);
+            }
+        }
+        out.extend_from_slice(&[0, 1]);
+    }
+}
+
+impl EncodeKey for Vec<u8> {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        self.as_slice().encode_key(out);
+    }
+}
+
+impl EncodeKey for str {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        self.as_bytes().encode_key(out);
+    }
+}
+
+impl EncodeKey for String {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        self.as_bytes().encode_key(out);
+    }
+}
+
+impl<K> EncodeKey for Option<K>
+where
+    K: EncodeKey,
+{
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        // `None` sorts first
+        match *self {
+            Some(ref key) => {
+                out.push(1);
+                key.encode_key(out);
+            },
+            None => out.push(0),
+        }
+    }
+}
+
+impl<K> EncodeKey for &K
+where
+    K: EncodeKey + ?Sized,
+{
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        (**self).encode_key(out);
+    }
+}
+
+macro_rules! encode_tuple {
+    ($($name:ident)+) => {
+        impl<$($name: EncodeKey),+> EncodeKey for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode_key(&self, out: &mut Vec<u8>) {
+                let ($(ref $name,)+) = *self;
+                $($name.encode_key(out);)+
+            }
+        }
+    };
+}
+
+encode_tuple!(A);
+encode_tuple!(A B);
+encode_tuple!(A B C);
+encode_tuple!(A B C D);
+encode_tuple!(A B C D E);
+encode_tuple!(A B C D E F);
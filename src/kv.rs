@@ -0,0 +1,77 @@
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{DedupPolicy, ExternalSorter, ExternallySortable};
+
+/// Key-value pair of raw bytes, as ingested by key-value stores
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct KeyValue {
+    /// Key of the pair
+    pub key: Vec<u8>,
+    /// Value of the pair
+    pub value: Vec<u8>,
+}
+
+impl ExternallySortable for Vec<u8> {
+    fn get_size(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl ExternallySortable for KeyValue {
+    fn get_size(&self) -> u64 {
+        (self.key.len() + self.value.len()) as u64
+    }
+}
+
+/// What [bulk_load](struct.ExternalSorter.html#method.bulk_load) does with
+/// pairs that share a key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DuplicateKeys {
+    /// Fail at the first duplicate key
+    Fail,
+    /// Keep the pair provided first
+    KeepFirst,
+    /// Keep the pair provided last
+    KeepLast,
+}
+
+impl ExternalSorter<KeyValue> {
+    /// Sort the key-value pairs provided by `pairs` by key, and pass them to
+    /// `write` in strictly increasing key order, e.g. to build a sorted
+    /// table file for bulk ingestion into a key-value store
+    ///
+    /// Pairs that share a key are resolved according to `duplicates`, in the
+    /// order the pairs were provided, and the number of pairs dropped as
+    /// duplicates is returned.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing or reading intermediate
+    /// sorted chunks, due to serde (de)serialization issues, if `write` fails,
+    /// or at the first duplicate key with [DuplicateKeys::Fail]
+    pub fn bulk_load<I, F>(&self, pairs: I, duplicates: DuplicateKeys, mut write: F)
+                           -> Result<u64, Box<dyn Error>>
+    where
+        I: Iterator<Item = KeyValue>,
+        F: FnMut(&[u8], &[u8]) -> Result<(), Box<dyn Error>>,
+    {
+        let policy = match duplicates {
+            DuplicateKeys::KeepLast => DedupPolicy::KeepLast,
+            DuplicateKeys::Fail | DuplicateKeys::KeepFirst => DedupPolicy::KeepFirst,
+        };
+        let iter = self.sort_by_sync(pairs, |a, b| a.key.cmp(&b.key))?.dedup_policy(policy);
+        let mut dropped = 0;
+        for pair in iter.counts() {
+            let (pair, count) = pair?;
+            if count > 1 && duplicates == DuplicateKeys::Fail {
+                return Err(format!("duplicate key {:?}", pair.key).into());
+            }
+            dropped += count - 1;
+            write(&pair.key, &pair.value)?;
+        }
+
+        Ok(dropped)
+    }
+}
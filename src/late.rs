@@ -0,0 +1,145 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fs::File;
+use std::io::SeekFrom::Start;
+use std::io::{BufWriter, Read, Seek, Write};
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tempdir::TempDir;
+
+use crate::{ExtSortedIterator, ExternalSorter, ExternallySortable};
+
+/// Name of the file staging the payloads of a sort
+const PAYLOADS_FILE: &str = "payloads";
+
+/// Sort key of a record, along with where it is staged in the payload file
+#[derive(Serialize, Deserialize, Clone)]
+struct PayloadRef<K> {
+    key: K,
+    offset: u64,
+    len: u64,
+}
+
+impl<K: Ord> PartialEq for PayloadRef<K> {
+    fn eq(&self, other: &PayloadRef<K>) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K: Ord> Eq for PayloadRef<K> {}
+
+impl<K: Ord> PartialOrd for PayloadRef<K> {
+    fn partial_cmp(&self, other: &PayloadRef<K>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord> Ord for PayloadRef<K> {
+    fn cmp(&self, other: &PayloadRef<K>) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+impl<K> ExternallySortable for PayloadRef<K>
+where
+    K: Ord + Clone + Serialize + DeserializeOwned,
+{
+    fn get_size(&self) -> u64 {
+        mem::size_of::<PayloadRef<K>>() as u64
+    }
+}
+
+/// Iterator that provides the `T`s sorted by
+/// [ExternalSorter::sort_by_key_late](struct.ExternalSorter.html#method.sort_by_key_late),
+/// reading each of them back from the payload file as it is yielded
+pub struct MaterializedIterator<T, K> {
+    keys: ExtSortedIterator<PayloadRef<K>>,
+    payloads: File,
+    /// Serialized payload read last
+    payload: Vec<u8>,
+    /// Directory of the payload file, removed along with the iterator
+    _tmp_dir: Arc<TempDir>,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<T, K> Iterator for MaterializedIterator<T, K>
+where
+    T: DeserializeOwned,
+    K: Ord + Clone + Serialize + DeserializeOwned + Send,
+{
+    type Item = Result<T, Box<dyn Error>>;
+
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// or payloads from disk, or due to serde deserialization issues
+    fn next(&mut self) -> Option<Self::Item> {
+        let payload = match self.keys.next()? {
+            Ok(payload) => payload,
+            Err(e) => return Some(Err(e)),
+        };
+        let mut read = || -> Result<T, Box<dyn Error>> {
+            self.payloads.seek(Start(payload.offset))?;
+            self.payload.resize(payload.len as usize, 0);
+            self.payloads.read_exact(&mut self.payload)?;
+            Ok(serde_json::from_slice(&self.payload)?)
+        };
+        Some(read())
+    }
+}
+
+impl<T> ExternalSorter<T>
+where
+    T: ExternallySortable,
+{
+    /// Sort the `T`s provided by `unsorted` by the key that `key` extracts from
+    /// each of them, spilling and merging only the keys, and return an
+    /// iterator reading the records back in sorted order
+    ///
+    /// Every record is serialized once to a payload file as it is read, and
+    /// only its key and position in that file are sorted, so records with
+    /// small keys and large bodies spill, compare and merge a fraction of
+    /// their size. The payloads are then read back one by one in sorted order,
+    /// which is a random read per record. Records with equal keys keep their
+    /// input order.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing the payloads or
+    /// intermediate sorted chunks to disk, or due to serde serialization
+    /// issues
+    pub fn sort_by_key_late<I, K, KF>(&self, unsorted: I, key: KF)
+                                      -> Result<MaterializedIterator<T, K>, Box<dyn Error>>
+    where
+        I: Iterator<Item = T>,
+        K: Ord + Clone + Serialize + DeserializeOwned + Send,
+        KF: Fn(&T) -> K,
+    {
+        let tmp_dir = Arc::new(self.make_tmp_dir().map_err(|e| e as Box<dyn Error>)?);
+        let path = tmp_dir.path().join(PAYLOADS_FILE);
+        let mut staged = BufWriter::new(File::create(&path)?);
+        let mut offset = 0;
+        let keys = unsorted.map(|t| -> Result<_, Box<dyn Error>> {
+                               let payload = serde_json::to_vec(&t)?;
+                               staged.write_all(&payload)?;
+                               let len = payload.len() as u64;
+                               offset += len;
+                               Ok(PayloadRef { key: key(&t), offset: offset - len, len })
+                           });
+        let keys = self.retype::<PayloadRef<K>>().try_sort(keys)?;
+        staged.flush()?;
+
+        Ok(MaterializedIterator {
+            keys,
+            payloads: File::open(&path)?,
+            payload: Vec::new(),
+            _tmp_dir: tmp_dir,
+            phantom: PhantomData,
+        })
+    }
+}
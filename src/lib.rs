@@ -2,6 +2,100 @@
 
 //! Provides the ability to perform external sorts on structs
 
+mod align;
+mod arena;
+mod argsort;
+mod capture;
+mod checksum;
+mod codec;
+#[cfg(feature = "csv")]
+mod csv_record;
+mod decimal;
+mod diff;
+mod dyn_sorter;
 mod external_sort;
+mod fallible;
+#[cfg(feature = "testing")]
+mod fault;
+mod float;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod group;
+mod inspect;
+mod ioprio;
+mod join;
+pub mod keyenc;
+mod keyed;
+mod kv;
+mod late;
+mod lines;
+mod log_merge;
+#[cfg(feature = "mmap")]
+mod mapped;
+mod memory;
+mod merge_plan;
+mod nulls;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod partition;
+mod pool;
+mod prefetch;
+#[cfg(feature = "pressure")]
+mod pressure;
+mod progress;
+#[cfg(feature = "indicatif")]
+mod progress_bar;
+#[cfg(feature = "python")]
+mod python;
+mod reader;
+mod reduce;
+mod select;
+mod session;
+mod set;
+mod shuffle;
+mod sink;
+mod store;
+mod tag;
+mod timing;
+mod version;
 
-pub use crate::external_sort::{ExtSortedIterator, ExternalSorter, ExternallySortable};
+pub use crate::align::{AlignedIterator, EitherOrBoth};
+pub use crate::arena::ArenaStr;
+pub use crate::argsort::ArgsortIterator;
+pub use crate::codec::{Decoder, Encoder, JsonLines};
+#[cfg(feature = "csv")]
+pub use crate::csv_record::{CsvByteRecord, CsvOrder, CsvRecord};
+pub use crate::decimal::Decimal;
+pub use crate::diff::{Change, DiffIterator};
+pub use crate::dyn_sorter::{DynCompareFn, DynSorted, DynSorter};
+pub use crate::external_sort::{CountedIterator, DedupPolicy, ExtSortedIterator, ExternalSorter,
+                               ExternallySortable, RankedIterator, SortConfig, SortStats,
+                               SortedRun, TieBreak};
+#[cfg(feature = "testing")]
+pub use crate::fault::Fault;
+pub use crate::float::{by_f64_key, cmp_f32, cmp_f64, NanOrder, TotalF32, TotalF64};
+pub use crate::group::GroupedIterator;
+pub use crate::inspect::{inspect_run, ManifestEntry, RunBlock, RunInfo};
+pub use crate::ioprio::IoPriority;
+pub use crate::join::{JoinKind, JoinedIterator};
+pub use crate::keyed::SortKeyed;
+pub use crate::kv::{DuplicateKeys, KeyValue};
+pub use crate::late::MaterializedIterator;
+pub use crate::lines::{JsonKey, KeyedLine, TextRecords};
+pub use crate::log_merge::MergedLines;
+#[cfg(feature = "mmap")]
+pub use crate::mapped::MappedBytes;
+pub use crate::merge_plan::{DryRun, MergePlan, RunSummary, SortPlan};
+pub use crate::nulls::{cmp_nullable, NullOrder};
+pub use crate::partition::{PartitionOrder, PartitionedIterator};
+pub use crate::pool::BufferAllocator;
+pub use crate::prefetch::PrefetchIterator;
+pub use crate::progress::{Progress, ProgressPhase};
+pub use crate::reader::{SeekIterator, SortedReader};
+pub use crate::reduce::ReducedIterator;
+pub use crate::session::SortSession;
+pub use crate::set::SetIterator;
+pub use crate::sink::SortSink;
+pub use crate::store::SortedStore;
+pub use crate::timing::PhaseTimings;
+pub use crate::version::compare_versions;
@@ -0,0 +1,318 @@
+use std::cmp::Ordering::{self, Equal};
+use std::error::Error;
+use std::io::{self, BufRead, ErrorKind, Write};
+use std::mem;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{ExtSortedIterator, ExternalSorter, ExternallySortable};
+
+/// A line of text along with its sort key, sorted by
+/// [ExternalSorter::sort_lines_by_key](struct.ExternalSorter.html#method.sort_lines_by_key)
+///
+/// Lines are ordered (and equal) by key only, and are written to the sorted
+/// chunks as opaque strings, without being parsed again.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct KeyedLine<K> {
+    /// Key of the line
+    pub key: K,
+    /// The line, without its line ending
+    pub line: String,
+}
+
+impl<K: Ord> PartialEq for KeyedLine<K> {
+    fn eq(&self, other: &KeyedLine<K>) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K: Ord> Eq for KeyedLine<K> {}
+
+impl<K: Ord> PartialOrd for KeyedLine<K> {
+    fn partial_cmp(&self, other: &KeyedLine<K>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord> Ord for KeyedLine<K> {
+    fn cmp(&self, other: &KeyedLine<K>) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+impl<K> ExternallySortable for KeyedLine<K>
+where
+    K: Ord + Clone + Serialize + DeserializeOwned,
+{
+    fn get_size(&self) -> u64 {
+        (mem::size_of::<K>() + self.line.len()) as u64
+    }
+}
+
+/// A JSON value used as a sort key, ordered by type (null, booleans, numbers,
+/// strings, arrays, then objects) and then by value
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct JsonKey(pub Value);
+
+impl PartialEq for JsonKey {
+    fn eq(&self, other: &JsonKey) -> bool {
+        self.cmp(other) == Equal
+    }
+}
+
+impl Eq for JsonKey {}
+
+impl PartialOrd for JsonKey {
+    fn partial_cmp(&self, other: &JsonKey) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for JsonKey {
+    fn cmp(&self, other: &JsonKey) -> Ordering {
+        compare_values(&self.0, &other.0)
+    }
+}
+
+fn compare_values(a: &Value, b: &Value) -> Ordering {
+    fn rank(value: &Value) -> u8 {
+        match *value {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Number(_) => 2,
+            Value::String(_) => 3,
+            Value::Array(_) => 4,
+            Value::Object(_) => 5,
+        }
+    }
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => {
+                let (a, b) = (a.as_f64().unwrap_or(0.0), b.as_f64().unwrap_or(0.0));
+                a.partial_cmp(&b).unwrap_or(Equal)
+            },
+        },
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Array(a), Value::Array(b)) => {
+            a.iter()
+             .zip(b)
+             .map(|(a, b)| compare_values(a, b))
+             .find(|o| *o != Equal)
+             .unwrap_or_else(|| a.len().cmp(&b.len()))
+        },
+        (Value::Object(_), Value::Object(_)) => a.to_string().cmp(&b.to_string()),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+/// Iterator over the records of a text input, each ended by a terminator
+/// byte (or by the end of the input), without their terminators
+///
+/// With a newline terminator, a carriage return before it is dropped as well,
+/// so CRLF input reads the same as LF input. Records that are not valid UTF-8
+/// are errors.
+pub struct TextRecords<R> {
+    input: R,
+    terminator: u8,
+}
+
+impl<R> TextRecords<R>
+where
+    R: BufRead,
+{
+    /// Read the records of `input`, ended by `terminator`
+    pub fn new(input: R, terminator: u8) -> TextRecords<R> {
+        TextRecords { input, terminator }
+    }
+}
+
+impl<R> Iterator for TextRecords<R>
+where
+    R: BufRead,
+{
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<io::Result<String>> {
+        let mut record = Vec::new();
+        match self.input.read_until(self.terminator, &mut record) {
+            Ok(0) => return None,
+            Ok(_) => {},
+            Err(e) => return Some(Err(e)),
+        }
+        if record.last() == Some(&self.terminator) {
+            record.pop();
+            if self.terminator == b'\n' && record.last() == Some(&b'\r') {
+                record.pop();
+            }
+        }
+        Some(String::from_utf8(record).map_err(|e| io::Error::new(ErrorKind::InvalidData, e)))
+    }
+}
+
+impl ExternallySortable for String {
+    fn get_size(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl ExternalSorter<String> {
+    /// Sort the lines of all of `inputs` together, without their line
+    /// endings (or other [terminator](#method.terminator)), like `sort(1)`
+    /// given several files
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading the inputs (including lines
+    /// that are not valid UTF-8), writing intermediate sorted chunks to disk,
+    /// or due to serde serialization issues
+    pub fn sort_lines<I, R>(&self, inputs: I) -> Result<ExtSortedIterator<String>, Box<dyn Error>>
+    where
+        I: IntoIterator<Item = R>,
+        R: BufRead,
+    {
+        self.sort_lines_by(inputs, |a: &String, b: &String| a.cmp(b))
+    }
+
+    /// Sort the lines of all of `inputs` together, without their line
+    /// endings, using a custom compare function
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading the inputs (including lines
+    /// that are not valid UTF-8), writing intermediate sorted chunks to disk,
+    /// or due to serde serialization issues
+    pub fn sort_lines_by<I, R, F>(&self, inputs: I, compare: F)
+                                  -> Result<ExtSortedIterator<String>, Box<dyn Error>>
+    where
+        I: IntoIterator<Item = R>,
+        R: BufRead,
+        F: 'static + Fn(&String, &String) -> Ordering + Send + Sync,
+    {
+        let terminator = self.terminator;
+        let lines = inputs.into_iter().flat_map(|input| TextRecords::new(input, terminator));
+        self.try_sort_by(lines, compare)
+    }
+
+    /// Sort the lines of all of `inputs` together by the key that `key`
+    /// extracts from each of them, treating the lines as opaque payloads
+    ///
+    /// Only the keys are compared, and the lines are written to the sorted
+    /// chunks as they were read, so records never round-trip through typed
+    /// serde. Lines with equal keys keep their input order.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading the inputs, writing
+    /// intermediate sorted chunks to disk, due to serde serialization issues,
+    /// or at the first line `key` fails on
+    pub fn sort_lines_by_key<I, R, K, KF>(&self, inputs: I, mut key: KF)
+                                          -> Result<ExtSortedIterator<KeyedLine<K>>, Box<dyn Error>>
+    where
+        I: IntoIterator<Item = R>,
+        R: BufRead,
+        K: Ord + Clone + Serialize + DeserializeOwned + Send,
+        KF: FnMut(&str) -> Result<K, Box<dyn Error>>,
+    {
+        let terminator = self.terminator;
+        let lines = inputs.into_iter()
+                          .flat_map(|input| TextRecords::new(input, terminator))
+                          .enumerate()
+                          .map(|(i, line)| {
+                              line.map_err(Box::<dyn Error>::from)
+                                  .and_then(|line| Ok(KeyedLine { key: key(&line)?, line }))
+                                  .map_err(|e| format!("line {}: {}", i + 1, e))
+                          });
+        self.retype::<KeyedLine<K>>().try_sort(lines)
+    }
+
+    /// Sort the newline-delimited JSON records of all of `inputs` together by
+    /// the value at the JSON `pointer` (e.g. `/user/id`), without
+    /// re-serializing them
+    ///
+    /// Records without a value at `pointer` sort first, as `null`. Blank
+    /// lines are kept, and also sort as `null`.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading the inputs, writing
+    /// intermediate sorted chunks to disk, or due to serde issues, including
+    /// lines that are not valid JSON
+    pub fn sort_json_lines<I, R>(&self, inputs: I, pointer: &str)
+                                 -> Result<ExtSortedIterator<KeyedLine<JsonKey>>, Box<dyn Error>>
+    where
+        I: IntoIterator<Item = R>,
+        R: BufRead,
+    {
+        self.sort_lines_by_key(inputs, |line| json_key(line, pointer))
+    }
+}
+
+/// Find the key of a JSON line at `pointer`, which is `null` for blank lines
+/// and missing values
+pub(crate) fn json_key(line: &str, pointer: &str) -> Result<JsonKey, Box<dyn Error>> {
+    if line.trim().is_empty() {
+        return Ok(JsonKey(Value::Null));
+    }
+    let value: Value = serde_json::from_str(line)?;
+    Ok(JsonKey(value.pointer(pointer).cloned().unwrap_or(Value::Null)))
+}
+
+impl ExtSortedIterator<String> {
+    /// Write the remaining sorted lines to `output`, each followed by a
+    /// newline (or the [terminator](struct.ExternalSorter.html#method.terminator)
+    /// of the sorter), returning the number of lines written
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// from disk or writing the output, or due to serde deserialization issues
+    pub fn write_lines<W>(self, mut output: W) -> Result<u64, Box<dyn Error>>
+    where
+        W: Write,
+    {
+        let terminator = [self.terminator];
+        let mut lines = 0;
+        for line in self {
+            output.write_all(line?.as_bytes())?;
+            output.write_all(&terminator)?;
+            lines += 1;
+        }
+        output.flush()?;
+
+        Ok(lines)
+    }
+}
+
+impl<K> ExtSortedIterator<KeyedLine<K>>
+where
+    K: Ord + Clone + Serialize + DeserializeOwned + Send,
+{
+    /// Write the remaining sorted lines (without their keys) to `output`, each
+    /// followed by a newline (or the
+    /// [terminator](struct.ExternalSorter.html#method.terminator) of the
+    /// sorter), returning the number of lines written
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// from disk or writing the output, or due to serde deserialization issues
+    pub fn write_lines<W>(self, mut output: W) -> Result<u64, Box<dyn Error>>
+    where
+        W: Write,
+    {
+        let terminator = [self.terminator];
+        let mut lines = 0;
+        for keyed in self {
+            output.write_all(keyed?.line.as_bytes())?;
+            output.write_all(&terminator)?;
+            lines += 1;
+        }
+        output.flush()?;
+
+        Ok(lines)
+    }
+}
@@ -0,0 +1,168 @@
+use std::cmp::Ordering::Less;
+use std::error::Error;
+use std::io::{BufRead, Lines, Write};
+
+use crate::lines::json_key;
+use crate::{JsonKey, KeyedLine};
+
+/// Function extracting the key of a line
+type KeyFn<K> = dyn FnMut(&str) -> Result<K, Box<dyn Error>>;
+
+/// Iterator merging the lines of several inputs that are each already in
+/// order of a key (such as the timestamps of log files) into one ordered
+/// stream, created by [by_key](#method.by_key) or [json](#method.json)
+///
+/// Unlike [ExternalSorter::sort_lines_by_key](struct.ExternalSorter.html#method.sort_lines_by_key),
+/// the inputs are not sorted again: only their next lines are held in memory,
+/// and they are merged as they are read. Lines with equal keys are yielded
+/// in the order of the inputs.
+pub struct MergedLines<R, K> {
+    inputs: Vec<Lines<R>>,
+    /// Next line of each input along with its line number, if any
+    heads: Vec<Option<(usize, KeyedLine<K>)>>,
+    key: Box<KeyFn<K>>,
+    /// Error reading the line after the last one returned, returned next
+    error: Option<Box<dyn Error>>,
+    failed: bool,
+}
+
+impl<R, K> MergedLines<R, K>
+where
+    R: BufRead,
+    K: Ord,
+{
+    /// Merge the lines of `inputs`, without their line endings, by the key
+    /// that `key` extracts from each of them
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading the first line of the
+    /// inputs, or if `key` fails on one of them
+    pub fn by_key<I, KF>(inputs: I, key: KF) -> Result<MergedLines<R, K>, Box<dyn Error>>
+    where
+        I: IntoIterator<Item = R>,
+        KF: 'static + FnMut(&str) -> Result<K, Box<dyn Error>>,
+    {
+        let inputs: Vec<_> = inputs.into_iter().map(BufRead::lines).collect();
+        let mut merged = MergedLines {
+            heads: inputs.iter().map(|_| None).collect(),
+            inputs,
+            key: Box::new(key),
+            error: None,
+            failed: false,
+        };
+        for input in 0..merged.inputs.len() {
+            merged.advance(input, 0, None)?;
+        }
+
+        Ok(merged)
+    }
+
+    /// Read the line following line `line_num` of an input into its head
+    /// (which is empty), checking that it is not before `prev`
+    fn advance(&mut self, input: usize, line_num: usize, prev: Option<&K>)
+               -> Result<(), Box<dyn Error>> {
+        let line = match self.inputs[input].next() {
+            Some(line) => line?,
+            None => return Ok(()),
+        };
+        let line_num = line_num + 1;
+        let key = (self.key)(&line).map_err(|e| {
+                                       format!("input {}, line {}: {}", input + 1, line_num, e)
+                                   })?;
+        if prev.is_some_and(|prev| key < *prev) {
+            return Err(format!("input {}, line {}: out of order", input + 1, line_num).into());
+        }
+        self.heads[input] = Some((line_num, KeyedLine { key, line }));
+
+        Ok(())
+    }
+
+    /// Write the remaining merged lines (without their keys) to `output`,
+    /// each followed by a newline, returning the number of lines written
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading the inputs or writing the
+    /// output, if `key` fails on a line, or at the first line out of order
+    pub fn write_lines<W>(self, mut output: W) -> Result<u64, Box<dyn Error>>
+    where
+        W: Write,
+    {
+        let mut lines = 0;
+        for keyed in self {
+            output.write_all(keyed?.line.as_bytes())?;
+            output.write_all(b"\n")?;
+            lines += 1;
+        }
+        output.flush()?;
+
+        Ok(lines)
+    }
+}
+
+impl<R> MergedLines<R, JsonKey>
+where
+    R: BufRead,
+{
+    /// Merge the newline-delimited JSON records of `inputs` by the value at
+    /// the JSON `pointer` (e.g. `/timestamp`), without re-serializing them
+    ///
+    /// As with [ExternalSorter::sort_json_lines](struct.ExternalSorter.html#method.sort_json_lines),
+    /// records without a value at `pointer` and blank lines have a `null` key.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading the first line of the
+    /// inputs, or if one of them is not valid JSON
+    pub fn json<I>(inputs: I, pointer: &str) -> Result<MergedLines<R, JsonKey>, Box<dyn Error>>
+    where
+        I: IntoIterator<Item = R>,
+    {
+        let pointer = pointer.to_string();
+        MergedLines::by_key(inputs, move |line| json_key(line, &pointer))
+    }
+}
+
+impl<R, K> Iterator for MergedLines<R, K>
+where
+    R: BufRead,
+    K: Ord,
+{
+    type Item = Result<KeyedLine<K>, Box<dyn Error>>;
+
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading the inputs, if `key` fails
+    /// on a line, or at the first line that is out of order within its input
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        if let Some(e) = self.error.take() {
+            self.failed = true;
+            return Some(Err(e));
+        }
+        let mut next: Option<usize> = None;
+        for (input, head) in self.heads.iter().enumerate() {
+            let head = match *head {
+                Some((_, ref head)) => head,
+                None => continue,
+            };
+            // check is_some() before unwrap()ing
+            if next.is_none_or(|n| head.key.cmp(&self.heads[n].as_ref().unwrap().1.key) == Less) {
+                next = Some(input);
+            }
+        }
+
+        let input = next?;
+        // unwrap due to the check above
+        let (line_num, keyed) = self.heads[input].take().unwrap();
+        if let Err(e) = self.advance(input, line_num, Some(&keyed.key)) {
+            self.error = Some(e);
+        }
+
+        Some(Ok(keyed))
+    }
+}
@@ -0,0 +1,191 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use memmap2::Mmap;
+use tempdir::TempDir;
+
+use crate::external_sort::footprint;
+use crate::ExternalSorter;
+
+/// Size of the length written before every record of a run
+const LEN_BYTES: usize = 8;
+
+/// Alignment of every record (and its length) within a run, which is enough
+/// for Cap'n Proto messages to be read in place
+const RECORD_ALIGN: usize = 8;
+
+/// Function comparing two byte records
+type CompareBytes = dyn Fn(&[u8], &[u8]) -> Ordering + Send + Sync;
+
+/// Merge of byte records sorted by
+/// [ExternalSorter::sort_mapped](struct.ExternalSorter.html#method.sort_mapped),
+/// built with the `mmap` feature
+///
+/// The runs are memory-mapped, and the merge yields every record as a slice of
+/// the mapping of its run, without copying it. As a slice borrows the merge,
+/// records are read with [next_record](#method.next_record) rather than
+/// through `Iterator`. Every record starts at an offset of its mapping that
+/// is a multiple of 8 bytes.
+pub struct MappedBytes {
+    runs: Vec<Mmap>,
+    compare: Box<CompareBytes>,
+    /// Range of the next record of each run within its mapping, if any
+    heads: Vec<Option<Range<usize>>>,
+    // dropped after the mappings of its runs
+    _tmp_dir: TempDir,
+}
+
+impl MappedBytes {
+    /// Merge the next record out of the runs, in byte order (or the order of
+    /// [sort_mapped_by](struct.ExternalSorter.html#method.sort_mapped_by)),
+    /// or return `None` once all of them have been
+    pub fn next_record(&mut self) -> Option<&[u8]> {
+        let mut next: Option<usize> = None;
+        for (run, head) in self.heads.iter().enumerate() {
+            let head = match *head {
+                Some(ref head) => &self.runs[run][head.clone()],
+                None => continue,
+            };
+            // the first run holding the smallest record wins
+            let smaller = next.is_none_or(|n| {
+                                 let next = self.heads[n].clone().unwrap();
+                                 (self.compare)(head, &self.runs[n][next]) == Ordering::Less
+                             });
+            if smaller {
+                next = Some(run);
+            }
+        }
+
+        let run = next?;
+        // unwrap due to the check above
+        let record = self.heads[run].take().unwrap();
+        self.heads[run] = head(&self.runs[run], padded(record.end));
+        Some(&self.runs[run][record])
+    }
+
+    /// Write the remaining records to `output`, each followed by
+    /// `terminator`, returning the number of records written
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing the output
+    pub fn write_all<W>(&mut self, mut output: W, terminator: &[u8]) -> io::Result<u64>
+    where
+        W: Write,
+    {
+        let mut records = 0;
+        while let Some(record) = self.next_record() {
+            output.write_all(record)?;
+            output.write_all(terminator)?;
+            records += 1;
+        }
+        output.flush()?;
+
+        Ok(records)
+    }
+}
+
+/// Round `offset` up to the alignment of records
+fn padded(offset: usize) -> usize {
+    offset.div_ceil(RECORD_ALIGN) * RECORD_ALIGN
+}
+
+/// Find the range of the record of `run` starting at `offset`, if any
+fn head(run: &[u8], offset: usize) -> Option<Range<usize>> {
+    if offset >= run.len() {
+        return None;
+    }
+    let mut len = [0; LEN_BYTES];
+    len.copy_from_slice(&run[offset..offset + LEN_BYTES]);
+    let start = offset + LEN_BYTES;
+    Some(start..start + u64::from_le_bytes(len) as usize)
+}
+
+/// Sort `chunk` with `compare` and write it to the run at `path`, as raw
+/// records each preceded by their length and padded to the alignment of
+/// records
+fn write_run(chunk: &mut Vec<Vec<u8>>, path: &Path, compare: &CompareBytes) -> io::Result<()> {
+    chunk.sort_unstable_by(|a, b| compare(a, b));
+    let mut file = BufWriter::new(File::create(path)?);
+    let padding = [0; RECORD_ALIGN];
+    for record in chunk.drain(..) {
+        file.write_all(&(record.len() as u64).to_le_bytes())?;
+        file.write_all(&record)?;
+        file.write_all(&padding[..padded(record.len()) - record.len()])?;
+    }
+    file.flush()
+}
+
+impl ExternalSorter<Vec<u8>> {
+    /// Sort byte records in byte order, into runs written as raw bytes that
+    /// are memory-mapped for the merge, so that the sorted records can be
+    /// read as borrowed slices instead of allocated one by one
+    ///
+    /// This only uses the memory buffer and temporary directory of the
+    /// sorter, and sorts on the calling thread.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing the runs to disk or mapping
+    /// them into memory
+    pub fn sort_mapped<I>(&self, records: I) -> Result<MappedBytes, Box<dyn Error>>
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+    {
+        self.sort_mapped_by(records, |a, b| a.cmp(b))
+    }
+
+    /// Sort byte records based on `compare`, into memory-mapped runs like
+    /// [sort_mapped](#method.sort_mapped)
+    ///
+    /// This suits records that are messages of a binary format read in place,
+    /// such as Cap'n Proto or FlatBuffers: they are spilled as they are, and
+    /// `compare` reads the fields it orders by straight from their bytes, so
+    /// they are never decoded into owned records nor encoded again. Records
+    /// start at offsets of their run that are a multiple of 8 bytes. Records
+    /// that compare equal are merged in the order of their runs, but are not
+    /// kept in input order within a run.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing the runs to disk or mapping
+    /// them into memory
+    pub fn sort_mapped_by<I, F>(&self, records: I, compare: F)
+                                -> Result<MappedBytes, Box<dyn Error>>
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        F: 'static + Fn(&[u8], &[u8]) -> Ordering + Send + Sync,
+    {
+        let tmp_dir = self.make_tmp_dir().map_err(|e| e as Box<dyn Error>)?;
+        let mut paths = Vec::new();
+        let mut total_read = 0;
+        let mut chunk = Vec::new();
+        for record in records {
+            total_read += record.len() as u64;
+            chunk.push(record);
+            if footprint(&chunk, total_read) >= self.buffer_bytes {
+                paths.push(tmp_dir.path().join(paths.len().to_string()));
+                write_run(&mut chunk, paths.last().unwrap(), &compare)?;
+                total_read = 0;
+            }
+        }
+        if !chunk.is_empty() {
+            paths.push(tmp_dir.path().join(paths.len().to_string()));
+            write_run(&mut chunk, paths.last().unwrap(), &compare)?;
+        }
+
+        let mut runs = Vec::with_capacity(paths.len());
+        for path in paths {
+            // the runs are private to the temporary directory, and are never
+            // changed once written
+            runs.push(unsafe { Mmap::map(&File::open(path)?)? });
+        }
+        let heads = runs.iter().map(|run| head(run, 0)).collect();
+
+        Ok(MappedBytes { runs, compare: Box::new(compare), heads, _tmp_dir: tmp_dir })
+    }
+}
@@ -0,0 +1,38 @@
+//! Memory available to the process
+
+use std::fs;
+
+/// Memory assumed to be available where it cannot be detected
+pub(crate) const FALLBACK_MEMORY: u64 = 1 << 30;
+
+/// Memory available to the process, as the least of what is left under the
+/// limit of its cgroup and of the memory available system-wide, if known
+pub(crate) fn available_memory() -> Option<u64> {
+    match (system_available(), cgroup_available()) {
+        (Some(system), Some(cgroup)) => Some(system.min(cgroup)),
+        (system, cgroup) => system.or(cgroup),
+    }
+}
+
+/// Memory available system-wide, from `/proc/meminfo`
+fn system_available() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|l| l.starts_with("MemAvailable:"))?;
+    let kb: u64 = line["MemAvailable:".len()..].trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// Memory left under the limit of the cgroup of the process, with cgroup v2
+/// or v1
+fn cgroup_available() -> Option<u64> {
+    let read = |path: &str| fs::read_to_string(path).ok()?.trim().parse::<u64>().ok();
+    // an unlimited cgroup v2 has a limit of `max`
+    let (limit, usage) = match read("/sys/fs/cgroup/memory.max") {
+        Some(limit) => (limit, read("/sys/fs/cgroup/memory.current")?),
+        None => {
+            (read("/sys/fs/cgroup/memory/memory.limit_in_bytes")?,
+             read("/sys/fs/cgroup/memory/memory.usage_in_bytes")?)
+        },
+    };
+    Some(limit.saturating_sub(usage))
+}
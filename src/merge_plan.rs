@@ -0,0 +1,148 @@
+use std::iter;
+use std::ops::Range;
+
+/// Size of a sorted run, as described by a [MergePlan](struct.MergePlan.html)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RunSummary {
+    /// Number of records of the run
+    pub records: u64,
+    /// Total size of the records, as reported by `get_size()`
+    pub bytes: u64,
+}
+
+/// Plan of how the sorted runs of a sort are merged, passed to the hook set
+/// with [ExternalSorter::on_merge_plan](struct.ExternalSorter.html#method.on_merge_plan)
+///
+/// The runs are merged in passes. Each pass merges groups of adjacent runs
+/// left by the previous pass (or written by the first pass of the sort) into
+/// single runs on disk, and the last pass merges every run left into the
+/// sorted output. By default, the plan is a single pass merging all of the
+/// runs at once.
+#[derive(Clone, Debug)]
+pub struct MergePlan {
+    /// Runs written by the first pass of the sort, in input order
+    pub runs: Vec<RunSummary>,
+    /// Merge passes, in order, as the ranges (by index) of the runs of the
+    /// previous pass merged together, which must cover all of them in order,
+    /// where the last pass is the single range of the final merge
+    pub passes: Vec<Vec<Range<usize>>>,
+    /// [Tag](struct.ExternalSorter.html#method.tag) of the sort, if any
+    pub tag: Option<String>,
+}
+
+impl MergePlan {
+    pub(crate) fn new(runs: Vec<RunSummary>, tag: Option<String>) -> MergePlan {
+        let passes = vec![vec![0..runs.len()]];
+        MergePlan { runs, passes, tag }
+    }
+
+    /// Replace the passes with a cascade merging up to `fan_in` adjacent runs
+    /// at a time, until at most `fan_in` runs are left for the final merge
+    pub fn cascade(&mut self, fan_in: usize) {
+        let fan_in = fan_in.max(2);
+        let mut runs = self.runs.len();
+        self.passes.clear();
+        while runs > fan_in {
+            let pass: Vec<_> = (0..runs).step_by(fan_in)
+                                        .map(|start| start..runs.min(start + fan_in))
+                                        .collect();
+            runs = pass.len();
+            self.passes.push(pass);
+        }
+        self.passes.push(iter::once(0..runs).collect());
+    }
+
+    /// Total size of the records read back from disk by the merge, counting
+    /// the records of runs rewritten by intermediate passes once per pass
+    /// that reads them
+    pub fn bytes_read(&self) -> u64 {
+        let mut sizes: Vec<u64> = self.runs.iter().map(|r| r.bytes).collect();
+        let mut read = 0;
+        for (i, pass) in self.passes.iter().enumerate() {
+            let last = i == self.passes.len() - 1;
+            let mut next = Vec::new();
+            for group in pass {
+                let bytes = sizes.get(group.clone()).map_or(0, |s| s.iter().sum());
+                // runs merged by themselves are left as they are
+                if last || group.len() > 1 {
+                    read += bytes;
+                }
+                next.push(bytes);
+            }
+            sizes = next;
+        }
+
+        read
+    }
+
+    /// Check that the passes cover every run in order, and end with a single
+    /// final merge
+    pub(crate) fn check(&self) -> Result<(), String> {
+        let mut runs = self.runs.len();
+        for (i, pass) in self.passes.iter().enumerate() {
+            let mut end = 0;
+            for group in pass {
+                if group.start != end || (group.is_empty() && runs > 0) {
+                    return Err(format!("merge pass {} does not cover runs {}..{} in order",
+                                       i, end, runs));
+                }
+                end = group.end;
+            }
+            if end != runs || pass.is_empty() {
+                return Err(format!("merge pass {} does not cover runs {}..{} in order",
+                                   i, end, runs));
+            }
+            runs = pass.len();
+        }
+        match self.passes.last() {
+            Some(last) if last.len() == 1 => Ok(()),
+            _ => Err("the last merge pass must merge all runs together".to_string()),
+        }
+    }
+}
+
+/// Predicted shape and resource needs of a sort, returned by
+/// [ExternalSorter::plan](struct.ExternalSorter.html#method.plan)
+///
+/// Sizes predicted by [plan](struct.ExternalSorter.html#method.plan) are in
+/// the units of the estimated record size, which stands for both the size
+/// reported by `get_size()` and the serialized size of the records on disk.
+/// Those projected by [dry_run](struct.ExternalSorter.html#method.dry_run)
+/// measure the disk usage in serialized bytes.
+#[derive(Clone, Debug)]
+pub struct SortPlan {
+    /// Number of records in every initial sorted run (except maybe the last)
+    pub records_per_run: u64,
+    /// Number of initial sorted runs written by the first pass
+    pub runs: u64,
+    /// Plan of the merge of the runs left once the input ends (after any
+    /// background merges), as passed to the
+    /// [on_merge_plan](struct.ExternalSorter.html#method.on_merge_plan) hook
+    pub merge: MergePlan,
+    /// Number of passes merging the records, counting every level of
+    /// background merges, every intermediate pass of the merge plan and the
+    /// final merge
+    pub merge_passes: usize,
+    /// Largest total size of the runs on disk at any time, including a merged
+    /// run being written while its sources still exist
+    pub peak_disk_bytes: u64,
+    /// Largest memory held by the chunks being filled and the buffers of the
+    /// runs being merged, as accounted against the memory buffer
+    pub peak_memory_bytes: u64,
+}
+
+/// Measurements of an input consumed by
+/// [ExternalSorter::dry_run](struct.ExternalSorter.html#method.dry_run),
+/// along with the sort they project
+#[derive(Clone, Debug)]
+pub struct DryRun {
+    /// Number of records of the input
+    pub records: u64,
+    /// Total size of the records, as reported by `get_size()`
+    pub bytes: u64,
+    /// Total serialized size of the records, as written to the runs
+    pub serialized_bytes: u64,
+    /// The sort of the input, from its runs as measured, where the disk
+    /// usage is in serialized bytes
+    pub plan: SortPlan,
+}
@@ -0,0 +1,67 @@
+//! Metrics of sorts published with the `metrics` crate, built with the
+//! `metrics` feature
+
+use std::fs;
+use std::path::Path;
+
+use metrics::{counter, gauge, Counter};
+
+use crate::external_sort::MANIFEST_FILE;
+
+/// Counter of the records read from the inputs of sorts
+const RECORDS_IN: &str = "external_sort_records_in";
+/// Counter of the records yielded by sorted iterators
+const RECORDS_OUT: &str = "external_sort_records_out";
+/// Counter of the bytes written to sorted runs on disk
+const BYTES_SPILLED: &str = "external_sort_bytes_spilled";
+/// Counter of the comparisons made while merging runs
+const MERGE_COMPARISONS: &str = "external_sort_merge_comparisons";
+/// Gauge of the runs currently on disk
+const OPEN_RUNS: &str = "external_sort_open_runs";
+/// Gauge of the bytes of the runs currently on disk
+const TEMP_BYTES: &str = "external_sort_temp_bytes";
+
+/// Handles to the metrics updated for every record merged
+#[derive(Clone)]
+pub(crate) struct MergeMetrics {
+    pub(crate) records_out: Counter,
+    pub(crate) comparisons: Counter,
+}
+
+impl MergeMetrics {
+    pub(crate) fn new() -> MergeMetrics {
+        MergeMetrics {
+            records_out: counter!(RECORDS_OUT),
+            comparisons: counter!(MERGE_COMPARISONS),
+        }
+    }
+}
+
+/// Handle to the counter of records read from the inputs
+pub(crate) fn records_in() -> Counter {
+    counter!(RECORDS_IN)
+}
+
+/// Count a run of `bytes` bytes written to disk
+pub(crate) fn run_written(bytes: u64) {
+    counter!(BYTES_SPILLED).increment(bytes);
+    gauge!(OPEN_RUNS).increment(1.0);
+    gauge!(TEMP_BYTES).increment(bytes as f64);
+}
+
+/// Count the run at `path` as removed, before removing it
+pub(crate) fn run_removed(path: &Path) {
+    let bytes = fs::metadata(path).map_or(0, |m| m.len());
+    gauge!(OPEN_RUNS).decrement(1.0);
+    gauge!(TEMP_BYTES).decrement(bytes as f64);
+}
+
+/// Count the runs left in the temporary directory at `path` (besides its
+/// manifest) as removed, before removing it
+pub(crate) fn dir_removed(path: &Path) {
+    if let Ok(entries) = fs::read_dir(path) {
+        for entry in entries.flatten().filter(|entry| entry.file_name() != MANIFEST_FILE) {
+            run_removed(&entry.path());
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::cmp::Ordering;
+use std::error::Error;
+
+use crate::{ExtSortedIterator, ExternalSorter, ExternallySortable};
+
+/// Where records with a `None` key sort, for
+/// [ExternalSorter::sort_by_nullable_key](struct.ExternalSorter.html#method.sort_by_nullable_key)
+/// and [cmp_nullable](fn.cmp_nullable.html)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NullOrder {
+    /// `None` sorts before every other key, as `Option`'s own order does
+    NullsFirst,
+    /// `None` sorts after every other key
+    NullsLast,
+}
+
+/// Compare two optional keys, with `None` placed according to `nulls`
+pub fn cmp_nullable<K>(a: &Option<K>, b: &Option<K>, nulls: NullOrder) -> Ordering
+where
+    K: Ord,
+{
+    match (a, b, nulls) {
+        (Some(a), Some(b), _) => a.cmp(b),
+        (None, None, _) => Ordering::Equal,
+        (None, Some(_), NullOrder::NullsFirst) | (Some(_), None, NullOrder::NullsLast) => {
+            Ordering::Less
+        },
+        _ => Ordering::Greater,
+    }
+}
+
+impl<T> ExternalSorter<T>
+where
+    T: ExternallySortable,
+{
+    /// Sort the `T`s provided by `unsorted` by the optional key that `key`
+    /// extracts from each of them, with the records without a key placed
+    /// according to `nulls`, and return an iterator
+    ///
+    /// As with [sort_by_key](#method.sort_by_key), the same order applies
+    /// to sorting the chunks and to merging them, and records with equal keys
+    /// (or without one) are merged in the order of the input.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    pub fn sort_by_nullable_key<I, K, KF>(&self, unsorted: I, key: KF, nulls: NullOrder)
+                                          -> Result<ExtSortedIterator<T>, Box<dyn Error>>
+    where
+        I: Iterator<Item = T>,
+        K: Ord,
+        KF: 'static + Fn(&T) -> Option<K> + Send + Sync,
+    {
+        self.sort_by_sync(unsorted, move |a, b| cmp_nullable(&key(a), &key(b), nulls))
+    }
+}
@@ -0,0 +1,149 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::external_sort::CompareFn;
+use crate::{ExtSortedIterator, ExternalSorter, ExternallySortable};
+
+type KeyFn<T, K> = dyn Fn(&T) -> K + Send + Sync;
+
+/// Order of the partitions in the output of
+/// [ExternalSorter::sort_within_partitions](struct.ExternalSorter.html#method.sort_within_partitions)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PartitionOrder {
+    /// Partitions are in the order their first record was provided
+    FirstSeen,
+    /// Partitions are in the (ascending) order of their keys
+    Sorted,
+}
+
+/// Record tagged with the order of its partition
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Tagged<T> {
+    partition: u64,
+    record: T,
+}
+
+impl<T> ExternallySortable for Tagged<T>
+where
+    T: ExternallySortable,
+{
+    fn get_size(&self) -> u64 {
+        self.record.get_size()
+    }
+}
+
+/// Iterator that provides `T`s grouped by partition and sorted within each
+/// partition, created by
+/// [ExternalSorter::sort_within_partitions](struct.ExternalSorter.html#method.sort_within_partitions)
+pub struct PartitionedIterator<T, K> {
+    iter: ExtSortedIterator<Tagged<T>>,
+    key: Arc<KeyFn<T, K>>,
+    order: PartitionOrder,
+    last: Option<K>,
+    segment: u64,
+}
+
+impl<T> ExternalSorter<T>
+where
+    T: ExternallySortable,
+{
+    /// Group the `T`s provided by `unsorted` into partitions by
+    /// `partition_key`, and sort the records of each partition with
+    /// `compare`
+    ///
+    /// The iterator yields every record along with the (0-based) number of
+    /// its partition in the output, so a new segment starts whenever the
+    /// number changes. With [PartitionOrder::FirstSeen], the order of the
+    /// partitions is tracked in memory while consuming the input, which needs
+    /// room for every distinct partition key.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    pub fn sort_within_partitions<I, K, KF, F>(&self, unsorted: I, partition_key: KF,
+                                               order: PartitionOrder, compare: F)
+                                               -> Result<PartitionedIterator<T, K>, Box<dyn Error>>
+    where
+        T: 'static + Send,
+        I: Iterator<Item = T>,
+        K: 'static + Ord,
+        KF: 'static + Fn(&T) -> K + Send + Sync,
+        F: 'static + Fn(&T, &T) -> Ordering + Send + Sync,
+    {
+        let key: Arc<KeyFn<T, K>> = Arc::new(partition_key);
+        let cmp_key = key.clone();
+        let compare: Arc<CompareFn<Tagged<T>>> = match order {
+            PartitionOrder::FirstSeen => Arc::new(move |a: &Tagged<T>, b: &Tagged<T>| {
+                                                      a.partition
+                                                       .cmp(&b.partition)
+                                                       .then_with(|| compare(&a.record, &b.record))
+                                                  }),
+            PartitionOrder::Sorted => Arc::new(move |a: &Tagged<T>, b: &Tagged<T>| {
+                                                   cmp_key(&a.record)
+                                                       .cmp(&cmp_key(&b.record))
+                                                       .then_with(|| compare(&a.record, &b.record))
+                                               }),
+        };
+        let mut partitions = BTreeMap::new();
+        let tag_key = key.clone();
+        let tagged = unsorted.map(|record| {
+                                  let partition = match order {
+                                      PartitionOrder::FirstSeen => {
+                                          let next = partitions.len() as u64;
+                                          *partitions.entry(tag_key(&record)).or_insert(next)
+                                      },
+                                      PartitionOrder::Sorted => 0,
+                                  };
+                                  Tagged { partition, record }
+                              });
+        let iter = self.retype::<Tagged<T>>()
+                       .sort_shared(tagged, compare)
+                       .map_err(|e| e as Box<dyn Error>)?;
+
+        Ok(PartitionedIterator {
+            iter,
+            key,
+            order,
+            last: None,
+            segment: 0,
+        })
+    }
+}
+
+impl<T, K> Iterator for PartitionedIterator<T, K>
+where
+    T: ExternallySortable,
+    K: PartialEq,
+{
+    type Item = Result<(u64, T), Box<dyn Error>>;
+
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// from disk, or due to serde deserialization issues
+    fn next(&mut self) -> Option<Self::Item> {
+        let tagged = match self.iter.next()? {
+            Ok(tagged) => tagged,
+            Err(e) => return Some(Err(e)),
+        };
+        let segment = match self.order {
+            PartitionOrder::FirstSeen => tagged.partition,
+            PartitionOrder::Sorted => {
+                let key = (self.key)(&tagged.record);
+                if self.last.as_ref().is_some_and(|last| *last != key) {
+                    self.segment += 1;
+                }
+                self.last = Some(key);
+                self.segment
+            },
+        };
+
+        Some(Ok((segment, tagged.record)))
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::Mutex;
+
+/// Source of the buffers that a sorter set with
+/// [ExternalSorter::buffer_allocator](struct.ExternalSorter.html#method.buffer_allocator)
+/// fills with records: the chunks of records read from the input, the merge
+/// buffers of the sorted runs, and the scratch lines records are read into
+///
+/// These make up most of the memory a sort allocates. An allocator can hand
+/// out buffers it set aside up front, and take them back once the sorter is
+/// done with them, so that an embedder bounding its allocations decides where
+/// they come from and accounts for them. Buffers still grow with the global
+/// allocator past the capacity they are handed out with. Every method has a
+/// default that allocates (or drops) buffers as the sorter does without an
+/// allocator.
+pub trait BufferAllocator<T>: Send + Sync {
+    /// Allocate an empty chunk, which is filled with up to `max_bytes` of
+    /// records (as counted by the memory buffer) before it is sorted and
+    /// written to disk
+    fn chunk(&self, max_bytes: u64) -> Vec<T> {
+        let _ = max_bytes;
+        Vec::new()
+    }
+
+    /// Take back a chunk once its records are written to disk, empty
+    fn release_chunk(&self, chunk: Vec<T>) {
+        drop(chunk);
+    }
+
+    /// Allocate an empty merge buffer, which is filled with up to
+    /// `max_bytes` of records of a run at a time
+    fn merge_buffer(&self, max_bytes: u64) -> VecDeque<T> {
+        let _ = max_bytes;
+        VecDeque::new()
+    }
+
+    /// Allocate an empty scratch line, which the records of the runs are read
+    /// into one at a time
+    fn line(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Take back the merge buffers and scratch line of a finished merge, with
+    /// any records left in the buffers
+    fn release_merge(&self, buffers: Vec<VecDeque<T>>, line: Vec<u8>) {
+        drop((buffers, line));
+    }
+}
+
+/// Merge buffers and scratch lines left over by finished merges, handed out
+/// again to the next merges of a sorter set with
+/// [ExternalSorter::reuse_buffers](struct.ExternalSorter.html#method.reuse_buffers)
+pub(crate) struct BufferPool<T> {
+    /// Most bytes of capacity kept in the pool
+    max_bytes: u64,
+    buffers: Mutex<Vec<VecDeque<T>>>,
+    lines: Mutex<Vec<Vec<u8>>>,
+}
+
+impl<T> BufferPool<T> {
+    pub(crate) fn new(max_bytes: u64) -> BufferPool<T> {
+        BufferPool { max_bytes, buffers: Mutex::new(Vec::new()), lines: Mutex::new(Vec::new()) }
+    }
+
+    /// Take an empty merge buffer, with the largest capacity pooled but no
+    /// more than `max_bytes` of it, so that it does not use up more than its
+    /// share of the memory buffer before it is filled
+    pub(crate) fn take_buffer(&self, max_bytes: u64) -> VecDeque<T> {
+        let mut buffers = self.buffers.lock().unwrap();
+        let mut buffer = match buffers.pop() {
+            Some(buffer) => buffer,
+            None => return VecDeque::new(),
+        };
+        drop(buffers);
+        let size = mem::size_of::<T>() as u64;
+        if size > 0 && buffer.capacity() as u64 * size > max_bytes {
+            buffer.shrink_to((max_bytes / size) as usize);
+        }
+        buffer
+    }
+
+    /// Take an empty scratch line for reading records
+    pub(crate) fn take_line(&self) -> Vec<u8> {
+        self.lines.lock().unwrap().pop().unwrap_or_default()
+    }
+
+    /// Return the merge buffers and scratch line of a finished merge, keeping
+    /// those with the largest capacity that fit in the pool
+    pub(crate) fn give(&self, returned: Vec<VecDeque<T>>, line: Vec<u8>) {
+        let size = mem::size_of::<T>() as u64;
+        let mut buffers = self.buffers.lock().unwrap();
+        let mut lines = self.lines.lock().unwrap();
+        buffers.extend(returned.into_iter().filter(|b| b.capacity() > 0).map(|mut b| {
+                                                                          b.clear();
+                                                                          b
+                                                                      }));
+        if line.capacity() > 0 {
+            lines.push(line);
+        }
+        // the buffers handed out first are the last ones, with the largest
+        // capacity
+        buffers.sort_by_key(|b| b.capacity());
+        lines.sort_by_key(|l| l.capacity());
+        let mut pooled: u64 = buffers.iter().map(|b| b.capacity() as u64 * size).sum::<u64>()
+                              + lines.iter().map(|l| l.capacity() as u64).sum::<u64>();
+        while pooled > self.max_bytes {
+            let smallest_buffer = buffers.first().map(|b| b.capacity() as u64 * size);
+            let smallest_line = lines.first().map(|l| l.capacity() as u64);
+            match (smallest_buffer, smallest_line) {
+                (Some(b), Some(l)) if l < b => pooled -= lines.remove(0).capacity() as u64,
+                (Some(b), _) => {
+                    buffers.remove(0);
+                    pooled -= b;
+                },
+                (None, Some(l)) => {
+                    lines.remove(0);
+                    pooled -= l;
+                },
+                (None, None) => break,
+            }
+        }
+    }
+}
+
+impl<T> BufferAllocator<T> for BufferPool<T>
+where
+    T: Send,
+{
+    fn merge_buffer(&self, max_bytes: u64) -> VecDeque<T> {
+        self.take_buffer(max_bytes)
+    }
+
+    fn line(&self) -> Vec<u8> {
+        self.take_line()
+    }
+
+    fn release_merge(&self, buffers: Vec<VecDeque<T>>, line: Vec<u8>) {
+        self.give(buffers, line)
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::panic;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::external_sort::SendError;
+use crate::{ExtSortedIterator, ExternallySortable};
+
+/// Records merged ahead of the consumer, and how far ahead they are
+struct Queue<T> {
+    records: VecDeque<Result<T, SendError>>,
+    /// Total size of the queued records
+    bytes: u64,
+    /// Whether the merge thread has stopped
+    done: bool,
+    /// Whether the consumer has been dropped
+    closed: bool,
+}
+
+/// Queue shared between the merge thread and the consumer
+struct Shared<T> {
+    queue: Mutex<Queue<T>>,
+    /// Signaled when a record is queued or the merge stops
+    filled: Condvar,
+    /// Signaled when a record is taken or the consumer is dropped
+    drained: Condvar,
+    max_bytes: u64,
+}
+
+/// Marks the merge as stopped when dropped, even if the merge thread panics
+struct Done<'a, T>(&'a Shared<T>);
+
+impl<'a, T> Drop for Done<'a, T> {
+    fn drop(&mut self) {
+        let mut queue = self.0.queue.lock().unwrap_or_else(|e| e.into_inner());
+        queue.done = true;
+        self.0.filled.notify_one();
+    }
+}
+
+/// Iterator that provides the sorted `T`s merged ahead on a background
+/// thread, created by
+/// [ExtSortedIterator::prefetch](struct.ExtSortedIterator.html#method.prefetch)
+///
+/// Dropping the iterator stops the merge, and waits for the merge thread to
+/// remove the intermediate sorted chunks.
+pub struct PrefetchIterator<T> {
+    shared: Arc<Shared<T>>,
+    merger: Option<JoinHandle<()>>,
+}
+
+impl<T> PrefetchIterator<T> {
+    /// Wait for the merge thread, resuming its panic if it had one
+    fn join(&mut self) {
+        if let Some(merger) = self.merger.take() {
+            merger.join().unwrap_or_else(|e| panic::resume_unwind(e));
+        }
+    }
+}
+
+impl<T> Iterator for PrefetchIterator<T>
+where
+    T: ExternallySortable,
+{
+    type Item = Result<T, Box<dyn Error>>;
+
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// from disk, or due to serde deserialization issues
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = {
+            let mut queue = self.shared.queue.lock().unwrap();
+            while queue.records.is_empty() && !queue.done {
+                queue = self.shared.filled.wait(queue).unwrap();
+            }
+            let next = queue.records.pop_front();
+            if let Some(Ok(ref t)) = next {
+                queue.bytes -= t.get_size();
+                self.shared.drained.notify_one();
+            }
+            next
+        };
+        if next.is_none() {
+            self.join();
+        }
+        next.map(|r| r.map_err(|e| e as Box<dyn Error>))
+    }
+}
+
+impl<T> Drop for PrefetchIterator<T> {
+    fn drop(&mut self) {
+        {
+            let mut queue = self.shared.queue.lock().unwrap_or_else(|e| e.into_inner());
+            queue.closed = true;
+            self.shared.drained.notify_one();
+        }
+        if !thread::panicking() {
+            self.join();
+        }
+    }
+}
+
+impl<T> ExtSortedIterator<T>
+where
+    T: ExternallySortable + 'static,
+{
+    /// Merge the remaining sorted output on a background thread, which reads
+    /// ahead of the returned iterator by up to `max_bytes` (according to
+    /// [get_size](trait.ExternallySortable.html#method.get_size)) of records
+    ///
+    /// The merge blocks once `max_bytes` of records are waiting, so a slow
+    /// consumer throttles the reads from disk rather than letting the merged
+    /// records pile up in memory. A record larger than `max_bytes` is still
+    /// merged ahead, alone. The merge stops after the first error, which is
+    /// yielded once the records merged before it have been.
+    pub fn prefetch(mut self, max_bytes: u64) -> PrefetchIterator<T>
+    where
+        T: Send,
+    {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                records: VecDeque::new(),
+                bytes: 0,
+                done: false,
+                closed: false,
+            }),
+            filled: Condvar::new(),
+            drained: Condvar::new(),
+            max_bytes,
+        });
+        let merge = shared.clone();
+        let merger = thread::spawn(move || {
+            let _done = Done(&merge);
+            while let Some(next) = self.next_record() {
+                let size = next.as_ref().map_or(0, |t| t.get_size());
+                let failed = next.is_err();
+                let mut queue = merge.queue.lock().unwrap();
+                while queue.bytes > 0 && queue.bytes + size > merge.max_bytes && !queue.closed {
+                    queue = merge.drained.wait(queue).unwrap();
+                }
+                if queue.closed {
+                    return;
+                }
+                queue.records.push_back(next);
+                queue.bytes += size;
+                merge.filled.notify_one();
+                if failed {
+                    return;
+                }
+            }
+        });
+
+        PrefetchIterator { shared, merger: Some(merger) }
+    }
+}
@@ -0,0 +1,45 @@
+//! Spilling chunks early while memory is short, built with the `pressure`
+//! feature
+
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use crate::memory::available_memory;
+
+/// Number of records between two checks of the available memory
+const CHECK_INTERVAL: u64 = 1024;
+/// Most halvings of the chunk size under pressure, down to 1/64 of it
+const MAX_SHIFT: u32 = 6;
+
+/// Chunk size of a sort, adapted to the memory available
+pub(crate) struct MemoryPressure {
+    /// Available memory under which chunks are shrunk
+    threshold: u64,
+    records: AtomicU64,
+    /// Number of times the chunk size is currently halved
+    shift: AtomicU32,
+}
+
+impl MemoryPressure {
+    pub(crate) fn new(threshold: u64) -> MemoryPressure {
+        MemoryPressure { threshold, records: AtomicU64::new(0), shift: AtomicU32::new(0) }
+    }
+
+    /// Shrink `chunk_bytes` for the record read, checking the available
+    /// memory every so often: the size is halved at every check under the
+    /// threshold, and doubled back at every check above it
+    pub(crate) fn limit(&self, chunk_bytes: u64) -> u64 {
+        if self.records.fetch_add(1, Ordering::Relaxed).is_multiple_of(CHECK_INTERVAL) {
+            if let Some(available) = available_memory() {
+                let shift = self.shift.load(Ordering::Relaxed);
+                let shift = if available < self.threshold {
+                    (shift + 1).min(MAX_SHIFT)
+                } else {
+                    shift.saturating_sub(1)
+                };
+                self.shift.store(shift, Ordering::Relaxed);
+            }
+        }
+
+        chunk_bytes >> self.shift.load(Ordering::Relaxed)
+    }
+}
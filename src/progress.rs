@@ -0,0 +1,128 @@
+//! Progress reports of sorts, for
+//! [ExternalSorter::on_progress](struct.ExternalSorter.html#method.on_progress)
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+#[cfg(feature = "metrics")]
+use metrics::Counter;
+
+#[cfg(feature = "indicatif")]
+use crate::progress_bar::InputProgress;
+use crate::ExternallySortable;
+
+/// Phase of a sort, reported by [Progress](struct.Progress.html)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressPhase {
+    /// Reading the input and writing it to sorted runs on disk
+    Spilling,
+    /// Merging the sorted runs into the sorted output
+    Merging,
+    /// The sorted output has been read to the end
+    Done,
+}
+
+/// Progress of a sort, reported to the callback set with
+/// [ExternalSorter::on_progress](struct.ExternalSorter.html#method.on_progress)
+#[derive(Clone, Copy, Debug)]
+pub struct Progress {
+    /// Current phase of the sort
+    pub phase: ProgressPhase,
+    /// Number of records processed in the phase, i.e. read from the input
+    /// while spilling, and merged out of the runs while merging
+    pub records: u64,
+    /// Total size of the records processed in the phase, as reported by
+    /// `get_size()`
+    pub bytes: u64,
+    /// Estimated fraction of the phase that is complete, from 0 to 1, or
+    /// `None` while spilling since the size of the input is unknown
+    pub fraction: Option<f64>,
+}
+
+pub(crate) type ProgressFn = dyn Fn(Progress) + Send + Sync;
+
+/// Counts the records of a phase, reporting them every `interval` records
+pub(crate) struct ProgressTracker {
+    callback: Arc<ProgressFn>,
+    interval: u64,
+    phase: ProgressPhase,
+    /// Number of records of the phase, if known
+    total: Option<u64>,
+    records: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl ProgressTracker {
+    pub(crate) fn new(callback: Arc<ProgressFn>, interval: u64, phase: ProgressPhase,
+                      total: Option<u64>)
+                      -> ProgressTracker {
+        ProgressTracker {
+            callback,
+            interval: interval.max(1),
+            phase,
+            total,
+            records: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Count `records` more records of `bytes` bytes, reporting progress if
+    /// they cross a multiple of the interval
+    pub(crate) fn advance(&self, records: u64, bytes: u64) {
+        let before = self.records.fetch_add(records, Ordering::Relaxed);
+        let bytes = self.bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if (before + records) / self.interval > before / self.interval {
+            self.report(self.phase, before + records, bytes);
+        }
+    }
+
+    /// Report the progress of the phase so far
+    pub(crate) fn report_now(&self) {
+        let records = self.records.load(Ordering::Relaxed);
+        self.report(self.phase, records, self.bytes.load(Ordering::Relaxed));
+    }
+
+    /// Report the end of the sort
+    pub(crate) fn finish(&self) {
+        let records = self.records.load(Ordering::Relaxed);
+        self.report(ProgressPhase::Done, records, self.bytes.load(Ordering::Relaxed));
+    }
+
+    fn report(&self, phase: ProgressPhase, records: u64, bytes: u64) {
+        let fraction = match phase {
+            ProgressPhase::Spilling => None,
+            ProgressPhase::Merging => {
+                self.total.map(|total| (records as f64 / total.max(1) as f64).min(1.0))
+            },
+            ProgressPhase::Done => Some(1.0),
+        };
+        (self.callback)(Progress { phase, records, bytes, fraction });
+    }
+}
+
+/// Everything following the records read from the input of a sort
+pub(crate) struct InputTracker {
+    pub(crate) callback: Option<ProgressTracker>,
+    #[cfg(feature = "indicatif")]
+    pub(crate) bar: Option<InputProgress>,
+    #[cfg(feature = "metrics")]
+    pub(crate) records_in: Counter,
+}
+
+impl InputTracker {
+    /// Count a record read from the input
+    pub(crate) fn record<T>(&self, t: &T)
+    where
+        T: ExternallySortable,
+    {
+        if let Some(ref callback) = self.callback {
+            callback.advance(1, t.get_size());
+        }
+        #[cfg(feature = "indicatif")]
+        if let Some(ref bar) = self.bar {
+            bar.record(t);
+        }
+        #[cfg(feature = "metrics")]
+        self.records_in.increment(1);
+    }
+}
@@ -0,0 +1,58 @@
+//! Progress bars for sorts, built with the `indicatif` feature
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+
+use crate::ExternallySortable;
+
+/// Number of records between updates of the number of bytes read, which is
+/// shown as the message of the bar
+const MESSAGE_INTERVAL: u64 = 4096;
+
+/// Progress of reading the input and writing it to sorted chunks, before the
+/// total number of records is known
+pub(crate) struct InputProgress {
+    bar: ProgressBar,
+    bytes: AtomicU64,
+}
+
+impl InputProgress {
+    /// Show a spinner counting the records and bytes read on `bar`
+    pub(crate) fn new(bar: ProgressBar) -> InputProgress {
+        // unwrap since the template is known to be valid
+        let style = ProgressStyle::with_template("{spinner} sorting {human_pos} records ({msg}) \
+                                                  [{elapsed}]")
+            .unwrap();
+        bar.set_style(style);
+        bar.set_position(0);
+        bar.set_message(HumanBytes(0).to_string());
+        InputProgress { bar, bytes: AtomicU64::new(0) }
+    }
+
+    /// Count a record read from the input
+    pub(crate) fn record<T>(&self, t: &T)
+    where
+        T: ExternallySortable,
+    {
+        let bytes = self.bytes.fetch_add(t.get_size(), Ordering::Relaxed) + t.get_size();
+        self.bar.inc(1);
+        if self.bar.position().is_multiple_of(MESSAGE_INTERVAL) {
+            self.bar.set_message(HumanBytes(bytes).to_string());
+        }
+    }
+}
+
+/// Switch `bar` to counting the `records` (of `bytes` bytes in total) merged
+/// out of the sorted chunks
+pub(crate) fn start_merge(bar: &ProgressBar, records: u64, bytes: u64) {
+    // unwrap since the template is known to be valid
+    let style = ProgressStyle::with_template("{bar:40} merging {human_pos}/{human_len} records \
+                                              ({msg}) [{elapsed}<{eta}]")
+        .unwrap();
+    bar.set_style(style);
+    bar.set_length(records);
+    bar.set_position(0);
+    bar.set_message(HumanBytes(bytes).to_string());
+    bar.reset_eta();
+}
@@ -0,0 +1,116 @@
+//! Python bindings, built with the `python` feature
+//!
+//! The module is named `external_sort`, and sorts iterables of `bytes` or
+//! `str` records:
+//!
+//! ```python
+//! import external_sort
+//!
+//! with open("words.txt") as words:
+//!     lines = (line.rstrip("\n") for line in words)
+//!     for line in external_sort.sort_lines(lines, buffer_bytes=64 << 20):
+//!         print(line)
+//! ```
+//!
+//! The crate is only built as a Rust library, so the extension module is
+//! built with `maturin build --features python`, or with
+//! `cargo rustc --release --lib --features python --crate-type cdylib`,
+//! copying `target/release/libexternal_sort.so` to `external_sort.so` (or
+//! `external_sort.pyd` on Windows).
+
+use std::error::Error;
+use std::path::PathBuf;
+
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyString};
+
+use crate::fallible::until_error;
+use crate::{ExtSortedIterator, ExternalSorter, ExternallySortable};
+
+/// Sorted records of either kind
+enum Records {
+    Bytes(ExtSortedIterator<Vec<u8>>),
+    Lines(ExtSortedIterator<String>),
+}
+
+/// Iterator over the sorted records, returned by `sort_bytes()` and
+/// `sort_lines()`
+#[pyclass(name = "SortedIterator")]
+struct SortedIterator {
+    records: Records,
+}
+
+#[pymethods]
+impl SortedIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<Py<PyAny>>> {
+        let next = match self.records {
+            Records::Bytes(ref mut iter) => {
+                iter.next().map(|r| r.map(|r| PyBytes::new(py, &r).into_any().unbind()))
+            },
+            Records::Lines(ref mut iter) => {
+                iter.next().map(|r| r.map(|r| PyString::new(py, &r).into_any().unbind()))
+            },
+        };
+        next.transpose().map_err(to_py_err)
+    }
+}
+
+/// Sort an iterable of `bytes` records in byte order, holding about
+/// `buffer_bytes` of them in memory and spilling the rest to sorted chunks in
+/// `tmp_dir` (or the system temporary directory)
+#[pyfunction]
+#[pyo3(signature = (records, buffer_bytes, tmp_dir = None))]
+fn sort_bytes(records: &Bound<'_, PyAny>, buffer_bytes: u64, tmp_dir: Option<PathBuf>)
+              -> PyResult<SortedIterator> {
+    let sorted = sort(records, buffer_bytes, tmp_dir, |r| {
+                     Ok(r.cast::<PyBytes>()?.as_bytes().to_vec())
+                 })?;
+    Ok(SortedIterator { records: Records::Bytes(sorted) })
+}
+
+/// Sort an iterable of `str` records, e.g. the lines of a text or JSON lines
+/// file, holding about `buffer_bytes` of them in memory and spilling the rest
+/// to sorted chunks in `tmp_dir` (or the system temporary directory)
+#[pyfunction]
+#[pyo3(signature = (lines, buffer_bytes, tmp_dir = None))]
+fn sort_lines(lines: &Bound<'_, PyAny>, buffer_bytes: u64, tmp_dir: Option<PathBuf>)
+              -> PyResult<SortedIterator> {
+    let sorted = sort(lines, buffer_bytes, tmp_dir, |r| r.extract::<String>())?;
+    Ok(SortedIterator { records: Records::Lines(sorted) })
+}
+
+/// Sort the records of a Python iterable, converted by `extract`
+fn sort<T, F>(records: &Bound<'_, PyAny>, buffer_bytes: u64, tmp_dir: Option<PathBuf>,
+              extract: F)
+              -> PyResult<ExtSortedIterator<T>>
+where
+    T: ExternallySortable,
+    F: Fn(&Bound<'_, PyAny>) -> PyResult<T>,
+{
+    // the first error of the iterable is raised as is, once the records
+    // before it are sorted
+    let (records, error) = until_error(records.try_iter()?.map(|r| r.and_then(|r| extract(&r))));
+    let sorted = ExternalSorter::new(buffer_bytes, tmp_dir).sort(records).map_err(to_py_err)?;
+    match error.take() {
+        Some(e) => Err(e),
+        None => Ok(sorted),
+    }
+}
+
+fn to_py_err(e: Box<dyn Error>) -> PyErr {
+    PyRuntimeError::new_err(e.to_string())
+}
+
+#[pymodule]
+#[pyo3(name = "external_sort")]
+fn python_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(sort_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(sort_lines, m)?)?;
+    m.add_class::<SortedIterator>()?;
+    Ok(())
+}
@@ -0,0 +1,236 @@
+use std::cmp::Ordering::{self, Equal, Greater, Less};
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::SeekFrom::Start;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::external_sort::{to_line, CompareFn};
+use crate::{ExtSortedIterator, ExternallySortable};
+
+/// Read-only lookups into sorted output persisted with
+/// [ExtSortedIterator::write_indexed](struct.ExtSortedIterator.html#method.write_indexed)
+///
+/// The sparse index of the output is held in memory, and lookups binary
+/// search it for the block that may hold a key, then scan the block on disk.
+pub struct SortedReader<T> {
+    path: PathBuf,
+    /// Offset and record of the first record of every block
+    index: Vec<(u64, T)>,
+    compare: Arc<CompareFn<T>>,
+}
+
+/// Iterator that provides the records of a
+/// [SortedReader](struct.SortedReader.html) from a key onward, created by
+/// [SortedReader::seek](struct.SortedReader.html#method.seek)
+pub struct SeekIterator<T> {
+    lines: Lines<BufReader<File>>,
+    /// Records preceding this one are skipped
+    key: Option<T>,
+    /// Whether records equal to `key` are skipped as well
+    after: bool,
+    compare: Arc<CompareFn<T>>,
+    failed: bool,
+}
+
+impl<T> ExtSortedIterator<T>
+where
+    T: ExternallySortable,
+{
+    /// Write the remaining sorted output into a newline-delimited JSON file
+    /// at `path`, along with a sparse index of the first record of every
+    /// block of `block_records` records (written next to it, with an `.index`
+    /// extension appended), returning the number of records written
+    ///
+    /// The output can then be opened with
+    /// [SortedReader::open](struct.SortedReader.html#method.open) for
+    /// lookups. Smaller blocks make lookups scan less of the file, at the cost
+    /// of a larger index.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// from disk or writing the output files, or due to serde issues
+    pub fn write_indexed<P>(self, path: P, block_records: u64) -> Result<u64, Box<dyn Error>>
+    where
+        P: AsRef<Path>,
+    {
+        let block_records = block_records.max(1);
+        let mut file = BufWriter::new(File::create(&path)?);
+        let mut index = BufWriter::new(File::create(index_path(path.as_ref()))?);
+        let mut offset = 0;
+        let mut records = 0;
+        for t in self {
+            let t = t?;
+            let line = to_line(&t).map_err(|e| e as Box<dyn Error>)?;
+            if records % block_records == 0 {
+                serde_json::to_writer(&mut index, &(offset, &t))?;
+                index.write_all(b"\n")?;
+            }
+            file.write_all(line.as_bytes())?;
+            offset += line.len() as u64;
+            records += 1;
+        }
+        file.flush()?;
+        index.flush()?;
+
+        Ok(records)
+    }
+}
+
+impl<T> SortedReader<T>
+where
+    T: ExternallySortable,
+{
+    /// Open the sorted output written to `path` by
+    /// [ExtSortedIterator::write_indexed](struct.ExtSortedIterator.html#method.write_indexed),
+    /// which must be sorted in the natural order of `T`
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading the index from disk, or due
+    /// to serde deserialization issues
+    pub fn open<P>(path: P) -> Result<SortedReader<T>, Box<dyn Error>>
+    where
+        P: AsRef<Path>,
+        T: 'static,
+    {
+        SortedReader::open_by(path, |a: &T, b: &T| a.cmp(b))
+    }
+
+    /// Open the sorted output written to `path` by
+    /// [ExtSortedIterator::write_indexed](struct.ExtSortedIterator.html#method.write_indexed),
+    /// which must be sorted in the order of `compare`
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading the index from disk, or due
+    /// to serde deserialization issues
+    pub fn open_by<P, F>(path: P, compare: F) -> Result<SortedReader<T>, Box<dyn Error>>
+    where
+        P: AsRef<Path>,
+        F: 'static + Fn(&T, &T) -> Ordering + Send + Sync,
+    {
+        let mut index = Vec::new();
+        for line in BufReader::new(File::open(index_path(path.as_ref()))?).lines() {
+            index.push(serde_json::from_str(&line?)?);
+        }
+
+        Ok(SortedReader {
+            path: path.as_ref().to_path_buf(),
+            index,
+            compare: Arc::new(compare),
+        })
+    }
+
+    /// Look up the first record comparing as equal to `key`
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading the output from disk, or
+    /// due to serde deserialization issues
+    pub fn get(&self, key: &T) -> Result<Option<T>, Box<dyn Error>> {
+        match self.seek(key)?.next() {
+            Some(Ok(t)) if (self.compare)(&t, key) == Ordering::Equal => Ok(Some(t)),
+            Some(Err(e)) => Err(e),
+            _ => Ok(None),
+        }
+    }
+
+    /// Iterate over the records from the first one not preceding `key` (i.e.
+    /// the first one greater than or equal to it) to the end of the output
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues opening the output
+    pub fn seek(&self, key: &T) -> Result<SeekIterator<T>, Box<dyn Error>> {
+        // records equal to the key may start in the block before the first
+        // indexed record not preceding it
+        let block = self.index
+                        .partition_point(|(_, t)| (self.compare)(t, key) == Less)
+                        .saturating_sub(1);
+        self.seek_block(block, key, false)
+    }
+
+    /// Iterate over the records following every record not greater than
+    /// `key` to the end of the output, e.g. to resume a cursor from the last
+    /// key it processed
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues opening the output
+    pub fn seek_after(&self, key: &T) -> Result<SeekIterator<T>, Box<dyn Error>> {
+        // the last records equal to the key are in the block of the last
+        // indexed record not following it
+        let block = self.index
+                        .partition_point(|(_, t)| (self.compare)(t, key) != Greater)
+                        .saturating_sub(1);
+        self.seek_block(block, key, true)
+    }
+
+    /// Iterate over the records from the start of `block`, skipping those
+    /// before `key` (or up to it, if `after`)
+    fn seek_block(&self, block: usize, key: &T, after: bool)
+                  -> Result<SeekIterator<T>, Box<dyn Error>> {
+        let mut file = File::open(&self.path)?;
+        if let Some((offset, _)) = self.index.get(block) {
+            file.seek(Start(*offset))?;
+        }
+
+        Ok(SeekIterator {
+            lines: BufReader::new(file).lines(),
+            key: Some(key.clone()),
+            after,
+            compare: self.compare.clone(),
+            failed: false,
+        })
+    }
+}
+
+impl<T> Iterator for SeekIterator<T>
+where
+    T: ExternallySortable,
+{
+    type Item = Result<T, Box<dyn Error>>;
+
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading the output from disk, or
+    /// due to serde deserialization issues
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        for line in self.lines.by_ref() {
+            let t = match line.map_err(|e| e.into())
+                              .and_then(|l| serde_json::from_str::<T>(&l).map_err(|e| e.into()))
+            {
+                Ok(t) => t,
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                },
+            };
+            if let Some(ref key) = self.key {
+                match (self.compare)(&t, key) {
+                    Less => continue,
+                    Equal if self.after => continue,
+                    _ => self.key = None,
+                }
+            }
+            return Some(Ok(t));
+        }
+
+        None
+    }
+}
+
+/// Path of the index of the sorted output at `path`
+fn index_path(path: &Path) -> PathBuf {
+    let mut index = OsString::from(path.as_os_str());
+    index.push(".index");
+    PathBuf::from(index)
+}
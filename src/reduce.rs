@@ -0,0 +1,208 @@
+use std::cmp::Ordering;
+use std::collections::btree_map::{self, BTreeMap};
+use std::error::Error;
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::{DedupPolicy, ExtSortedIterator, ExternalSorter, ExternallySortable};
+
+/// Partially aggregated value for a key, as spilled to disk by
+/// [ExternalSorter::sort_and_reduce](struct.ExternalSorter.html#method.sort_and_reduce)
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct Aggregate<K, A> {
+    key: K,
+    acc: A,
+    /// Size of the record the aggregate started from
+    size: u64,
+}
+
+impl<K: Ord, A> PartialEq for Aggregate<K, A> {
+    fn eq(&self, other: &Aggregate<K, A>) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K: Ord, A> Eq for Aggregate<K, A> {}
+
+impl<K: Ord, A> PartialOrd for Aggregate<K, A> {
+    fn partial_cmp(&self, other: &Aggregate<K, A>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, A> Ord for Aggregate<K, A> {
+    fn cmp(&self, other: &Aggregate<K, A>) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+impl<K, A> ExternallySortable for Aggregate<K, A>
+where
+    K: Ord + Clone + Serialize + DeserializeOwned,
+    A: Clone + Serialize + DeserializeOwned,
+{
+    fn get_size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// Iterator that aggregates runs of the input in memory and yields the
+/// aggregates of each run in key order
+struct PreAggregate<I, K, A, KF, IF, FF> {
+    unsorted: I,
+    key: KF,
+    init: IF,
+    fold: Arc<FF>,
+    chunk_bytes: u64,
+    aggregated: btree_map::IntoIter<K, (A, u64)>,
+}
+
+impl<T, I, K, A, KF, IF, FF> Iterator for PreAggregate<I, K, A, KF, IF, FF>
+where
+    T: ExternallySortable,
+    I: Iterator<Item = T>,
+    K: Ord,
+    KF: Fn(&T) -> K,
+    IF: Fn(T) -> A,
+    FF: Fn(A, A) -> A,
+{
+    type Item = Aggregate<K, A>;
+
+    fn next(&mut self) -> Option<Aggregate<K, A>> {
+        if let Some((key, (acc, size))) = self.aggregated.next() {
+            return Some(Aggregate { key, acc, size });
+        }
+
+        let mut groups = BTreeMap::new();
+        let mut total_read = 0;
+        for t in self.unsorted.by_ref() {
+            let size = t.get_size();
+            let key = (self.key)(&t);
+            let acc = (self.init)(t);
+            match groups.remove(&key) {
+                Some((prev, size)) => {
+                    groups.insert(key, ((self.fold)(prev, acc), size));
+                },
+                None => {
+                    groups.insert(key, (acc, size));
+                    total_read += size;
+                    if total_read >= self.chunk_bytes {
+                        break;
+                    }
+                },
+            }
+        }
+        self.aggregated = groups.into_iter();
+
+        self.aggregated
+            .next()
+            .map(|(key, (acc, size))| Aggregate { key, acc, size })
+    }
+}
+
+/// Iterator that provides one aggregated value per key, in key order,
+/// created by
+/// [ExternalSorter::sort_and_reduce](struct.ExternalSorter.html#method.sort_and_reduce)
+pub struct ReducedIterator<K, A> {
+    iter: ExtSortedIterator<Aggregate<K, A>>,
+}
+
+impl<K, A> Iterator for ReducedIterator<K, A>
+where
+    K: Ord + Clone + Serialize + DeserializeOwned,
+    A: Clone + Serialize + DeserializeOwned,
+{
+    type Item = Result<(K, A), Box<dyn Error>>;
+
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// from disk, or due to serde deserialization issues
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|r| r.map(|a| (a.key, a.acc)))
+    }
+}
+
+impl<T> ExternalSorter<T>
+where
+    T: ExternallySortable,
+{
+    /// Group the `T`s provided by `unsorted` by `key` and reduce every group
+    /// to a single value, returning an iterator over the keys (ascending) and
+    /// their values
+    ///
+    /// Each record is turned into a value with `init`, and the values of a
+    /// key are combined with `fold`. Records with the same key are already
+    /// combined in memory before they are spilled, so a chunk on disk holds
+    /// each key at most once, and the chunks are combined again in the final
+    /// merge. `fold` must therefore not depend on the order in which the
+    /// values are combined. Half of the memory buffer is used to hold the
+    /// pre-aggregated keys, and the buffer is measured by the size of the
+    /// first record seen for each key. The keys and values need not be
+    /// `Send`, so the aggregates are spilled on the calling thread, whatever
+    /// the [threads](#method.threads) setting.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    pub fn sort_and_reduce<I, K, A, KF, IF, FF>(&self, unsorted: I, key: KF, init: IF, fold: FF)
+                                                -> Result<ReducedIterator<K, A>, Box<dyn Error>>
+    where
+        I: Iterator<Item = T>,
+        K: 'static + Ord + Clone + Serialize + DeserializeOwned,
+        A: 'static + Clone + Serialize + DeserializeOwned,
+        KF: Fn(&T) -> K,
+        IF: Fn(T) -> A,
+        FF: 'static + Fn(A, A) -> A + Send + Sync,
+    {
+        let fold = Arc::new(fold);
+        let aggregated = PreAggregate {
+            unsorted,
+            key,
+            init,
+            fold: fold.clone(),
+            chunk_bytes: self.buffer_bytes / 2,
+            aggregated: BTreeMap::new().into_iter(),
+        };
+        let mut sorter = self.retype_local::<Aggregate<K, A>>();
+        sorter.buffer_bytes -= sorter.buffer_bytes / 2;
+        let combine = move |a: Aggregate<K, A>, b: Aggregate<K, A>| Aggregate {
+            key: a.key,
+            acc: fold(a.acc, b.acc),
+            size: a.size,
+        };
+        let iter = sorter.sort(aggregated)?
+                         .dedup_policy(DedupPolicy::Combine(Arc::new(combine)));
+
+        Ok(ReducedIterator { iter })
+    }
+
+    /// Count the distinct keys of the `T`s provided by `unsorted` exactly
+    ///
+    /// Only the keys are sorted and spilled, rather than whole records, and
+    /// duplicate keys are already dropped in memory before they are spilled,
+    /// as with [sort_and_reduce](#method.sort_and_reduce).
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing or reading intermediate
+    /// sorted chunks, or due to serde (de)serialization issues
+    pub fn count_distinct<I, K, KF>(&self, unsorted: I, key: KF) -> Result<u64, Box<dyn Error>>
+    where
+        I: Iterator<Item = T>,
+        K: 'static + Ord + Clone + Serialize + DeserializeOwned,
+        KF: Fn(&T) -> K,
+    {
+        let mut distinct = 0;
+        for key in self.sort_and_reduce(unsorted, key, |_| (), |_, _| ())? {
+            key?;
+            distinct += 1;
+        }
+
+        Ok(distinct)
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::hash::{BuildHasher, Hasher};
+use std::iter::Take;
+use std::sync::Arc;
+
+use crate::external_sort::{CompareFn, SendError};
+use crate::{ExtSortedIterator, ExternalSorter, ExternallySortable};
+
+impl<T> ExternalSorter<T>
+where
+    T: ExternallySortable,
+{
+    /// Return an iterator over the `k` greatest `T`s provided by `unsorted`,
+    /// in descending order
+    ///
+    /// See [bottom_k](#method.bottom_k) for how memory is used.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    pub fn top_k<I>(&self, unsorted: I, k: usize)
+                    -> Result<Take<ExtSortedIterator<T>>, Box<dyn Error>>
+    where
+        I: Iterator<Item = T>,
+    {
+        self.select_k(unsorted, k, Arc::new(|a: &T, b: &T| b.cmp(a)))
+            .map_err(|e| e as Box<dyn Error>)
+    }
+
+    /// Return an iterator over the `k` smallest `T`s provided by `unsorted`,
+    /// in ascending order
+    ///
+    /// Only the `k` smallest records seen so far are kept during the input
+    /// pass, so nothing is written to disk unless the `k` records themselves
+    /// do not fit in the memory buffer. In that case, the rest of the input is
+    /// sorted externally.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    pub fn bottom_k<I>(&self, unsorted: I, k: usize)
+                       -> Result<Take<ExtSortedIterator<T>>, Box<dyn Error>>
+    where
+        I: Iterator<Item = T>,
+    {
+        self.select_k(unsorted, k, Arc::new(|a: &T, b: &T| a.cmp(b)))
+            .map_err(|e| e as Box<dyn Error>)
+    }
+
+    /// Return the records at each of the given `percentiles` (from `0.0` to
+    /// `100.0`) of the `T`s provided by `unsorted`
+    ///
+    /// The percentiles are exact, using the nearest-rank method: the `p`th
+    /// percentile of `n` records is the record at (1-based) rank
+    /// `ceil(p / 100 * n)`, or the first record for `p = 0`. The input is
+    /// sorted externally, and the sorted output is only read up to the
+    /// highest requested rank. The records are returned in the order of
+    /// `percentiles`.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing or reading intermediate
+    /// sorted chunks, due to serde (de)serialization issues, if a percentile
+    /// is outside of `0.0..=100.0`, or if `unsorted` is empty
+    pub fn percentiles<I>(&self, unsorted: I, percentiles: &[f64]) -> Result<Vec<T>, Box<dyn Error>>
+    where
+        I: Iterator<Item = T>,
+    {
+        if let Some(p) = percentiles.iter().find(|p| !(0.0..=100.0).contains(*p)) {
+            return Err(format!("percentile {} is outside of 0..=100", p).into());
+        }
+        let iter = self.sort(unsorted)?;
+        let records = iter.chunk_records();
+        if records == 0 {
+            return Err("no records to compute percentiles of".into());
+        }
+        let rank = |p: f64| ((p * records as f64 / 100.0).ceil() as u64).max(1) - 1;
+        let ranks: Vec<u64> = percentiles.iter().map(|&p| rank(p)).collect();
+        let mut wanted = ranks.clone();
+        wanted.sort();
+        wanted.dedup();
+        let mut found = Vec::with_capacity(wanted.len());
+        let mut wanted_iter = wanted.iter().peekable();
+        for (rank, t) in iter.enumerate() {
+            let t = t?;
+            while wanted_iter.peek() == Some(&&(rank as u64)) {
+                wanted_iter.next();
+                found.push(t.clone());
+            }
+            if wanted_iter.peek().is_none() {
+                break;
+            }
+        }
+
+        // the ranks are all below the number of records
+        Ok(ranks.iter()
+                .map(|rank| found[wanted.binary_search(rank).unwrap()].clone())
+                .collect())
+    }
+
+    /// Return the `n`th (counting from `0`) smallest of the `T`s provided by
+    /// `unsorted`, or `None` if there are not more than `n` of them
+    ///
+    /// The input is sorted into chunks as usual, but rather than merging all
+    /// of the chunks up to the `n`th record, the records sampled from each
+    /// chunk are used to skip the parts of the chunks that must precede it,
+    /// so only a small part of the data is read back and merged.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing or reading intermediate
+    /// sorted chunks, or due to serde (de)serialization issues
+    pub fn select_nth<I>(&self, unsorted: I, n: u64) -> Result<Option<T>, Box<dyn Error>>
+    where
+        I: Iterator<Item = T>,
+    {
+        let mut iter = self.sort(unsorted)?;
+        let skipped = iter.skip_to_rank(n).map_err(|e| e as Box<dyn Error>)?;
+        iter.nth((n - skipped) as usize).transpose()
+    }
+
+    /// Return a uniform random sample of `n` of the `T`s provided by
+    /// `unsorted` (or all of them, if there are fewer), in the order they
+    /// were provided
+    ///
+    /// The sample is drawn with reservoir sampling during a single pass over
+    /// the input, without sorting or writing anything to disk, so it must fit
+    /// in memory.
+    pub fn sample<I>(&self, unsorted: I, n: usize) -> Vec<T>
+    where
+        I: Iterator<Item = T>,
+    {
+        let mut rng = XorShift::new();
+        let mut sample: Vec<(usize, T)> = Vec::with_capacity(n);
+        for (i, t) in unsorted.enumerate() {
+            if sample.len() < n {
+                sample.push((i, t));
+            } else {
+                let j = rng.below(i + 1);
+                if j < n {
+                    sample[j] = (i, t);
+                }
+            }
+        }
+        sample.sort_by_key(|&(i, _)| i);
+
+        sample.into_iter().map(|(_, t)| t).collect()
+    }
+
+    /// Return a uniform random sample of `n` of the `T`s provided by
+    /// `unsorted`, sorted (ascending)
+    ///
+    /// Only the sample is sorted, in memory. See [sample](#method.sample).
+    pub fn sorted_sample<I>(&self, unsorted: I, n: usize) -> Vec<T>
+    where
+        I: Iterator<Item = T>,
+    {
+        let mut sample = self.sample(unsorted, n);
+        sample.sort();
+        sample
+    }
+
+    /// Select the `k` first `T`s provided by `unsorted` according to
+    /// `compare`
+    fn select_k<I>(&self, mut unsorted: I, k: usize, compare: Arc<CompareFn<T>>)
+                   -> Result<Take<ExtSortedIterator<T>>, SendError>
+    where
+        I: Iterator<Item = T>,
+    {
+        let mut kept = Vec::new();
+        let mut total_read = 0;
+        if k > 0 {
+            for t in unsorted.by_ref() {
+                total_read += t.get_size();
+                kept.push(t);
+                if kept.len() < 2 * k && total_read <= self.buffer_bytes {
+                    continue;
+                }
+                if kept.len() > k {
+                    // only keep the first k records, in any order
+                    kept.select_nth_unstable_by(k - 1, |a, b| compare(a, b));
+                    kept.truncate(k);
+                    total_read = kept.iter().map(|t| t.get_size()).sum();
+                }
+                if total_read > self.buffer_bytes {
+                    // the k records don't fit in memory, so sort everything
+                    // that may still be among them
+                    let iter = self.sort_shared(kept.into_iter().chain(unsorted), compare)?;
+                    return Ok(iter.take(k));
+                }
+            }
+        }
+        kept.sort_by(|a, b| compare(a, b));
+        kept.truncate(k);
+        let tmp_dir = Arc::new(self.make_tmp_dir()?);
+
+        Ok(ExtSortedIterator::from_memory(tmp_dir, compare, kept).take(k))
+    }
+}
+
+/// A xorshift64* generator, random enough to pick samples without a
+/// dependency on `rand`
+struct XorShift(u64);
+
+impl XorShift {
+    /// Seed the generator from the random keys of a `RandomState`
+    fn new() -> Self {
+        XorShift(RandomState::new().build_hasher().finish() | 1)
+    }
+
+    /// Return a number in `0..bound`
+    fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let x = self.0.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        ((u128::from(x) * bound as u128) >> 64) as usize
+    }
+}
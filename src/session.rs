@@ -0,0 +1,241 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tempdir::TempDir;
+
+use crate::external_sort::{save_json, ChunkMeta, CompareFn, RunCheckpoint, SortConfig};
+use crate::progress::InputTracker;
+use crate::timing::Timer;
+use crate::{ExtSortedIterator, ExternalSorter, ExternallySortable};
+
+/// Name of the file describing a saved session within its directory
+const SESSION_FILE: &str = "session.json";
+
+/// Runs and configuration of a session, saved by
+/// [SortSession::save](struct.SortSession.html#method.save)
+#[derive(Serialize, Deserialize)]
+struct SavedSession<T> {
+    config: SortConfig,
+    tmp_dir: Option<PathBuf>,
+    runs: Vec<RunCheckpoint<T>>,
+}
+
+/// Sort of several inputs added over time, which are merged together once the
+/// session is finished, created by
+/// [ExternalSorter::session](struct.ExternalSorter.html#method.session)
+///
+/// Every input is spilled to sorted runs as it is added, like a
+/// [sort](struct.ExternalSorter.html#method.sort) of its own, and
+/// [finish](#method.finish) merges the runs of all of them in a single merge.
+/// Records that compare as equal are merged in the order they were added.
+///
+/// # Examples
+///
+/// ```
+/// extern crate external_sort;
+/// #[macro_use]
+/// extern crate serde_derive;
+///
+/// use external_sort::{ExternallySortable, ExternalSorter};
+///
+/// #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// struct Num {
+///     the_num: u32
+/// }
+///
+/// impl ExternallySortable for Num {
+///     fn get_size(&self) -> u64 {
+///         4
+///     }
+/// }
+///
+/// fn main() {
+///     let mut session = ExternalSorter::new(16, None).session();
+///     for file in 0..4u32 {
+///         session.add_input((0..10).map(|i| Num { the_num: i * 4 + file })).unwrap();
+///     }
+///
+///     let iter = session.finish().unwrap();
+///     for (idx, i) in iter.enumerate() {
+///         assert_eq!(i.unwrap().the_num, idx as u32);
+///     }
+/// }
+/// ```
+pub struct SortSession<T>
+where
+    T: ExternallySortable,
+{
+    sorter: ExternalSorter<T>,
+    compare: Arc<CompareFn<T>>,
+    input: InputTracker,
+    // dropped before the directories holding them, so that the runs are
+    // closed by the time the directories are removed
+    chunk_meta: Vec<ChunkMeta<T>>,
+    /// Directories holding the runs of every input
+    tmp_dirs: Vec<Arc<TempDir>>,
+    /// Started along with the session
+    timer: Timer,
+}
+
+impl<T> SortSession<T>
+where
+    T: ExternallySortable,
+{
+    /// Spill the `T`s provided by `unsorted` to sorted runs, to be merged
+    /// with those of the other inputs
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    pub fn add_input<I>(&mut self, unsorted: I) -> Result<(), Box<dyn Error>>
+    where
+        I: Iterator<Item = T>,
+    {
+        let (tmp_dir, mut chunk_meta) = self.sorter
+                                            .spill_runs(unsorted, &self.compare, &self.input)
+                                            .map_err(|e| e as Box<dyn Error>)?;
+        self.tmp_dirs.push(tmp_dir);
+        self.chunk_meta.append(&mut chunk_meta);
+        Ok(())
+    }
+
+    /// Save the runs of every input added so far, along with the
+    /// configuration of the sorter, into the directory `dir` (which is
+    /// created if needed), to continue the session later with
+    /// [load](#method.load) or [load_by](#method.load_by), e.g. in a later
+    /// run of the process
+    ///
+    /// The runs are hard-linked (or copied, across file systems) into `dir`,
+    /// so they outlive this session, and later saves into the same directory
+    /// only add the runs of the inputs added since. Each run is read once to
+    /// checksum it, so that [load](#method.load) can tell if it changed. The directory is left for
+    /// the caller to remove once the session is finished. Only the settings
+    /// reported in a [SortConfig](struct.SortConfig.html) and the temporary
+    /// directory are saved; callbacks and tie-breaking have to be set again on
+    /// the loaded session.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing the runs or the session
+    /// file, or due to serde serialization issues
+    pub fn save<P>(&self, dir: P) -> Result<(), Box<dyn Error>>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let mut runs = Vec::with_capacity(self.chunk_meta.len());
+        for (chunk_num, meta) in self.chunk_meta.iter().enumerate() {
+            let run = meta.save(dir, format!("run_{}", chunk_num), 0);
+            runs.push(run.map_err(|e| e as Box<dyn Error>)?);
+        }
+        let saved = SavedSession { config: self.sorter.config(),
+                                   tmp_dir: self.sorter.tmp_dir.clone(),
+                                   runs };
+        save_json(dir, SESSION_FILE, &saved)
+    }
+
+    /// Continue a session saved by [save](#method.save) into `dir`, with
+    /// records that sort by their natural order
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading the session file, if a run
+    /// no longer has the size or checksum it was saved with, or due to serde
+    /// deserialization issues
+    pub fn load<P>(dir: P) -> Result<SortSession<T>, Box<dyn Error>>
+    where
+        P: AsRef<Path>,
+        T: 'static + Send,
+    {
+        SortSession::load_by(dir, |a: &T, b: &T| a.cmp(b))
+    }
+
+    /// Continue a session saved by [save](#method.save) into `dir`, with
+    /// records that sort by `compare`, which must be the order its runs were
+    /// sorted by
+    ///
+    /// The runs are merged from `dir`, which must be kept until the iterator
+    /// returned by [finish](#method.finish) is dropped.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading the session file, if a run
+    /// no longer has the size or checksum it was saved with, or due to serde
+    /// deserialization issues
+    pub fn load_by<P, F>(dir: P, compare: F) -> Result<SortSession<T>, Box<dyn Error>>
+    where
+        T: Send,
+        P: AsRef<Path>,
+        F: 'static + Fn(&T, &T) -> Ordering + Send + Sync,
+    {
+        let dir = dir.as_ref();
+        let file = BufReader::new(File::open(dir.join(SESSION_FILE))?);
+        let saved: SavedSession<T> = serde_json::from_reader(file)?;
+        let config = saved.config;
+        let mut sorter = ExternalSorter::new(config.buffer_bytes, saved.tmp_dir);
+        if let Some(threads) = config.threads {
+            sorter = sorter.threads(threads);
+        }
+        if let Some(fan_in) = config.premerge {
+            sorter = sorter.premerge(fan_in);
+        }
+        if config.check_sorted {
+            sorter = sorter.check_sorted();
+        }
+        let mut session = sorter.session_by(compare);
+        for run in saved.runs {
+            session.chunk_meta.push(ChunkMeta::saved(dir, run).map_err(|e| e as Box<dyn Error>)?);
+        }
+        Ok(session)
+    }
+
+    /// Merge the runs of every input added, and return an iterator over all
+    /// of their `T`s in sorted order
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate merge passes
+    /// (if the sorter plans any) to disk, or due to serde issues
+    pub fn finish(self) -> Result<ExtSortedIterator<T>, Box<dyn Error>> {
+        self.sorter
+            .merge_runs(self.tmp_dirs, self.compare, self.chunk_meta, self.timer)
+            .map_err(|e| e as Box<dyn Error>)
+    }
+}
+
+impl<T> ExternalSorter<T>
+where
+    T: ExternallySortable,
+{
+    /// Start a [SortSession](struct.SortSession.html) sorting several inputs
+    /// together (ascending), added one after the other
+    pub fn session(&self) -> SortSession<T>
+    where
+        T: 'static,
+    {
+        self.session_by(|a: &T, b: &T| a.cmp(b))
+    }
+
+    /// Start a [SortSession](struct.SortSession.html) sorting several inputs
+    /// together based on `compare`, added one after the other
+    pub fn session_by<F>(&self, compare: F) -> SortSession<T>
+    where
+        F: 'static + Fn(&T, &T) -> Ordering + Send + Sync,
+    {
+        SortSession {
+            sorter: self.clone(),
+            compare: self.break_ties(Arc::new(compare)),
+            input: self.track_input(),
+            tmp_dirs: Vec::new(),
+            chunk_meta: Vec::new(),
+            timer: Timer::start(),
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::cmp::Ordering::{self, Equal, Greater};
+use std::error::Error;
+use std::iter::Peekable;
+
+use crate::{ExtSortedIterator, ExternallySortable};
+
+type MatchFn<T, U> = dyn Fn(&T, &U) -> Ordering + Send + Sync;
+
+/// Iterator that provides the records of one sorted iterator that do (or do
+/// not) have an equal record in another, created by
+/// [ExtSortedIterator::intersection](struct.ExtSortedIterator.html#method.intersection),
+/// [ExtSortedIterator::difference](struct.ExtSortedIterator.html#method.difference)
+/// and
+/// [ExtSortedIterator::difference_by_key](struct.ExtSortedIterator.html#method.difference_by_key)
+pub struct SetIterator<T, U>
+where
+    T: ExternallySortable,
+    U: ExternallySortable,
+{
+    iter: ExtSortedIterator<T>,
+    other: Peekable<ExtSortedIterator<U>>,
+    compare: Box<MatchFn<T, U>>,
+    /// Whether records with a match are kept, rather than those without one
+    matched: bool,
+    failed: bool,
+}
+
+impl<T> ExtSortedIterator<T>
+where
+    T: ExternallySortable,
+{
+    /// Only yield the records that compare as equal to some record of
+    /// `other`, which must be sorted in the same order
+    ///
+    /// Both iterators are read in a single streaming pass, and every record
+    /// of this iterator that has a match is yielded, including duplicates.
+    pub fn intersection(self, other: ExtSortedIterator<T>) -> SetIterator<T, T>
+    where
+        T: 'static,
+    {
+        let compare = self.sort_by_fn();
+        self.matching(other, Box::new(move |a: &T, b: &T| compare(a, b)), true)
+    }
+
+    /// Only yield the records that do not compare as equal to any record of
+    /// `other`, which must be sorted in the same order
+    ///
+    /// Both iterators are read in a single streaming pass.
+    pub fn difference(self, other: ExtSortedIterator<T>) -> SetIterator<T, T>
+    where
+        T: 'static,
+    {
+        let compare = self.sort_by_fn();
+        self.matching(other, Box::new(move |a: &T, b: &T| compare(a, b)), false)
+    }
+
+    /// Only yield the records whose key is not the key of any record of
+    /// `other`, like an anti-join, e.g. to drop the records of a blocklist
+    ///
+    /// Both iterators must be sorted in the order of their keys. They are
+    /// read in a single streaming pass.
+    pub fn difference_by_key<U, K, KF, UF>(self, other: ExtSortedIterator<U>, key: KF,
+                                           other_key: UF)
+                                           -> SetIterator<T, U>
+    where
+        U: ExternallySortable,
+        K: Ord,
+        KF: 'static + Fn(&T) -> K + Send + Sync,
+        UF: 'static + Fn(&U) -> K + Send + Sync,
+    {
+        self.matching(other, Box::new(move |a: &T, b: &U| key(a).cmp(&other_key(b))), false)
+    }
+
+    fn matching<U>(self, other: ExtSortedIterator<U>, compare: Box<MatchFn<T, U>>, matched: bool)
+                   -> SetIterator<T, U>
+    where
+        U: ExternallySortable,
+    {
+        SetIterator {
+            iter: self,
+            other: other.peekable(),
+            compare,
+            matched,
+            failed: false,
+        }
+    }
+}
+
+/// Whether `t` has a match in `other`, which is advanced to the first record
+/// not preceding `t`
+fn has_match<T, U>(other: &mut Peekable<ExtSortedIterator<U>>, compare: &MatchFn<T, U>, t: &T)
+                   -> Result<bool, Box<dyn Error>>
+where
+    U: ExternallySortable,
+{
+    loop {
+        match other.peek() {
+            Some(Ok(u)) => match compare(t, u) {
+                Equal => return Ok(true),
+                Greater => {},
+                _ => return Ok(false),
+            },
+            Some(Err(_)) => {},
+            None => return Ok(false),
+        }
+        // unwrap due to the peek() above
+        other.next().unwrap()?;
+    }
+}
+
+impl<T, U> Iterator for SetIterator<T, U>
+where
+    T: ExternallySortable,
+    U: ExternallySortable,
+{
+    type Item = Result<T, Box<dyn Error>>;
+
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading intermediate sorted chunks
+    /// of either iterator from disk, or due to serde deserialization issues
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let (other, compare) = (&mut self.other, &*self.compare);
+        for t in self.iter.by_ref() {
+            match t.and_then(|t| has_match(other, compare, &t).map(|m| (t, m))) {
+                Ok((t, has_match)) if has_match == self.matched => return Some(Ok(t)),
+                Ok(_) => {},
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                },
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,108 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::sync::Arc;
+
+use crate::checksum::Checksum;
+use crate::external_sort::{write_chunk, CompareFn};
+use crate::{ExtSortedIterator, ExternalSorter, ExternallySortable};
+
+impl<T> ExternalSorter<T>
+where
+    T: ExternallySortable,
+{
+    /// Partition the `T`s provided by `unsorted` into `partitions` buckets by
+    /// `hash(key(t)) % partitions`, and return a sorted (ascending) iterator
+    /// over each bucket
+    ///
+    /// Records with equal keys always end up in the same bucket, and the
+    /// hash (FNV-1a) is fixed rather than randomly seeded, so separate
+    /// datasets shuffled with the same key function and number of partitions
+    /// are partitioned consistently, across runs and processes of the same
+    /// build.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    pub fn shuffle<I, K, KF>(&self, unsorted: I, partitions: usize, key: KF)
+                             -> Result<Vec<ExtSortedIterator<T>>, Box<dyn Error>>
+    where
+        I: Iterator<Item = T>,
+        K: Hash,
+        KF: Fn(&T) -> K,
+    {
+        self.shuffle_by(unsorted, partitions, key, |a, b| a.cmp(b))
+    }
+
+    /// Partition the `T`s provided by `unsorted` into `partitions` buckets by
+    /// `hash(key(t)) % partitions`, and return an iterator over each bucket
+    /// sorted based on `compare`
+    ///
+    /// The memory buffer is shared between the buckets: once it is full, the
+    /// largest bucket is sorted and written to disk.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues
+    pub fn shuffle_by<I, K, KF, F>(&self, unsorted: I, partitions: usize, key: KF, compare: F)
+                                   -> Result<Vec<ExtSortedIterator<T>>, Box<dyn Error>>
+    where
+        I: Iterator<Item = T>,
+        K: Hash,
+        KF: Fn(&T) -> K,
+        F: 'static + Fn(&T, &T) -> Ordering + Send + Sync,
+    {
+        let partitions = partitions.max(1);
+        let tmp_dir = Arc::new(self.make_tmp_dir().map_err(|e| e as Box<dyn Error>)?);
+        let compare: Arc<CompareFn<T>> = Arc::new(compare);
+        let mut buckets: Vec<(Vec<T>, u64)> = (0..partitions).map(|_| (Vec::new(), 0)).collect();
+        let mut chunk_meta = vec![Vec::new(); partitions];
+        let mut total_read = 0;
+
+        let mut spill = |bucket: usize, chunk: &mut Vec<T>| -> Result<(), Box<dyn Error>> {
+            chunk.sort_by(|a, b| compare(a, b));
+            let path = tmp_dir.path().join(format!("{}_{}", bucket, chunk_meta[bucket].len()));
+            let mut meta = write_chunk(&path, chunk, false).map_err(|e| e as Box<dyn Error>)?;
+            meta.delete_on_close()?;
+            chunk_meta[bucket].push(meta);
+            chunk.clear();
+            Ok(())
+        };
+
+        for t in unsorted {
+            let mut hasher = Checksum::new();
+            key(&t).hash(&mut hasher);
+            let bucket = (hasher.finish() % partitions as u64) as usize;
+            // every record also takes its slot in the bucket, as in the chunks
+            // of a sort
+            let size = t.get_size() + mem::size_of::<T>() as u64;
+            buckets[bucket].0.push(t);
+            buckets[bucket].1 += size;
+            total_read += size;
+            if total_read >= self.buffer_bytes {
+                // unwrap since there is at least one bucket
+                let largest = (0..partitions).max_by_key(|b| buckets[*b].1).unwrap();
+                spill(largest, &mut buckets[largest].0)?;
+                total_read -= mem::replace(&mut buckets[largest].1, 0);
+            }
+        }
+        for (bucket, (chunk, _)) in buckets.iter_mut().enumerate() {
+            if !chunk.is_empty() {
+                spill(bucket, chunk)?;
+            }
+        }
+
+        // every partition is primed with its share of the buffer
+        let buffer_bytes = self.buffer_bytes / partitions as u64;
+        chunk_meta.into_iter()
+                  .map(|meta| {
+                      ExtSortedIterator::from_chunks(tmp_dir.clone(), compare.clone(), meta,
+                                                     buffer_bytes, self.allocator.clone())
+                          .map_err(|e| e as Box<dyn Error>)
+                  })
+                  .collect()
+    }
+}
@@ -0,0 +1,154 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::panic;
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::external_sort::{CompareFn, SendError};
+use crate::{ExtSortedIterator, ExternalSorter, ExternallySortable};
+
+/// Number of records that may be queued between the producers and the sorter
+/// thread before `push()` blocks
+const SINK_CAPACITY: usize = 1024;
+
+type SortThread<T> = JoinHandle<Result<ExtSortedIterator<T>, SendError>>;
+
+/// Clonable handle for pushing `T`s into a sort from several producer threads
+///
+/// Records pushed into any clone of the sink are sent to a single sorter
+/// thread, which buffers and spills them like
+/// [ExternalSorter::sort](struct.ExternalSorter.html#method.sort).
+/// [finish](#method.finish) returns the merged iterator once every clone of
+/// the sink has been dropped or finished.
+///
+/// # Examples
+///
+/// ```
+/// extern crate external_sort;
+/// #[macro_use]
+/// extern crate serde_derive;
+///
+/// use std::thread;
+///
+/// use external_sort::{ExternallySortable, ExternalSorter};
+///
+/// #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// struct Num {
+///     the_num: u32
+/// }
+///
+/// impl ExternallySortable for Num {
+///     fn get_size(&self) -> u64 {
+///         4
+///     }
+/// }
+///
+/// fn main() {
+///     let sink = ExternalSorter::new(16, None).sink();
+///     let producers: Vec<_> = (0..4u32).map(|p| {
+///         let sink = sink.clone();
+///         thread::spawn(move || {
+///             for i in 0..10 {
+///                 sink.push(Num { the_num: i * 4 + p }).unwrap();
+///             }
+///         })
+///     }).collect();
+///     for producer in producers {
+///         producer.join().unwrap();
+///     }
+///
+///     let iter = sink.finish().unwrap();
+///     for (idx, i) in iter.enumerate() {
+///         assert_eq!(i.unwrap().the_num, idx as u32);
+///     }
+/// }
+/// ```
+pub struct SortSink<T>
+where
+    T: ExternallySortable,
+{
+    tx: SyncSender<T>,
+    sorter: Arc<Mutex<Option<SortThread<T>>>>,
+}
+
+impl<T> Clone for SortSink<T>
+where
+    T: ExternallySortable,
+{
+    fn clone(&self) -> SortSink<T> {
+        SortSink {
+            tx: self.tx.clone(),
+            sorter: self.sorter.clone(),
+        }
+    }
+}
+
+impl<T> SortSink<T>
+where
+    T: ExternallySortable + Send + 'static,
+{
+    fn new(sorter: ExternalSorter<T>, compare: Arc<CompareFn<T>>) -> SortSink<T> {
+        let (tx, rx) = mpsc::sync_channel(SINK_CAPACITY);
+        let handle = thread::spawn(move || sorter.sort_shared(rx.into_iter(), compare));
+        SortSink {
+            tx,
+            sorter: Arc::new(Mutex::new(Some(handle))),
+        }
+    }
+
+    /// Push a `T` into the sort, blocking while the sorter thread is busy
+    /// spilling and its queue is full
+    ///
+    /// # Errors
+    ///
+    /// This method fails if the sorter thread has stopped due to an error,
+    /// which is returned by [finish](#method.finish)
+    pub fn push(&self, t: T) -> Result<(), Box<dyn Error>> {
+        self.tx.send(t).map_err(|_| "sort failed, see SortSink::finish() for the error".into())
+    }
+
+    /// Wait for every other clone of this sink to be dropped or finished, and
+    /// return an iterator over all of the pushed `T`s in sorted order
+    ///
+    /// This blocks until all other clones are gone, so it must not be called
+    /// while the current thread still holds another clone.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing intermediate sorted chunks
+    /// to disk, or due to serde serialization issues. It also fails if
+    /// `finish()` has already been called on another clone of this sink.
+    pub fn finish(self) -> Result<ExtSortedIterator<T>, Box<dyn Error>> {
+        let handle = self.sorter.lock().unwrap().take();
+        drop(self.tx);
+        match handle {
+            Some(handle) => handle.join()
+                                  .unwrap_or_else(|e| panic::resume_unwind(e))
+                                  .map_err(|e| e as Box<dyn Error>),
+            None => Err("finish() was already called on another clone of this sink".into()),
+        }
+    }
+}
+
+impl<T> ExternalSorter<T>
+where
+    T: ExternallySortable + Send + 'static,
+{
+    /// Start a sort on a background thread, returning a clonable
+    /// [SortSink](struct.SortSink.html) that producer threads can push `T`s
+    /// into, to be sorted (ascending)
+    pub fn sink(&self) -> SortSink<T> {
+        self.sink_by(|a, b| a.cmp(b))
+    }
+
+    /// Start a sort (based on `compare`) on a background thread, returning a
+    /// clonable [SortSink](struct.SortSink.html) that producer threads can
+    /// push `T`s into
+    pub fn sink_by<F>(&self, compare: F) -> SortSink<T>
+    where
+        F: 'static + Fn(&T, &T) -> Ordering + Send + Sync,
+    {
+        SortSink::new(self.clone(), Arc::new(compare))
+    }
+}
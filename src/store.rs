@@ -0,0 +1,395 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use serde::{Deserialize, Serialize};
+use tempdir::TempDir;
+
+use crate::external_sort::{scan_run, write_chunk, write_merged, ChunkMeta, CompareFn, SendError};
+use crate::{ExtSortedIterator, ExternalSorter, ExternallySortable};
+
+/// Name of the file listing the runs of a store
+const MANIFEST_FILE: &str = "store.json";
+/// Prefix of the names of the runs of a store
+const RUN_PREFIX: &str = "run_";
+/// Prefix of the directories holding the runs read by an iterator
+const SNAPSHOT_PREFIX: &str = "snapshot";
+/// Number of runs of a level compacted together, unless set with
+/// `premerge()`
+const STORE_FAN_IN: usize = 8;
+
+/// Runs of a store, as saved in its manifest
+#[derive(Serialize, Deserialize, Default)]
+struct Manifest {
+    /// Names of the runs of every level, oldest first within a level
+    levels: Vec<Vec<String>>,
+    /// Number naming the next run
+    next_run: u64,
+}
+
+/// Sorted runs of a store by level, from the runs flushed from memory at
+/// level 0
+struct Levels<T> {
+    dir: PathBuf,
+    /// Runs of every level, oldest first within a level
+    runs: Vec<Vec<ChunkMeta<T>>>,
+    next_run: u64,
+}
+
+impl<T> Levels<T> {
+    /// Reserve the path of a new run
+    fn next_path(&mut self) -> PathBuf {
+        self.next_run += 1;
+        self.dir.join(format!("{}{}", RUN_PREFIX, self.next_run - 1))
+    }
+
+    /// Add a run at the end of `level`
+    fn push(&mut self, level: usize, run: ChunkMeta<T>) {
+        if self.runs.len() <= level {
+            self.runs.resize_with(level + 1, Vec::new);
+        }
+        self.runs[level].push(run);
+    }
+
+    /// First level holding at least `fan_in` runs
+    fn full(&self, fan_in: usize) -> Option<usize> {
+        self.runs.iter().position(|runs| runs.len() >= fan_in)
+    }
+
+    /// Replace the manifest of the store with the current runs at once, so
+    /// that a failure leaves either one in place
+    fn save(&self) -> Result<(), SendError> {
+        let name = |run: &ChunkMeta<T>| {
+            run.path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+        };
+        let manifest = Manifest {
+            levels: self.runs.iter().map(|runs| runs.iter().map(name).collect()).collect(),
+            next_run: self.next_run,
+        };
+        let tmp = self.dir.join(format!("{}.tmp", MANIFEST_FILE));
+        let mut file = BufWriter::new(File::create(&tmp)?);
+        serde_json::to_writer(&mut file, &manifest)?;
+        file.flush()?;
+        file.get_ref().sync_all()?;
+        fs::rename(tmp, self.dir.join(MANIFEST_FILE))?;
+        Ok(())
+    }
+}
+
+/// Persistent sorted collection of `T`s, kept as levels of sorted runs in a
+/// directory, created by
+/// [ExternalSorter::open_store](struct.ExternalSorter.html#method.open_store)
+///
+/// Inserted records are buffered in memory, and flushed to a new sorted run
+/// once they fill half of the memory buffer of the sorter. Whenever a level
+/// holds as many runs as the fan-in of the store, a background thread
+/// compacts them into a single run of the next level, using the other half
+/// of the buffer, so that iterating over the store merges few runs. Records
+/// inserted since the last [flush](#method.flush) are lost unless the store is
+/// flushed or [closed](#method.close). A store must only be opened once at a
+/// time.
+///
+/// # Examples
+///
+/// ```
+/// extern crate external_sort;
+/// #[macro_use]
+/// extern crate serde_derive;
+/// extern crate tempdir;
+///
+/// use external_sort::{ExternallySortable, ExternalSorter};
+///
+/// #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// struct Num {
+///     the_num: u32
+/// }
+///
+/// impl ExternallySortable for Num {
+///     fn get_size(&self) -> u64 {
+///         4
+///     }
+/// }
+///
+/// fn main() {
+///     let dir = tempdir::TempDir::new("store").unwrap();
+///     let sorter = ExternalSorter::new(64, None);
+///     let mut store = sorter.open_store(dir.path()).unwrap();
+///     for n in (0..100).rev() {
+///         store.insert(Num { the_num: n }).unwrap();
+///     }
+///     store.close().unwrap();
+///
+///     let store = sorter.open_store(dir.path()).unwrap();
+///     for (idx, n) in store.iter().unwrap().enumerate() {
+///         assert_eq!(n.unwrap().the_num, idx as u32);
+///     }
+/// }
+/// ```
+pub struct SortedStore<T> {
+    compare: Arc<CompareFn<T>>,
+    buffer_bytes: u64,
+    fan_in: usize,
+    /// Records inserted since the last flush
+    inserted: Vec<T>,
+    inserted_bytes: u64,
+    levels: Arc<Mutex<Levels<T>>>,
+    /// Thread compacting full levels, if any
+    compactor: Option<JoinHandle<Result<(), SendError>>>,
+}
+
+impl<T> SortedStore<T>
+where
+    T: ExternallySortable + Send + 'static,
+{
+    /// Insert a `T` into the store, flushing the records inserted so far to a
+    /// new run if they fill half of the memory buffer
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing the runs of the store to
+    /// disk, or due to serde serialization issues, including those of the
+    /// last background compaction
+    pub fn insert(&mut self, t: T) -> Result<(), Box<dyn Error>> {
+        self.inserted_bytes += t.get_size();
+        self.inserted.push(t);
+        if self.inserted_bytes >= self.buffer_bytes / 2 {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write the records inserted since the last flush to a new run, and
+    /// start compacting the full levels in the background
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing the run to disk, or due to
+    /// serde serialization issues, including those of the last background
+    /// compaction
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.compactor.as_ref().is_some_and(|c| c.is_finished()) {
+            self.join_compactor()?;
+        }
+        if !self.inserted.is_empty() {
+            let compare = &self.compare;
+            self.inserted.sort_by(|a, b| compare(a, b));
+            let path = self.levels.lock().unwrap().next_path();
+            let run = write_chunk(&path, &self.inserted, false).map_err(|e| e as Box<dyn Error>)?;
+            let mut levels = self.levels.lock().unwrap();
+            levels.push(0, run);
+            levels.save().map_err(|e| e as Box<dyn Error>)?;
+            self.inserted.clear();
+            self.inserted_bytes = 0;
+        }
+        if self.compactor.is_none() && self.levels.lock().unwrap().full(self.fan_in).is_some() {
+            let (levels, compare) = (self.levels.clone(), self.compare.clone());
+            let (fan_in, buffer_bytes) = (self.fan_in, self.buffer_bytes / 2);
+            self.compactor =
+                Some(thread::spawn(move || compact(&levels, &compare, fan_in, buffer_bytes)));
+        }
+        Ok(())
+    }
+
+    /// Flush the store, and wait until no level is full
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues writing the runs of the store to
+    /// disk, or due to serde serialization issues
+    pub fn close(mut self) -> Result<(), Box<dyn Error>> {
+        self.flush()?;
+        self.join_compactor()?;
+        // levels filled once the background compaction was done with them
+        compact(&self.levels, &self.compare, self.fan_in, self.buffer_bytes / 2)
+            .map_err(|e| e as Box<dyn Error>)
+    }
+
+    /// Iterate over every `T` of the store in sorted order, including those
+    /// not flushed yet
+    ///
+    /// The iterator reads the runs of the store at the time it is created,
+    /// which are linked into a directory of its own, so that they can be
+    /// compacted while it is in use. The records not flushed yet are copied.
+    /// Equal records are yielded in the order they were inserted.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues linking or reading the runs of the
+    /// store
+    pub fn iter(&self) -> Result<ExtSortedIterator<T>, Box<dyn Error>> {
+        let levels = self.levels.lock().unwrap();
+        let snapshot = Arc::new(TempDir::new_in(&levels.dir, SNAPSHOT_PREFIX)?);
+        let mut runs = Vec::new();
+        // older levels first, so that equal records are merged in the order
+        // they were inserted
+        for run in levels.runs.iter().rev().flatten() {
+            let mut run = run.clone();
+            let link = snapshot.path().join(run.path.file_name().unwrap_or_default());
+            if fs::hard_link(&run.path, &link).is_err() {
+                fs::copy(&run.path, &link)?;
+            }
+            run.path = link;
+            runs.push(run);
+        }
+        drop(levels);
+
+        let compare = &self.compare;
+        let mut inserted = self.inserted.clone();
+        inserted.sort_by(|a, b| compare(a, b));
+        let flushed = ExtSortedIterator::from_runs(vec![snapshot.clone()],
+                                                   compare.clone(),
+                                                   runs,
+                                                   self.buffer_bytes)
+            .map_err(|e| e as Box<dyn Error>)?;
+        let inserted = ExtSortedIterator::from_memory(snapshot, compare.clone(), inserted);
+        ExtSortedIterator::union(vec![flushed, inserted])
+    }
+
+    /// Number of runs of every level of the store, from the runs flushed
+    /// from memory at level 0
+    pub fn levels(&self) -> Vec<usize> {
+        self.levels.lock().unwrap().runs.iter().map(Vec::len).collect()
+    }
+
+    /// Wait for the background compaction, if any
+    fn join_compactor(&mut self) -> Result<(), Box<dyn Error>> {
+        match self.compactor.take() {
+            Some(compactor) => compactor.join()
+                                        .unwrap_or_else(|e| panic::resume_unwind(e))
+                                        .map_err(|e| e as Box<dyn Error>),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T> Drop for SortedStore<T> {
+    fn drop(&mut self) {
+        // the compaction is finished, rather than left to be cleaned up when
+        // the store is opened again
+        if let Some(compactor) = self.compactor.take() {
+            let _ = compactor.join();
+        }
+    }
+}
+
+/// Compact the first `fan_in` runs of the first full level into a single run
+/// of the next level, until no level is full
+fn compact<T>(levels: &Mutex<Levels<T>>, compare: &Arc<CompareFn<T>>, fan_in: usize,
+              buffer_bytes: u64)
+              -> Result<(), SendError>
+where
+    T: ExternallySortable,
+{
+    loop {
+        // only the compaction removes runs, so the runs taken are still the
+        // first of their level once merged
+        let (level, sources, path) = {
+            let mut levels = levels.lock().unwrap();
+            let level = match levels.full(fan_in) {
+                Some(level) => level,
+                None => return Ok(()),
+            };
+            let sources: Vec<_> = levels.runs[level][..fan_in].to_vec();
+            (level, sources, levels.next_path())
+        };
+        let records = sources.iter().map(|run| run.records).sum();
+        let paths: Vec<_> = sources.iter().map(|run| run.path.clone()).collect();
+        let merged = ExtSortedIterator::from_runs(Vec::new(), compare.clone(), sources, buffer_bytes)?;
+        let run = write_merged(merged, &path, records)?;
+        {
+            let mut levels = levels.lock().unwrap();
+            levels.runs[level].drain(..fan_in);
+            levels.push(level + 1, run);
+            levels.save()?;
+        }
+        for path in paths {
+            fs::remove_file(path)?;
+        }
+    }
+}
+
+impl<T> ExternalSorter<T>
+where
+    T: ExternallySortable + Send + 'static,
+{
+    /// Open the [SortedStore](struct.SortedStore.html) kept in `dir` (which is
+    /// created if needed), sorted in ascending order
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading the runs of the store, or
+    /// due to serde deserialization issues
+    pub fn open_store<P>(&self, dir: P) -> Result<SortedStore<T>, Box<dyn Error>>
+    where
+        P: AsRef<Path>,
+    {
+        self.open_store_by(dir, |a, b| a.cmp(b))
+    }
+
+    /// Open the [SortedStore](struct.SortedStore.html) kept in `dir` (which is
+    /// created if needed), sorted based on `compare`
+    ///
+    /// The store compacts levels of [premerge](#method.premerge) runs (8 by
+    /// default), and follows the tie-breaking policy of the sorter. Every run
+    /// of the store is read once to check its order, and the runs and
+    /// iterator directories left over by a store that was not closed are
+    /// removed.
+    ///
+    /// # Errors
+    ///
+    /// This method can fail due to issues reading the runs of the store
+    /// (including runs out of the order of `compare`), or due to serde
+    /// deserialization issues
+    pub fn open_store_by<P, F>(&self, dir: P, compare: F) -> Result<SortedStore<T>, Box<dyn Error>>
+    where
+        P: AsRef<Path>,
+        F: 'static + Fn(&T, &T) -> Ordering + Send + Sync,
+    {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let manifest: Manifest = match File::open(dir.join(MANIFEST_FILE)) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file))?,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Manifest::default(),
+            Err(e) => return Err(e.into()),
+        };
+        let compare = self.break_ties(Arc::new(compare));
+        let mut runs = Vec::with_capacity(manifest.levels.len());
+        for level in &manifest.levels {
+            let level: Result<Vec<_>, _> =
+                level.iter().map(|run| scan_run(&dir.join(run), &*compare)).collect();
+            runs.push(level.map_err(|e| e as Box<dyn Error>)?);
+        }
+        // runs written by an interrupted flush or compaction are not listed
+        // yet, or anymore
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type()?.is_dir() && name.starts_with(SNAPSHOT_PREFIX) {
+                fs::remove_dir_all(entry.path())?;
+            } else if name.starts_with(RUN_PREFIX)
+                      && !manifest.levels.iter().flatten().any(|run| *run == name)
+            {
+                fs::remove_file(entry.path())?;
+            }
+        }
+
+        Ok(SortedStore {
+            compare,
+            buffer_bytes: self.buffer_bytes,
+            fan_in: self.premerge.unwrap_or(STORE_FAN_IN),
+            inserted: Vec::new(),
+            inserted_bytes: 0,
+            levels: Arc::new(Mutex::new(Levels {
+                dir: dir.to_path_buf(),
+                runs,
+                next_run: manifest.next_run,
+            })),
+            compactor: None,
+        })
+    }
+}
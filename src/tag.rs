@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::external_sort::SendError;
+
+/// Spills of the sorts of every tag of a sorter, shared with its clones
+#[derive(Default)]
+pub(crate) struct Tags {
+    tags: Mutex<HashMap<String, Arc<TagUsage>>>,
+}
+
+impl Tags {
+    /// Spills of `tag`, tracked from now on if they were not yet
+    pub(crate) fn usage(&self, tag: &str) -> Arc<TagUsage> {
+        let mut tags = self.tags.lock().unwrap();
+        tags.entry(tag.to_string())
+            .or_insert_with(|| {
+                                Arc::new(TagUsage { tag: tag.to_string(),
+                                                    max_bytes: AtomicU64::new(u64::MAX),
+                                                    dirs: Mutex::new(Vec::new()) })
+                            })
+            .clone()
+    }
+
+    /// Bytes spilled by the sorts of `tag` that are still on disk
+    pub(crate) fn disk_usage(&self, tag: &str) -> u64 {
+        let usage = self.tags.lock().unwrap().get(tag).cloned();
+        usage.map_or(0, |usage| usage.bytes())
+    }
+}
+
+/// Temporary directories of the sorts of a tag, and the disk quota they
+/// share
+pub(crate) struct TagUsage {
+    tag: String,
+    /// Most bytes the sorts can spill, or `u64::MAX` if unbounded
+    max_bytes: AtomicU64,
+    dirs: Mutex<Vec<PathBuf>>,
+}
+
+impl TagUsage {
+    pub(crate) fn set_quota(&self, max_bytes: u64) {
+        self.max_bytes.store(max_bytes, Ordering::Relaxed);
+    }
+
+    /// Track the temporary directory of a sort of the tag, until it is
+    /// removed
+    pub(crate) fn register(&self, dir: &Path) {
+        self.dirs.lock().unwrap().push(dir.to_path_buf());
+    }
+
+    /// Bytes of the files in the temporary directories of the tag, forgetting
+    /// the directories that were removed
+    pub(crate) fn bytes(&self) -> u64 {
+        let mut dirs = self.dirs.lock().unwrap();
+        dirs.retain(|dir| dir.exists());
+        dirs.iter()
+            .filter_map(|dir| fs::read_dir(dir).ok())
+            .flat_map(|entries| entries.filter_map(|entry| entry.ok()?.metadata().ok()))
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+            .sum()
+    }
+
+    /// Fail if the sorts of the tag spill more than its quota
+    pub(crate) fn check(&self) -> Result<(), SendError> {
+        let max_bytes = self.max_bytes.load(Ordering::Relaxed);
+        if max_bytes == u64::MAX {
+            return Ok(());
+        }
+        let bytes = self.bytes();
+        if bytes > max_bytes {
+            return Err(format!("sorts tagged {} spilled {} bytes, over their disk quota of {} \
+                                bytes",
+                               self.tag, bytes, max_bytes).into());
+        }
+        Ok(())
+    }
+}
+
+/// Part of the name of the temporary directories of the sorts of `tag`, with
+/// any character other than ASCII letters, digits, `-` and `_` replaced by `_`
+pub(crate) fn dir_name(tag: &str) -> String {
+    tag.chars()
+       .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+       .collect()
+}
@@ -0,0 +1,78 @@
+//! Timings of the phases of sorts, reported in their
+//! [SortStats](struct.SortStats.html)
+
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// Wall and CPU time spent in each phase of a sort
+///
+/// Times summed over several threads can exceed the wall time of their
+/// phase. The final merge is only timed by sorters set with
+/// [time_merge](struct.ExternalSorter.html#method.time_merge), and its
+/// timings are zero (or `None`) otherwise.
+#[derive(Serialize, Clone, Copy, Debug, Default)]
+pub struct PhaseTimings {
+    /// Wall time of the first pass, from the start of the sort until every
+    /// run was written, including the time to read the input (and, when the
+    /// chunks are not handed off to worker threads, to sort and write them)
+    pub ingest: Duration,
+    /// CPU time of the process during the first pass, where available
+    pub ingest_cpu: Option<Duration>,
+    /// Time spent sorting chunks in memory, summed over threads
+    pub sort: Duration,
+    /// Time spent writing runs to disk, including merging them in the
+    /// background, summed over threads
+    pub spill_write: Duration,
+    /// Time spent reading and deserializing records from the runs during the
+    /// final merge
+    pub merge_read: Duration,
+    /// Time spent in the comparator choosing the next record of the final
+    /// merge
+    pub compare: Duration,
+    /// Wall time between the records returned by the final merge, spent by
+    /// the consumer of the sorted output
+    pub output: Duration,
+    /// CPU time of the process from the first record merged to the last,
+    /// where available
+    pub merge_cpu: Option<Duration>,
+}
+
+/// Measures the wall and CPU time since it was started
+#[derive(Clone, Copy)]
+pub(crate) struct Timer {
+    wall: Instant,
+    cpu: Option<Duration>,
+}
+
+impl Timer {
+    pub(crate) fn start() -> Timer {
+        Timer { wall: Instant::now(), cpu: cpu_time() }
+    }
+
+    /// The wall and CPU time since the timer started
+    pub(crate) fn elapsed(&self) -> (Duration, Option<Duration>) {
+        let cpu = match (self.cpu, cpu_time()) {
+            (Some(start), Some(now)) => Some(now.saturating_sub(start)),
+            _ => None,
+        };
+        (self.wall.elapsed(), cpu)
+    }
+}
+
+/// CPU time used by the process so far
+#[cfg(unix)]
+fn cpu_time() -> Option<Duration> {
+    // the struct is plain data, for getrusage() to fill in
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return None;
+    }
+    let micros = |t: libc::timeval| t.tv_sec as u64 * 1_000_000 + t.tv_usec as u64;
+    Some(Duration::from_micros(micros(usage.ru_utime) + micros(usage.ru_stime)))
+}
+
+#[cfg(not(unix))]
+fn cpu_time() -> Option<Duration> {
+    None
+}
@@ -0,0 +1,49 @@
+use std::cmp::Ordering::{self, Equal, Greater, Less};
+
+/// Compare version-like strings, such as `1.2.9` and `1.2.10` or
+/// `openssl-1.1.1k`, ordering runs of digits by their numeric value and
+/// everything else byte by byte
+///
+/// As with semantic versioning, a pre-release sorts before its release, so
+/// `1.0.0-rc.1` comes before `1.0.0`: when one string ends where the other
+/// goes on with a `-`, the longer one sorts first. Strings that are equal as
+/// versions but not as text (such as `1.02` and `1.2`) fall back on their
+/// byte order. This can be used as the comparator of
+/// [ExternalSorter::sort_lines_by](struct.ExternalSorter.html#method.sort_lines_by).
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a_bytes, b_bytes) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    loop {
+        match (a_bytes.get(i), b_bytes.get(j)) {
+            (None, None) => return a.cmp(b),
+            (None, Some(&c)) => return if c == b'-' { Greater } else { Less },
+            (Some(&c), None) => return if c == b'-' { Less } else { Greater },
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (a_digits, a_end) = digits(a_bytes, i);
+                let (b_digits, b_end) = digits(b_bytes, j);
+                // without leading zeros, longer numbers are larger
+                let order = a_digits.len().cmp(&b_digits.len()).then(a_digits.cmp(b_digits));
+                if order != Equal {
+                    return order;
+                }
+                i = a_end;
+                j = b_end;
+            },
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(y);
+                }
+                i += 1;
+                j += 1;
+            },
+        }
+    }
+}
+
+/// Find the run of digits of `s` starting at `start`, without its leading
+/// zeros, and the index following it
+fn digits(s: &[u8], start: usize) -> (&[u8], usize) {
+    let end = start + s[start..].iter().take_while(|c| c.is_ascii_digit()).count();
+    let first = start + s[start..end].iter().take_while(|&&c| c == b'0').count();
+    (&s[first..end], end)
+}
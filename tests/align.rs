@@ -0,0 +1,29 @@
+use external_sort::{EitherOrBoth, ExternalSorter};
+
+mod common;
+use common::Num;
+
+#[test]
+fn align_by_key() {
+    let sorter = ExternalSorter::new(4, None);
+    let a = sorter.sort(vec![13, 10, 21, 11, 40].into_iter().map(Num::new)).unwrap();
+    let b = sorter.sort(vec![32, 12, 24, 15, 16, 35].into_iter().map(Num::new)).unwrap();
+    let aligned: Vec<(Option<u8>, Option<u8>)> =
+        a.align_by_key(b, |n| n.the_num / 10, |n| n.the_num / 10)
+         .map(|i| match i.unwrap() {
+                  EitherOrBoth::Left(l) => (Some(l.the_num), None),
+                  EitherOrBoth::Right(r) => (None, Some(r.the_num)),
+                  EitherOrBoth::Both(l, r) => (Some(l.the_num), Some(r.the_num)),
+              })
+         .collect();
+    let expected = vec![
+        (Some(10), Some(12)),
+        (Some(11), Some(15)),
+        (Some(13), Some(16)),
+        (Some(21), Some(24)),
+        (None, Some(32)),
+        (None, Some(35)),
+        (Some(40), None),
+    ];
+    assert_eq!(aligned, expected);
+}
@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+
+use external_sort::{ArenaStr, ExternalSorter, ExternallySortable};
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Row {
+    name: ArenaStr,
+    tag: ArenaStr,
+}
+
+impl ExternallySortable for Row {
+    fn get_size(&self) -> u64 {
+        (self.name.len() + self.tag.len()) as u64
+    }
+}
+
+#[test]
+fn arena_batches() {
+    let unsorted = || {
+        (0..2_000u32).map(|n| Row { name: format!("name \"{}\"", n * 7 % 2_000).into(),
+                                    tag: ArenaStr::new(if n % 2 == 0 { "even" } else { "odd" }) })
+    };
+    let sorter = ExternalSorter::new(2_000, None).arena_batches();
+
+    // records dropped as they are merged let their arenas be reused
+    let mut previous: Option<String> = None;
+    for row in sorter.sort(unsorted()).unwrap() {
+        let row = row.unwrap();
+        assert!(previous.as_deref() <= Some(row.name.as_str()));
+        previous = Some(row.name.to_string());
+    }
+
+    // records kept keep their arenas
+    let sorted: Vec<Row> = sorter.sort(unsorted()).unwrap().map(|r| r.unwrap()).collect();
+    let mut expected: Vec<Row> = unsorted().collect();
+    expected.sort();
+    assert_eq!(sorted.len(), 2_000);
+    assert!(sorted == expected);
+    assert_eq!(sorted[0].name.as_str(), "name \"0\"");
+    assert_eq!(&*sorted[0].tag, "even");
+}
@@ -0,0 +1,28 @@
+use external_sort::ExternalSorter;
+
+mod common;
+use common::Num;
+
+#[test]
+fn argsort() {
+    let unsorted: Vec<Num> = (0..10_000).map(|_| Num::new(rand::random())).collect();
+    let mut expected: Vec<u64> = (0..unsorted.len() as u64).collect();
+    expected.sort_by_key(|&i| &unsorted[i as usize]);
+
+    let sorter = ExternalSorter::new(10_000, None);
+    let indices: Vec<u64> = sorter.argsort(unsorted.clone().into_iter())
+                                  .unwrap()
+                                  .map(|i| i.unwrap())
+                                  .collect();
+    assert_eq!(indices, expected);
+
+    expected.reverse();
+    // equal records keep their input order
+    expected.chunk_by_mut(|&a, &b| unsorted[a as usize] == unsorted[b as usize])
+            .for_each(|equal| equal.reverse());
+    let indices: Vec<u64> = sorter.argsort_by(unsorted.into_iter(), |a, b| b.cmp(a))
+                                  .unwrap()
+                                  .map(|i| i.unwrap())
+                                  .collect();
+    assert_eq!(indices, expected);
+}
@@ -0,0 +1,72 @@
+#![cfg(feature = "cli")]
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn extsort(args: &[&str], input: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_extsort"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn json() {
+    let input = concat!("{\"id\":{\"n\":3}}\n{\"id\":{\"n\":1}}\n{}\n",
+                        "{\"id\":{\"n\":2}}\n{\"id\":{\"n\":1}}\n");
+    assert_eq!(extsort(&["--key", "id.n", "-S", "16"], input),
+               concat!("{}\n{\"id\":{\"n\":1}}\n{\"id\":{\"n\":1}}\n",
+                       "{\"id\":{\"n\":2}}\n{\"id\":{\"n\":3}}\n"));
+    assert_eq!(extsort(&["--key=id.n", "--reverse", "--unique"], input),
+               "{\"id\":{\"n\":3}}\n{\"id\":{\"n\":2}}\n{\"id\":{\"n\":1}}\n{}\n");
+}
+
+#[test]
+fn zero_terminated() {
+    assert_eq!(extsort(&["--key", "n", "-z"], "{\"n\":2}\0{\"n\":1}\0"), "{\"n\":1}\0{\"n\":2}\0");
+    assert_eq!(extsort(&["lines", "-z"], "b\na\0a\0"), "a\0b\na\0");
+}
+
+#[test]
+fn csv() {
+    let input = "name,age\n\"Smith, J\",30\nbob,9\nal,100\n";
+    assert_eq!(extsort(&["csv", "--column", "age", "--numeric"], input),
+               "name,age\nbob,9\n\"Smith, J\",30\nal,100\n");
+    assert_eq!(extsort(&["csv", "-c", "1", "-r"], input),
+               "name,age\nbob,9\nal,100\n\"Smith, J\",30\n");
+    assert_eq!(extsort(&["csv", "-t", "--no-header", "-c", "2", "-u"], "b\t2\na\t1\nc\t2\n"),
+               "a\t1\nb\t2\n");
+}
+
+#[test]
+fn lines() {
+    let input = "10\n1 b\nx\n01 c\n-3\n";
+    assert_eq!(extsort(&["lines"], input), "-3\n01 c\n1 b\n10\nx\n");
+    assert_eq!(extsort(&["lines", "-n", "--parallel", "2"], input), "-3\nx\n01 c\n1 b\n10\n");
+    assert_eq!(extsort(&["lines", "-n", "--stable"], input), "-3\nx\n1 b\n01 c\n10\n");
+    assert_eq!(extsort(&["lines", "-n", "-u", "-r"], input), "10\n1 b\nx\n-3\n");
+    assert_eq!(extsort(&["lines", "-V"], "1.10\n1.9\n1.9-rc1\n"), "1.9-rc1\n1.9\n1.10\n");
+}
+
+#[test]
+fn inspect() {
+    let dir = tempdir::TempDir::new("extsort_inspect").unwrap();
+    let run = dir.path().join("0");
+    std::fs::write(&run, "{\"n\":1}\n{\"n\":2}\n{\"n\":3}\n").unwrap();
+    let run = run.to_str().unwrap();
+    let output = extsort(&["inspect", "--block-records", "2", run], "");
+    assert!(output.contains("  records: 3\n  min: {\"n\":1}\n  max: {\"n\":3}\n"));
+    assert!(output.contains(concat!("  manifest: none\n  blocks:\n",
+                                    "    0 at byte 0: {\"n\":1}\n    2 at byte 16: {\"n\":3}\n")));
+    let output = extsort(&["inspect", "--json", "-r", run], "");
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].contains("\"records\":3"));
+    assert_eq!(lines[1..], ["{\"n\":1}", "{\"n\":2}", "{\"n\":3}"]);
+}
@@ -0,0 +1,72 @@
+use std::error::Error;
+use std::fs;
+use std::io::{BufRead, Cursor};
+
+use serde::{Deserialize, Serialize};
+
+use external_sort::{ExternalSorter, ExternallySortable, JsonLines};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(transparent)]
+struct Num(u32);
+
+impl ExternallySortable for Num {}
+
+#[test]
+fn sort_reader() {
+    let input: String = (0..1_000u32).map(|n| format!("{}\n\n", n * 7 % 1_000)).collect();
+    let sorter = ExternalSorter::<Num>::new(400, None);
+    let sorted: Vec<Num> = sorter.sort_reader(Cursor::new(input.clone()), JsonLines)
+                                 .unwrap()
+                                 .map(|n| n.unwrap())
+                                 .collect();
+    assert_eq!(sorted, (0..1_000).map(Num).collect::<Vec<_>>());
+
+    // records of four little-endian bytes
+    let input: Vec<u8> = (0..1_000u32).rev().flat_map(|n| n.to_le_bytes()).collect();
+    let decoder = |input: &mut dyn BufRead| -> Result<Option<Num>, Box<dyn Error>> {
+        let mut bytes = [0; 4];
+        match input.read_exact(&mut bytes) {
+            Ok(()) => Ok(Some(Num(u32::from_le_bytes(bytes)))),
+            Err(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    };
+    let sorted: Vec<Num> = sorter.sort_reader_by(Cursor::new(input), decoder, |a, b| b.cmp(a))
+                                 .unwrap()
+                                 .map(|n| n.unwrap())
+                                 .collect();
+    assert_eq!(sorted, (0..1_000).rev().map(Num).collect::<Vec<_>>());
+
+    let err = sorter.sort_reader(Cursor::new("3\n1\nnope\n2\n"), JsonLines).err().unwrap();
+    assert!(err.to_string().starts_with("record 3:"));
+}
+
+#[test]
+fn sort_file() {
+    let dir = tempdir::TempDir::new("external_sort_sort_file").unwrap();
+    let input = dir.path().join("input.jsonl");
+    let output = dir.path().join("output.jsonl");
+    let records: String = (0..1_000u32).map(|n| format!("{}\n", n * 7 % 1_000)).collect();
+    fs::write(&input, records).unwrap();
+
+    let sorter = ExternalSorter::<Num>::new(400, None);
+    assert_eq!(sorter.sort_file(&input, &output, JsonLines, |a, b| a.cmp(b)).unwrap(), 1_000);
+    let expected: String = (0..1_000u32).map(|n| format!("{}\n", n)).collect();
+    assert_eq!(fs::read_to_string(&output).unwrap(), expected);
+
+    // in place
+    assert_eq!(sorter.sort_file(&input, &input, JsonLines, |a, b| b.cmp(a)).unwrap(), 1_000);
+    let expected: String = (0..1_000u32).rev().map(|n| format!("{}\n", n)).collect();
+    assert_eq!(fs::read_to_string(&input).unwrap(), expected);
+
+    // a failed sort leaves the output as it was, without a temporary file
+    fs::write(&input, "3\nnope\n").unwrap();
+    assert!(sorter.sort_file(&input, &output, JsonLines, |a, b| a.cmp(b)).is_err());
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    assert!(fs::read_to_string(&output).unwrap().starts_with("0\n1\n"));
+
+    fs::write(&input, "").unwrap();
+    assert_eq!(sorter.sort_file(&input, &output, JsonLines, |a, b| a.cmp(b)).unwrap(), 0);
+    assert_eq!(fs::read_to_string(&output).unwrap(), "");
+}
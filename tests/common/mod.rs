@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+use external_sort::ExternallySortable;
+
+/// A record of one byte, counted as a size of `1`, shared by the tests that
+/// sort a handful of small numbers
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Num {
+    pub the_num: u8,
+}
+
+impl Num {
+    #[allow(dead_code)]
+    pub fn new(num: u8) -> Num {
+        Num { the_num: num }
+    }
+}
+
+impl ExternallySortable for Num {
+    fn get_size(&self) -> u64 {
+        1
+    }
+}
@@ -0,0 +1,37 @@
+#![cfg(feature = "csv")]
+
+use csv::{ByteRecord, StringRecord};
+
+use external_sort::{CsvByteRecord, CsvOrder, CsvRecord, ExternalSorter};
+
+const DATA: &str = "name,team,score\nbob,red,30\nal,blue,9\ncy,red,100\ndee,blue,n/a\n";
+
+#[test]
+fn sort_string_records() {
+    let mut reader = csv::Reader::from_reader(DATA.as_bytes());
+    let order = CsvOrder::new().header(reader.headers().unwrap(), "team")
+                               .unwrap()
+                               .descending()
+                               .column(2)
+                               .numeric();
+    let records = reader.into_records().map(|r| CsvRecord(r.unwrap()));
+    let sorted = ExternalSorter::new(10, None)
+        .sort_by(records, move |a, b| order.compare(a, b))
+        .unwrap();
+    let names: Vec<StringRecord> = sorted.map(|r| r.unwrap().0).collect();
+    let names: Vec<&str> = names.iter().map(|r| &r[0]).collect();
+    assert_eq!(names, vec!["bob", "cy", "dee", "al"]);
+}
+
+#[test]
+fn sort_byte_records() {
+    let mut reader = csv::Reader::from_reader(DATA.as_bytes());
+    let records = reader.byte_records().map(|r| CsvByteRecord(r.unwrap()));
+    let order = CsvOrder::new().column(0).descending();
+    let sorted = ExternalSorter::new(10, None)
+        .sort_by(records, move |a, b| order.compare_bytes(a, b))
+        .unwrap();
+    let names: Vec<ByteRecord> = sorted.map(|r| r.unwrap().0).collect();
+    let names: Vec<&[u8]> = names.iter().map(|r| &r[0]).collect();
+    assert_eq!(names, vec![&b"dee"[..], b"cy", b"bob", b"al"]);
+}
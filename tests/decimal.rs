@@ -0,0 +1,42 @@
+use external_sort::{Decimal, ExternalSorter};
+
+fn dec(s: &str) -> Decimal {
+    Decimal::parse(s).unwrap()
+}
+
+#[test]
+fn parse_and_display() {
+    let cases = [("0", "0"), ("-0.000", "0"), ("+1.50", "1.5"), ("007", "7"), (".05", "0.05"),
+                 ("1500", "1500"), ("-12.340e1", "-123.4"), ("1E-3", "0.001"), ("1e30", "1e30"),
+                 ("-1.25e-30", "-1.25e-30"),
+                 ("123456789012345678901234567890.5", "123456789012345678901234567890.5")];
+    for (input, shown) in cases {
+        assert_eq!(dec(input).to_string(), shown);
+        assert_eq!(dec(shown), dec(input));
+    }
+    for invalid in ["", "-", ".", "1.2.3", "1e", "e5", "--1", "1_000", "NaN"] {
+        assert!(Decimal::parse(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn order() {
+    let sorted = ["-1e30", "-100", "-99.99", "-0.5", "-0.05", "0", "1e-30", "0.1", "0.10001", "1",
+                  "9.999999999999999999999", "10", "18446744073709551616", "1e30"];
+    for (i, a) in sorted.iter().enumerate() {
+        for (j, b) in sorted.iter().enumerate() {
+            assert_eq!(dec(a).cmp(&dec(b)), i.cmp(&j), "{} {}", a, b);
+        }
+    }
+}
+
+#[test]
+fn serde() {
+    let amounts: Vec<Decimal> = serde_json::from_str(r#"["10.10", 3, -2.5, "1e-2"]"#).unwrap();
+    assert_eq!(amounts, vec![dec("10.1"), dec("3"), dec("-2.5"), dec("0.01")]);
+    assert_eq!(serde_json::to_string(&amounts).unwrap(), r#"["10.1","3","-2.5","0.01"]"#);
+
+    let sorted = ExternalSorter::new(100, None).sort(amounts.into_iter()).unwrap();
+    let sorted: Vec<String> = sorted.map(|d| d.unwrap().to_string()).collect();
+    assert_eq!(sorted, ["-2.5", "0.01", "3", "10.1"]);
+}
@@ -0,0 +1,49 @@
+use external_sort::{Change, ExternalSorter};
+
+mod common;
+use common::Num;
+
+fn label(change: Change<Num>) -> (char, u8) {
+    match change {
+        Change::Added(n) => ('+', n.the_num),
+        Change::Removed(n) => ('-', n.the_num),
+        Change::Unchanged(n) => ('=', n.the_num),
+    }
+}
+
+#[test]
+fn diff() {
+    let old: Vec<u8> = (0..30).map(|n| n * 2).chain(vec![6]).rev().collect();
+    let new: Vec<u8> = (0..20).map(|n| n * 3).rev().collect();
+    let mut expected = Vec::new();
+    for n in 0..60 {
+        let in_old = old.iter().filter(|o| **o == n).count();
+        let in_new = new.iter().filter(|o| **o == n).count();
+        for _ in 0..in_old.min(in_new) {
+            expected.push(('=', n));
+        }
+        for _ in in_new..in_old {
+            expected.push(('-', n));
+        }
+        for _ in in_old..in_new {
+            expected.push(('+', n));
+        }
+    }
+
+    let sorter = ExternalSorter::new(10, None);
+    let changes: Vec<(char, u8)> = sorter
+        .diff(old.iter().map(|n| Num::new(*n)), new.iter().map(|n| Num::new(*n)))
+        .unwrap()
+        .with_unchanged()
+        .map(|c| label(c.unwrap()))
+        .collect();
+    assert_eq!(changes, expected);
+
+    let changes: Vec<(char, u8)> = sorter
+        .diff(old.iter().map(|n| Num::new(*n)), new.iter().map(|n| Num::new(*n)))
+        .unwrap()
+        .map(|c| label(c.unwrap()))
+        .collect();
+    expected.retain(|(c, _)| *c != '=');
+    assert_eq!(changes, expected);
+}
@@ -1,27 +1,21 @@
-use rand;
 use serde::{Deserialize, Serialize};
 
+use std::cell::Cell;
+use std::collections::VecDeque;
 use std::env;
 use std::fs;
+use std::iter;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
-use external_sort::{ExternalSorter, ExternallySortable};
+use external_sort::{BufferAllocator, DedupPolicy, DynSorter, ExtSortedIterator, ExternalSorter,
+                    ExternallySortable, IoPriority, NullOrder, ProgressPhase, TieBreak};
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct Num {
-    the_num: u8,
-}
-
-impl Num {
-    fn new(num: u8) -> Num {
-        Num { the_num: num }
-    }
-}
-
-impl ExternallySortable for Num {
-    fn get_size(&self) -> u64 {
-        1
-    }
-}
+mod common;
+use common::Num;
 
 #[test]
 fn sort() {
@@ -32,7 +26,7 @@ fn sort() {
         Num::new(3),
         Num::new(4),
     ];
-    let sorted = vec![
+    let sorted = [
         Num::new(1),
         Num::new(2),
         Num::new(3),
@@ -56,7 +50,7 @@ fn reverse() {
         Num::new(3),
         Num::new(4),
     ];
-    let sorted = vec![
+    let sorted = [
         Num::new(5),
         Num::new(4),
         Num::new(3),
@@ -71,6 +65,73 @@ fn reverse() {
     }
 }
 
+#[test]
+fn sort_by_mut() {
+    // the comparator may keep state between calls, and need not be Send
+    let counted = Rc::new(Cell::new(0u64));
+    let total = counted.clone();
+    let mut comparisons = 0;
+    let unsorted = || (0..1_000u32).map(|n| Num::new((n * 7 % 256) as u8));
+    let sorter = ExternalSorter::new(100, None).threads(4);
+    let iter = sorter.sort_by(unsorted(), move |a, b| {
+                         comparisons += 1;
+                         total.set(comparisons);
+                         a.cmp(b)
+                     })
+                     .unwrap();
+    let sorted: Vec<u8> = iter.map(|n| n.unwrap().the_num).collect();
+    assert!(sorted.windows(2).all(|w| w[0] <= w[1]));
+    assert_eq!(sorted.len(), 1_000);
+    assert!(counted.get() >= 999);
+
+    // but it is only called on the thread that sorted
+    let iter = sorter.sort_by(unsorted(), |a, b| a.cmp(b)).unwrap();
+    let merge = thread::spawn(move || iter.map(|n| n.unwrap().the_num).collect::<Vec<u8>>());
+    assert!(merge.join().is_err());
+}
+
+#[test]
+fn not_send() {
+    // records that cannot be sent between threads sort on the calling thread
+    #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+    struct Local {
+        num: u8,
+        local: PhantomData<*const u8>,
+    }
+
+    impl ExternallySortable for Local {
+        fn get_size(&self) -> u64 {
+            1
+        }
+    }
+
+    let unsorted = (0..100u8).rev().map(|num| Local { num, local: PhantomData });
+    let iter = ExternalSorter::new(16, None).sort(unsorted).unwrap();
+    let sorted: Vec<u8> = iter.map(|r| r.unwrap().num).collect();
+    assert_eq!(sorted, (0..100u8).collect::<Vec<_>>());
+}
+
+#[test]
+fn try_sort() {
+    let sorter = ExternalSorter::new(16, None);
+    let unsorted = (0..100u8).rev().map(|n| Ok::<_, String>(Num::new(n)));
+    let sorted: Vec<u8> = sorter.try_sort(unsorted).unwrap().map(|n| n.unwrap().the_num).collect();
+    assert_eq!(sorted, (0..100u8).collect::<Vec<_>>());
+
+    // the input is not read past its first error
+    let mut read = 0;
+    let unsorted = (0..100u8).rev().map(|n| {
+                                       read += 1;
+                                       if n == 50 {
+                                           return Err(format!("bad {}", n));
+                                       }
+                                       Ok(Num::new(n))
+                                   });
+    let e = sorter.try_sort_by(unsorted, |a, b| b.cmp(a)).err().unwrap();
+    assert_eq!(e.to_string(), "bad 50");
+    assert_eq!(read, 50);
+}
+
 #[test]
 fn zero_buff() {
     let unsorted = vec![
@@ -80,7 +141,7 @@ fn zero_buff() {
         Num::new(3),
         Num::new(4),
     ];
-    let sorted = vec![
+    let sorted = [
         Num::new(1),
         Num::new(2),
         Num::new(3),
@@ -104,7 +165,7 @@ fn large_buff() {
         Num::new(3),
         Num::new(4),
     ];
-    let sorted = vec![
+    let sorted = [
         Num::new(1),
         Num::new(2),
         Num::new(3),
@@ -119,6 +180,23 @@ fn large_buff() {
     }
 }
 
+#[test]
+fn auto_buffer() {
+    let buffer_bytes = |fraction| {
+        let sorter = ExternalSorter::with_auto_buffer(fraction, None);
+        let iter = sorter.sort((0..100).rev().map(Num::new)).unwrap();
+        let config = iter.stats().config.unwrap();
+        let sorted: Vec<u8> = iter.map(|n| n.unwrap().the_num).collect();
+        assert_eq!(sorted, (0..100).collect::<Vec<_>>());
+        config.buffer_bytes
+    };
+    assert_eq!(buffer_bytes(0.0), 0);
+    assert_eq!(buffer_bytes(-1.0), 0);
+    let quarter = buffer_bytes(0.25);
+    assert!(quarter > 0);
+    assert!(buffer_bytes(0.5) > quarter);
+}
+
 #[test]
 fn reuse() {
     let unsorted = vec![
@@ -128,7 +206,7 @@ fn reuse() {
         Num::new(3),
         Num::new(4),
     ];
-    let sorted = vec![
+    let sorted = [
         Num::new(1),
         Num::new(2),
         Num::new(3),
@@ -187,3 +265,1260 @@ fn handle_fail() {
     }
     assert!(fail);
 }
+
+#[test]
+fn threads() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted = unsorted.clone();
+    sorted.sort();
+    let iter = ExternalSorter::new(100, None)
+        .threads(4)
+        .sort(unsorted.into_iter())
+        .unwrap();
+    let mut count = 0;
+    for (idx, i) in iter.enumerate() {
+        assert_eq!(i.unwrap().the_num, sorted[idx].the_num);
+        count += 1;
+    }
+    assert_eq!(count, sorted.len());
+}
+
+#[test]
+fn premerge() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted = unsorted.clone();
+    sorted.sort();
+    let iter = ExternalSorter::new(100, None)
+        .premerge(4)
+        .sort(unsorted.clone().into_iter())
+        .unwrap();
+    let result: Vec<Num> = iter.map(|i| i.unwrap()).collect();
+    assert!(result == sorted);
+
+    let iter = ExternalSorter::new(100, None)
+        .premerge(3)
+        .threads(3)
+        .sort(unsorted.into_iter())
+        .unwrap();
+    let result: Vec<Num> = iter.map(|i| i.unwrap()).collect();
+    assert!(result == sorted);
+}
+
+#[test]
+fn shared_sorter() {
+    let r = env::temp_dir().join("external_sort_shared_test");
+    fs::create_dir_all(r.clone()).unwrap();
+    let sorter = Arc::new(ExternalSorter::new(100, Some(r.clone())));
+
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let sorter = sorter.clone();
+            thread::spawn(move || {
+                let mut unsorted = Vec::new();
+                for _ in 0..1_000 {
+                    unsorted.push(Num::new(rand::random()));
+                }
+                let mut sorted: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+                sorted.sort();
+                let iter = sorter.sort(unsorted.into_iter()).unwrap();
+                let result: Vec<u8> = iter.map(|i| i.unwrap().the_num).collect();
+                assert_eq!(result, sorted);
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    fs::remove_dir_all(r).unwrap();
+}
+
+#[test]
+fn split() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+    sorted.sort();
+    let iter = ExternalSorter::new(100, None)
+        .sort(unsorted.into_iter())
+        .unwrap();
+    let parts = iter.split(4);
+    assert_eq!(parts.len(), 4);
+    let handles: Vec<_> = parts
+        .into_iter()
+        .map(|part| thread::spawn(move || part.map(|i| i.unwrap().the_num).collect::<Vec<_>>()))
+        .collect();
+    let mut merged = Vec::new();
+    for handle in handles {
+        let part = handle.join().unwrap();
+        assert!(!part.is_empty());
+        merged.extend(part);
+    }
+    assert_eq!(merged, sorted);
+}
+
+#[test]
+fn split_partially_consumed() {
+    let mut unsorted = Vec::new();
+    for _ in 0..1_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+    sorted.sort();
+    let mut iter = ExternalSorter::new(50, None)
+        .sort(unsorted.into_iter())
+        .unwrap();
+    let mut merged: Vec<u8> = iter.by_ref().take(100).map(|i| i.unwrap().the_num).collect();
+    for part in iter.split(3) {
+        merged.extend(part.map(|i| i.unwrap().the_num));
+    }
+    assert_eq!(merged, sorted);
+}
+
+#[test]
+fn write_partitions() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+    sorted.sort();
+    let dir = tempdir::TempDir::new("external_sort_partitions").unwrap();
+    let paths: Vec<_> = (0..4).map(|i| dir.path().join(format!("part-{}", i))).collect();
+
+    let iter = ExternalSorter::new(100, None)
+        .sort(unsorted.into_iter())
+        .unwrap();
+    let boundaries = [Num::new(64), Num::new(128), Num::new(192)];
+    let counts = iter.write_partitions(&boundaries, &paths).unwrap();
+
+    let mut merged = Vec::new();
+    for (i, path) in paths.iter().enumerate() {
+        let part: Vec<u8> = fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str::<Num>(l).unwrap().the_num)
+            .collect();
+        assert_eq!(part.len() as u64, counts[i]);
+        assert!(part.iter().all(|n| (*n as usize) / 64 == i));
+        merged.extend(part);
+    }
+    assert_eq!(merged, sorted);
+}
+
+#[test]
+fn write_shards() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+    sorted.sort();
+    let dir = tempdir::TempDir::new("external_sort_shards").unwrap();
+    let paths: Vec<_> = (0..4).map(|i| dir.path().join(format!("shard-{}", i))).collect();
+
+    let iter = ExternalSorter::new(100, None)
+        .sort(unsorted.into_iter())
+        .unwrap();
+    let boundaries: Vec<u8> = iter.write_shards(&paths)
+                                  .unwrap()
+                                  .into_iter()
+                                  .map(|n| n.the_num)
+                                  .collect();
+    assert_eq!(boundaries.len(), 3);
+
+    let mut merged = Vec::new();
+    for (i, path) in paths.iter().enumerate() {
+        let shard: Vec<u8> = fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str::<Num>(l).unwrap().the_num)
+            .collect();
+        // each key holds about 40 records, which are never split
+        assert!(shard.len() > 2_000 && shard.len() < 3_000);
+        assert!(i == 0 || shard[0] == boundaries[i - 1]);
+        assert!(i == 3 || shard.iter().all(|n| *n < boundaries[i]));
+        merged.extend(shard);
+    }
+    assert_eq!(merged, sorted);
+}
+
+#[test]
+fn write_sampled_partitions() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let dir = tempdir::TempDir::new("external_sort_partitions").unwrap();
+    let iter = ExternalSorter::new(1_000, None)
+        .sort(unsorted.into_iter())
+        .unwrap();
+    let boundaries = iter.boundaries(4);
+    assert_eq!(boundaries.len(), 3);
+    let paths: Vec<_> = (0..4).map(|i| dir.path().join(format!("part-{}", i))).collect();
+    let counts = iter.write_partitions(&boundaries, &paths).unwrap();
+    assert_eq!(counts.iter().sum::<u64>(), 10_000);
+    assert!(counts.iter().all(|c| *c > 1_000));
+
+    let iter = ExternalSorter::new(100, None)
+        .sort(vec![Num::new(1)].into_iter())
+        .unwrap();
+    assert!(iter.write_partitions(&[], &paths).is_err());
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn par_sort() {
+    use rayon::prelude::*;
+
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted = unsorted.clone();
+    sorted.sort();
+    let iter = ExternalSorter::new(100, None)
+        .par_sort(unsorted.into_par_iter())
+        .unwrap();
+    for (idx, i) in iter.enumerate() {
+        assert_eq!(i.unwrap().the_num, sorted[idx].the_num);
+    }
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn par_sort_by() {
+    use rayon::prelude::*;
+
+    let unsorted = vec![
+        Num::new(5),
+        Num::new(2),
+        Num::new(1),
+        Num::new(3),
+        Num::new(4),
+    ];
+    let sorted = [
+        Num::new(5),
+        Num::new(4),
+        Num::new(3),
+        Num::new(2),
+        Num::new(1),
+    ];
+    let iter = ExternalSorter::new(2, None)
+        .par_sort_by(unsorted.into_par_iter(), |a, b| b.cmp(a))
+        .unwrap();
+    let mut count = 0;
+    for (idx, i) in iter.enumerate() {
+        assert_eq!(i.unwrap().the_num, sorted[idx].the_num);
+        count += 1;
+    }
+    assert_eq!(count, 5);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn par_sort_stable() {
+    use rayon::prelude::*;
+
+    // records sort by their first digit, so that many of them are equal
+    let unsorted: Vec<String> = (0..2_000u32).map(|n| format!("{}{:04}", n * 7 % 10, n)).collect();
+    let first_digit = |a: &String, b: &String| a[..1].cmp(&b[..1]);
+    let mut expected = unsorted.clone();
+    expected.sort_by(first_digit);
+    let pool = Arc::new(rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap());
+    for sorter in [ExternalSorter::new(2_000, None), ExternalSorter::new(2_000, None).premerge(2)] {
+        let sorted: Vec<String> = sorter.thread_pool(pool.clone())
+                                        .par_sort_stable_by(unsorted.clone().into_par_iter(),
+                                                            first_digit)
+                                        .unwrap()
+                                        .map(|s| s.unwrap())
+                                        .collect();
+        assert_eq!(sorted, expected);
+    }
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn par_sort_thread_pool() {
+    use rayon::prelude::*;
+
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted = unsorted.clone();
+    sorted.sort();
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+    let iter = ExternalSorter::new(100, None)
+        .thread_pool(Arc::new(pool))
+        .par_sort(unsorted.into_par_iter())
+        .unwrap();
+    for (idx, i) in iter.enumerate() {
+        assert_eq!(i.unwrap().the_num, sorted[idx].the_num);
+    }
+}
+
+#[test]
+fn dedup() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+    sorted.sort();
+    sorted.dedup();
+    let iter = ExternalSorter::new(100, None)
+        .sort(unsorted.into_iter())
+        .unwrap()
+        .dedup();
+    let result: Vec<u8> = iter.map(|i| i.unwrap().the_num).collect();
+    assert_eq!(result, sorted);
+}
+
+#[test]
+fn dedup_with() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+    sorted.sort();
+    sorted.reverse();
+    sorted.dedup_by_key(|n| *n / 10);
+    sorted.reverse();
+    let iter = ExternalSorter::new(100, None)
+        .sort_by(unsorted.into_iter(), |a, b| (a.the_num / 10).cmp(&(b.the_num / 10)))
+        .unwrap()
+        .dedup_with(|a, b| if b > a { b } else { a });
+    let result: Vec<u8> = iter.map(|i| i.unwrap().the_num).collect();
+    assert_eq!(result, sorted);
+}
+
+#[test]
+fn dedup_by_key() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+    sorted.sort();
+    sorted.dedup();
+    let mut first: Vec<u8> = sorted.clone();
+    first.dedup_by_key(|n| *n / 10);
+    let mut last: Vec<u8> = sorted.iter().rev().cloned().collect();
+    last.dedup_by_key(|n| *n / 10);
+    last.reverse();
+
+    let sorter = ExternalSorter::new(100, None);
+    let policies = vec![
+        (DedupPolicy::KeepFirst, first),
+        (DedupPolicy::KeepLast, last.clone()),
+        (DedupPolicy::Combine(Arc::new(|a: Num, b: Num| if b > a { b } else { a })), last),
+    ];
+    for (policy, expected) in policies {
+        let iter = sorter
+            .sort(unsorted.clone().into_iter())
+            .unwrap()
+            .dedup_by_key(|n| n.the_num / 10, policy);
+        let result: Vec<u8> = iter.map(|i| i.unwrap().the_num).collect();
+        assert_eq!(result, expected);
+    }
+}
+
+#[test]
+fn counts() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random::<u8>() % 50));
+    }
+    let mut expected = vec![0u64; 50];
+    for n in unsorted.iter() {
+        expected[n.the_num as usize] += 1;
+    }
+    let expected: Vec<(u8, u64)> = expected.into_iter()
+                                           .enumerate()
+                                           .filter(|&(_, c)| c > 0)
+                                           .map(|(n, c)| (n as u8, c))
+                                           .collect();
+    let iter = ExternalSorter::new(100, None)
+        .sort(unsorted.into_iter())
+        .unwrap()
+        .counts();
+    let result: Vec<(u8, u64)> = iter.map(|i| i.unwrap())
+                                     .map(|(n, c)| (n.the_num, c))
+                                     .collect();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn union() {
+    let mut sorted = Vec::new();
+    let mut iters = Vec::new();
+    let sorter = ExternalSorter::new(100, None);
+    for _ in 0..3 {
+        let mut unsorted = Vec::new();
+        for _ in 0..1000 {
+            unsorted.push(Num::new(rand::random()));
+        }
+        sorted.extend(unsorted.iter().map(|n| n.the_num));
+        iters.push(sorter.sort(unsorted.into_iter()).unwrap());
+    }
+    sorted.sort();
+    let iter = ExtSortedIterator::union(iters).unwrap();
+    let result: Vec<u8> = iter.map(|i| i.unwrap().the_num).collect();
+    assert_eq!(result, sorted);
+}
+
+#[test]
+fn union_dedup() {
+    let sorter = ExternalSorter::new(10, None);
+    let iters = vec![
+        sorter.sort((0..50).map(|n| Num::new(n * 2))).unwrap(),
+        sorter.sort((0..50).map(|n| Num::new(n * 3))).unwrap(),
+    ];
+    let mut sorted: Vec<u8> = (0..50).map(|n| n * 2).chain((0..50).map(|n| n * 3)).collect();
+    sorted.sort();
+    sorted.dedup();
+    let iter = ExtSortedIterator::union(iters).unwrap().dedup();
+    let result: Vec<u8> = iter.map(|i| i.unwrap().the_num).collect();
+    assert_eq!(result, sorted);
+    assert!(ExtSortedIterator::<Num>::union(Vec::new()).is_err());
+}
+
+#[test]
+fn check_sorted() {
+    let r = env::temp_dir().join("external_sort_check_sorted_test");
+    fs::create_dir_all(&r).unwrap();
+    let sorter = ExternalSorter::new(10, Some(r.clone())).check_sorted();
+    let iter = sorter.sort((0..200).map(|n| Num::new(n as u8))).unwrap();
+    // the sorted input is written to a single chunk
+    let tmp_dir = fs::read_dir(&r).unwrap().next().unwrap().unwrap().path();
+    assert_eq!(fs::read_dir(&tmp_dir).unwrap().count(), 1);
+    let result: Vec<u8> = iter.map(|i| i.unwrap().the_num).collect();
+    assert_eq!(result, (0..200).collect::<Vec<u8>>());
+    fs::remove_dir_all(&r).unwrap();
+
+    let mut unsorted: Vec<Num> = (0..100).map(Num::new).collect();
+    for _ in 0..1000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted = unsorted.clone();
+    sorted.sort();
+    let iter = ExternalSorter::new(10, None)
+        .check_sorted()
+        .sort(unsorted.into_iter())
+        .unwrap();
+    let result: Vec<Num> = iter.map(|i| i.unwrap()).collect();
+    assert!(result == sorted);
+}
+
+#[test]
+fn ranked() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+    sorted.sort();
+    let iter = ExternalSorter::new(100, None)
+        .sort(unsorted.clone().into_iter())
+        .unwrap();
+    let parts = iter.split(3);
+    let mut result = Vec::new();
+    for part in parts {
+        result.extend(part.ranked().map(|i| i.unwrap()).map(|(rank, n)| (rank, n.the_num)));
+    }
+    let expected: Vec<(u64, u8)> = sorted.iter().enumerate().map(|(i, n)| (i as u64, *n)).collect();
+    assert_eq!(result, expected);
+
+    let iter = ExternalSorter::new(100, None)
+        .sort(unsorted.into_iter())
+        .unwrap()
+        .dedup();
+    for (rank, n) in iter.ranked().map(|i| i.unwrap()) {
+        assert_eq!(sorted[rank as usize], n.the_num);
+        assert!(rank == 0 || sorted[rank as usize - 1] < n.the_num);
+    }
+}
+
+#[test]
+fn stats() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+    sorted.sort();
+    let iter = ExternalSorter::new(100, None)
+        .premerge(4)
+        .sort(unsorted.into_iter())
+        .unwrap();
+    let stats = iter.stats();
+    assert_eq!(stats.records, 10_000);
+    assert_eq!(stats.bytes, 10_000);
+    assert_eq!(stats.min.map(|n| n.the_num), sorted.first().cloned());
+    assert_eq!(stats.max.map(|n| n.the_num), sorted.last().cloned());
+    assert!(!stats.histogram.is_empty() && stats.histogram.len() <= 33);
+    assert_eq!(stats.histogram.iter().map(|(_, n)| n).sum::<u64>(), 10_000);
+    assert!(stats.histogram.windows(2).all(|w| w[0].0 <= w[1].0));
+}
+
+#[test]
+fn stats_to_json() {
+    let iter = ExternalSorter::new(52, None).threads(2)
+                                            .sort((0..20).rev().map(Num::new))
+                                            .unwrap();
+    let json: serde_json::Value = serde_json::from_str(&iter.stats().to_json().unwrap()).unwrap();
+    assert_eq!(json["config"]["buffer_bytes"], 52);
+    assert_eq!(json["config"]["threads"], 2);
+    assert_eq!(json["runs"], 2);
+    assert_eq!(json["records"], 20);
+    assert_eq!(json["min"]["the_num"], 0);
+    assert_eq!(json["max"]["the_num"], 19);
+}
+
+#[test]
+fn stats_timings() {
+    let mut iter = ExternalSorter::new(10, None).premerge(2)
+                                                .time_merge()
+                                                .sort((0..100).rev().map(Num::new))
+                                                .unwrap();
+    let timings = iter.stats().timings;
+    assert!(timings.ingest > timings.sort);
+    assert!(timings.spill_write > Duration::ZERO);
+    assert_eq!(timings.merge_cpu, None);
+
+    assert!(iter.next().is_some());
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(iter.by_ref().count(), 99);
+    let timings = iter.stats().timings;
+    assert!(timings.output >= Duration::from_millis(20));
+    assert!(timings.merge_read > Duration::ZERO);
+    assert!(timings.compare > Duration::ZERO);
+    assert_eq!(timings.merge_cpu.is_some(), cfg!(unix));
+
+    // the merge is not timed unless asked to
+    let mut iter = ExternalSorter::new(10, None).sort((0..100).rev().map(Num::new)).unwrap();
+    assert_eq!(iter.by_ref().count(), 100);
+    let timings = iter.stats().timings;
+    assert!(timings.spill_write > Duration::ZERO);
+    assert_eq!(timings.merge_read + timings.compare + timings.output, Duration::ZERO);
+    assert_eq!(timings.merge_cpu, None);
+}
+
+#[test]
+fn container_overhead() {
+    // every one-byte record also takes a byte in the chunk vector, so chunks
+    // are spilled at 5 records rather than at 10
+    let iter = ExternalSorter::new(10, None).sort((0..100).rev().map(Num::new)).unwrap();
+    assert_eq!(iter.stats().runs, 20);
+    let sorted: Vec<u8> = iter.map(|n| n.unwrap().the_num).collect();
+    assert_eq!(sorted, (0..100).collect::<Vec<u8>>());
+
+    // the chunk vector keeps its capacity from one chunk to the next, but
+    // only the records it holds count, so every chunk is as large as the first
+    let per_record = std::mem::size_of::<Num>() as u64 + 1;
+    let unsorted = (0..2_000u32).map(|n| Num::new((n * 7 % 256) as u8));
+    let iter = ExternalSorter::new(1_000 * per_record, None).sort(unsorted).unwrap();
+    assert_eq!(iter.stats().runs, 2);
+}
+
+#[test]
+fn on_progress() {
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let sorter = {
+        let reports = reports.clone();
+        ExternalSorter::new(10, None).on_progress(move |p| reports.lock().unwrap().push(p))
+                                     .progress_interval(40)
+    };
+    let sorted = sorter.sort((0..100).rev().map(Num::new)).unwrap();
+    assert_eq!(sorted.count(), 100);
+
+    let reports = reports.lock().unwrap();
+    let phases: Vec<_> = reports.iter().map(|p| (p.phase, p.records)).collect();
+    assert_eq!(phases,
+               vec![(ProgressPhase::Spilling, 40),
+                    (ProgressPhase::Spilling, 80),
+                    (ProgressPhase::Merging, 0),
+                    (ProgressPhase::Merging, 40),
+                    (ProgressPhase::Merging, 80),
+                    (ProgressPhase::Done, 100)]);
+    assert_eq!(reports[0].fraction, None);
+    assert_eq!(reports[3].fraction, Some(0.4));
+    assert_eq!(reports[5].bytes, 100);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn io_priority() {
+    // the I/O priority of the calling thread
+    let ioprio = || unsafe { libc::syscall(libc::SYS_ioprio_get, 1, 0) };
+    let before = ioprio();
+    let spilling = Arc::new(Mutex::new(Vec::new()));
+    let sorter = {
+        let spilling = spilling.clone();
+        ExternalSorter::new(10, None).io_priority(IoPriority::Idle)
+                                     .on_progress(move |p| {
+                                                      if p.phase == ProgressPhase::Spilling {
+                                                          spilling.lock().unwrap().push(ioprio());
+                                                      }
+                                                  })
+                                     .progress_interval(40)
+    };
+    let sorted = sorter.sort((0..100).rev().map(Num::new)).unwrap();
+    assert_eq!(ioprio(), before);
+    assert_eq!(sorted.map(Result::unwrap).count(), 100);
+
+    // the idle class, while the input was spilled
+    assert_eq!(*spilling.lock().unwrap(), vec![3 << 13, 3 << 13]);
+    assert_eq!(ioprio(), before);
+}
+
+#[test]
+fn on_merge_plan() {
+    let planned = Arc::new(Mutex::new(None));
+    let sorter = {
+        let planned = planned.clone();
+        ExternalSorter::new(20, None).on_merge_plan(move |plan| {
+                                         let single = plan.bytes_read();
+                                         plan.cascade(3);
+                                         *planned.lock().unwrap() =
+                                             Some((plan.runs.len(), single, plan.bytes_read()));
+                                     })
+    };
+    // runs of 10 records fill the buffer along with their 10 slots
+    let iter = sorter.sort((0..100).rev().map(Num::new)).unwrap();
+    // 10 runs are merged into 4, then 2 before the final merge, where the
+    // last run of each pass is left as it is
+    assert_eq!(*planned.lock().unwrap(), Some((10, 100, 280)));
+    assert_eq!(iter.stats().runs, 2);
+    let sorted: Vec<u8> = iter.map(|n| n.unwrap().the_num).collect();
+    assert_eq!(sorted, (0..100).collect::<Vec<u8>>());
+
+    let invalid = ExternalSorter::new(10, None).on_merge_plan(|plan| plan.passes.clear());
+    assert!(invalid.sort((0..100).map(Num::new)).is_err());
+}
+
+#[test]
+fn into_runs() {
+    let mut iter = ExternalSorter::new(20, None).sort((0..100).rev().map(Num::new)).unwrap();
+    assert_eq!(iter.next().unwrap().unwrap().the_num, 0);
+    let runs = iter.into_runs();
+    assert_eq!(runs.len(), 10);
+    assert_eq!(runs.iter().map(|r| r.summary().records).sum::<u64>(), 100);
+    // the input was reversed, so the first run holds the largest records
+    assert_eq!(runs[0].smallest().map(|n| n.the_num), Some(90));
+    assert_eq!(runs[0].largest().map(|n| n.the_num), Some(99));
+
+    let mut runs = runs.into_iter();
+    let first: Vec<u8> = runs.next().unwrap().map(|n| n.unwrap().the_num).collect();
+    assert_eq!(first, (90..100).collect::<Vec<u8>>());
+    let last = runs.next_back().unwrap();
+    assert_eq!(last.summary().records, 10);
+    assert_eq!(last.count(), 9);
+
+    let rest = ExtSortedIterator::union(runs.map(|r| r.into_sorted()).collect()).unwrap();
+    let rest: Vec<u8> = rest.map(|n| n.unwrap().the_num).collect();
+    assert_eq!(rest, (10..90).collect::<Vec<u8>>());
+}
+
+#[test]
+fn dyn_sorter() {
+    let sorters: Vec<Box<dyn DynSorter>> =
+        vec![Box::new(ExternalSorter::new(100, None)),
+             Box::new(ExternalSorter::new(100, None).threads(2).premerge(2))];
+    for sorter in sorters {
+        let mut records = (0..100u8).map(|i| vec![i % 10, i]);
+        let sorted = sorter.sort_dyn(&mut records, Arc::new(|a: &[u8], b: &[u8]| b.cmp(a)))
+                           .unwrap();
+        let sorted: Vec<Vec<u8>> = sorted.map(Result::unwrap).collect();
+        assert_eq!(sorted.len(), 100);
+        assert_eq!(sorted[0], vec![9, 99]);
+        assert_eq!(sorted[99], vec![0, 0]);
+        assert!(sorted.windows(2).all(|w| w[0] >= w[1]));
+    }
+}
+
+#[test]
+fn checkpoint_restore() {
+    let dir = tempdir::TempDir::new("external_sort_checkpoint").unwrap();
+    let mut iter = ExternalSorter::new(20, None).sort((0..100).rev().map(Num::new)).unwrap();
+    assert_eq!(iter.by_ref().take(37).count(), 37);
+    iter.checkpoint(dir.path()).unwrap();
+    assert_eq!(iter.by_ref().take(20).count(), 20);
+    drop(iter);
+
+    let mut restored: ExtSortedIterator<Num> = ExtSortedIterator::restore(dir.path()).unwrap();
+    assert_eq!(restored.position(), 37);
+    assert_eq!(restored.next().unwrap().unwrap().the_num, 37);
+    restored.checkpoint(dir.path()).unwrap();
+    let rest: Vec<u8> = restored.map(|n| n.unwrap().the_num).collect();
+    assert_eq!(rest, (38..100).collect::<Vec<u8>>());
+
+    let restored = ExtSortedIterator::restore_by(dir.path(), |a: &Num, b: &Num| a.cmp(b)).unwrap();
+    assert_eq!(restored.count(), 62);
+}
+
+#[test]
+fn checkpoint_verify() {
+    let dir = tempdir::TempDir::new("external_sort_checkpoint_verify").unwrap();
+    let iter = ExternalSorter::new(20, None).sort((0..100).rev().map(Num::new)).unwrap();
+    iter.checkpoint(dir.path()).unwrap();
+    drop(iter);
+
+    // a run changed since the checkpoint, even at the same size, is caught
+    // before merging
+    let run = dir.path().join("run_0");
+    let changed = fs::read_to_string(&run).unwrap().replacen('9', "8", 1);
+    fs::write(&run, changed).unwrap();
+    assert!(ExtSortedIterator::<Num>::restore(dir.path()).is_err());
+    fs::write(&run, "").unwrap();
+    assert!(ExtSortedIterator::<Num>::restore(dir.path()).is_err());
+}
+
+#[test]
+fn verify_runs() {
+    let root = tempdir::TempDir::new("external_sort_verify_runs").unwrap();
+    let sorter = ExternalSorter::new(20, Some(root.path().to_path_buf())).verify_runs();
+    let iter = sorter.sort((0..100).rev().map(Num::new)).unwrap();
+    let tmp_dir = fs::read_dir(root.path()).unwrap().next().unwrap().unwrap().path();
+    let manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(tmp_dir.join("runs.json")).unwrap()).unwrap();
+    let runs = manifest.as_array().unwrap();
+    assert_eq!(runs.len(), 10);
+    assert_eq!(runs[0]["records"], 10);
+    assert_eq!(runs[0]["digest"]["file_bytes"], fs::metadata(tmp_dir.join("0")).unwrap().len());
+    let result: Vec<u8> = iter.map(|n| n.unwrap().the_num).collect();
+    assert_eq!(result, (0..100).collect::<Vec<u8>>());
+
+    // runs are neither checksummed nor listed by default
+    let default = root.path().join("default");
+    fs::create_dir_all(&default).unwrap();
+    let sorter = ExternalSorter::new(20, Some(default.clone()));
+    let iter = sorter.sort((0..100).rev().map(Num::new)).unwrap();
+    let tmp_dir = fs::read_dir(&default).unwrap().next().unwrap().unwrap().path();
+    assert!(!tmp_dir.join("runs.json").exists());
+    assert_eq!(iter.count(), 100);
+}
+
+#[test]
+fn sort_by_nullable_key() {
+    let sorter = ExternalSorter::new(20, None);
+    let sorted = sorter.sort_by_key((0..100).map(Num::new), |n| n.the_num % 10).unwrap();
+    let sorted: Vec<u8> = sorted.map(|n| n.unwrap().the_num).collect();
+    // equal keys keep their input order
+    assert_eq!(sorted[..12].to_vec(), vec![0, 10, 20, 30, 40, 50, 60, 70, 80, 90, 1, 11]);
+
+    let key = |n: &Num| if n.the_num.is_multiple_of(3) { None } else { Some(n.the_num / 10) };
+    let unsorted = (0..100).rev().map(Num::new);
+    let sorted = sorter.sort_by_nullable_key(unsorted, key, NullOrder::NullsLast).unwrap();
+    let sorted: Vec<u8> = sorted.map(|n| n.unwrap().the_num).collect();
+    assert_eq!(sorted[..7].to_vec(), vec![8, 7, 5, 4, 2, 1, 19]);
+    assert_eq!(sorted[66..].to_vec(), (0..100).rev().filter(|n: &u8| n.is_multiple_of(3)).collect::<Vec<u8>>());
+    let sorted = sorter.sort_by_nullable_key((0..100).map(Num::new), key, NullOrder::NullsFirst)
+                       .unwrap();
+    assert_eq!(sorted.map(|n| n.unwrap().the_num).nth(34), Some(1));
+}
+
+#[test]
+fn tie_break() {
+    let by_tens = |a: &Num, b: &Num| (a.the_num / 10).cmp(&(b.the_num / 10));
+    let unsorted = || (0..100u32).map(|n| Num::new((n * 37 % 100) as u8));
+    let sorter = ExternalSorter::new(20, None);
+    let sorted = sorter.sort_by(unsorted(), by_tens).unwrap();
+    let sorted: Vec<u8> = sorted.map(|n| n.unwrap().the_num).collect();
+    let in_order: Vec<u8> = unsorted().map(|n| n.the_num).filter(|n| *n < 10).collect();
+    assert_eq!(sorted[..10].to_vec(), in_order);
+
+    let descending = Arc::new(|a: &Num, b: &Num| b.cmp(a));
+    let sorter = ExternalSorter::new(20, None).tie_break(TieBreak::Then(descending));
+    let sorted = sorter.sort_by(unsorted(), by_tens).unwrap();
+    let sorted: Vec<u8> = sorted.map(|n| n.unwrap().the_num).collect();
+    assert_eq!(sorted[..12].to_vec(), vec![9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 19, 18]);
+
+    let sorter = ExternalSorter::new(20, None).tie_break(TieBreak::Unspecified);
+    let sorted = sorter.sort_by(unsorted(), by_tens).unwrap();
+    let mut sorted: Vec<u8> = sorted.map(|n| n.unwrap().the_num).collect();
+    assert!(sorted.windows(2).all(|w| w[0] / 10 <= w[1] / 10));
+    sorted.sort();
+    assert_eq!(sorted, (0..100).collect::<Vec<u8>>());
+}
+
+#[test]
+fn plan() {
+    let plan = ExternalSorter::<Num>::new(20, None).plan(100, 1);
+    assert_eq!((plan.records_per_run, plan.runs, plan.merge_passes), (10, 10, 1));
+    assert_eq!((plan.peak_disk_bytes, plan.peak_memory_bytes), (100, 20));
+    assert_eq!(plan.merge.bytes_read(), 100);
+
+    let sorter = ExternalSorter::<Num>::new(20, None).on_merge_plan(|plan| plan.cascade(3));
+    let plan = sorter.plan(100, 1);
+    assert_eq!((plan.merge.runs.len(), plan.merge_passes), (10, 3));
+    // the second pass writes a run of 90 records while its sources exist
+    assert_eq!(plan.peak_disk_bytes, 190);
+
+    // runs of 10 records are merged in pairs in the background, leaving runs
+    // of 80 and 20 records
+    let sorter = ExternalSorter::new(40, None).premerge(2);
+    let plan = sorter.plan(100, 1);
+    assert_eq!((plan.runs, plan.merge.runs.len(), plan.merge_passes), (10, 2, 4));
+    let iter = sorter.sort((0..100).rev().map(Num::new)).unwrap();
+    assert_eq!(iter.stats().runs, 2);
+
+    let plan = ExternalSorter::<Num>::new(20, None).plan(0, 1);
+    assert_eq!((plan.runs, plan.peak_disk_bytes), (0, 0));
+}
+
+#[test]
+fn dry_run() {
+    let sorter = ExternalSorter::new(20, None);
+    let dry_run = sorter.dry_run((0..100).rev().map(Num::new)).unwrap();
+    assert_eq!((dry_run.records, dry_run.bytes), (100, 100));
+    // `{"the_num":N}` and a newline, for 10 one-digit and 90 two-digit numbers
+    assert_eq!(dry_run.serialized_bytes, 10 * 14 + 90 * 15);
+    assert_eq!((dry_run.plan.records_per_run, dry_run.plan.runs), (10, 10));
+    assert_eq!(dry_run.plan.peak_disk_bytes, dry_run.serialized_bytes);
+    assert_eq!(dry_run.plan.merge.runs.iter().map(|r| r.bytes).sum::<u64>(), 100);
+    let iter = sorter.sort((0..100).rev().map(Num::new)).unwrap();
+    assert_eq!(iter.stats().runs, 10);
+
+    let sorter = ExternalSorter::new(20, None).threads(2);
+    let dry_run = sorter.dry_run((0..100).map(Num::new)).unwrap();
+    assert_eq!(dry_run.plan.runs, sorter.plan(100, 1).runs);
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Fixed {
+    key: u8,
+}
+
+impl ExternallySortable for Fixed {
+    const APPROX_SIZE: Option<u64> = Some(3);
+    const STABLE_TIES: bool = false;
+}
+
+#[test]
+fn type_defaults() {
+    assert_eq!(Fixed { key: 1 }.get_size(), 3);
+    let iter = ExternalSorter::new(300, None).sort((0..100).rev().map(|key| Fixed { key })).unwrap();
+    // 75 records of 3 bytes and their slots fill the buffer, where 100
+    // records of a single byte would not
+    assert_eq!(iter.stats().runs, 2);
+    let sorted: Vec<u8> = iter.map(|f| f.unwrap().key).collect();
+    assert_eq!(sorted, (0..100).collect::<Vec<u8>>());
+}
+
+#[test]
+fn giant_records() {
+    // three records of 10000 bytes, among smaller ones
+    let unsorted = || {
+        (0..50u32).map(|n| if n % 20 == 7 { "g".repeat(10_000) } else { format!("{:02}", n) })
+    };
+    let sorter = ExternalSorter::new(1_000, None);
+    for sorter in [sorter.clone(), sorter.threads(2)] {
+        let runs = sorter.sort(unsorted()).unwrap().into_runs();
+        let giants: Vec<_> = runs.iter().filter(|r| r.summary().bytes >= 10_000).collect();
+        assert_eq!(giants.len(), 3);
+        assert!(giants.iter().all(|r| r.summary().records == 1));
+
+        let sorted: Vec<String> = sorter.sort(unsorted()).unwrap().map(|s| s.unwrap()).collect();
+        let mut expected: Vec<String> = unsorted().collect();
+        expected.sort();
+        assert_eq!(sorted, expected);
+    }
+}
+
+#[test]
+fn split_skewed() {
+    // every 25th record is a hundred times as large as the others
+    let unsorted = || {
+        (0..200u32).map(|n| if n % 25 == 0 { format!("{:0300}", n) } else { format!("{:03}", n) })
+    };
+    let sorter = ExternalSorter::new(1_000, None);
+    let runs = sorter.sort(unsorted()).unwrap().into_runs().len();
+    for sorter in [sorter.clone().split_skewed(4), sorter.threads(2).split_skewed(4)] {
+        let split = sorter.sort(unsorted()).unwrap().into_runs();
+        assert!(split.len() > runs);
+        // the large records are split off to runs of their own
+        let large: u64 = split.iter()
+                              .filter(|r| r.summary().bytes == 300 * r.summary().records)
+                              .map(|r| r.summary().records)
+                              .sum();
+        assert_eq!(large, 8);
+
+        let sorted: Vec<String> = sorter.sort(unsorted()).unwrap().map(|s| s.unwrap()).collect();
+        let mut expected: Vec<String> = unsorted().collect();
+        expected.sort();
+        assert_eq!(sorted, expected);
+    }
+}
+
+#[test]
+fn watermarks() {
+    // records sort by their first digit, so that many of them are equal
+    let unsorted = || (0..500u32).map(|n| format!("{}{:03}", n * 7 % 10, n));
+    let sorter = ExternalSorter::new(1_000, None);
+    let runs = sorter.sort(unsorted()).unwrap().into_runs().len();
+    let sorter = sorter.watermarks(0.75, 0.5);
+    // runs are written once a quarter of the buffer is left over
+    assert!(sorter.sort(unsorted()).unwrap().into_runs().len() > runs);
+
+    let first_digit = |a: &String, b: &String| a[..1].cmp(&b[..1]);
+    let sorted: Vec<String> = sorter.sort_by_sync(unsorted(), first_digit)
+                                    .unwrap()
+                                    .map(|s| s.unwrap())
+                                    .collect();
+    let mut expected: Vec<String> = unsorted().collect();
+    expected.sort_by(first_digit);
+    assert_eq!(sorted, expected);
+}
+
+#[test]
+fn prefetch() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+    sorted.sort();
+    let sorter = ExternalSorter::new(100, None);
+    for max_bytes in [0, 1, 64, 1 << 20] {
+        let iter = sorter.sort(unsorted.clone().into_iter()).unwrap().prefetch(max_bytes);
+        let result: Vec<u8> = iter.map(|i| i.unwrap().the_num).collect();
+        assert_eq!(result, sorted);
+    }
+
+    // dropping the iterator stops the merge blocked on a full queue
+    let mut iter = sorter.sort(unsorted.into_iter()).unwrap().prefetch(16);
+    assert_eq!(iter.next().unwrap().unwrap().the_num, sorted[0]);
+    drop(iter);
+}
+
+#[test]
+fn sort_onto() {
+    let dir = tempdir::TempDir::new("external_sort_onto").unwrap();
+    let (old, new) = (dir.path().join("old.jsonl"), dir.path().join("new.jsonl"));
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let sorter = ExternalSorter::new(100, None);
+    sorter.sort(unsorted.clone().into_iter()).unwrap().write_indexed(&old, 64).unwrap();
+
+    let batch: Vec<Num> = (0..500).map(|_| Num::new(rand::random())).collect();
+    let merged = sorter.sort_onto(&old, batch.clone().into_iter()).unwrap();
+    assert_eq!(merged.write_indexed(&new, 64).unwrap(), 10_500);
+    unsorted.extend(batch);
+    unsorted.sort();
+    let result: Vec<u8> = fs::read_to_string(&new)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str::<Num>(l).unwrap().the_num)
+        .collect();
+    assert_eq!(result, unsorted.iter().map(|n| n.the_num).collect::<Vec<_>>());
+
+    // the existing output must be sorted by the comparator
+    assert!(sorter.sort_onto_by(&old, iter::empty(), |a, b| b.cmp(a)).is_err());
+}
+
+#[test]
+fn count() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut distinct: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+    distinct.sort();
+    distinct.dedup();
+    let sorter = ExternalSorter::new(100, None);
+    assert_eq!(sorter.sort(unsorted.clone().into_iter()).unwrap().count(), 10_000);
+    let mut iter = sorter.sort(unsorted.clone().into_iter()).unwrap();
+    iter.by_ref().take(1_234).for_each(drop);
+    assert_eq!(iter.count(), 10_000 - 1_234);
+    // de-duplicated and split iterators are counted by merging them
+    let iter = sorter.sort(unsorted.clone().into_iter()).unwrap().dedup();
+    assert_eq!(iter.count(), distinct.len());
+    let parts = sorter.sort(unsorted.into_iter()).unwrap().split(3);
+    assert_eq!(parts.into_iter().map(|p| p.count()).sum::<usize>(), 10_000);
+}
+
+#[test]
+fn smallest_largest() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random::<u8>() / 2 + 10));
+    }
+    let min = unsorted.iter().min().unwrap().the_num;
+    let max = unsorted.iter().max().unwrap().the_num;
+    let sorter = ExternalSorter::new(100, None);
+    let mut iter = sorter.sort(unsorted.clone().into_iter()).unwrap();
+    assert_eq!(iter.smallest().unwrap().unwrap().the_num, min);
+    assert_eq!(iter.largest().unwrap().unwrap().the_num, max);
+    // the records are left to merge
+    assert_eq!(iter.next().unwrap().unwrap().the_num, min);
+    assert_eq!(iter.count(), 9_999);
+
+    let boundaries = [Num::new(40), Num::new(60), Num::new(200)];
+    let parts = sorter.sort(unsorted.into_iter()).unwrap().split_at(&boundaries);
+    for mut part in parts {
+        let (smallest, largest) = (part.smallest().unwrap(), part.largest().unwrap());
+        let records: Vec<Num> = part.map(|n| n.unwrap()).collect();
+        assert!(smallest == records.first().cloned());
+        assert!(largest == records.last().cloned());
+    }
+}
+
+#[test]
+fn compact_onto() {
+    #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+    struct Row {
+        key: u8,
+        value: u32,
+    }
+
+    impl ExternallySortable for Row {
+        fn get_size(&self) -> u64 {
+            5
+        }
+    }
+
+    let dir = tempdir::TempDir::new("external_sort_compact").unwrap();
+    let snapshot = dir.path().join("snapshot.jsonl");
+    let mut latest = vec![None; 256];
+    let rows: Vec<Row> = (0..200u8).map(|key| Row { key, value: 0 }).collect();
+    for row in &rows {
+        latest[row.key as usize] = Some(row.value);
+    }
+    let sorter = ExternalSorter::new(100, None);
+    sorter.sort(rows.into_iter()).unwrap().write_indexed(&snapshot, 64).unwrap();
+
+    // updates of existing and new keys, several times for some of them
+    let delta: Vec<Row> = (1..2_000u32).map(|value| Row { key: rand::random(), value }).collect();
+    for row in &delta {
+        latest[row.key as usize] = Some(row.value);
+    }
+    let compacted: Vec<(u8, u32)> = sorter.compact_onto(&snapshot, delta.into_iter(), |r| r.key)
+                                          .unwrap()
+                                          .map(|r| r.map(|r| (r.key, r.value)).unwrap())
+                                          .collect();
+    let expected: Vec<(u8, u32)> =
+        (0..=255u8).filter_map(|key| latest[key as usize].map(|v| (key, v))).collect();
+    assert_eq!(compacted, expected);
+
+    let unordered = sorter.clone().tie_break(TieBreak::Unspecified);
+    assert!(unordered.compact_onto(&snapshot, iter::empty(), |r| r.key).is_err());
+}
+
+#[test]
+fn reuse_buffers() {
+    let sorter = ExternalSorter::new(100, None).premerge(2).reuse_buffers();
+    for _ in 0..3 {
+        let mut unsorted = Vec::new();
+        for _ in 0..2_000 {
+            unsorted.push(Num::new(rand::random()));
+        }
+        let mut expected: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+        expected.sort();
+        // clones and sessions share the buffers of the sorter
+        let mut session = sorter.clone().session();
+        session.add_input(unsorted.into_iter()).unwrap();
+        let sorted: Vec<u8> = session.finish().unwrap().map(|n| n.unwrap().the_num).collect();
+        assert_eq!(sorted, expected);
+    }
+}
+
+/// Allocator counting the buffers it hands out and takes back
+#[derive(Clone, Default)]
+struct Counting {
+    chunks: Arc<Mutex<(usize, usize)>>,
+    merge_buffers: Arc<Mutex<(usize, usize)>>,
+}
+
+impl BufferAllocator<Num> for Counting {
+    fn chunk(&self, _max_bytes: u64) -> Vec<Num> {
+        self.chunks.lock().unwrap().0 += 1;
+        Vec::new()
+    }
+
+    fn release_chunk(&self, chunk: Vec<Num>) {
+        assert!(chunk.is_empty());
+        self.chunks.lock().unwrap().1 += 1;
+    }
+
+    fn merge_buffer(&self, _max_bytes: u64) -> VecDeque<Num> {
+        self.merge_buffers.lock().unwrap().0 += 1;
+        VecDeque::new()
+    }
+
+    fn release_merge(&self, buffers: Vec<VecDeque<Num>>, _line: Vec<u8>) {
+        self.merge_buffers.lock().unwrap().1 += buffers.len();
+    }
+}
+
+#[test]
+fn buffer_allocator() {
+    let allocator = Counting::default();
+    for threads in 1..3 {
+        let sorter = ExternalSorter::new(100, None).threads(threads)
+                                                   .premerge(2)
+                                                   .buffer_allocator(allocator.clone());
+        let unsorted: Vec<Num> = (0..2_000).map(|_| Num::new(rand::random())).collect();
+        let mut expected: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+        expected.sort();
+        let sorted: Vec<u8> = sorter.sort(unsorted.into_iter())
+                                    .unwrap()
+                                    .map(|n| n.unwrap().the_num)
+                                    .collect();
+        assert_eq!(sorted, expected);
+    }
+    let chunks = *allocator.chunks.lock().unwrap();
+    assert!(chunks.0 > 2);
+    assert_eq!(chunks.0, chunks.1);
+    let merge_buffers = *allocator.merge_buffers.lock().unwrap();
+    assert!(merge_buffers.0 > 2);
+    assert_eq!(merge_buffers.0, merge_buffers.1);
+}
+
+#[test]
+fn compact_records() {
+    #[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    struct Pair(u32, u64);
+
+    impl ExternallySortable for Pair {}
+
+    let unsorted: Vec<Pair> = (0..5_000).map(|i| Pair(rand::random::<u32>() % 100, i)).collect();
+    let mut expected = unsorted.clone();
+    expected.sort_by_key(|p| p.0);
+    let sorter = ExternalSorter::new(2_000, None);
+    let sorted: Vec<Pair> = sorter.sort_by_key(unsorted.clone().into_iter(), |p| p.0)
+                                  .unwrap()
+                                  .map(|p| p.unwrap())
+                                  .collect();
+    assert!(sorted == expected);
+
+    // the heads of the runs follow a restored merge
+    let dir = tempdir::TempDir::new("external_sort_compact_records").unwrap();
+    let mut iter = sorter.sort(unsorted.into_iter()).unwrap();
+    iter.by_ref().take(1_000).for_each(drop);
+    iter.checkpoint(dir.path()).unwrap();
+    let rest: Vec<Pair> = ExtSortedIterator::restore(dir.path()).unwrap()
+                                                                .map(|p| p.unwrap())
+                                                                .collect();
+    expected.sort();
+    assert!(rest == expected[1_000..]);
+}
+
+/// Number that fails to serialize when it is 13
+#[derive(Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Unlucky(u8);
+
+impl Serialize for Unlucky {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        if self.0 == 13 {
+            return Err(serde::ser::Error::custom("unlucky"));
+        }
+        serializer.serialize_u8(self.0)
+    }
+}
+
+impl ExternallySortable for Unlucky {
+    fn get_size(&self) -> u64 {
+        1
+    }
+}
+
+#[test]
+fn capture_on_error() {
+    let dir = tempdir::TempDir::new("external_sort_capture").unwrap();
+    let path = dir.path().join("capture.jsonl");
+    let sorter = ExternalSorter::new(4, None).capture_on_error(&path, 3);
+
+    let sorted = sorter.sort((0..10).rev().map(Unlucky)).unwrap();
+    assert_eq!(sorted.count(), 10);
+    assert!(!path.exists());
+
+    // the last records read before the chunk holding 13 is spilled
+    let err = sorter.sort((10..20).map(Unlucky)).err().unwrap();
+    assert!(err.to_string().starts_with("unlucky (input captured to"));
+    assert!(err.to_string().ends_with("without 1 records that failed to serialize)"));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "11\n12\n");
+
+    // a panicking comparator
+    let sorter = ExternalSorter::new(1_000, None).capture_on_error(&path, usize::MAX);
+    let sort = thread::spawn(move || {
+                   sorter.sort_by((0..6).map(Unlucky), |a, b| {
+                                     assert!(a.0 != 5 && b.0 != 5);
+                                     a.cmp(b)
+                                 })
+                         .map(|sorted| sorted.count())
+                         .map_err(|e| e.to_string())
+               });
+    assert!(sort.join().is_err());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "0\n1\n2\n3\n4\n5\n");
+}
+
+#[test]
+fn run_length_encoding() {
+    let unsorted = || (0..10_000u32).map(|i| Num::new((i * 7 % 10) as u8));
+    let nums = |iter: ExtSortedIterator<Num>| -> Vec<u8> {
+        iter.map(|n| n.unwrap().the_num).collect()
+    };
+    let plain = ExternalSorter::new(1_000, None).tag("plain");
+    let encoded = ExternalSorter::new(1_000, None).tag("encoded").run_length_encoding();
+    let expected = plain.sort(unsorted()).unwrap();
+    let sorted = encoded.sort(unsorted()).unwrap();
+    assert_eq!(sorted.stats().runs, expected.stats().runs);
+    assert!(encoded.tag_disk_usage("encoded") * 4 < plain.tag_disk_usage("plain"));
+    assert_eq!(nums(sorted), nums(expected));
+
+    // parts of the merge start reading the runs at their samples
+    let parts = encoded.sort(unsorted()).unwrap().split(3);
+    assert_eq!(parts.into_iter().flat_map(nums).collect::<Vec<_>>(),
+               nums(plain.sort(unsorted()).unwrap()));
+    for n in [0, 999, 1_000, 5_555, 9_999] {
+        let nth = encoded.select_nth(unsorted(), n).unwrap().unwrap();
+        assert_eq!(nth.the_num as u64, n / 1_000);
+    }
+
+    let counts: Vec<(u8, u64)> = encoded.sort(unsorted())
+                                        .unwrap()
+                                        .counts()
+                                        .map(|c| c.map(|(n, count)| (n.the_num, count)).unwrap())
+                                        .collect();
+    assert_eq!(counts, (0..10).map(|n| (n, 1_000)).collect::<Vec<_>>());
+}
+
+#[test]
+fn run_length_encoding_samples() {
+    let run_bytes = |buffer_bytes, records, run_length| {
+        let root = tempdir::TempDir::new("external_sort_rle").unwrap();
+        let mut sorter = ExternalSorter::new(buffer_bytes, Some(root.path().to_path_buf()));
+        if run_length {
+            sorter = sorter.run_length_encoding();
+        }
+        let iter = sorter.sort(iter::repeat_n(Num::new(7), records)).unwrap();
+        let tmp_dir = fs::read_dir(root.path()).unwrap().next().unwrap().unwrap().path();
+        let bytes: u64 = fs::read_dir(tmp_dir).unwrap()
+                                              .map(|entry| entry.unwrap().metadata().unwrap().len())
+                                              .sum();
+        assert_eq!(iter.count(), records);
+        bytes
+    };
+    // a single chunk of 10,000 repeats is cut at its 32 samples, each 312
+    // records apart
+    let line = r#"{"the_num":7}"#.len() as u64 + 1;
+    assert_eq!(run_bytes(100_000, 10_000, false), 10_000 * line);
+    assert!(run_bytes(100_000, 10_000, true) <= 33 * ("*312\n".len() as u64 + line));
+    // chunks of fewer than 64 records sample every record
+    assert_eq!(run_bytes(50, 1_000, true), 1_000 * line);
+}
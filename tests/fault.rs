@@ -0,0 +1,52 @@
+#![cfg(feature = "testing")]
+
+use std::io::{self, ErrorKind};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use external_sort::{ExtSortedIterator, ExternalSorter, ExternallySortable, Fault};
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Num(u32);
+
+impl ExternallySortable for Num {
+    fn get_size(&self) -> u64 {
+        4
+    }
+}
+
+fn sorted() -> ExtSortedIterator<Num> {
+    ExternalSorter::new(400, None).sort((0..1_000).rev().map(Num)).unwrap()
+}
+
+#[test]
+fn inject_io_error() {
+    let mut iter = sorted();
+    assert!(iter.stats().runs > 1);
+    iter.inject_fault(1, 100, Fault::Io(ErrorKind::ConnectionReset)).unwrap();
+    let results: Vec<_> = iter.collect();
+    assert!(results.len() < 1_000);
+    let err = results.last().unwrap().as_ref().err().unwrap();
+    assert_eq!(err.downcast_ref::<io::Error>().unwrap().kind(), ErrorKind::ConnectionReset);
+    assert!(results[..results.len() - 1].iter().all(|r| r.is_ok()));
+}
+
+#[test]
+fn inject_corruption() {
+    let mut iter = sorted();
+    iter.inject_fault(0, 100, Fault::Corrupt).unwrap();
+    let results: Vec<_> = iter.collect();
+    assert!(results.len() < 1_000);
+    assert!(results.last().unwrap().is_err());
+}
+
+#[test]
+fn inject_delay() {
+    let mut iter = sorted();
+    iter.inject_fault(0, 0, Fault::Delay(Duration::from_millis(50))).unwrap();
+    assert!(iter.inject_fault(iter.stats().runs as usize, 0, Fault::Corrupt).is_err());
+    let started = Instant::now();
+    assert!(iter.map(|r| r.unwrap().0).eq(0..1_000));
+    assert!(started.elapsed() >= Duration::from_millis(50));
+}
@@ -0,0 +1,34 @@
+#![cfg(feature = "ffi")]
+
+use std::ptr;
+use std::slice;
+
+use external_sort::ffi::*;
+
+#[test]
+fn push_and_next() {
+    let records: Vec<Vec<u8>> = (0..1_000u32).rev().map(|i| i.to_be_bytes().to_vec()).collect();
+    unsafe {
+        let sort = extsort_new(16, ptr::null());
+        for record in &records {
+            assert_eq!(extsort_push(sort, record.as_ptr(), record.len()), 0);
+        }
+        assert_eq!(extsort_push(sort, ptr::null(), 0), 0);
+        assert_eq!(extsort_finish(sort), 0);
+
+        let (mut bytes, mut len) = (ptr::null(), 0);
+        let mut sorted = Vec::new();
+        while extsort_next(sort, &mut bytes, &mut len) == 1 {
+            sorted.push(slice::from_raw_parts(bytes, len).to_vec());
+        }
+        assert!(extsort_last_error(sort).is_null());
+        assert_eq!(extsort_push(sort, ptr::null(), 0), -1);
+        assert!(!extsort_last_error(sort).is_null());
+        extsort_free(sort);
+
+        let mut expected = records.clone();
+        expected.push(Vec::new());
+        expected.sort();
+        assert_eq!(sorted, expected);
+    }
+}
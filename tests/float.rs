@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+
+use std::cmp::Ordering;
+
+use external_sort::{by_f64_key, cmp_f64, ExternalSorter, ExternallySortable, NanOrder, TotalF64};
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Reading {
+    id: u8,
+    value: TotalF64,
+}
+
+impl ExternallySortable for Reading {
+    fn get_size(&self) -> u64 {
+        1
+    }
+}
+
+#[test]
+fn sort_total_f64() {
+    let floats = vec![2.5, f64::NAN, -0.0, f64::NEG_INFINITY, 0.0, -f64::NAN, 1e-9, f64::INFINITY];
+    let sorted = ExternalSorter::new(16, None).sort(floats.into_iter().map(TotalF64)).unwrap();
+    let sorted: Vec<f64> = sorted.map(|f| f.unwrap().0).collect();
+    assert!(sorted[0].is_nan() && sorted[0].is_sign_negative());
+    assert_eq!(sorted[1..7].to_vec(), vec![f64::NEG_INFINITY, -0.0, 0.0, 1e-9, 2.5, f64::INFINITY]);
+    assert!(sorted[2].is_sign_negative());
+    assert!(sorted[7].is_nan() && sorted[7].is_sign_positive());
+}
+
+#[test]
+fn nan_order() {
+    assert_eq!(cmp_f64(-f64::NAN, 1.0, NanOrder::Last), Ordering::Greater);
+    assert_eq!(cmp_f64(1.0, f64::NAN, NanOrder::First), Ordering::Greater);
+    assert_eq!(cmp_f64(f64::NAN, -f64::NAN, NanOrder::First), Ordering::Equal);
+    assert_eq!(cmp_f64(-f64::NAN, 1.0, NanOrder::Total), Ordering::Less);
+    assert_eq!(cmp_f64(-0.0, 0.0, NanOrder::First), Ordering::Less);
+
+    let readings = vec![(1, 2.0), (2, f64::NAN), (3, -1.0)];
+    let readings = readings.into_iter().map(|(id, v)| Reading { id, value: TotalF64(v) });
+    let compare = by_f64_key(|r: &Reading| r.value.0, NanOrder::First);
+    let sorted = ExternalSorter::new(16, None).sort_by(readings, compare).unwrap();
+    let ids: Vec<u8> = sorted.map(|r| r.unwrap().id).collect();
+    assert_eq!(ids, vec![2, 3, 1]);
+}
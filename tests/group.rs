@@ -0,0 +1,41 @@
+use external_sort::ExternalSorter;
+
+mod common;
+use common::Num;
+
+#[test]
+fn group_by_key() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+    sorted.sort();
+    let iter = ExternalSorter::new(100, None)
+        .sort(unsorted.into_iter())
+        .unwrap()
+        .group_by_key(|n| n.the_num / 10, 10_000);
+    let mut result = Vec::new();
+    let mut last_key = None;
+    for group in iter {
+        let (key, group) = group.unwrap();
+        assert!(last_key < Some(key));
+        last_key = Some(key);
+        for n in group {
+            assert_eq!(n.the_num / 10, key);
+            result.push(n.the_num);
+        }
+    }
+    assert_eq!(result, sorted);
+}
+
+#[test]
+fn group_too_large() {
+    let unsorted: Vec<Num> = (0..100).map(|n| Num::new(n % 2)).collect();
+    let mut iter = ExternalSorter::new(10, None)
+        .sort(unsorted.into_iter())
+        .unwrap()
+        .group_by_key(|n| n.the_num, 10);
+    assert!(iter.next().unwrap().is_err());
+    assert!(iter.next().is_none());
+}
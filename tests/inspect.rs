@@ -0,0 +1,70 @@
+use std::fs;
+
+use serde_json::Value;
+
+use external_sort::{inspect_run, ExternalSorter, RunInfo};
+
+mod common;
+use common::Num;
+
+#[test]
+fn inspect() {
+    let root = tempdir::TempDir::new("external_sort_inspect").unwrap();
+    let sorter = ExternalSorter::new(20, Some(root.path().to_path_buf())).verify_runs();
+    let iter = sorter.sort((0..100).rev().map(Num::new)).unwrap();
+    let tmp_dir = fs::read_dir(root.path()).unwrap().next().unwrap().unwrap().path();
+
+    // the first run holds the first 10 records of the input
+    let run = tmp_dir.join("0");
+    let info = inspect_run(&run, 4).unwrap();
+    assert_eq!(info.records, 10);
+    assert_eq!(info.file_bytes, fs::metadata(&run).unwrap().len());
+    assert_eq!(info.min.as_ref().unwrap()["the_num"], 90);
+    assert_eq!(info.max.as_ref().unwrap()["the_num"], 99);
+    let blocks: Vec<(u64, u64)> = info.blocks.iter().map(|b| (b.position, b.offset)).collect();
+    assert_eq!(blocks, vec![(0, 0), (4, 60), (8, 120)]);
+    assert_eq!(info.blocks[1].first["the_num"], 94);
+    assert_eq!(info.intact(), Some(true));
+    assert_eq!(info.manifest.unwrap().records, 10);
+
+    fs::write(&run, "{\"the_num\":90}\n").unwrap();
+    assert_eq!(inspect_run(&run, 4).unwrap().intact(), Some(false));
+    fs::write(&run, "not json\n").unwrap();
+    assert!(inspect_run(&run, 4).is_err());
+    drop(iter);
+}
+
+#[test]
+fn inspect_run_length_encoded() {
+    let root = tempdir::TempDir::new("external_sort_inspect").unwrap();
+    let run_of = |run_length: bool| {
+        let dir = root.path().join(if run_length { "encoded" } else { "plain" });
+        fs::create_dir(&dir).unwrap();
+        let mut sorter = ExternalSorter::new(1_000, Some(dir.clone())).verify_runs();
+        if run_length {
+            sorter = sorter.run_length_encoding();
+        }
+        let iter = sorter.sort((0..2_000u32).map(|i| Num::new((i % 4) as u8))).unwrap();
+        let tmp_dir = fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+        (iter, inspect_run(tmp_dir.join("0"), 100).unwrap())
+    };
+    let (_plain_iter, plain) = run_of(false);
+    let (_iter, info) = run_of(true);
+    assert_eq!(info.intact(), Some(true));
+    assert_eq!(info.records, plain.records);
+    assert!(info.file_bytes * 4 < plain.file_bytes);
+    assert_eq!(info.min, plain.min);
+    assert_eq!(info.max, plain.max);
+    let blocks = |info: &RunInfo| -> Vec<(u64, Value)> {
+        info.blocks.iter().map(|b| (b.position, b.first.clone())).collect()
+    };
+    assert_eq!(blocks(&info), blocks(&plain));
+    assert!(info.blocks.windows(2).all(|b| b[0].offset < b[1].offset));
+
+    // no more repeats are read back than are ever written as one
+    let run = root.path().join("corrupt");
+    fs::write(&run, "*1024\n{\"the_num\":7}\n").unwrap();
+    assert_eq!(inspect_run(&run, 100).unwrap().records, 1024);
+    fs::write(&run, "*1025\n{\"the_num\":7}\n").unwrap();
+    assert!(inspect_run(&run, 100).is_err());
+}
@@ -0,0 +1,54 @@
+use external_sort::{ExternalSorter, JoinKind};
+
+mod common;
+use common::Num;
+
+type Joined = Vec<(Option<u8>, Option<u8>)>;
+
+fn expected(left: &[u8], right: &[u8], kind: JoinKind) -> Joined {
+    let mut joined = Vec::new();
+    for &l in left {
+        let matches: Vec<u8> = right.iter().cloned().filter(|r| *r / 2 == l / 2).collect();
+        if matches.is_empty() && kind != JoinKind::Inner {
+            joined.push((Some(l), None));
+        }
+        joined.extend(matches.into_iter().map(|r| (Some(l), Some(r))));
+    }
+    if kind == JoinKind::Full {
+        for &r in right {
+            if left.iter().all(|l| l / 2 != r / 2) {
+                joined.push((None, Some(r)));
+            }
+        }
+    }
+    joined.sort();
+    joined
+}
+
+#[test]
+fn join() {
+    let left: Vec<u8> = (0..300).map(|_| rand::random::<u8>() % 60).collect();
+    let right: Vec<u8> = (0..200).map(|_| rand::random::<u8>() % 60 + 20).collect();
+    // groups of right records outgrow the memory buffer
+    let sorter = ExternalSorter::new(5, None);
+    for &kind in [JoinKind::Inner, JoinKind::Left, JoinKind::Full].iter() {
+        let iter = sorter.join(left.iter().map(|&n| Num::new(n)),
+                               right.iter().map(|&n| Num::new(n)),
+                               |l| l.the_num / 2,
+                               |r| r.the_num / 2,
+                               kind)
+                         .unwrap();
+        let mut last_key = None;
+        let mut result = Vec::new();
+        for pair in iter {
+            let (l, r) = pair.unwrap();
+            let (l, r) = (l.map(|l| l.the_num), r.map(|r| r.the_num));
+            let key = l.or(r).map(|n| n / 2);
+            assert!(last_key <= key);
+            last_key = key;
+            result.push((l, r));
+        }
+        result.sort();
+        assert_eq!(result, expected(&left, &right, kind));
+    }
+}
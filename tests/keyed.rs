@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+use external_sort::{ExternalSorter, ExternallySortable, SortKeyed};
+
+/// Payload without any ordering
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct Reading {
+    sensor: String,
+    value: f64,
+}
+
+#[test]
+fn sort_keyed() {
+    let readings: Vec<SortKeyed<u32, Reading>> =
+        (0..1000u32).map(|i| {
+                        let reading = Reading { sensor: format!("s{}", 999 - i), value: i as f64 };
+                        SortKeyed::new(i % 10, reading)
+                    })
+                    .collect();
+    let sorted = ExternalSorter::new(4_000, None).sort(readings.clone().into_iter()).unwrap();
+    assert!(sorted.stats().runs > 1);
+    let sorted: Vec<SortKeyed<u32, Reading>> = sorted.map(Result::unwrap).collect();
+    // equal keys keep their input order, whatever their payloads
+    let mut expected = readings;
+    expected.sort_by_key(|r| r.key);
+    let pairs = |records: Vec<SortKeyed<u32, Reading>>| -> Vec<(u32, Reading)> {
+        records.into_iter().map(SortKeyed::into_pair).collect()
+    };
+    assert_eq!(pairs(sorted), pairs(expected));
+
+    let a = SortKeyed::new(1, Reading { sensor: "a".to_string(), value: 1.0 });
+    let b = SortKeyed::new(1, Reading { sensor: "b".to_string(), value: f64::NAN });
+    assert_eq!(a, b);
+    assert!(a < SortKeyed::new(2, b.value.clone()));
+    assert_eq!(a.get_size(), serde_json::to_string(&a).unwrap().len() as u64);
+}
+
+#[test]
+fn sort_pairs() {
+    let pairs = (0..100u32).map(|i| (format!("k{}", i % 7), i));
+    let sorted = ExternalSorter::new(200, None).sort_pairs(pairs).unwrap().dedup();
+    let firsts: Vec<(String, u32)> = sorted.map(|r| r.unwrap().into_pair()).collect();
+    let expected: Vec<(String, u32)> = (0..7).map(|i| (format!("k{}", i), i)).collect();
+    assert_eq!(firsts, expected);
+
+    let json = serde_json::to_string(&SortKeyed::from((1, "x".to_string()))).unwrap();
+    assert_eq!(json, r#"{"key":1,"value":"x"}"#);
+}
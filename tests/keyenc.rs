@@ -0,0 +1,64 @@
+use std::cmp::Ordering;
+
+use external_sort::keyenc::{self, Descending, EncodeKey};
+use external_sort::Decimal;
+
+/// Check that the encodings of `keys` compare as `compare` does
+fn check_order<K, F>(keys: &[K], compare: F)
+where
+    K: EncodeKey,
+    F: Fn(&K, &K) -> Ordering,
+{
+    for a in keys {
+        for b in keys {
+            assert_eq!(keyenc::encode(a).cmp(&keyenc::encode(b)), compare(a, b));
+        }
+    }
+}
+
+#[test]
+fn integers() {
+    check_order(&[i64::MIN, -300, -1, 0, 1, 255, 256, i64::MAX], Ord::cmp);
+    check_order(&[i8::MIN, -1, 0, 1, i8::MAX], Ord::cmp);
+    check_order(&[0u32, 1, 255, 256, u32::MAX], Ord::cmp);
+    check_order(&[0u128, 1, u64::MAX as u128 + 1, u128::MAX], Ord::cmp);
+    check_order(&[i128::MIN, i64::MIN as i128 - 1, -1, 0, u64::MAX as i128 + 1, i128::MAX],
+                Ord::cmp);
+}
+
+#[test]
+fn decimals() {
+    let decimals: Vec<Decimal> =
+        ["-1e30", "-100", "-99.99", "-0.5", "-0.05", "0", "1e-30", "0.1", "0.10001", "1", "10"]
+            .iter()
+            .map(|s| Decimal::parse(s).unwrap())
+            .collect();
+    check_order(&decimals, Ord::cmp);
+    check_order(&[(Decimal::parse("1.5").unwrap(), 2u8), (Decimal::parse("1.50").unwrap(), 3)],
+                Ord::cmp);
+}
+
+#[test]
+fn floats() {
+    let floats =
+        [f64::NEG_INFINITY, -2.5, -1e-300, -0.0, 0.0, 1e-300, 2.5, f64::INFINITY, f64::NAN];
+    check_order(&floats, |a, b| a.total_cmp(b));
+    check_order(&[-1.5f32, -0.0, 0.0, 3.0], |a, b| a.total_cmp(b));
+}
+
+#[test]
+fn strings() {
+    let strings = ["", "\0", "\0\0", "\0a", "a", "a\0", "a\0b", "a\x01", "ab", "b"];
+    check_order(&strings, |a, b| a.cmp(b));
+}
+
+#[test]
+fn tuples() {
+    let keys = [("", 2u8), ("", 10), ("a", 0), ("a\0", 0), ("ab", 1)];
+    check_order(&keys, Ord::cmp);
+    let keys = [(None, Descending(-1i32)), (Some(5u16), Descending(7)), (Some(5), Descending(-1))];
+    check_order(&keys, |a, b| a.0.cmp(&b.0).then(b.1 .0.cmp(&a.1 .0)));
+    let keys =
+        [(Descending("b"), 0u8), (Descending("ab"), 1), (Descending("a"), 2), (Descending(""), 0)];
+    check_order(&keys, |a, b| b.0 .0.cmp(a.0 .0).then(a.1.cmp(&b.1)));
+}
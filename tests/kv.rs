@@ -0,0 +1,43 @@
+use external_sort::{DuplicateKeys, ExternalSorter, KeyValue};
+
+fn pairs() -> Vec<KeyValue> {
+    (0..1000u32).map(|i| KeyValue {
+                        key: (i % 300).to_be_bytes().to_vec(),
+                        value: i.to_be_bytes().to_vec(),
+                    })
+                .collect()
+}
+
+#[test]
+fn bulk_load() {
+    let sorter = ExternalSorter::new(400, None);
+    let policies = [(DuplicateKeys::KeepFirst, false), (DuplicateKeys::KeepLast, true)];
+    for &(duplicates, last) in policies.iter() {
+        let mut written = Vec::new();
+        let dropped = sorter.bulk_load(pairs().into_iter(), duplicates, |key, value| {
+                                 written.push((key.to_vec(), value.to_vec()));
+                                 Ok(())
+                             })
+                            .unwrap();
+        assert_eq!(dropped, 700);
+        let expected: Vec<(Vec<u8>, Vec<u8>)> =
+            (0..300u32).map(|i| {
+                           // keys below 100 are provided 4 times, the others 3 times
+                           let value = match (last, i < 100) {
+                               (false, _) => i,
+                               (true, true) => i + 900,
+                               (true, false) => i + 600,
+                           };
+                           (i.to_be_bytes().to_vec(), value.to_be_bytes().to_vec())
+                       })
+                       .collect();
+        assert_eq!(written, expected);
+    }
+}
+
+#[test]
+fn bulk_load_fail() {
+    let sorter = ExternalSorter::new(400, None);
+    let result = sorter.bulk_load(pairs().into_iter(), DuplicateKeys::Fail, |_, _| Ok(()));
+    assert!(result.is_err());
+}
@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+use external_sort::{ExternalSorter, ExternallySortable};
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+struct Document {
+    id: u32,
+    body: String,
+}
+
+impl ExternallySortable for Document {
+    fn get_size(&self) -> u64 {
+        4 + self.body.len() as u64
+    }
+}
+
+#[test]
+fn sort_by_key_late() {
+    let unsorted: Vec<Document> = (0..2_000u32)
+        .map(|i| Document {
+            id: rand::random::<u32>() % 500,
+            body: format!("{:0200}", i),
+        })
+        .collect();
+    let mut expected = unsorted.clone();
+    // a stable sort keeps documents with equal ids in input order
+    expected.sort_by_key(|d| d.id);
+
+    let sorted: Vec<Document> = ExternalSorter::new(1_000, None)
+        .sort_by_key_late(unsorted.into_iter(), |d| d.id)
+        .unwrap()
+        .map(|d| d.unwrap())
+        .collect();
+    assert_eq!(sorted, expected);
+}
@@ -0,0 +1,75 @@
+use std::io::Cursor;
+
+use serde_json::json;
+
+use external_sort::{compare_versions, ExternalSorter, JsonKey};
+
+#[test]
+fn sort_lines() {
+    let inputs = vec![Cursor::new("b\nd\na"), Cursor::new("c\r\ne\n")];
+    let sorted = ExternalSorter::new(2, None).sort_lines(inputs).unwrap();
+    let mut output = Vec::new();
+    assert_eq!(sorted.write_lines(&mut output).unwrap(), 5);
+    assert_eq!(String::from_utf8(output).unwrap(), "a\nb\nc\nd\ne\n");
+}
+
+#[test]
+fn sort_json_lines() {
+    let inputs = vec![Cursor::new("{\"id\": 3, \"v\": \"a\"}\n{\"v\": \"b\"}\n"),
+                      Cursor::new("{\"id\": 1,  \"v\": \"c\"}\n{\"id\": 3, \"v\": \"d\"}\n")];
+    let sorted = ExternalSorter::new(20, None).sort_json_lines(inputs, "/id").unwrap();
+    let mut output = Vec::new();
+    assert_eq!(sorted.write_lines(&mut output).unwrap(), 4);
+    // lines are written as they were read, and equal keys keep their order
+    assert_eq!(String::from_utf8(output).unwrap(),
+               "{\"v\": \"b\"}\n{\"id\": 1,  \"v\": \"c\"}\n{\"id\": 3, \"v\": \"a\"}\n\
+                {\"id\": 3, \"v\": \"d\"}\n");
+}
+
+#[test]
+fn json_key_eq() {
+    // keys are equal exactly when they compare equal
+    assert_eq!(JsonKey(json!(1)), JsonKey(json!(1.0)));
+    assert_eq!(JsonKey(json!([1, "a"])), JsonKey(json!([1.0, "a"])));
+    assert_ne!(JsonKey(json!(1)), JsonKey(json!("1")));
+}
+
+#[test]
+fn sort_lines_by_key_error() {
+    let inputs = vec![Cursor::new("2\n1\nx\n")];
+    let error = ExternalSorter::new(2, None).sort_lines_by_key(inputs, |line| {
+                                                Ok(line.parse::<u32>()?)
+                                            })
+                                            .err()
+                                            .unwrap();
+    assert!(error.to_string().starts_with("line 3: "));
+}
+
+#[test]
+fn sort_lines_terminator() {
+    let inputs = vec![Cursor::new("b\0a\nc\0"), Cursor::new("\r\n\0")];
+    let sorted = ExternalSorter::new(2, None).terminator(b'\0').sort_lines(inputs).unwrap();
+    let mut output = Vec::new();
+    assert_eq!(sorted.write_lines(&mut output).unwrap(), 3);
+    assert_eq!(output, b"\r\n\0a\nc\0b\0");
+}
+
+#[test]
+fn sort_json_lines_crlf() {
+    let inputs = vec![Cursor::new("{\"id\": 2}\r\n\r\n{\"id\": 1}\r\n")];
+    let sorted = ExternalSorter::new(20, None).sort_json_lines(inputs, "/id").unwrap();
+    let lines: Vec<String> = sorted.map(|r| r.unwrap().line).collect();
+    assert_eq!(lines, vec!["", "{\"id\": 1}", "{\"id\": 2}"]);
+}
+
+#[test]
+fn sort_versions() {
+    let inputs = vec![Cursor::new("1.2.10\n1.2.9\n1.10\nv2\n1.2.0\n1.02\n"),
+                      Cursor::new("1.2.0-rc.1\n1.2\n1.2.0-alpha\n1.2.0-rc.10\n1.2.0-rc.2\n")];
+    let sorted = ExternalSorter::new(20, None).sort_lines_by(inputs, |a, b| compare_versions(a, b))
+                                              .unwrap();
+    let sorted: Vec<String> = sorted.map(Result::unwrap).collect();
+    assert_eq!(sorted,
+               vec!["1.02", "1.2", "1.2.0-alpha", "1.2.0-rc.1", "1.2.0-rc.2", "1.2.0-rc.10",
+                    "1.2.0", "1.2.9", "1.2.10", "1.10", "v2"]);
+}
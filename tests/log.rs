@@ -0,0 +1,46 @@
+#![cfg(feature = "log")]
+
+use std::sync::Mutex;
+
+use external_sort::ExternalSorter;
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// Logger recording every message
+struct Recorder {
+    messages: Mutex<Vec<String>>,
+}
+
+impl Log for Recorder {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        self.messages.lock().unwrap().push(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+static RECORDER: Recorder = Recorder { messages: Mutex::new(Vec::new()) };
+
+#[test]
+fn lifecycle_messages() {
+    log::set_logger(&RECORDER).unwrap();
+    log::set_max_level(LevelFilter::Debug);
+
+    let sorted = ExternalSorter::new(10, None).premerge(2)
+                                              .sort((0..100u32).rev().map(|i| i.to_string()))
+                                              .unwrap();
+    assert_eq!(sorted.map(Result::unwrap).count(), 100);
+
+    let messages = RECORDER.messages.lock().unwrap();
+    for prefix in &["created temporary directory ",
+                    "wrote run ",
+                    "merging 2 runs of level 0 ",
+                    "removing temporary directory "]
+    {
+        assert!(messages.iter().any(|m| m.starts_with(prefix)), "no {} message", prefix);
+    }
+    assert!(messages.iter().any(|m| m.starts_with("merging ") && m.ends_with(" bytes)")));
+}
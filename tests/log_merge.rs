@@ -0,0 +1,28 @@
+use std::io::Cursor;
+
+use external_sort::MergedLines;
+
+#[test]
+fn merge_json_logs() {
+    let inputs = vec![Cursor::new("{\"t\":1,\"m\":\"a\"}\n{\"t\":4}\n{\"t\":4,\"m\":\"b\"}\n"),
+                      Cursor::new("{\"t\":0}\n{\"t\":4,\"m\":\"c\"}\n"),
+                      Cursor::new(""),
+                      Cursor::new("{\"t\":2}\n{\"t\":9}")];
+    let mut output = Vec::new();
+    let lines = MergedLines::json(inputs, "/t").unwrap().write_lines(&mut output).unwrap();
+    assert_eq!(lines, 7);
+    assert_eq!(String::from_utf8(output).unwrap(),
+               "{\"t\":0}\n{\"t\":1,\"m\":\"a\"}\n{\"t\":2}\n{\"t\":4}\n{\"t\":4,\"m\":\"b\"}\n\
+                {\"t\":4,\"m\":\"c\"}\n{\"t\":9}\n");
+}
+
+#[test]
+fn merge_out_of_order() {
+    let inputs = vec![Cursor::new("2 b\n1 a\n"), Cursor::new("3 c\n")];
+    let mut merged =
+        MergedLines::by_key(inputs, |line| Ok(line.split(' ').next().unwrap().parse::<u32>()?))
+            .unwrap();
+    assert_eq!(merged.next().unwrap().unwrap().line, "2 b");
+    assert_eq!(merged.next().unwrap().unwrap_err().to_string(), "input 1, line 2: out of order");
+    assert!(merged.next().is_none());
+}
@@ -0,0 +1,44 @@
+#![cfg(feature = "mmap")]
+
+use external_sort::ExternalSorter;
+
+#[test]
+fn sort_mapped() {
+    let records = (0..1000).rev().map(|i| format!("{:04}", i).into_bytes());
+    let mut sorted = ExternalSorter::new(1_000, None).sort_mapped(records).unwrap();
+
+    assert_eq!(sorted.next_record(), Some(&b"0000"[..]));
+    assert_eq!(sorted.next_record(), Some(&b"0001"[..]));
+    let mut output = Vec::new();
+    assert_eq!(sorted.write_all(&mut output, b"\n").unwrap(), 998);
+    let expected: String = (2..1000).map(|i| format!("{:04}\n", i)).collect();
+    assert_eq!(output, expected.into_bytes());
+    assert_eq!(sorted.next_record(), None);
+}
+
+#[test]
+fn sort_mapped_empty_records() {
+    let records = vec![b"b".to_vec(), Vec::new(), b"a".to_vec(), Vec::new()];
+    let mut sorted = ExternalSorter::new(40, None).sort_mapped(records).unwrap();
+    let mut output = Vec::new();
+    assert_eq!(sorted.write_all(&mut output, b",").unwrap(), 4);
+    assert_eq!(output, b",,a,b,");
+}
+
+#[test]
+fn sort_mapped_by() {
+    // three-byte messages, ordered by their second byte
+    let records = (0..1000u32).map(|i| vec![3, (i * 7 % 256) as u8, (i % 3) as u8]);
+    let sorter = ExternalSorter::new(1_000, None);
+    let mut sorted = sorter.sort_mapped_by(records, |a, b| a[1].cmp(&b[1])).unwrap();
+    let mut previous = 0;
+    let mut records = 0;
+    while let Some(record) = sorted.next_record() {
+        assert_eq!(record.as_ptr() as usize % 8, 0);
+        assert_eq!(record.len(), 3);
+        assert!(record[1] >= previous);
+        previous = record[1];
+        records += 1;
+    }
+    assert_eq!(records, 1000);
+}
@@ -0,0 +1,69 @@
+#![cfg(feature = "metrics")]
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use external_sort::ExternalSorter;
+use metrics::{Counter, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit};
+
+/// Recorder keeping the value of every counter and gauge
+#[derive(Default)]
+struct Values {
+    values: Mutex<HashMap<String, Arc<AtomicU64>>>,
+}
+
+impl Values {
+    fn value(&self, key: &Key) -> Arc<AtomicU64> {
+        let mut values = self.values.lock().unwrap();
+        values.entry(key.name().to_string()).or_default().clone()
+    }
+
+    fn counter(&self, name: &str) -> u64 {
+        self.values.lock().unwrap()[name].load(Ordering::SeqCst)
+    }
+
+    fn gauge(&self, name: &str) -> f64 {
+        f64::from_bits(self.values.lock().unwrap()[name].load(Ordering::SeqCst))
+    }
+}
+
+impl Recorder for Values {
+    fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+    fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+    fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+    fn register_counter(&self, key: &Key, _: &Metadata) -> Counter {
+        Counter::from_arc(self.value(key))
+    }
+
+    fn register_gauge(&self, key: &Key, _: &Metadata) -> Gauge {
+        Gauge::from_arc(self.value(key))
+    }
+
+    fn register_histogram(&self, _: &Key, _: &Metadata) -> Histogram {
+        Histogram::noop()
+    }
+}
+
+#[test]
+fn counters_and_gauges() {
+    let values = Values::default();
+    metrics::with_local_recorder(&values, || {
+        let sorted = ExternalSorter::new(10, None).sort((0..100u32).rev().map(|i| i.to_string()))
+                                                  .unwrap();
+        assert!(values.gauge("external_sort_open_runs") > 1.0);
+        assert!(values.gauge("external_sort_temp_bytes") > 0.0);
+        assert_eq!(sorted.map(Result::unwrap).count(), 100);
+    });
+
+    assert_eq!(values.counter("external_sort_records_in"), 100);
+    assert_eq!(values.counter("external_sort_records_out"), 100);
+    assert!(values.counter("external_sort_bytes_spilled") > 0);
+    assert!(values.counter("external_sort_merge_comparisons") > 0);
+    // the runs are removed along with the iterator
+    assert_eq!(values.gauge("external_sort_open_runs"), 0.0);
+    assert_eq!(values.gauge("external_sort_temp_bytes"), 0.0);
+}
@@ -0,0 +1,35 @@
+use std::cmp::Reverse;
+
+use external_sort::{ExternalSorter, PartitionOrder};
+
+mod common;
+use common::Num;
+
+
+#[test]
+fn sort_within_partitions() {
+    let mut unsorted = vec![Num::new(2), Num::new(1), Num::new(0)];
+    for _ in 0..1000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let sorter = ExternalSorter::new(100, None);
+    // partitions are first seen in the order 2, 1, 0
+    let first_seen: fn(u8) -> u8 = |n| 2 - n % 3;
+    let sorted: fn(u8) -> u8 = |n| n % 3;
+    for &(order, segment) in [(PartitionOrder::FirstSeen, first_seen),
+                              (PartitionOrder::Sorted, sorted)].iter()
+    {
+        let mut expected: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+        expected.sort_by_key(|&n| (segment(n), Reverse(n)));
+        let iter = sorter.sort_within_partitions(unsorted.clone().into_iter(),
+                                                 |n| n.the_num % 3,
+                                                 order,
+                                                 |a, b| b.cmp(a))
+                         .unwrap();
+        let result: Vec<(u64, u8)> = iter.map(|i| i.unwrap())
+                                         .map(|(segment, n)| (segment, n.the_num))
+                                         .collect();
+        let segments: Vec<u64> = expected.iter().map(|&n| u64::from(segment(n))).collect();
+        assert_eq!(result, segments.into_iter().zip(expected).collect::<Vec<_>>());
+    }
+}
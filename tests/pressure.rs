@@ -0,0 +1,31 @@
+#![cfg(feature = "pressure")]
+
+use std::path::Path;
+
+use external_sort::ExternalSorter;
+
+mod common;
+use common::Num;
+
+#[test]
+fn memory_pressure() {
+    // the available memory is only known on Linux
+    if !Path::new("/proc/meminfo").exists() {
+        return;
+    }
+    let unsorted = || (0..10_000u32).map(|n| Num { the_num: (n * 7 % 256) as u8 });
+    let iter = ExternalSorter::new(15_000, None).sort(unsorted()).unwrap();
+    assert_eq!(iter.stats().runs, 2);
+
+    // no memory is ever short of a threshold of 0
+    let sorter = ExternalSorter::new(15_000, None).memory_pressure(0);
+    assert_eq!(sorter.sort(unsorted()).unwrap().stats().runs, 2);
+
+    // while memory is always short, chunks shrink at every check
+    let sorter = ExternalSorter::new(15_000, None).memory_pressure(u64::MAX);
+    let iter = sorter.sort(unsorted()).unwrap();
+    assert!(iter.stats().runs > 2);
+    let sorted: Vec<u8> = iter.map(|n| n.unwrap().the_num).collect();
+    assert_eq!(sorted.len(), 10_000);
+    assert!(sorted.windows(2).all(|w| w[0] <= w[1]));
+}
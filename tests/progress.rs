@@ -0,0 +1,19 @@
+#![cfg(feature = "indicatif")]
+
+use external_sort::ExternalSorter;
+use indicatif::ProgressBar;
+
+#[test]
+fn progress_bar() {
+    let bar = ProgressBar::hidden();
+    let unsorted = (0..100u32).rev().map(|i| format!("{:03}", i));
+    let sorted = ExternalSorter::new(30, None).progress_bar(bar.clone()).sort(unsorted).unwrap();
+    // the input was counted, and the bar now measures the merge
+    assert_eq!(bar.length(), Some(100));
+    assert_eq!(bar.position(), 0);
+
+    let sorted: Vec<String> = sorted.map(Result::unwrap).collect();
+    assert_eq!(sorted.len(), 100);
+    assert_eq!(bar.position(), 100);
+    assert!(bar.is_finished());
+}
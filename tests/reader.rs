@@ -0,0 +1,46 @@
+use external_sort::{ExternalSorter, SortedReader};
+
+mod common;
+use common::Num;
+
+#[test]
+fn get_and_seek() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        let n: u8 = rand::random();
+        if n != 77 {
+            unsorted.push(Num::new(n));
+        }
+    }
+    let mut sorted: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+    sorted.sort();
+    let dir = tempdir::TempDir::new("external_sort_reader").unwrap();
+    let path = dir.path().join("sorted");
+
+    let records = ExternalSorter::new(100, None)
+        .sort(unsorted.into_iter())
+        .unwrap()
+        .write_indexed(&path, 64)
+        .unwrap();
+    assert_eq!(records, sorted.len() as u64);
+
+    let reader = SortedReader::open(&path).unwrap();
+    for n in [0, 76, 77, 78, 255].iter() {
+        let found = reader.get(&Num::new(*n)).unwrap().map(|n| n.the_num);
+        assert_eq!(found, sorted.iter().find(|s| *s == n).cloned());
+
+        let from: Vec<u8> = reader.seek(&Num::new(*n))
+                                  .unwrap()
+                                  .map(|t| t.unwrap().the_num)
+                                  .collect();
+        let expected: Vec<u8> = sorted.iter().filter(|s| *s >= n).cloned().collect();
+        assert_eq!(from, expected);
+
+        let after: Vec<u8> = reader.seek_after(&Num::new(*n))
+                                   .unwrap()
+                                   .map(|t| t.unwrap().the_num)
+                                   .collect();
+        let expected: Vec<u8> = sorted.iter().filter(|s| *s > n).cloned().collect();
+        assert_eq!(after, expected);
+    }
+}
@@ -0,0 +1,40 @@
+use std::collections::{BTreeMap, HashSet};
+
+use external_sort::ExternalSorter;
+
+mod common;
+use common::Num;
+
+#[test]
+fn sort_and_reduce() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut expected = BTreeMap::new();
+    for n in unsorted.iter() {
+        *expected.entry(n.the_num / 3).or_insert(0u64) += u64::from(n.the_num);
+    }
+    let expected: Vec<(u8, u64)> = expected.into_iter().collect();
+    let iter = ExternalSorter::new(20, None)
+        .sort_and_reduce(unsorted.into_iter(),
+                         |n| n.the_num / 3,
+                         |n| u64::from(n.the_num),
+                         |a, b| a + b)
+        .unwrap();
+    let result: Vec<(u8, u64)> = iter.map(|i| i.unwrap()).collect();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn count_distinct() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let expected = unsorted.iter().map(|n| n.the_num / 3).collect::<HashSet<u8>>().len();
+    let distinct = ExternalSorter::new(20, None)
+        .count_distinct(unsorted.into_iter(), |n| n.the_num / 3)
+        .unwrap();
+    assert_eq!(distinct, expected as u64);
+}
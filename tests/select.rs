@@ -0,0 +1,101 @@
+use external_sort::ExternalSorter;
+
+mod common;
+use common::Num;
+
+#[test]
+fn top_k() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+    sorted.sort();
+    sorted.reverse();
+    let sorter = ExternalSorter::new(100, None);
+    for &k in [0, 10, 100, 1000].iter() {
+        let iter = sorter.top_k(unsorted.clone().into_iter(), k).unwrap();
+        let result: Vec<u8> = iter.map(|i| i.unwrap().the_num).collect();
+        assert_eq!(result, &sorted[..k]);
+    }
+}
+
+#[test]
+fn bottom_k() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+    sorted.sort();
+    let sorter = ExternalSorter::new(100, None);
+    for &k in [0, 10, 100, 1000, 20_000].iter() {
+        let iter = sorter.bottom_k(unsorted.clone().into_iter(), k).unwrap();
+        let result: Vec<u8> = iter.map(|i| i.unwrap().the_num).collect();
+        assert_eq!(result, &sorted[..k.min(sorted.len())]);
+    }
+}
+
+#[test]
+fn percentiles() {
+    let mut unsorted = Vec::new();
+    for _ in 0..1000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+    sorted.sort();
+    let result = ExternalSorter::new(100, None)
+        .percentiles(unsorted.into_iter(), &[50.0, 0.0, 100.0, 99.9, 25.0])
+        .unwrap();
+    let result: Vec<u8> = result.into_iter().map(|n| n.the_num).collect();
+    assert_eq!(result, [sorted[499], sorted[0], sorted[999], sorted[998], sorted[249]]);
+}
+
+#[test]
+fn percentiles_fail() {
+    let sorter = ExternalSorter::new(100, None);
+    assert!(sorter.percentiles(vec![Num::new(1)].into_iter(), &[101.0]).is_err());
+    assert!(sorter.percentiles(Vec::<Num>::new().into_iter(), &[50.0]).is_err());
+}
+
+#[test]
+fn select_nth() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+    sorted.sort();
+    let sorter = ExternalSorter::new(100, None);
+    for &n in [0, 1, 99, 5000, 9999].iter() {
+        let nth = sorter.select_nth(unsorted.clone().into_iter(), n as u64).unwrap();
+        assert_eq!(nth.map(|n| n.the_num), Some(sorted[n]));
+    }
+    assert!(sorter.select_nth(unsorted.into_iter(), 10_000).unwrap().is_none());
+}
+
+#[test]
+fn sample() {
+    let sorter = ExternalSorter::new(100, None);
+    let sample = sorter.sample((0..=255).map(Num::new), 50);
+    assert_eq!(sample.len(), 50);
+    assert!(sample.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(sorter.sample((0..10).map(Num::new), 50).len(), 10);
+    // every record is as likely to be picked
+    let mut picked = [false; 10];
+    for _ in 0..200 {
+        picked[sorter.sample((0..10).map(Num::new), 1)[0].the_num as usize] = true;
+    }
+    assert!(picked.iter().all(|p| *p));
+
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let sample: Vec<u8> = sorter.sorted_sample(unsorted.into_iter(), 100)
+                                .into_iter()
+                                .map(|n| n.the_num)
+                                .collect();
+    assert_eq!(sample.len(), 100);
+    assert!(sample.windows(2).all(|w| w[0] <= w[1]));
+}
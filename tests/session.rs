@@ -0,0 +1,70 @@
+use std::fs;
+
+use external_sort::{ExternalSorter, SortSession};
+
+mod common;
+use common::Num;
+
+#[test]
+fn session() {
+    let random = || (0..300).map(|_| Num::new(rand::random())).collect();
+    let inputs: Vec<Vec<Num>> = (0..5).map(|_| random()).collect();
+    let mut expected: Vec<Num> = inputs.concat();
+    expected.sort();
+
+    let sorter = ExternalSorter::new(100, None);
+    let mut session = sorter.session();
+    for input in inputs.iter() {
+        session.add_input(input.clone().into_iter()).unwrap();
+    }
+    let sorted: Vec<Num> = session.finish().unwrap().map(|n| n.unwrap()).collect();
+    assert_eq!(sorted, expected);
+
+    // equal records are merged in the order they were added
+    let mut expected: Vec<Num> = inputs.concat();
+    expected.sort_by_key(|n| n.the_num / 16);
+    let mut session = sorter.session_by(|a: &Num, b: &Num| {
+                                            (a.the_num / 16).cmp(&(b.the_num / 16))
+                                        });
+    for input in inputs.iter() {
+        session.add_input(input.clone().into_iter()).unwrap();
+    }
+    let sorted: Vec<Num> = session.finish().unwrap().map(|n| n.unwrap()).collect();
+    assert_eq!(sorted, expected);
+
+    let empty = sorter.session().finish().unwrap();
+    assert_eq!(empty.count(), 0);
+}
+
+#[test]
+fn save_load() {
+    let random = || (0..300).map(|_| Num::new(rand::random())).collect();
+    let inputs: Vec<Vec<Num>> = (0..3).map(|_| random()).collect();
+    let mut expected: Vec<Num> = inputs.concat();
+    expected.sort();
+    let dir = tempdir::TempDir::new("session").unwrap();
+
+    let mut session = ExternalSorter::new(100, None).threads(2).session();
+    session.add_input(inputs[0].clone().into_iter()).unwrap();
+    session.save(dir.path()).unwrap();
+    drop(session);
+
+    // the saved runs outlive the session, and later saves only add new runs
+    let mut session = SortSession::load(dir.path()).unwrap();
+    session.add_input(inputs[1].clone().into_iter()).unwrap();
+    session.save(dir.path()).unwrap();
+    drop(session);
+
+    let mut session = SortSession::load(dir.path()).unwrap();
+    session.add_input(inputs[2].clone().into_iter()).unwrap();
+    let iter = session.finish().unwrap();
+    assert_eq!(iter.stats().config.unwrap().threads, Some(2));
+    let sorted: Vec<Num> = iter.map(|n| n.unwrap()).collect();
+    assert_eq!(sorted, expected);
+
+    assert!(SortSession::<Num>::load(dir.path().join("missing")).is_err());
+
+    // a run lost since the session was saved is caught when it is loaded
+    fs::remove_file(dir.path().join("run_0")).unwrap();
+    assert!(SortSession::<Num>::load(dir.path()).is_err());
+}
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+use external_sort::{ExternalSorter, ExternallySortable};
+
+mod common;
+use common::Num;
+
+#[test]
+fn intersection() {
+    let sorter = ExternalSorter::new(10, None);
+    let a = sorter.sort((0..100).map(|n| Num::new(n % 50 * 2))).unwrap();
+    let b = sorter.sort((0..50).map(|n| Num::new(n * 3))).unwrap();
+    let mut sorted: Vec<u8> = (0..100).map(|n| n % 50 * 2).filter(|n| n % 3 == 0).collect();
+    sorted.sort();
+    let result: Vec<u8> = a.intersection(b).map(|i| i.unwrap().the_num).collect();
+    assert_eq!(result, sorted);
+}
+
+#[test]
+fn difference() {
+    let sorter = ExternalSorter::new(10, None);
+    let a = sorter.sort((0..100).map(|n| Num::new(n % 50 * 2))).unwrap();
+    let b = sorter.sort((0..50).map(|n| Num::new(n * 3))).unwrap();
+    let mut sorted: Vec<u8> = (0..100).map(|n| n % 50 * 2).filter(|n| n % 3 != 0).collect();
+    sorted.sort();
+    let result: Vec<u8> = a.difference(b).map(|i| i.unwrap().the_num).collect();
+    assert_eq!(result, sorted);
+}
+
+#[test]
+fn difference_by_key() {
+    let sorter = ExternalSorter::new(10, None);
+    let blocklist = ExternalSorter::<Block>::new(10, None);
+    let a = sorter.sort((0..100).map(Num::new)).unwrap();
+    let b = blocklist.sort((0..10).map(|n| Block { tens: n * 2 })).unwrap();
+    let sorted: Vec<u8> = (0..100).filter(|n| n / 10 % 2 == 1).collect();
+    let result: Vec<u8> = a.difference_by_key(b, |n| n.the_num / 10, |b| b.tens)
+                           .map(|i| i.unwrap().the_num)
+                           .collect();
+    assert_eq!(result, sorted);
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Block {
+    tens: u8,
+}
+
+impl ExternallySortable for Block {
+    fn get_size(&self) -> u64 {
+        1
+    }
+}
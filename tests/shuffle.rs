@@ -0,0 +1,51 @@
+use std::collections::HashSet;
+
+use external_sort::ExternalSorter;
+
+mod common;
+use common::Num;
+
+#[test]
+fn shuffle() {
+    let mut unsorted = Vec::new();
+    for _ in 0..10_000 {
+        unsorted.push(Num::new(rand::random()));
+    }
+    let mut sorted: Vec<u8> = unsorted.iter().map(|n| n.the_num).collect();
+    sorted.sort();
+
+    let parts = ExternalSorter::new(100, None)
+        .shuffle(unsorted.into_iter(), 4, |n| n.the_num)
+        .unwrap();
+    assert_eq!(parts.len(), 4);
+    let mut seen = HashSet::new();
+    let mut merged = Vec::new();
+    for part in parts {
+        let part: Vec<u8> = part.map(|i| i.unwrap().the_num).collect();
+        assert!(part.windows(2).all(|w| w[0] <= w[1]));
+        // every key lands in exactly one partition
+        let keys: HashSet<u8> = part.iter().cloned().collect();
+        assert!(seen.is_disjoint(&keys));
+        seen.extend(keys);
+        merged.extend(part);
+    }
+    merged.sort();
+    assert_eq!(merged, sorted);
+}
+
+#[test]
+fn shuffle_by() {
+    let unsorted = vec![Num::new(5), Num::new(2), Num::new(1), Num::new(3), Num::new(4)];
+    let parts = ExternalSorter::new(2, None)
+        .shuffle_by(unsorted.into_iter(), 2, |n| n.the_num % 2, |a, b| b.cmp(a))
+        .unwrap();
+    let mut merged = Vec::new();
+    for part in parts {
+        let part: Vec<u8> = part.map(|i| i.unwrap().the_num).collect();
+        assert!(part.windows(2).all(|w| w[0] >= w[1]));
+        merged.extend(part);
+    }
+    // the hash is fixed, so odd numbers always land in the first partition
+    // and even numbers in the second
+    assert_eq!(merged, vec![5, 3, 1, 4, 2]);
+}
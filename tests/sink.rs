@@ -0,0 +1,53 @@
+use std::thread;
+
+use external_sort::ExternalSorter;
+
+mod common;
+use common::Num;
+
+#[test]
+fn sink() {
+    let sink = ExternalSorter::new(100, None).sink();
+    let producers: Vec<_> = (0..4)
+        .map(|_| {
+            let sink = sink.clone();
+            thread::spawn(move || {
+                let mut pushed = Vec::new();
+                for _ in 0..2_500 {
+                    let n: u8 = rand::random();
+                    sink.push(Num::new(n)).unwrap();
+                    pushed.push(n);
+                }
+                pushed
+            })
+        })
+        .collect();
+    let mut sorted = Vec::new();
+    for producer in producers {
+        sorted.extend(producer.join().unwrap());
+    }
+    sorted.sort();
+
+    let iter = sink.finish().unwrap();
+    let result: Vec<u8> = iter.map(|i| i.unwrap().the_num).collect();
+    assert_eq!(result, sorted);
+}
+
+#[test]
+fn sink_by() {
+    let sink = ExternalSorter::new(2, None).sink_by(|a: &Num, b: &Num| b.cmp(a));
+    for n in &[5, 2, 1, 3, 4] {
+        sink.push(Num::new(*n)).unwrap();
+    }
+    let result: Vec<u8> = sink.finish().unwrap().map(|i| i.unwrap().the_num).collect();
+    assert_eq!(result, vec![5, 4, 3, 2, 1]);
+}
+
+#[test]
+fn finish_twice() {
+    let sink = ExternalSorter::<Num>::new(2, None).sink();
+    let other = sink.clone();
+    let handle = thread::spawn(move || other.finish().is_ok());
+    let finished = sink.finish().is_ok();
+    assert!(finished != handle.join().unwrap());
+}
@@ -0,0 +1,33 @@
+use std::fs;
+
+use external_sort::ExternalSorter;
+
+mod common;
+use common::Num;
+
+#[test]
+fn store() {
+    let dir = tempdir::TempDir::new("external_sort_store").unwrap();
+    let sorter = ExternalSorter::new(20, None).premerge(2);
+    let mut store = sorter.open_store(dir.path()).unwrap();
+    let mut inserted = Vec::new();
+    for _ in 0..2_005 {
+        let n: u8 = rand::random();
+        store.insert(Num::new(n)).unwrap();
+        inserted.push(n);
+    }
+    inserted.sort();
+    // records not flushed yet are included
+    let sorted: Vec<u8> = store.iter().unwrap().map(|n| n.unwrap().the_num).collect();
+    assert_eq!(sorted, inserted);
+    store.close().unwrap();
+
+    // a run left over by an interrupted flush is removed
+    fs::write(dir.path().join("run_1000"), "{\"the_num\":0}\n").unwrap();
+    let store = sorter.open_store(dir.path()).unwrap();
+    assert!(!dir.path().join("run_1000").exists());
+    // every level was compacted once full
+    assert!(store.levels().iter().all(|&runs| runs < 2));
+    let sorted: Vec<u8> = store.iter().unwrap().map(|n| n.unwrap().the_num).collect();
+    assert_eq!(sorted, inserted);
+}
@@ -0,0 +1,61 @@
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use external_sort::{ExternalSorter, ExternallySortable};
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Num(u32);
+
+impl ExternallySortable for Num {
+    fn get_size(&self) -> u64 {
+        4
+    }
+}
+
+#[test]
+fn tag() {
+    let dir = tempdir::TempDir::new("external_sort_tag").unwrap();
+    let planned = Arc::new(Mutex::new(None));
+    let hook = planned.clone();
+    let sorter = ExternalSorter::new(400, Some(dir.path().to_path_buf()))
+        .on_merge_plan(move |plan| *hook.lock().unwrap() = plan.tag.clone())
+        .tag("tenant a/1");
+
+    let sorted = sorter.sort((0..1_000).rev().map(Num)).unwrap();
+    let names: Vec<String> = fs::read_dir(dir.path()).unwrap()
+                                                     .map(|e| e.unwrap().file_name())
+                                                     .map(|n| n.to_string_lossy().into_owned())
+                                                     .collect();
+    assert_eq!(names.len(), 1);
+    assert!(names[0].starts_with("sort_fasta_tenant_a_1"));
+    assert_eq!(sorted.stats().config.unwrap().tag.as_deref(), Some("tenant a/1"));
+    assert_eq!(planned.lock().unwrap().as_deref(), Some("tenant a/1"));
+    assert!(sorter.tag_disk_usage("tenant a/1") > 0);
+    assert_eq!(sorter.tag_disk_usage("tenant b"), 0);
+
+    drop(sorted);
+    assert_eq!(sorter.tag_disk_usage("tenant a/1"), 0);
+}
+
+#[test]
+fn tag_quota() {
+    let sorter = ExternalSorter::new(400, None).tag_quota("small", 1_000);
+    let small = sorter.clone().tag("small");
+    let large = sorter.clone().tag("large");
+
+    let sorted = small.sort((0..100).rev().map(Num)).unwrap();
+    assert!(sorted.map(|r| r.unwrap().0).eq(0..100));
+
+    let kept = small.sort((0..100).rev().map(Num)).unwrap();
+    let err = small.sort((0..1_000).rev().map(Num)).err().unwrap();
+    assert!(err.to_string().contains("over their disk quota of 1000 bytes"));
+    assert!(sorter.tag_disk_usage("small") < 1_000);
+
+    // other tags are not bound by the quota
+    assert!(large.sort((0..1_000).rev().map(Num)).is_ok());
+    // runs of iterators that are dropped no longer count
+    drop(kept);
+    assert!(small.sort((0..200).rev().map(Num)).is_ok());
+}
@@ -0,0 +1,73 @@
+#![cfg(feature = "tracing")]
+
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use external_sort::ExternalSorter;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// Subscriber recording the names of spans and the messages of events
+#[derive(Default)]
+struct Recorder {
+    next_id: AtomicU64,
+    spans: Mutex<Vec<String>>,
+    events: Mutex<Vec<String>>,
+}
+
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes) -> Id {
+        self.spans.lock().unwrap().push(span.metadata().name().to_string());
+        Id::from_u64(self.next_id.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    fn record(&self, _: &Id, _: &Record) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event) {
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        self.events.lock().unwrap().push(message.0);
+    }
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+#[test]
+fn spans_and_events() {
+    let recorder = Arc::new(Recorder::default());
+    tracing::subscriber::with_default(recorder.clone(), || {
+        let sorted = ExternalSorter::new(10, None).premerge(2)
+                                                  .sort((0..100u32).rev().map(|i| i.to_string()))
+                                                  .unwrap();
+        assert_eq!(sorted.map(Result::unwrap).count(), 100);
+    });
+
+    let spans = recorder.spans.lock().unwrap();
+    let events = recorder.events.lock().unwrap();
+    for name in &["run_generation", "write_run", "merge_pass"] {
+        assert!(spans.iter().any(|s| s == name), "no {} span", name);
+    }
+    for message in &["wrote run", "generated runs", "starting merge", "refilled run buffer"] {
+        assert!(events.iter().any(|e| e == message), "no {} event", message);
+    }
+}